@@ -2,36 +2,310 @@ extern crate proc_macro;
 
 use proc_macro2::TokenStream;
 use quote::{quote, quote_spanned};
+use syn::parse::{Parse, ParseStream};
 use syn::spanned::Spanned;
-use syn::{parse_macro_input, Data, DeriveInput, Fields};
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Field, Fields, GenericArgument, Lit, Meta,
+    NestedMeta, PathArguments, Type,
+};
 
-
-#[proc_macro_derive(EvgFields)]
-pub fn evg_fields(input: proc_macro::TokenStream) -> proc_macro::TokenStream 
-{
+#[proc_macro_derive(EvgFields, attributes(evg))]
+pub fn evg_fields(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     // Parse the input tokens into a syntax tree
     let input = parse_macro_input!(input as DeriveInput);
 
-
     // Used in the quasi-quotation below as `#name`.
-    let name = input.ident;
+    let name = &input.ident;
+
+    // Carry generic parameters, lifetimes and where clauses over to the impl, along with the
+    // bounds the generated code needs
+    let generics = bounded_generics(&input, fields_bound);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
     // Generate an expression to add fields to a vector
     let add_fields = evg_fields_impl(&input.data);
 
+    // And one that lists them without an instance
+    let add_type_fields = evg_type_fields_impl(&input.data);
+
+    // And a table describing them
+    let field_infos = evg_field_infos(&input.data);
+
+    // And an enum of the paths, for structs without type parameters
+    let field_enum = field_enum(&input);
+
+    // And the names evg_field! follows
+    let field_names = field_names(&input);
+
     let expanded = quote! {
         // The generated impl.
-        impl evergreen_rs_types::EvgFields for #name {
+        impl #impl_generics evergreen_rs_types::EvgFields for #name #ty_generics #where_clause {
+            const FIELDS: &'static [evergreen_rs_types::EvgFieldInfo] = &[#(#field_infos),*];
+
             fn evg_fields_nested(&self, prefix: &str, out: &mut Vec<String>) {
                 #add_fields
             }
+
+            fn evg_type_fields(prefix: &str, out: &mut Vec<String>) {
+                #add_type_fields
+            }
         }
+
+        #field_enum
+
+        #field_names
     };
 
     // Hand the output tokens back to the compiler.
     proc_macro::TokenStream::from(expanded)
 }
 
+#[proc_macro_derive(EvgValues, attributes(evg))]
+pub fn evg_values(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = bounded_generics(&input, values_bound);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let add_values = evg_values_impl(&input.data);
+
+    let expanded = quote! {
+        impl #impl_generics evergreen_rs_types::EvgValues for #name #ty_generics #where_clause {
+            fn evg_values_nested(
+                &self,
+                prefix: &str,
+                out: &mut Vec<(String, evergreen_rs_types::serde_json::Value)>,
+            ) {
+                #add_values
+            }
+        }
+    };
+
+    proc_macro::TokenStream::from(expanded)
+}
+
+// The type a field needs a bound on and the trait, None when the generated code needs nothing
+type FieldBound = fn(&Field, bool) -> Option<(Type, TokenStream)>;
+
+// Bounds for EvgFields, `newtype` is true for the field of a newtype enum variant
+fn fields_bound(field: &Field, newtype: bool) -> Option<(Type, TokenStream)> {
+    let attrs = parse_field_attrs(&field.attrs).ok()?;
+    if attrs.skip || !(attrs.nested || newtype) {
+        return None;
+    }
+    let ty = match wrapper(&field.ty) {
+        Some(Wrapper::Vec(inner)) | Some(Wrapper::Option(inner)) if !newtype => inner,
+        _ => &field.ty,
+    };
+    Some((ty.clone(), quote!(evergreen_rs_types::EvgFields)))
+}
+
+// Bounds for EvgValues, nested fields need EvgValues and the rest are serialized
+fn values_bound(field: &Field, newtype: bool) -> Option<(Type, TokenStream)> {
+    let attrs = parse_field_attrs(&field.attrs).ok()?;
+    if attrs.skip {
+        return None;
+    }
+    let (ty, bound) = match wrapper(&field.ty) {
+        _ if newtype => (&field.ty, quote!(evergreen_rs_types::EvgValues)),
+        None if attrs.nested => (&field.ty, quote!(evergreen_rs_types::EvgValues)),
+        Some(Wrapper::Option(inner)) if attrs.nested => {
+            (inner, quote!(evergreen_rs_types::EvgValues))
+        }
+        _ => (&field.ty, quote!(evergreen_rs_types::serde::Serialize)),
+    };
+    Some((ty.clone(), bound))
+}
+
+// Whether a type uses any of the type parameters
+fn mentions_param(tokens: TokenStream, params: &[String]) -> bool {
+    tokens.into_iter().any(|t| match t {
+        proc_macro2::TokenTree::Ident(ident) => params.contains(&ident.to_string()),
+        proc_macro2::TokenTree::Group(group) => mentions_param(group.stream(), params),
+        _ => false,
+    })
+}
+
+// The generics of the input with a bound added for each field that uses a type parameter
+fn bounded_generics(input: &DeriveInput, bound: FieldBound) -> syn::Generics {
+    let params: Vec<String> = input
+        .generics
+        .type_params()
+        .map(|p| p.ident.to_string())
+        .collect();
+    let mut generics = input.generics.clone();
+    if params.is_empty() {
+        return generics;
+    }
+
+    let fields: Vec<(&Field, bool)> = match input.data {
+        Data::Struct(ref data) => data.fields.iter().map(|f| (f, false)).collect(),
+        Data::Enum(ref data) => data
+            .variants
+            .iter()
+            .flat_map(|v| {
+                let newtype = is_newtype(&v.fields);
+                v.fields.iter().map(move |f| (f, newtype))
+            })
+            .collect(),
+        Data::Union(_) => Vec::new(),
+    };
+
+    let where_clause = generics.make_where_clause();
+    for (field, newtype) in fields {
+        let ty = &field.ty;
+        if !mentions_param(quote!(#ty), &params) {
+            continue;
+        }
+        if let Some((ty, bound)) = bound(field, newtype) {
+            where_clause.predicates.push(syn::parse_quote!(#ty: #bound));
+        }
+    }
+    generics
+}
+
+// A field path in evg_field!, e.g. distro.distro_id or instance_tags[].key
+struct FieldPathInput {
+    ty: Type,
+    // Each segment and whether it is a list whose members the rest of the path is in
+    segments: Vec<(syn::Member, bool)>,
+}
+
+impl Parse for FieldPathInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ty = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+
+        let mut segments = Vec::new();
+        loop {
+            let member: syn::Member = input.parse()?;
+            let mut list = false;
+            if input.peek(syn::token::Bracket) {
+                let content;
+                syn::bracketed!(content in input);
+                if !content.is_empty() {
+                    return Err(content.error("expected [] for the members of a list"));
+                }
+                list = true;
+            }
+            segments.push((member, list));
+
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<syn::Token![.]>()?;
+        }
+        Ok(FieldPathInput { ty, segments })
+    }
+}
+
+// FNV-1a of a Rust field name, the same as evergreen_rs_types::field_hash
+fn field_hash(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// Implement EvgFieldAt for each field of a struct so evg_field! can find its name and type
+//
+// Types with type parameters are left out, like the field enum, and so are newtype structs
+// since their field has no name in paths.
+fn field_names(input: &DeriveInput) -> TokenStream {
+    let data = match input.data {
+        Data::Struct(ref data) => data,
+        _ => return quote!(),
+    };
+    if input.generics.type_params().next().is_some() || is_newtype(&data.fields) {
+        return quote!();
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut impls = Vec::new();
+    for (i, field) in data.fields.iter().enumerate() {
+        let attrs = match parse_field_attrs(&field.attrs) {
+            Ok(attrs) => attrs,
+            Err(e) => return e.to_compile_error(),
+        };
+        if attrs.skip {
+            continue;
+        }
+
+        let hash = field_hash(&field_name(field, i, None));
+        // Escaped like evergreen_rs_types::escape_segment so a dotted name stays one segment
+        let path = field_name(field, i, attrs.rename)
+            .replace('\\', "\\\\")
+            .replace('.', "\\.");
+        let inner = match wrapper(&field.ty) {
+            Some(Wrapper::Vec(inner)) | Some(Wrapper::Option(inner)) => inner,
+            None => &field.ty,
+        };
+        impls.push(quote! {
+            impl #impl_generics evergreen_rs_types::EvgFieldAt<#hash> for #name #ty_generics #where_clause {
+                type Inner = #inner;
+                const NAME: &'static str = #path;
+            }
+        });
+    }
+    quote!(#(#impls)*)
+}
+
+/// A field path of a type as a string, checked when compiling
+///
+/// `evg_field!(Host, distro.distro_id)` is `"distro.distro_id"`, and fails to compile if `Host`
+/// has no `distro` with a `distro_id`. Use `name[]` for the members of a list, the rest of the
+/// path goes through `Vec` and `Option` fields to their members. The path takes the Rust field
+/// names and gives the `#[evg(rename)]` ones, e.g. `evg_field!(Build, id)` is `"_id"`. Each
+/// type in the path must derive EvgFields.
+#[proc_macro]
+pub fn evg_field(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let FieldPathInput { ty, segments } = parse_macro_input!(input as FieldPathInput);
+
+    let mut parent = quote!(#ty);
+    let mut parts = Vec::new();
+    let mut checks = Vec::new();
+    for (i, (member, list)) in segments.iter().enumerate() {
+        let name = match member {
+            syn::Member::Named(ident) => ident.to_string(),
+            syn::Member::Unnamed(index) => index.index.to_string(),
+        };
+        let hash = field_hash(&name);
+        let field = quote!(<#parent as evergreen_rs_types::EvgFieldAt<#hash>>);
+
+        // Never run, it only points a missing field out by name
+        let check = quote::format_ident!("check_{}", i);
+        checks.push(quote! {
+            #[allow(dead_code)]
+            fn #check(v: &#parent) {
+                let _ = &v.#member;
+            }
+        });
+
+        if i > 0 {
+            parts.push(quote!("."));
+        }
+        parts.push(quote!(#field::NAME));
+        if *list {
+            parts.push(quote!("[]"));
+        }
+        parent = quote!(#field::Inner);
+    }
+
+    let expanded = quote! {
+        {
+            #(#checks)*
+            const PARTS: &[&str] = &[#(#parts),*];
+            const BYTES: [u8; evergreen_rs_types::concat_len(PARTS)] =
+                evergreen_rs_types::concat_parts(PARTS);
+            const PATH: &str = match std::str::from_utf8(&BYTES) {
+                Ok(path) => path,
+                Err(_) => panic!("field names are UTF-8"),
+            };
+            PATH
+        }
+    };
+    proc_macro::TokenStream::from(expanded)
+}
 
 // Generate an expression to sum up the heap size of each field.
 fn evg_fields_impl(data: &Data) -> TokenStream {
@@ -52,18 +326,23 @@ fn evg_fields_impl(data: &Data) -> TokenStream {
                     // underlines which field it is. An example is shown in the
                     // readme of the parent directory.
                     let recurse = fields.named.iter().map(|f| {
-                        let name = &f.ident;
-                        let name_str = name.as_ref().unwrap().to_string();
-                        quote_spanned! {f.span()=>
-                            out.push(evergreen_rs_types::make_name(prefix, #name_str)) ;
-                        }
+                        let ident = &f.ident;
+                        field_stmt(f, 0, Some(quote!(&self.#ident)))
                     });
                     quote! {
                         #(#recurse)*
                     }
                 }
-                Fields::Unnamed(ref _fields) => {
+                Fields::Unnamed(_) => {
+                    // Tuple structs report positions, like serde's array of their fields,
+                    // and newtypes the value they wrap
+                    let fields = struct_fields(&data.fields);
+                    let recurse = fields.iter().enumerate().map(|(i, f)| {
+                        let index = syn::Index::from(i);
+                        field_stmt(f, i, Some(quote!(&self.#index)))
+                    });
                     quote! {
+                        #(#recurse)*
                     }
                 }
                 Fields::Unit => {
@@ -72,6 +351,670 @@ fn evg_fields_impl(data: &Data) -> TokenStream {
                 }
             }
         }
-        Data::Enum(_) | Data::Union(_) => unimplemented!(),
+        Data::Enum(ref data) => {
+            // Each variant reports its own fields so untagged enums list the fields of
+            // whichever shape was received
+            let arms = data.variants.iter().map(|v| {
+                let variant = &v.ident;
+                match v.fields {
+                    Fields::Named(ref fields) => {
+                        // Only bind the fields that are recursed into
+                        let bound = fields.named.iter()
+                            .filter(|f| uses_value(f))
+                            .map(|f| &f.ident);
+                        let stmts = fields.named.iter().map(|f| {
+                            let ident = &f.ident;
+                            field_stmt(f, 0, Some(quote!(#ident)))
+                        });
+                        quote_spanned! {v.span()=>
+                            Self::#variant { #(#bound,)* .. } => {
+                                #(#stmts)*
+                            }
+                        }
+                    }
+                    Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => {
+                        // Newtype variants delegate to the wrapped type
+                        let inner = &fields.unnamed[0];
+                        quote_spanned! {inner.span()=>
+                            Self::#variant(inner) => {
+                                evergreen_rs_types::EvgFields::evg_fields_nested(inner, prefix, out);
+                            }
+                        }
+                    }
+                    Fields::Unnamed(ref fields) => {
+                        // Tuple variants serialize as arrays so report positions
+                        let names = (0..fields.unnamed.len()).map(|i| i.to_string());
+                        quote_spanned! {v.span()=>
+                            Self::#variant(..) => {
+                                #(out.push(evergreen_rs_types::make_name(prefix, #names));)*
+                            }
+                        }
+                    }
+                    Fields::Unit => {
+                        quote_spanned! {v.span()=>
+                            Self::#variant => {}
+                        }
+                    }
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(ref data) => syn::Error::new(
+            data.union_token.span(),
+            "EvgFields cannot be derived for unions",
+        )
+        .to_compile_error(),
+    }
+}
+
+// Generate an expression to add the fields any value of the type may have
+fn evg_type_fields_impl(data: &Data) -> TokenStream {
+    match *data {
+        Data::Struct(ref data) => {
+            let recurse = struct_fields(&data.fields)
+                .iter()
+                .enumerate()
+                .map(|(i, f)| field_stmt(f, i, None))
+                .collect::<Vec<_>>();
+            quote! {
+                #(#recurse)*
+            }
+        }
+        Data::Enum(ref data) => {
+            // The union of the fields of every variant, in order of first appearance
+            let variants = data.variants.iter().map(|v| match v.fields {
+                Fields::Named(ref fields) => {
+                    let recurse = fields.named.iter().map(|f| field_stmt(f, 0, None));
+                    quote! {
+                        #(#recurse)*
+                    }
+                }
+                Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => {
+                    let inner = &fields.unnamed[0].ty;
+                    quote_spanned! {inner.span()=>
+                        <#inner as evergreen_rs_types::EvgFields>::evg_type_fields(prefix, out);
+                    }
+                }
+                Fields::Unnamed(ref fields) => {
+                    let names = (0..fields.unnamed.len()).map(|i| i.to_string());
+                    quote! {
+                        #(out.push(evergreen_rs_types::make_name(prefix, #names));)*
+                    }
+                }
+                Fields::Unit => quote!(),
+            });
+            quote! {
+                let start = out.len();
+                #(#variants)*
+                let mut seen = std::collections::HashSet::new();
+                let fields: Vec<String> = out.drain(start..).filter(|f| seen.insert(f.clone())).collect();
+                out.extend(fields);
+            }
+        }
+        Data::Union(_) => quote!(),
+    }
+}
+
+// Generate an expression to add the path and value of each field, in the order serde writes them
+fn evg_values_impl(data: &Data) -> TokenStream {
+    match *data {
+        Data::Struct(ref data) => {
+            let fields = struct_fields(&data.fields);
+            let recurse = fields.iter().enumerate().map(|(i, f)| match f.ident {
+                Some(ref ident) => value_stmt(f, i, quote!(&self.#ident)),
+                None => {
+                    let index = syn::Index::from(i);
+                    value_stmt(f, i, quote!(&self.#index))
+                }
+            });
+            quote! {
+                #(#recurse)*
+            }
+        }
+        Data::Enum(ref data) => {
+            let arms = data.variants.iter().map(|v| {
+                let variant = &v.ident;
+                match v.fields {
+                    Fields::Named(ref fields) => {
+                        let bound = fields.named.iter()
+                            .filter(|f| parse_field_attrs(&f.attrs).map_or(true, |a| !a.skip))
+                            .map(|f| &f.ident);
+                        let stmts = fields.named.iter().map(|f| {
+                            let ident = &f.ident;
+                            value_stmt(f, 0, quote!(#ident))
+                        });
+                        quote_spanned! {v.span()=>
+                            Self::#variant { #(#bound,)* .. } => {
+                                #(#stmts)*
+                            }
+                        }
+                    }
+                    Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => {
+                        let inner = &fields.unnamed[0];
+                        quote_spanned! {inner.span()=>
+                            Self::#variant(inner) => {
+                                evergreen_rs_types::EvgValues::evg_values_nested(inner, prefix, out);
+                            }
+                        }
+                    }
+                    Fields::Unnamed(ref fields) => {
+                        let bindings: Vec<_> = (0..fields.unnamed.len())
+                            .map(|i| quote::format_ident!("f{}", i))
+                            .collect();
+                        let names = (0..fields.unnamed.len()).map(|i| i.to_string());
+                        quote_spanned! {v.span()=>
+                            Self::#variant(#(#bindings),*) => {
+                                #(out.push((
+                                    evergreen_rs_types::make_name(prefix, #names),
+                                    evergreen_rs_types::serde_json::to_value(#bindings)
+                                        .unwrap_or(evergreen_rs_types::serde_json::Value::Null),
+                                ));)*
+                            }
+                        }
+                    }
+                    Fields::Unit => {
+                        quote_spanned! {v.span()=>
+                            Self::#variant => {}
+                        }
+                    }
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(ref data) => syn::Error::new(
+            data.union_token.span(),
+            "EvgValues cannot be derived for unions",
+        )
+        .to_compile_error(),
+    }
+}
+
+// Generate an EvgFieldInfo for each field
+fn evg_field_infos(data: &Data) -> Vec<TokenStream> {
+    let fields: Vec<Vec<Field>> = match *data {
+        Data::Struct(ref data) => vec![struct_fields(&data.fields)],
+        Data::Enum(ref data) => data
+            .variants
+            .iter()
+            .map(|v| v.fields.iter().cloned().collect())
+            .collect(),
+        Data::Union(_) => Vec::new(),
+    };
+
+    let mut infos = Vec::new();
+    for fields in fields {
+        match fields[..] {
+            [ref inner] if inner.ident.is_none() && matches!(data, Data::Enum(_)) => {
+                // Newtype variants have the fields of the wrapped type
+                let ty = &inner.ty;
+                let rust_type = type_name(ty);
+                infos.push(quote! {
+                    evergreen_rs_types::EvgFieldInfo {
+                        path: "",
+                        rust_type: #rust_type,
+                        is_collection: false,
+                        is_optional: false,
+                        nested: Some(|| <#ty as evergreen_rs_types::EvgFields>::FIELDS),
+                    }
+                });
+            }
+            _ => infos.extend(
+                fields
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, f)| field_info(f, i)),
+            ),
+        }
+    }
+    infos
+}
+
+// Generate the EvgFieldInfo of a field, None if it is skipped
+fn field_info(field: &Field, index: usize) -> Option<TokenStream> {
+    // Attribute errors are reported by field_stmt
+    let attrs = parse_field_attrs(&field.attrs).ok()?;
+    if attrs.skip {
+        return None;
+    }
+
+    let mut name = field_name(field, index, attrs.rename);
+    let (elem, is_collection, is_optional) = match wrapper(&field.ty) {
+        Some(Wrapper::Vec(inner)) => {
+            name.push_str("[]");
+            (inner, true, false)
+        }
+        Some(Wrapper::Option(inner)) => (inner, false, true),
+        None => (&field.ty, is_map(&field.ty), false),
+    };
+    let rust_type = type_name(&field.ty);
+    let nested = if attrs.nested {
+        quote!(Some(|| <#elem as evergreen_rs_types::EvgFields>::FIELDS))
+    } else {
+        quote!(None)
+    };
+
+    Some(quote_spanned! {field.span()=>
+        evergreen_rs_types::EvgFieldInfo {
+            path: #name,
+            rust_type: #rust_type,
+            is_collection: #is_collection,
+            is_optional: #is_optional,
+            nested: #nested,
+        }
+    })
+}
+
+// A type as it would be written, e.g. Option<DateTime<Utc>>
+fn type_name(ty: &Type) -> String {
+    quote!(#ty)
+        .to_string()
+        .replace(" <", "<")
+        .replace("< ", "<")
+        .replace(" >", ">")
+        .replace(" ,", ",")
+        .replace(" :: ", "::")
+        .replace("& ", "&")
+}
+
+// Whether a type is a map or set, which the derive treats as a single value
+fn is_map(ty: &Type) -> bool {
+    match ty {
+        Type::Path(ref path) => path.path.segments.last().is_some_and(|s| {
+            ["BTreeMap", "HashMap", "BTreeSet", "HashSet"]
+                .iter()
+                .any(|m| s.ident == m)
+        }),
+        _ => false,
+    }
+}
+
+// Turn a field name into a variant name, e.g. distro_id into DistroId and 0 into Field0
+fn variant_name(name: &str) -> String {
+    let mut variant = String::new();
+    for word in name.split(|c: char| !c.is_alphanumeric()) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            variant.extend(first.to_uppercase());
+            variant.push_str(chars.as_str());
+        }
+    }
+    if !variant.starts_with(|c: char| c.is_alphabetic()) {
+        variant.insert_str(0, "Field");
+    }
+    variant
+}
+
+// The field enum of a nested type, e.g. crate::models::DistroField for crate::models::Distro
+fn nested_field_enum(ty: &Type) -> syn::Result<syn::Path> {
+    match ty {
+        Type::Path(ref path) if path.qself.is_none() => {
+            let mut path = path.path.clone();
+            let last = path.segments.last_mut().unwrap();
+            last.ident = quote::format_ident!("{}Field", last.ident);
+            last.arguments = PathArguments::None;
+            Ok(path)
+        }
+        _ => Err(syn::Error::new(
+            ty.span(),
+            "evg(nested) needs a named type to find its field enum",
+        )),
+    }
+}
+
+// Generate `<Name>Field`, an enum of the field paths of a struct with FromStr and Display
+//
+// Types with type parameters get no enum since their nested fields are not known. A newtype
+// struct wrapping a nested type shares that type's enum, other newtypes have no fields.
+fn field_enum(input: &DeriveInput) -> TokenStream {
+    let data = match input.data {
+        Data::Struct(ref data) => data,
+        _ => return quote!(),
+    };
+    if input.generics.type_params().next().is_some() {
+        return quote!();
+    }
+
+    let name = &input.ident;
+    let vis = &input.vis;
+    let enum_name = quote::format_ident!("{}Field", name);
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    if is_newtype(&data.fields) {
+        let inner = data.fields.iter().next().unwrap();
+        let nested = match parse_field_attrs(&inner.attrs) {
+            Ok(attrs) if attrs.nested && wrapper(&inner.ty).is_none() => {
+                match nested_field_enum(&inner.ty) {
+                    Ok(nested) => nested,
+                    Err(e) => return e.to_compile_error(),
+                }
+            }
+            Ok(_) => return quote!(),
+            Err(e) => return e.to_compile_error(),
+        };
+        return quote! {
+            #[allow(dead_code)]
+            #vis type #enum_name = #nested;
+
+            impl #impl_generics evergreen_rs_types::EvgFieldEnum for #name #ty_generics #where_clause {
+                type Field = #enum_name;
+            }
+        };
+    }
+
+    let mut variants = Vec::new();
+    let mut display_arms = Vec::new();
+    let mut parse_arms = Vec::new();
+    for (i, field) in data.fields.iter().enumerate() {
+        let attrs = match parse_field_attrs(&field.attrs) {
+            Ok(attrs) => attrs,
+            Err(e) => return e.to_compile_error(),
+        };
+        if attrs.skip {
+            continue;
+        }
+
+        let field_name = field_name(field, i, attrs.rename);
+        let variant = quote::format_ident!("{}", variant_name(&field_name));
+        let (elem, list) = match wrapper(&field.ty) {
+            Some(Wrapper::Vec(inner)) => (inner, "[]"),
+            Some(Wrapper::Option(inner)) => (inner, ""),
+            None => (&field.ty, ""),
+        };
+        let display = quote!(evergreen_rs_types::escape_segment(#field_name), #list);
+
+        if attrs.nested {
+            let nested = match nested_field_enum(elem) {
+                Ok(nested) => nested,
+                Err(e) => return e.to_compile_error(),
+            };
+            variants.push(quote!(#variant(#nested)));
+            display_arms.push(quote! {
+                #enum_name::#variant(inner) => write!(f, "{}{}.{}", #display, inner),
+            });
+            parse_arms.push(quote! {
+                (#field_name, Some(rest)) => rest.parse().map(#enum_name::#variant).map_err(|_| unknown()),
+            });
+        } else {
+            variants.push(quote!(#variant));
+            display_arms.push(quote! {
+                #enum_name::#variant => write!(f, "{}{}", #display),
+            });
+            parse_arms.push(quote! {
+                (#field_name, None) => Ok(#enum_name::#variant),
+            });
+        }
+    }
+
+    quote! {
+        #[allow(dead_code)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #vis enum #enum_name {
+            #(#variants,)*
+        }
+
+        impl std::str::FromStr for #enum_name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let unknown = || {
+                    let mut fields = Vec::new();
+                    <#name as evergreen_rs_types::EvgFields>::evg_type_fields("", &mut fields);
+                    format!("Unknown field '{}', choose from {}", s, fields.join(", "))
+                };
+
+                // The [] of list members is optional
+                let (head, rest) = evergreen_rs_types::split_first_segment(s);
+                match (head.trim_end_matches("[]"), rest) {
+                    #(#parse_arms)*
+                    _ => Err(unknown()),
+                }
+            }
+        }
+
+        impl std::fmt::Display for #enum_name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                match *self {
+                    #(#display_arms)*
+                }
+            }
+        }
+
+        impl #impl_generics evergreen_rs_types::EvgFieldEnum for #name #ty_generics #where_clause {
+            type Field = #enum_name;
+        }
+    }
+}
+
+// Options set on a field with #[evg(...)]
+#[derive(Default)]
+struct FieldAttrs {
+    // #[evg(skip)] leaves the field out of the list
+    skip: bool,
+    // #[evg(rename = "name")] reports the field under its wire name
+    rename: Option<String>,
+    // #[evg(nested)] lists the fields of the child under this field's name
+    nested: bool,
+}
+
+fn parse_field_attrs(attrs: &[Attribute]) -> syn::Result<FieldAttrs> {
+    let mut parsed = FieldAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path.is_ident("evg")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(syn::Error::new(meta.span(), "expected #[evg(...)]")),
+        };
+
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("skip") => {
+                    parsed.skip = true;
+                }
+                NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("nested") => {
+                    parsed.nested = true;
+                }
+                NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("rename") => {
+                    match nv.lit {
+                        Lit::Str(ref s) => parsed.rename = Some(s.value()),
+                        ref lit => return Err(syn::Error::new(lit.span(), "expected a string")),
+                    }
+                }
+                other => {
+                    return Err(syn::Error::new(
+                        other.span(),
+                        "unknown evg attribute, expected skip, nested or rename = \"...\"",
+                    ))
+                }
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+// The name a field is reported under, its position for tuple structs
+fn field_name(field: &Field, index: usize, rename: Option<String>) -> String {
+    rename.unwrap_or_else(|| match field.ident {
+        Some(ref ident) => ident.to_string(),
+        None => index.to_string(),
+    })
+}
+
+// Whether a struct is a newtype, a tuple struct of one field
+fn is_newtype(fields: &Fields) -> bool {
+    matches!(fields, Fields::Unnamed(ref f) if f.unnamed.len() == 1)
+}
+
+// The fields of a struct, the field of a newtype struct is renamed to "" since serde writes
+// its value in place of the struct
+fn struct_fields(fields: &Fields) -> Vec<Field> {
+    let newtype = is_newtype(fields);
+    let mut fields: Vec<Field> = fields.iter().cloned().collect();
+    if newtype {
+        fields[0].attrs.push(syn::parse_quote!(#[evg(rename = "")]));
+    }
+    fields
+}
+
+// The path of a field named `name` under `prefix`, the field of a newtype struct is at `prefix`
+fn path_expr(name: &str) -> TokenStream {
+    match name {
+        "" | "[]" => quote!(format!("{}{}", prefix, #name)),
+        _ => quote!(evergreen_rs_types::make_name(prefix, #name)),
+    }
+}
+
+// Vec<T> and Option<T> fields, the kind of collection and T
+enum Wrapper<'a> {
+    Vec(&'a Type),
+    Option(&'a Type),
+}
+
+fn wrapper(ty: &Type) -> Option<Wrapper<'_>> {
+    let segment = match ty {
+        Type::Path(ref path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+    let inner = match segment.arguments {
+        PathArguments::AngleBracketed(ref args) if args.args.len() == 1 => match args.args[0] {
+            GenericArgument::Type(ref inner) => inner,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    if segment.ident == "Vec" {
+        Some(Wrapper::Vec(inner))
+    } else if segment.ident == "Option" {
+        Some(Wrapper::Option(inner))
+    } else {
+        None
+    }
+}
+
+// Whether the statement for a field reads the field's value
+fn uses_value(field: &Field) -> bool {
+    parse_field_attrs(&field.attrs).is_ok_and(|a| a.nested && !a.skip)
+        && wrapper(&field.ty).is_none()
+}
+
+// Generate the statement that reports a field
+//
+// `value` is a reference to the field, or None to list the fields of the type. Members of Vec
+// and Option fields always come from the type since there may not be one to look at.
+fn field_stmt(field: &Field, index: usize, value: Option<TokenStream>) -> TokenStream {
+    let attrs = match parse_field_attrs(&field.attrs) {
+        Ok(attrs) => attrs,
+        Err(e) => return e.to_compile_error(),
+    };
+    if attrs.skip {
+        return quote!();
+    }
+
+    let mut name = field_name(field, index, attrs.rename);
+    let (ty, value) = match wrapper(&field.ty) {
+        Some(Wrapper::Vec(inner)) => {
+            // Members of a list are reported once as name[]
+            name.push_str("[]");
+            (inner, None)
+        }
+        Some(Wrapper::Option(inner)) => (inner, None),
+        None => (&field.ty, value),
+    };
+    let path = path_expr(&name);
+
+    if !attrs.nested {
+        return quote_spanned! {field.span()=>
+            out.push(#path) ;
+        };
+    }
+
+    match value {
+        Some(value) => quote_spanned! {field.span()=>
+            evergreen_rs_types::EvgFields::evg_fields_nested(
+                #value, &#path, out);
+        },
+        None => quote_spanned! {field.span()=>
+            <#ty as evergreen_rs_types::EvgFields>::evg_type_fields(
+                &#path, out);
+        },
+    }
+}
+
+// The function in #[serde(skip_serializing_if = "...")], so values are left out when serde
+// leaves them out
+fn serde_skip_if(attrs: &[Attribute]) -> syn::Result<Option<syn::ExprPath>> {
+    for attr in attrs.iter().filter(|a| a.path.is_ident("serde")) {
+        if let Ok(Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested {
+                if let NestedMeta::Meta(Meta::NameValue(ref nv)) = nested {
+                    if nv.path.is_ident("skip_serializing_if") {
+                        if let Lit::Str(ref s) = nv.lit {
+                            return s.parse().map(Some);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Generate the statement that adds the value of a field, `value` is a reference to it
+//
+// Nested fields add the values of the child, other fields add the whole value under the
+// field's name.
+fn value_stmt(field: &Field, index: usize, value: TokenStream) -> TokenStream {
+    let attrs = match parse_field_attrs(&field.attrs) {
+        Ok(attrs) => attrs,
+        Err(e) => return e.to_compile_error(),
+    };
+    if attrs.skip {
+        return quote!();
+    }
+    let skip_if = match serde_skip_if(&field.attrs) {
+        Ok(skip_if) => skip_if,
+        Err(e) => return e.to_compile_error(),
+    };
+
+    let path = path_expr(&field_name(field, index, attrs.rename));
+    let stmt = match (attrs.nested, wrapper(&field.ty)) {
+        (true, None) => quote_spanned! {field.span()=>
+            evergreen_rs_types::EvgValues::evg_values_nested(
+                #value, &#path, out);
+        },
+        (true, Some(Wrapper::Option(_))) => quote_spanned! {field.span()=>
+            match #value {
+                Some(v) => evergreen_rs_types::EvgValues::evg_values_nested(
+                    v, &#path, out),
+                None => out.push((
+                    #path,
+                    evergreen_rs_types::serde_json::Value::Null,
+                )),
+            }
+        },
+        // Lists are added whole so the flattener numbers their members
+        _ => quote_spanned! {field.span()=>
+            out.push((
+                #path,
+                evergreen_rs_types::serde_json::to_value(#value)
+                    .unwrap_or(evergreen_rs_types::serde_json::Value::Null),
+            ));
+        },
+    };
+
+    match skip_if {
+        Some(skip_if) => quote! {
+            if !#skip_if(#value) {
+                #stmt
+            }
+        },
+        None => stmt,
     }
 }
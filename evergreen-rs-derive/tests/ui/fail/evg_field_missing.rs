@@ -0,0 +1,16 @@
+use evergreen_rs_derive::{evg_field, EvgFields};
+
+#[derive(EvgFields)]
+struct Distro {
+    distro_id: String,
+}
+
+#[derive(EvgFields)]
+struct Host {
+    #[evg(nested)]
+    distro: Distro,
+}
+
+const COLUMN: &str = evg_field!(Host, distro.arch);
+
+fn main() {}
@@ -0,0 +1,14 @@
+use evergreen_rs_derive::EvgFields;
+use evergreen_rs_types::EvgFields;
+
+#[derive(EvgFields)]
+struct Page<T> {
+    #[evg(nested)]
+    items: Vec<T>,
+}
+
+fn main() {
+    // u32 has no fields to nest
+    let page = Page { items: vec![1u32] };
+    page.evg_fields();
+}
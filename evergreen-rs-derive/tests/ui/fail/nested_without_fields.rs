@@ -0,0 +1,10 @@
+use evergreen_rs_derive::EvgFields;
+
+#[derive(EvgFields)]
+struct Host {
+    // String has no fields to nest
+    #[evg(nested)]
+    host_id: String,
+}
+
+fn main() {}
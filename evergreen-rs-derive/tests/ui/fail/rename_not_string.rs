@@ -0,0 +1,9 @@
+use evergreen_rs_derive::EvgFields;
+
+#[derive(EvgFields)]
+struct Build {
+    #[evg(rename = 1)]
+    id: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use evergreen_rs_derive::EvgFields;
+
+#[derive(EvgFields)]
+union Bits {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use evergreen_rs_derive::EvgFields;
+
+#[derive(EvgFields)]
+struct Host {
+    #[evg(hidden)]
+    host_id: String,
+}
+
+fn main() {}
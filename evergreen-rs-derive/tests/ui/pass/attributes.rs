@@ -0,0 +1,62 @@
+use evergreen_rs_derive::{evg_field, EvgFields, EvgValues};
+use evergreen_rs_types::serde_json;
+use evergreen_rs_types::{EvgFields, EvgValues};
+use serde::Serialize;
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Credentials {
+    user: String,
+    #[evg(skip)]
+    #[serde(skip)]
+    api_key: String,
+    #[serde(rename = "type")]
+    #[evg(rename = "type")]
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires: Option<String>,
+}
+
+#[derive(Serialize, EvgFields, EvgValues)]
+pub struct Tags {
+    #[serde(rename = "build.id")]
+    #[evg(rename = "build.id")]
+    pub build_id: String,
+}
+
+fn main() {
+    let creds = Credentials {
+        user: "u".to_owned(),
+        api_key: "secret".to_owned(),
+        kind: "api".to_owned(),
+        expires: None,
+    };
+    assert_eq!(creds.evg_fields(), vec!["user", "type", "expires"]);
+
+    // Values follow serde, which leaves out the empty expiry
+    assert_eq!(
+        creds.evg_values(),
+        vec![
+            ("user".to_owned(), serde_json::json!("u")),
+            ("type".to_owned(), serde_json::json!("api")),
+        ]
+    );
+
+    // Dots in a name are escaped so the path keeps one segment per field
+    let tags = Tags {
+        build_id: "b1".to_owned(),
+    };
+    assert_eq!(tags.evg_fields(), vec!["build\\.id"]);
+    assert_eq!(
+        tags.evg_values(),
+        vec![("build\\.id".to_owned(), serde_json::json!("b1"))]
+    );
+    let field: TagsField = "build\\.id".parse().unwrap();
+    assert_eq!(field, TagsField::BuildId);
+    assert_eq!(field.to_string(), "build\\.id");
+    assert!("build.id".parse::<TagsField>().is_err());
+
+    // evg_field! takes the Rust names and gives the renamed ones
+    assert_eq!(evg_field!(Credentials, kind), "type");
+    assert_eq!(evg_field!(Credentials, expires), "expires");
+    assert_eq!(evg_field!(Tags, build_id), "build\\.id");
+}
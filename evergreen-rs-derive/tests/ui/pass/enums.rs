@@ -0,0 +1,45 @@
+use evergreen_rs_derive::{EvgFields, EvgValues};
+use evergreen_rs_types::serde_json;
+use evergreen_rs_types::{EvgFields, EvgValues};
+use serde::Serialize;
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Reason {
+    code: i64,
+}
+
+#[derive(Serialize, EvgFields, EvgValues)]
+#[serde(untagged)]
+enum Response {
+    Named { status: String, reason: String },
+    Wrapped(Reason),
+    Pair(String, i64),
+    Empty,
+}
+
+fn main() {
+    let named = Response::Named {
+        status: "running".to_owned(),
+        reason: String::new(),
+    };
+    assert_eq!(named.evg_fields(), vec!["status", "reason"]);
+    assert_eq!(
+        Response::Wrapped(Reason { code: 1 }).evg_fields(),
+        vec!["code"]
+    );
+    assert_eq!(
+        Response::Pair("a".to_owned(), 1).evg_fields(),
+        vec!["0", "1"]
+    );
+    assert!(Response::Empty.evg_fields().is_empty());
+
+    // Without an instance the fields of every variant are listed once
+    let mut fields = Vec::new();
+    Response::evg_type_fields("", &mut fields);
+    assert_eq!(fields, vec!["status", "reason", "code", "0", "1"]);
+
+    assert_eq!(
+        Response::Wrapped(Reason { code: 1 }).evg_values(),
+        vec![("code".to_owned(), serde_json::json!(1))]
+    );
+}
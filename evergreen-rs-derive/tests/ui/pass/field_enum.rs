@@ -0,0 +1,37 @@
+use evergreen_rs_derive::{evg_field, EvgFields};
+
+#[derive(EvgFields)]
+pub struct Distro {
+    pub distro_id: String,
+}
+
+#[derive(EvgFields)]
+pub struct Host {
+    pub host_id: String,
+    #[evg(nested)]
+    pub distro: Distro,
+    pub instance_tags: Vec<String>,
+}
+
+const COLUMNS: &[&str] = &[
+    evg_field!(Host, host_id),
+    evg_field!(Host, distro.distro_id),
+];
+
+fn main() {
+    let field: HostField = "distro.distro_id".parse().unwrap();
+    assert_eq!(field, HostField::Distro(DistroField::DistroId));
+    assert_eq!(field.to_string(), "distro.distro_id");
+    assert_eq!(
+        "instance_tags".parse::<HostField>().unwrap().to_string(),
+        "instance_tags[]"
+    );
+    assert_eq!(
+        "distro.arch".parse::<HostField>().unwrap_err(),
+        "Unknown field 'distro.arch', choose from host_id, distro.distro_id, instance_tags[]"
+    );
+
+    for column in COLUMNS {
+        assert!(column.parse::<HostField>().is_ok());
+    }
+}
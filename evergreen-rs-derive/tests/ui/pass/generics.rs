@@ -0,0 +1,55 @@
+use evergreen_rs_derive::{EvgFields, EvgValues};
+use evergreen_rs_types::{EvgFields, EvgValues};
+use serde::Serialize;
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Distro {
+    distro_id: String,
+}
+
+// The derives add T: EvgFields and T: EvgValues since items are nested
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Page<'a, T, U: Clone>
+where
+    U: Default,
+{
+    #[evg(nested)]
+    items: Vec<T>,
+    #[evg(nested)]
+    first: Option<T>,
+    next: Option<&'a str>,
+    extra: U,
+}
+
+#[derive(Serialize, EvgFields, EvgValues)]
+#[serde(untagged)]
+enum Either<L, R> {
+    Left(L),
+    Right {
+        #[evg(nested)]
+        right: R,
+    },
+}
+
+fn main() {
+    let page = Page {
+        items: vec![Distro {
+            distro_id: "d".to_owned(),
+        }],
+        first: None,
+        next: Some("n"),
+        extra: 0u32,
+    };
+    assert_eq!(
+        page.evg_fields(),
+        vec!["items[].distro_id", "first.distro_id", "next", "extra"]
+    );
+    assert_eq!(page.evg_values()[1].0, "first");
+
+    let either: Either<Distro, Distro> = Either::Right {
+        right: Distro {
+            distro_id: "d".to_owned(),
+        },
+    };
+    assert_eq!(either.evg_fields(), vec!["right.distro_id"]);
+}
@@ -0,0 +1,41 @@
+use evergreen_rs_derive::{EvgFields, EvgValues};
+use evergreen_rs_types::serde_json;
+use evergreen_rs_types::{EvgFields, EvgValues};
+use serde::Serialize;
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Host {
+    host_id: String,
+    provisioned: bool,
+    expiration_time: Option<String>,
+    tags: Vec<String>,
+}
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Empty;
+
+fn main() {
+    let host = Host {
+        host_id: "h1".to_owned(),
+        provisioned: true,
+        expiration_time: None,
+        tags: vec!["a".to_owned()],
+    };
+    assert_eq!(
+        host.evg_fields(),
+        vec!["host_id", "provisioned", "expiration_time", "tags[]"]
+    );
+    assert_eq!(
+        host.evg_values(),
+        vec![
+            ("host_id".to_owned(), serde_json::json!("h1")),
+            ("provisioned".to_owned(), serde_json::json!(true)),
+            ("expiration_time".to_owned(), serde_json::Value::Null),
+            ("tags".to_owned(), serde_json::json!(["a"])),
+        ]
+    );
+    assert!(Empty.evg_fields().is_empty());
+    assert_eq!(Host::FIELDS.len(), 4);
+    assert!(Host::FIELDS[2].is_optional);
+    assert!(Host::FIELDS[3].is_collection);
+}
@@ -0,0 +1,61 @@
+use evergreen_rs_derive::{EvgFields, EvgValues};
+use evergreen_rs_types::serde_json;
+use evergreen_rs_types::{describe_fields, EvgFields, EvgValues};
+use serde::Serialize;
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Distro {
+    distro_id: String,
+    provider: String,
+}
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Tag {
+    key: String,
+}
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Host {
+    #[evg(nested)]
+    distro: Distro,
+    #[evg(nested)]
+    tags: Vec<Tag>,
+    #[evg(nested)]
+    previous: Option<Distro>,
+}
+
+fn main() {
+    let host = Host {
+        distro: Distro {
+            distro_id: "d".to_owned(),
+            provider: "ec2".to_owned(),
+        },
+        tags: Vec::new(),
+        previous: None,
+    };
+    let expected = vec![
+        "distro.distro_id",
+        "distro.provider",
+        "tags[].key",
+        "previous.distro_id",
+        "previous.provider",
+    ];
+    assert_eq!(host.evg_fields(), expected);
+
+    let mut described = Vec::new();
+    describe_fields(Host::FIELDS, "", &mut described);
+    assert_eq!(
+        described
+            .iter()
+            .map(|(p, _)| p.as_str())
+            .collect::<Vec<_>>(),
+        expected
+    );
+
+    let values = host.evg_values();
+    assert_eq!(
+        values[0],
+        ("distro.distro_id".to_owned(), serde_json::json!("d"))
+    );
+    assert_eq!(values[3], ("previous".to_owned(), serde_json::Value::Null));
+}
@@ -0,0 +1,57 @@
+use evergreen_rs_derive::{EvgFields, EvgValues};
+use evergreen_rs_types::serde_json;
+use evergreen_rs_types::{EvgFieldEnum, EvgFields, EvgValues};
+use serde::Serialize;
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Counts {
+    failed: i64,
+}
+
+// Newtypes serialize as the value they wrap, so they have no field 0
+#[derive(Serialize, EvgFields, EvgValues)]
+struct TaskId(String);
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Totals(#[evg(nested)] Counts);
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Tags(Vec<String>);
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Task {
+    id: TaskId,
+    #[evg(nested)]
+    totals: Totals,
+    tags: Tags,
+}
+
+fn main() {
+    let task = Task {
+        id: TaskId("t1".to_owned()),
+        totals: Totals(Counts { failed: 2 }),
+        tags: Tags(vec!["a".to_owned()]),
+    };
+    assert_eq!(task.evg_fields(), vec!["id", "totals.failed", "tags"]);
+    assert_eq!(
+        task.evg_values(),
+        vec![
+            ("id".to_owned(), serde_json::json!("t1")),
+            ("totals.failed".to_owned(), serde_json::json!(2)),
+            ("tags".to_owned(), serde_json::json!(["a"])),
+        ]
+    );
+    assert_eq!(Totals(Counts { failed: 1 }).evg_fields(), vec!["failed"]);
+    // Without a prefix the wrapped value is the record itself
+    assert_eq!(TaskId("t1".to_owned()).evg_fields(), vec![""]);
+    assert_eq!(Tags(Vec::new()).evg_fields(), vec!["[]"]);
+
+    let mut described = Vec::new();
+    evergreen_rs_types::describe_fields(Task::FIELDS, "", &mut described);
+    let paths: Vec<&str> = described.iter().map(|(p, _)| p.as_str()).collect();
+    assert_eq!(paths, vec!["id", "totals.failed", "tags"]);
+
+    // A newtype of a nested struct shares its field enum
+    let field: <Totals as EvgFieldEnum>::Field = "failed".parse().unwrap();
+    assert_eq!(field.to_string(), "failed");
+}
@@ -0,0 +1,24 @@
+use evergreen_rs_derive::{EvgFields, EvgValues};
+use evergreen_rs_types::serde_json;
+use evergreen_rs_types::{EvgFields, EvgValues};
+use serde::Serialize;
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Counts {
+    failed: i64,
+}
+
+#[derive(Serialize, EvgFields, EvgValues)]
+struct Pair(String, #[evg(nested)] Counts);
+
+fn main() {
+    let pair = Pair("a".to_owned(), Counts { failed: 2 });
+    assert_eq!(pair.evg_fields(), vec!["0", "1.failed"]);
+    assert_eq!(
+        pair.evg_values(),
+        vec![
+            ("0".to_owned(), serde_json::json!("a")),
+            ("1.failed".to_owned(), serde_json::json!(2)),
+        ]
+    );
+}
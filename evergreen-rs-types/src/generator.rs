@@ -0,0 +1,891 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Builders for the JSON files read by Evergreen's `generate.tasks` command.
+//!
+//! A generator builds a `GeneratedProject` of tasks and the variants that run them, and writes
+//! it where the `generate.tasks` command of its task expects the file:
+//!
+//! ```no_run
+//! # use evergreen_rs_types::generator::*;
+//! # fn main() -> anyhow::Result<()> {
+//! let test = GeneratedTask::new("test_0")
+//!     .depends_on("compile")
+//!     .command(EvgCommand::func("run tests").var("shard", 0));
+//! let project = GeneratedProject::new()
+//!     .task(test)
+//!     .variant(GeneratedVariant::new("linux").task("test_0"));
+//! std::fs::write("generated.json", project.to_json()?)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Empty lists and unset options are left out of the JSON, so Evergreen applies its defaults.
+//!
+//! The common commands have typed builders, `SubprocessExec`, `S3Put`, `AttachResults` and
+//! `ExpansionsUpdate`, which check their parameters when built rather than when the generated
+//! task runs. Values holding expansions such as `${bucket}` are only known then, so they are
+//! not checked.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// The contents of a `generate.tasks` file, merged by Evergreen into the project
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct GeneratedProject {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub buildvariants: Vec<GeneratedVariant>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tasks: Vec<GeneratedTask>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub functions: BTreeMap<String, Vec<EvgCommand>>,
+}
+
+impl GeneratedProject {
+    pub fn new() -> GeneratedProject {
+        GeneratedProject::default()
+    }
+
+    pub fn task(mut self, task: GeneratedTask) -> GeneratedProject {
+        self.tasks.push(task);
+        self
+    }
+
+    /// Add a variant, one named like a variant of the project adds its tasks to that variant
+    pub fn variant(mut self, variant: GeneratedVariant) -> GeneratedProject {
+        self.buildvariants.push(variant);
+        self
+    }
+
+    pub fn function(mut self, name: &str, commands: Vec<EvgCommand>) -> GeneratedProject {
+        self.functions.insert(name.to_owned(), commands);
+        self
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct GeneratedTask {
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<EvgCommand>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<TaskDependency>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exec_timeout_secs: Option<u64>,
+}
+
+impl GeneratedTask {
+    pub fn new(name: &str) -> GeneratedTask {
+        GeneratedTask {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn command(mut self, command: EvgCommand) -> GeneratedTask {
+        self.commands.push(command);
+        self
+    }
+
+    /// Wait on a task of the same variant
+    pub fn depends_on(mut self, task: &str) -> GeneratedTask {
+        self.depends_on.push(TaskDependency {
+            name: task.to_owned(),
+            variant: None,
+        });
+        self
+    }
+
+    /// Wait on a task of another variant
+    pub fn depends_on_variant(mut self, task: &str, variant: &str) -> GeneratedTask {
+        self.depends_on.push(TaskDependency {
+            name: task.to_owned(),
+            variant: Some(variant.to_owned()),
+        });
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> GeneratedTask {
+        self.tags.push(tag.to_owned());
+        self
+    }
+
+    /// Fail the task when it runs longer, Evergreen counts whole seconds
+    pub fn exec_timeout(mut self, timeout: Duration) -> GeneratedTask {
+        self.exec_timeout_secs = Some(timeout.as_secs());
+        self
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct TaskDependency {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct GeneratedVariant {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub run_on: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tasks: Vec<VariantTask>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub display_tasks: Vec<DisplayTask>,
+}
+
+impl GeneratedVariant {
+    /// A variant to define, or the name of an existing variant to add tasks to
+    pub fn new(name: &str) -> GeneratedVariant {
+        GeneratedVariant {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn display_name(mut self, display_name: &str) -> GeneratedVariant {
+        self.display_name = Some(display_name.to_owned());
+        self
+    }
+
+    pub fn run_on(mut self, distro: &str) -> GeneratedVariant {
+        self.run_on.push(distro.to_owned());
+        self
+    }
+
+    pub fn task(mut self, task: &str) -> GeneratedVariant {
+        self.tasks.push(VariantTask {
+            name: task.to_owned(),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Run a task on a distro other than the variant's
+    pub fn task_on(mut self, task: &str, distro: &str) -> GeneratedVariant {
+        self.tasks.push(VariantTask {
+            name: task.to_owned(),
+            run_on: vec![distro.to_owned()],
+        });
+        self
+    }
+
+    /// Show tasks as one in the UI, e.g. the shards of a test suite
+    pub fn display_task(mut self, name: &str, tasks: &[&str]) -> GeneratedVariant {
+        self.display_tasks.push(DisplayTask {
+            name: name.to_owned(),
+            execution_tasks: tasks.iter().map(|t| t.to_string()).collect(),
+        });
+        self
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct VariantTask {
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub run_on: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct DisplayTask {
+    pub name: String,
+    pub execution_tasks: Vec<String>,
+}
+
+/// A command, or a call of one of the project's functions
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct EvgCommand {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub func: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub params: Map<String, Value>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub vars: Map<String, Value>,
+}
+
+impl EvgCommand {
+    /// A built-in command such as `shell.exec`, configured with `param`
+    pub fn command(name: &str) -> EvgCommand {
+        EvgCommand {
+            command: Some(name.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// A function call, given expansions with `var`
+    pub fn func(name: &str) -> EvgCommand {
+        EvgCommand {
+            func: Some(name.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    pub fn param(mut self, key: &str, value: impl Into<Value>) -> EvgCommand {
+        self.params.insert(key.to_owned(), value.into());
+        self
+    }
+
+    pub fn var(mut self, key: &str, value: impl Into<Value>) -> EvgCommand {
+        self.vars.insert(key.to_owned(), value.into());
+        self
+    }
+
+    pub fn display_name(mut self, display_name: &str) -> EvgCommand {
+        self.display_name = Some(display_name.to_owned());
+        self
+    }
+
+    /// Fail the command when it goes this long without output
+    pub fn timeout(mut self, timeout: Duration) -> EvgCommand {
+        self.timeout_secs = Some(timeout.as_secs());
+        self
+    }
+}
+
+/// Whether a parameter is filled in from expansions when the command runs
+fn is_expansion(value: &str) -> bool {
+    value.contains("${")
+}
+
+fn require(command: &str, name: &str, value: &Option<String>) -> Result<String> {
+    match value {
+        Some(value) if !value.is_empty() => Ok(value.clone()),
+        _ => Err(anyhow!("{} needs {}", command, name)),
+    }
+}
+
+/// `subprocess.exec`, running a binary with its arguments, or a command line split by Evergreen
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SubprocessExec {
+    binary: Option<String>,
+    // A command line instead of a binary and arguments
+    command: Option<String>,
+    args: Vec<String>,
+    working_dir: Option<String>,
+    env: BTreeMap<String, String>,
+    add_expansions_to_env: bool,
+    include_expansions_in_env: Vec<String>,
+    add_to_path: Vec<String>,
+    background: bool,
+    redirect_standard_error_to_output: bool,
+}
+
+impl SubprocessExec {
+    pub fn new(binary: &str) -> SubprocessExec {
+        SubprocessExec {
+            binary: Some(binary.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// Run a command line such as `make test`, split on spaces by Evergreen
+    pub fn command_line(command: &str) -> SubprocessExec {
+        SubprocessExec {
+            command: Some(command.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    pub fn arg(mut self, arg: &str) -> SubprocessExec {
+        self.args.push(arg.to_owned());
+        self
+    }
+
+    pub fn args(mut self, args: &[&str]) -> SubprocessExec {
+        self.args.extend(args.iter().map(|a| a.to_string()));
+        self
+    }
+
+    pub fn working_dir(mut self, dir: &str) -> SubprocessExec {
+        self.working_dir = Some(dir.to_owned());
+        self
+    }
+
+    pub fn env(mut self, key: &str, value: &str) -> SubprocessExec {
+        self.env.insert(key.to_owned(), value.to_owned());
+        self
+    }
+
+    /// Pass every expansion to the process as an environment variable
+    pub fn add_expansions_to_env(mut self) -> SubprocessExec {
+        self.add_expansions_to_env = true;
+        self
+    }
+
+    /// Pass one expansion to the process as an environment variable
+    pub fn include_expansion_in_env(mut self, name: &str) -> SubprocessExec {
+        self.include_expansions_in_env.push(name.to_owned());
+        self
+    }
+
+    pub fn add_to_path(mut self, dir: &str) -> SubprocessExec {
+        self.add_to_path.push(dir.to_owned());
+        self
+    }
+
+    /// Start the process and go on to the next command without waiting for it
+    pub fn background(mut self) -> SubprocessExec {
+        self.background = true;
+        self
+    }
+
+    pub fn redirect_standard_error_to_output(mut self) -> SubprocessExec {
+        self.redirect_standard_error_to_output = true;
+        self
+    }
+
+    pub fn build(self) -> Result<EvgCommand> {
+        let mut cmd = EvgCommand::command("subprocess.exec");
+        match (self.binary, self.command) {
+            (Some(binary), None) if !binary.is_empty() => {
+                cmd = cmd.param("binary", binary);
+                if !self.args.is_empty() {
+                    cmd = cmd.param("args", self.args);
+                }
+            }
+            (None, Some(command)) if !command.is_empty() => {
+                if !self.args.is_empty() {
+                    return Err(anyhow!(
+                        "subprocess.exec takes arguments with a binary, not a command line"
+                    ));
+                }
+                cmd = cmd.param("command", command);
+            }
+            _ => return Err(anyhow!("subprocess.exec needs a binary or a command line")),
+        }
+
+        if let Some(dir) = self.working_dir {
+            cmd = cmd.param("working_dir", dir);
+        }
+        if !self.env.is_empty() {
+            cmd = cmd.param("env", json!(self.env));
+        }
+        if self.add_expansions_to_env {
+            cmd = cmd.param("add_expansions_to_env", true);
+        }
+        if !self.include_expansions_in_env.is_empty() {
+            cmd = cmd.param("include_expansions_in_env", self.include_expansions_in_env);
+        }
+        if !self.add_to_path.is_empty() {
+            cmd = cmd.param("add_to_path", self.add_to_path);
+        }
+        if self.background {
+            cmd = cmd.param("background", true);
+        }
+        if self.redirect_standard_error_to_output {
+            cmd = cmd.param("redirect_standard_error_to_output", true);
+        }
+        Ok(cmd)
+    }
+}
+
+/// Canned ACLs S3 accepts for `permissions`
+const S3_PERMISSIONS: &[&str] = &[
+    "private",
+    "public-read",
+    "public-read-write",
+    "authenticated-read",
+    "aws-exec-read",
+    "bucket-owner-read",
+    "bucket-owner-full-control",
+];
+
+/// Who can see the uploaded file's link in the UI
+const S3_VISIBILITIES: &[&str] = &["public", "private", "signed", "none"];
+
+/// `s3.put`, uploading a file, or the files matching filters, and linking them from the task
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct S3Put {
+    bucket: String,
+    remote_file: String,
+    local_file: Option<String>,
+    local_files_include_filter: Vec<String>,
+    aws_key: Option<String>,
+    aws_secret: Option<String>,
+    content_type: Option<String>,
+    permissions: Option<String>,
+    display_name: Option<String>,
+    visibility: Option<String>,
+    region: Option<String>,
+    optional: bool,
+}
+
+impl S3Put {
+    /// Upload to `remote_file` in `bucket`, a prefix when uploading the files matching filters
+    pub fn new(bucket: &str, remote_file: &str) -> S3Put {
+        S3Put {
+            bucket: bucket.to_owned(),
+            remote_file: remote_file.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn local_file(mut self, path: &str) -> S3Put {
+        self.local_file = Some(path.to_owned());
+        self
+    }
+
+    /// Upload every file matching a glob, in place of `local_file`
+    pub fn local_files_include_filter(mut self, glob: &str) -> S3Put {
+        self.local_files_include_filter.push(glob.to_owned());
+        self
+    }
+
+    /// Usually expansions, e.g. `${aws_key}` and `${aws_secret}`
+    pub fn credentials(mut self, aws_key: &str, aws_secret: &str) -> S3Put {
+        self.aws_key = Some(aws_key.to_owned());
+        self.aws_secret = Some(aws_secret.to_owned());
+        self
+    }
+
+    pub fn content_type(mut self, content_type: &str) -> S3Put {
+        self.content_type = Some(content_type.to_owned());
+        self
+    }
+
+    /// A canned ACL such as `public-read`
+    pub fn permissions(mut self, permissions: &str) -> S3Put {
+        self.permissions = Some(permissions.to_owned());
+        self
+    }
+
+    pub fn display_name(mut self, display_name: &str) -> S3Put {
+        self.display_name = Some(display_name.to_owned());
+        self
+    }
+
+    /// public, private, signed or none
+    pub fn visibility(mut self, visibility: &str) -> S3Put {
+        self.visibility = Some(visibility.to_owned());
+        self
+    }
+
+    pub fn region(mut self, region: &str) -> S3Put {
+        self.region = Some(region.to_owned());
+        self
+    }
+
+    /// Do not fail the task when the local file does not exist
+    pub fn optional(mut self) -> S3Put {
+        self.optional = true;
+        self
+    }
+
+    pub fn build(self) -> Result<EvgCommand> {
+        if !is_expansion(&self.bucket) && !is_bucket_name(&self.bucket) {
+            return Err(anyhow!(
+                "s3.put bucket '{}' is not a valid name",
+                self.bucket
+            ));
+        }
+        if self.remote_file.is_empty() {
+            return Err(anyhow!("s3.put needs a remote file"));
+        }
+        let aws_key = require("s3.put", "credentials", &self.aws_key)?;
+        let aws_secret = require("s3.put", "credentials", &self.aws_secret)?;
+        let content_type = require("s3.put", "a content type", &self.content_type)?;
+        let permissions = require("s3.put", "permissions", &self.permissions)?;
+        if !is_expansion(&permissions) && !S3_PERMISSIONS.contains(&permissions.as_str()) {
+            return Err(anyhow!(
+                "s3.put permissions '{}' is not one of {}",
+                permissions,
+                S3_PERMISSIONS.join(", ")
+            ));
+        }
+        if let Some(visibility) = &self.visibility {
+            if !is_expansion(visibility) && !S3_VISIBILITIES.contains(&visibility.as_str()) {
+                return Err(anyhow!(
+                    "s3.put visibility '{}' is not one of {}",
+                    visibility,
+                    S3_VISIBILITIES.join(", ")
+                ));
+            }
+        }
+
+        let mut cmd = EvgCommand::command("s3.put");
+        match (self.local_file, self.local_files_include_filter.is_empty()) {
+            (Some(file), true) => cmd = cmd.param("local_file", file),
+            (None, false) => {
+                cmd = cmd.param(
+                    "local_files_include_filter",
+                    self.local_files_include_filter,
+                )
+            }
+            _ => {
+                return Err(anyhow!(
+                    "s3.put needs either a local file or local file filters"
+                ))
+            }
+        }
+        cmd = cmd
+            .param("remote_file", self.remote_file)
+            .param("bucket", self.bucket)
+            .param("aws_key", aws_key)
+            .param("aws_secret", aws_secret)
+            .param("content_type", content_type)
+            .param("permissions", permissions);
+        if let Some(display_name) = self.display_name {
+            cmd = cmd.param("display_name", display_name);
+        }
+        if let Some(visibility) = self.visibility {
+            cmd = cmd.param("visibility", visibility);
+        }
+        if let Some(region) = self.region {
+            cmd = cmd.param("region", region);
+        }
+        if self.optional {
+            cmd = cmd.param("optional", true);
+        }
+        Ok(cmd)
+    }
+}
+
+/// S3's rules for bucket names, 3 to 63 lowercase letters, digits, dots and hyphens that start
+/// and end with a letter or digit
+fn is_bucket_name(name: &str) -> bool {
+    let edge = |c: Option<char>| c.is_some_and(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+    (3..=63).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '-')
+        && edge(name.chars().next())
+        && edge(name.chars().last())
+        && !name.contains("..")
+}
+
+/// `attach.results`, showing a JSON file of test results on the task page
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AttachResults {
+    file_location: String,
+}
+
+impl AttachResults {
+    pub fn new(file_location: &str) -> AttachResults {
+        AttachResults {
+            file_location: file_location.to_owned(),
+        }
+    }
+
+    pub fn build(self) -> Result<EvgCommand> {
+        if self.file_location.is_empty() {
+            return Err(anyhow!("attach.results needs a file location"));
+        }
+        Ok(EvgCommand::command("attach.results").param("file_location", self.file_location))
+    }
+}
+
+/// `expansions.update`, setting expansions for the commands that follow
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExpansionsUpdate {
+    updates: Vec<Value>,
+    file: Option<String>,
+    ignore_missing_file: bool,
+}
+
+impl ExpansionsUpdate {
+    pub fn new() -> ExpansionsUpdate {
+        ExpansionsUpdate::default()
+    }
+
+    pub fn set(mut self, key: &str, value: &str) -> ExpansionsUpdate {
+        self.updates.push(json!({ "key": key, "value": value }));
+        self
+    }
+
+    /// Append to the expansion's current value
+    pub fn concat(mut self, key: &str, value: &str) -> ExpansionsUpdate {
+        self.updates.push(json!({ "key": key, "concat": value }));
+        self
+    }
+
+    /// Read expansions from a YAML file of keys and values
+    pub fn file(mut self, path: &str) -> ExpansionsUpdate {
+        self.file = Some(path.to_owned());
+        self
+    }
+
+    pub fn ignore_missing_file(mut self) -> ExpansionsUpdate {
+        self.ignore_missing_file = true;
+        self
+    }
+
+    pub fn build(self) -> Result<EvgCommand> {
+        if self.updates.iter().any(|u| u["key"] == "") {
+            return Err(anyhow!("expansions.update cannot set an empty key"));
+        }
+        if self.ignore_missing_file && self.file.is_none() {
+            return Err(anyhow!(
+                "expansions.update can only ignore a missing file when given one"
+            ));
+        }
+
+        if self.updates.is_empty() && self.file.is_none() {
+            return Err(anyhow!("expansions.update needs updates or a file"));
+        }
+
+        let mut cmd = EvgCommand::command("expansions.update");
+        if !self.updates.is_empty() {
+            cmd = cmd.param("updates", self.updates);
+        }
+        if let Some(file) = self.file {
+            cmd = cmd.param("file", file);
+        }
+        if self.ignore_missing_file {
+            cmd = cmd.param("ignore_missing_file", true);
+        }
+        Ok(cmd)
+    }
+}
+
+#[test]
+fn test_generated_project() {
+    let shards: Vec<String> = (0..2).map(|i| format!("test_{}", i)).collect();
+    let mut project = GeneratedProject::new().function(
+        "run tests",
+        vec![EvgCommand::command("subprocess.exec")
+            .param("binary", "bash")
+            .param("args", vec!["run_tests.sh", "${shard}"])],
+    );
+    let mut variant = GeneratedVariant::new("linux").display_task(
+        "test",
+        &shards.iter().map(String::as_str).collect::<Vec<_>>(),
+    );
+    for (i, name) in shards.iter().enumerate() {
+        project = project.task(
+            GeneratedTask::new(name)
+                .depends_on("compile")
+                .tag("generated")
+                .exec_timeout(Duration::from_secs(3600))
+                .command(EvgCommand::func("run tests").var("shard", i)),
+        );
+        variant = variant.task(name);
+    }
+    project = project.variant(variant);
+
+    let json: Value = serde_json::from_str(&project.to_json().unwrap()).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "buildvariants": [{
+                "name": "linux",
+                "tasks": [{ "name": "test_0" }, { "name": "test_1" }],
+                "display_tasks": [{ "name": "test", "execution_tasks": ["test_0", "test_1"] }]
+            }],
+            "tasks": [
+                {
+                    "name": "test_0",
+                    "commands": [{ "func": "run tests", "vars": { "shard": 0 } }],
+                    "depends_on": [{ "name": "compile" }],
+                    "tags": ["generated"],
+                    "exec_timeout_secs": 3600
+                },
+                {
+                    "name": "test_1",
+                    "commands": [{ "func": "run tests", "vars": { "shard": 1 } }],
+                    "depends_on": [{ "name": "compile" }],
+                    "tags": ["generated"],
+                    "exec_timeout_secs": 3600
+                }
+            ],
+            "functions": {
+                "run tests": [{
+                    "command": "subprocess.exec",
+                    "params": { "binary": "bash", "args": ["run_tests.sh", "${shard}"] }
+                }]
+            }
+        })
+    );
+}
+
+#[test]
+fn test_generated_variant() {
+    let variant = GeneratedVariant::new("windows")
+        .display_name("Windows")
+        .run_on("windows-vs2019")
+        .task_on("compile", "windows-vs2019-large");
+    assert_eq!(
+        serde_json::to_value(&variant).unwrap(),
+        serde_json::json!({
+            "name": "windows",
+            "display_name": "Windows",
+            "run_on": ["windows-vs2019"],
+            "tasks": [{ "name": "compile", "run_on": ["windows-vs2019-large"] }]
+        })
+    );
+
+    let task = GeneratedTask::new("lint").depends_on_variant("compile", "linux");
+    assert_eq!(
+        serde_json::to_value(&task).unwrap(),
+        serde_json::json!({
+            "name": "lint",
+            "depends_on": [{ "name": "compile", "variant": "linux" }]
+        })
+    );
+}
+
+#[test]
+fn test_subprocess_exec() {
+    let cmd = SubprocessExec::new("bash")
+        .args(&["run_tests.sh", "--suite"])
+        .arg("${suite}")
+        .working_dir("src")
+        .env("CI", "true")
+        .include_expansion_in_env("suite")
+        .build()
+        .unwrap();
+    assert_eq!(
+        serde_json::to_value(&cmd).unwrap(),
+        json!({
+            "command": "subprocess.exec",
+            "params": {
+                "binary": "bash",
+                "args": ["run_tests.sh", "--suite", "${suite}"],
+                "working_dir": "src",
+                "env": { "CI": "true" },
+                "include_expansions_in_env": ["suite"]
+            }
+        })
+    );
+
+    let make = SubprocessExec::command_line("make test").build().unwrap();
+    assert_eq!(make.params["command"], "make test");
+    assert!(SubprocessExec::new("").build().is_err());
+    assert!(SubprocessExec::command_line("make")
+        .arg("test")
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_s3_put() {
+    let put = || {
+        S3Put::new("mciuploads", "${project}/${revision}/logs.tgz")
+            .local_file("logs.tgz")
+            .credentials("${aws_key}", "${aws_secret}")
+            .content_type("application/gzip")
+            .permissions("public-read")
+    };
+    let cmd = put().display_name("Logs").build().unwrap();
+    assert_eq!(
+        serde_json::to_value(&cmd).unwrap(),
+        json!({
+            "command": "s3.put",
+            "params": {
+                "local_file": "logs.tgz",
+                "remote_file": "${project}/${revision}/logs.tgz",
+                "bucket": "mciuploads",
+                "aws_key": "${aws_key}",
+                "aws_secret": "${aws_secret}",
+                "content_type": "application/gzip",
+                "permissions": "public-read",
+                "display_name": "Logs"
+            }
+        })
+    );
+
+    let err = |put: S3Put| put.build().unwrap_err().to_string();
+    assert_eq!(
+        err(put().permissions("world")),
+        "s3.put permissions 'world' is not one of private, public-read, public-read-write, authenticated-read, aws-exec-read, bucket-owner-read, bucket-owner-full-control"
+    );
+    assert_eq!(
+        err(put().local_files_include_filter("*.log")),
+        "s3.put needs either a local file or local file filters"
+    );
+    assert_eq!(
+        err(S3Put::new("Uploads", "f").local_file("f")),
+        "s3.put bucket 'Uploads' is not a valid name"
+    );
+    assert_eq!(
+        err(S3Put::new("uploads", "f").local_file("f")),
+        "s3.put needs credentials"
+    );
+    assert!(put().visibility("signed").build().is_ok());
+    assert!(put().visibility("everyone").build().is_err());
+    assert!(S3Put::new("${bucket}", "f")
+        .local_file("f")
+        .credentials("k", "s")
+        .content_type("text/plain")
+        .permissions("${permissions}")
+        .build()
+        .is_ok());
+}
+
+#[test]
+fn test_is_bucket_name() {
+    assert!(is_bucket_name("mciuploads"));
+    assert!(is_bucket_name("my.bucket-1"));
+    assert!(!is_bucket_name("ab"));
+    assert!(!is_bucket_name("-bucket"));
+    assert!(!is_bucket_name("my..bucket"));
+    assert!(!is_bucket_name("my_bucket"));
+}
+
+#[test]
+fn test_attach_results_and_expansions_update() {
+    assert_eq!(
+        AttachResults::new("report.json").build().unwrap(),
+        EvgCommand::command("attach.results").param("file_location", "report.json")
+    );
+    assert!(AttachResults::new("").build().is_err());
+
+    let cmd = ExpansionsUpdate::new()
+        .set("suite", "core")
+        .concat("flags", " --verbose")
+        .file("expansions.yml")
+        .ignore_missing_file()
+        .build()
+        .unwrap();
+    assert_eq!(
+        serde_json::to_value(&cmd).unwrap(),
+        json!({
+            "command": "expansions.update",
+            "params": {
+                "updates": [
+                    { "key": "suite", "value": "core" },
+                    { "key": "flags", "concat": " --verbose" }
+                ],
+                "file": "expansions.yml",
+                "ignore_missing_file": true
+            }
+        })
+    );
+    assert!(ExpansionsUpdate::new().build().is_err());
+    assert!(ExpansionsUpdate::new().set("", "x").build().is_err());
+    assert!(ExpansionsUpdate::new()
+        .set("a", "b")
+        .ignore_missing_file()
+        .build()
+        .is_err());
+}
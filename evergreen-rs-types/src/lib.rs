@@ -1,19 +1,385 @@
+pub use serde;
+pub use serde_json;
 
+pub mod generator;
+
+use std::borrow::Cow;
+
+/// Escape the `.` and `\` in a field name so it stays one segment of a path, e.g. the tag
+/// `build.id` is written as `build\.id`
+pub fn escape_segment(name: &str) -> Cow<'_, str> {
+    if !name.contains(['.', '\\']) {
+        return Cow::Borrowed(name);
+    }
+
+    let mut out = String::with_capacity(name.len() + 2);
+    for c in name.chars() {
+        if c == '.' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    Cow::Owned(out)
+}
+
+/// Append the field `suffix` to the path `prefix`
 pub fn make_name(prefix: &str, suffix: &str) -> String {
-    if prefix.len() > 0 {
+    let suffix = escape_segment(suffix);
+    if !prefix.is_empty() {
         format!("{}.{}", prefix, suffix)
     } else {
-        suffix.to_owned()
+        suffix.into_owned()
+    }
+}
+
+/// Split off the first field name of a path, returns it unescaped and the rest of the path
+pub fn split_first_segment(path: &str) -> (Cow<'_, str>, Option<&str>) {
+    let mut escaped = false;
+    let mut unescape = false;
+    for (i, c) in path.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+            unescape = true;
+        } else if c == '.' {
+            return (unescape_segment(&path[..i], unescape), Some(&path[i + 1..]));
+        }
+    }
+    (unescape_segment(path, unescape), None)
+}
+
+fn unescape_segment(segment: &str, unescape: bool) -> Cow<'_, str> {
+    if !unescape {
+        return Cow::Borrowed(segment);
+    }
+
+    let mut out = String::with_capacity(segment.len());
+    let mut chars = segment.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Split a path into its field names, the reverse of joining them with `make_name`
+pub fn split_path(path: &str) -> Vec<Cow<'_, str>> {
+    let mut out = Vec::new();
+    let mut rest = Some(path);
+    while let Some(path) = rest {
+        let (segment, next) = split_first_segment(path);
+        out.push(segment);
+        rest = next;
+    }
+    out
+}
+
+/// A parsed field path, e.g. `distro.distro_id` or `instance_tags[].key`
+///
+/// Comparisons ignore the `[]` that marks a list, so `instance_tags.key` and
+/// `instance_tags[].key` name the same field.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FieldPath {
+    segments: Vec<String>,
+}
+
+/// A segment without the [] of a list
+fn segment_name(segment: &str) -> &str {
+    segment.trim_end_matches("[]")
+}
+
+/// Match `text` against `pattern` where `*` matches any run of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Where to resume after the last `*` if the rest fails to match
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl FieldPath {
+    pub fn parse(path: &str) -> Result<FieldPath, String> {
+        let segments: Vec<String> = split_path(path).into_iter().map(Cow::into_owned).collect();
+        if segments.iter().any(|s| segment_name(s).is_empty()) {
+            return Err(format!("Invalid field path '{}', empty field name", path));
+        }
+        Ok(FieldPath { segments })
+    }
+
+    /// The unescaped field names of the path
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// The first `len` segments, e.g. `distro` of `distro.distro_id`
+    pub fn prefix(&self, len: usize) -> FieldPath {
+        FieldPath {
+            segments: self.segments.iter().take(len).cloned().collect(),
+        }
+    }
+
+    /// Whether `prefix` is this path or one of its parents
+    pub fn starts_with(&self, prefix: &FieldPath) -> bool {
+        prefix.len() <= self.len()
+            && self
+                .segments
+                .iter()
+                .zip(&prefix.segments)
+                .all(|(a, b)| segment_name(a) == segment_name(b))
+    }
+
+    /// Whether any segment has a `*` wildcard
+    pub fn is_glob(&self) -> bool {
+        self.segments.iter().any(|s| s.contains('*'))
+    }
+
+    /// Whether `path` matches this path as a glob, e.g. `distro.*` matches `distro.arch`
+    ///
+    /// A `*` matches within one segment, so both paths have the same number of segments.
+    pub fn matches(&self, path: &FieldPath) -> bool {
+        self.len() == path.len()
+            && self
+                .segments
+                .iter()
+                .zip(&path.segments)
+                .all(|(p, s)| glob_match(segment_name(p), segment_name(s)))
+    }
+
+    pub fn eq_ignore_case(&self, other: &FieldPath) -> bool {
+        self.len() == other.len()
+            && self
+                .segments
+                .iter()
+                .zip(&other.segments)
+                .all(|(a, b)| segment_name(a).eq_ignore_ascii_case(segment_name(b)))
+    }
+}
+
+impl std::str::FromStr for FieldPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FieldPath::parse(s)
+    }
+}
+
+impl std::fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut path = String::new();
+        for segment in &self.segments {
+            path = make_name(&path, segment);
+        }
+        f.write_str(&path)
+    }
+}
+
+/// A field of a model, generated by the EvgFields derive
+#[derive(Debug, Clone, Copy)]
+pub struct EvgFieldInfo {
+    /// Name of the field, with [] for lists, empty for the value of a newtype
+    pub path: &'static str,
+    pub rust_type: &'static str,
+    pub is_collection: bool,
+    pub is_optional: bool,
+    /// The fields of a nested type, listed under `path`
+    pub nested: Option<fn() -> &'static [EvgFieldInfo]>,
+}
+
+/// Expand nested field tables into each field's full path
+pub fn describe_fields(
+    fields: &[EvgFieldInfo],
+    prefix: &str,
+    out: &mut Vec<(String, EvgFieldInfo)>,
+) {
+    for field in fields {
+        let path = match field.path {
+            "" | "[]" => format!("{}{}", prefix, field.path),
+            name => make_name(prefix, name),
+        };
+        match field.nested {
+            Some(nested) => describe_fields(nested(), &path, out),
+            None => out.push((path, *field)),
+        }
     }
 }
 
 pub trait EvgFields {
+    /// The fields of the type, the same as `evg_type_fields` lists
+    const FIELDS: &'static [EvgFieldInfo];
+
     fn evg_fields_nested(&self, prefix: &str, out: &mut Vec<String>);
 
+    /// The fields any value of the type may have, for members of lists and options that may
+    /// be empty
+    fn evg_type_fields(prefix: &str, out: &mut Vec<String>)
+    where
+        Self: Sized;
+
     fn evg_fields(&self) -> Vec<String> {
-        let mut out : Vec<String> = Vec::new();
+        let mut out: Vec<String> = Vec::new();
         self.evg_fields_nested("", &mut out);
         out
     }
+}
+
+/// The path and value of each field of a record, in the order serde writes them
+pub trait EvgValues {
+    fn evg_values_nested(&self, prefix: &str, out: &mut Vec<(String, serde_json::Value)>);
+
+    fn evg_values(&self) -> Vec<(String, serde_json::Value)> {
+        let mut out = Vec::new();
+        self.evg_values_nested("", &mut out);
+        out
+    }
+}
+
+/// A model with a generated enum of its field paths, e.g. HostField for Host
+pub trait EvgFieldEnum {
+    type Field: std::str::FromStr<Err = String> + std::fmt::Display;
+}
+
+/// The field of a model whose Rust name hashes to `NAME` with `field_hash`, generated by the
+/// EvgFields derive so `evg_field!` can follow a path through renames, lists and options
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has no field in this evg_field! path",
+    note = "the type must derive EvgFields, and fields with #[evg(skip)] have no path"
+)]
+pub trait EvgFieldAt<const NAME: u64> {
+    /// The type the rest of a path is in, the T of a `Vec<T>` or `Option<T>` field
+    type Inner;
+
+    /// The name of the field in paths, its #[evg(rename)] if it has one
+    const NAME: &'static str;
+}
+
+/// FNV-1a of a Rust field name, the derive hashes names the same way
+#[doc(hidden)]
+pub const fn field_hash(name: &str) -> u64 {
+    let bytes = name.as_bytes();
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+        i += 1;
+    }
+    hash
+}
+
+/// The total length of `parts`, the size of `concat_parts`
+#[doc(hidden)]
+pub const fn concat_len(parts: &[&str]) -> usize {
+    let mut len = 0;
+    let mut i = 0;
+    while i < parts.len() {
+        len += parts[i].len();
+        i += 1;
+    }
+    len
+}
+
+/// Join `parts` when compiling, `N` must be their `concat_len`
+#[doc(hidden)]
+pub const fn concat_parts<const N: usize>(parts: &[&str]) -> [u8; N] {
+    let mut out = [0; N];
+    let mut pos = 0;
+    let mut i = 0;
+    while i < parts.len() {
+        let bytes = parts[i].as_bytes();
+        let mut j = 0;
+        while j < bytes.len() {
+            out[pos] = bytes[j];
+            pos += 1;
+            j += 1;
+        }
+        i += 1;
+    }
+    out
+}
+
+#[test]
+fn test_make_name() {
+    assert_eq!(make_name("", "distro"), "distro");
+    assert_eq!(make_name("distro", "arch"), "distro.arch");
+    assert_eq!(make_name("tags", "build.id"), "tags.build\\.id");
+    assert_eq!(make_name("", "a\\b"), "a\\\\b");
+
+    assert_eq!(split_path("distro.arch"), vec!["distro", "arch"]);
+    assert_eq!(split_path("tags.build\\.id"), vec!["tags", "build.id"]);
+    assert_eq!(split_path("a\\\\b.c"), vec!["a\\b", "c"]);
+    assert_eq!(split_path(""), vec![""]);
+    assert_eq!(
+        split_first_segment("build\\.id.key"),
+        (Cow::Borrowed("build.id"), Some("key"))
+    );
+
+    for name in &["plain", "a.b", "a\\.b", "trailing\\"] {
+        assert_eq!(split_path(&make_name("x", name)), vec!["x", *name]);
+    }
+}
+
+#[test]
+fn test_field_path() {
+    let path = FieldPath::parse("instance_tags[].key").unwrap();
+    assert_eq!(path.segments(), ["instance_tags[]", "key"]);
+    assert_eq!(path.to_string(), "instance_tags[].key");
+    assert_eq!(
+        "tags.build\\.id".parse::<FieldPath>().unwrap().segments(),
+        ["tags", "build.id"]
+    );
+    assert!(FieldPath::parse("distro..arch").is_err());
+    assert!(FieldPath::parse("").is_err());
+
+    let distro = FieldPath::parse("distro").unwrap();
+    let arch = FieldPath::parse("distro.arch").unwrap();
+    assert!(arch.starts_with(&distro));
+    assert!(arch.starts_with(&arch));
+    assert!(!distro.starts_with(&arch));
+    assert!(path.starts_with(&FieldPath::parse("instance_tags").unwrap()));
+    assert_eq!(arch.prefix(1), distro);
+
+    let glob = FieldPath::parse("distro.*").unwrap();
+    assert!(glob.is_glob());
+    assert!(!arch.is_glob());
+    assert!(glob.matches(&arch));
+    assert!(!glob.matches(&distro));
+    assert!(FieldPath::parse("*_id")
+        .unwrap()
+        .matches(&FieldPath::parse("host_id").unwrap()));
+    assert!(FieldPath::parse("d*o.a*h").unwrap().matches(&arch));
+    assert!(!FieldPath::parse("d*x").unwrap().matches(&distro));
 
+    assert!(FieldPath::parse("Distro.ARCH")
+        .unwrap()
+        .eq_ignore_case(&arch));
+    assert!(!FieldPath::parse("Distro").unwrap().eq_ignore_case(&arch));
 }
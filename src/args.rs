@@ -0,0 +1,202 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+
+// IDs for commands that operate on a set of objects, given on the command line, in a file or both
+#[derive(StructOpt, Debug)]
+pub struct IdArgs {
+    /// IDs to operate on
+    ids: Vec<String>,
+
+    /// Read additional IDs from a file, one per line, `-` for stdin
+    #[structopt(long = "ids-file", parse(from_os_str))]
+    ids_file: Option<PathBuf>,
+}
+
+impl IdArgs {
+    /// Resolve the full list of IDs, failing if none were given
+    pub fn resolve(&self) -> Result<Vec<String>> {
+        let mut ids = self.ids.clone();
+
+        if let Some(path) = &self.ids_file {
+            ids.extend(read_ids_file(path)?);
+        }
+
+        if ids.is_empty() {
+            return Err(anyhow!(
+                "No IDs given, pass them as arguments or with --ids-file"
+            ));
+        }
+
+        Ok(ids)
+    }
+}
+
+// Server side pagination for list commands
+#[derive(StructOpt, Debug, Default, Clone, Copy, PartialEq)]
+pub struct PageArgs {
+    /// Maximum number of records the server returns per page
+    #[structopt(long)]
+    pub limit: Option<u32>,
+
+    /// Follow the server's next page links until every record is fetched
+    #[structopt(long)]
+    pub all: bool,
+}
+
+impl PageArgs {
+    /// Every record, in pages of the server's default size
+    pub fn all() -> PageArgs {
+        PageArgs {
+            limit: None,
+            all: true,
+        }
+    }
+}
+
+/// A `--since` or `--before` value, a duration back from now such as 2d or a date
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeBound(pub DateTime<Utc>);
+
+impl TimeBound {
+    fn parse_at(s: &str, now: DateTime<Utc>) -> Result<TimeBound> {
+        if let Ok(ago) = humantime::parse_duration(s) {
+            return Ok(TimeBound(now - chrono::Duration::from_std(ago)?));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(TimeBound(date.and_hms_opt(0, 0, 0).unwrap().and_utc()));
+        }
+        match DateTime::parse_from_rfc3339(s) {
+            Ok(time) => Ok(TimeBound(time.with_timezone(&Utc))),
+            Err(_) => Err(anyhow!(
+                "Could not parse '{}' as a time, use a duration such as 2d or 12h, a UTC date such \
+                 as 2024-01-01 or an RFC 3339 time",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for TimeBound {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TimeBound::parse_at(s, Utc::now())
+    }
+}
+
+// A window of time for commands that list records with a timestamp
+#[derive(StructOpt, Debug, Default, Clone, Copy, PartialEq)]
+pub struct TimeArgs {
+    /// Only include records from this long ago or later, e.g. 2d, or from a UTC date, e.g.
+    /// 2024-01-01
+    #[structopt(long)]
+    pub since: Option<TimeBound>,
+
+    /// Only include records from before this long ago or before a UTC date
+    #[structopt(long)]
+    pub before: Option<TimeBound>,
+}
+
+impl TimeArgs {
+    /// Whether a time is in the window
+    pub fn contains(&self, time: DateTime<Utc>) -> bool {
+        self.since.is_none_or(|s| time >= s.0) && self.before.is_none_or(|b| time < b.0)
+    }
+
+    /// Whether an RFC 3339 timestamp is in the window, times that cannot be read are kept
+    pub fn contains_timestamp(&self, timestamp: &str) -> bool {
+        DateTime::parse_from_rfc3339(timestamp)
+            .map_or(true, |t| self.contains(t.with_timezone(&Utc)))
+    }
+
+    /// Whether the pages after one sorted by time from `first` to `last` are all outside the
+    /// window, newest first pages end before `--since` and oldest first ones after `--before`
+    pub fn is_past(&self, first: &str, last: &str) -> bool {
+        let parse = |t: &str| DateTime::parse_from_rfc3339(t).map(|t| t.with_timezone(&Utc));
+        let (first, last) = match (parse(first), parse(last)) {
+            (Ok(first), Ok(last)) => (first, last),
+            _ => return false,
+        };
+        match first >= last {
+            true => self.since.is_some_and(|s| last < s.0),
+            false => self.before.is_some_and(|b| last >= b.0),
+        }
+    }
+}
+
+/// Read IDs from a file, `-` reads stdin
+pub fn read_ids_file(path: &Path) -> Result<Vec<String>> {
+    if path.to_str() == Some("-") {
+        let stdin = io::stdin();
+        read_ids(stdin.lock())
+    } else {
+        read_ids(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Read one ID per line, ignoring blank lines and `#` comments
+fn read_ids<R: BufRead>(reader: R) -> Result<Vec<String>> {
+    let mut ids = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let id = line.trim();
+        if id.is_empty() || id.starts_with('#') {
+            continue;
+        }
+        ids.push(id.to_owned());
+    }
+    Ok(ids)
+}
+
+#[test]
+fn test_read_ids() {
+    let input = "a\n  b  \n\n# comment\nc\n";
+    assert_eq!(read_ids(input.as_bytes()).unwrap(), vec!["a", "b", "c"]);
+}
+
+#[test]
+fn test_time_args() {
+    let now = DateTime::parse_from_rfc3339("2024-01-10T12:00:00Z")
+        .unwrap()
+        .with_timezone(&Utc);
+    let at = |s: &str| TimeBound::parse_at(s, now).unwrap().0.to_rfc3339();
+    assert_eq!(at("2d"), "2024-01-08T12:00:00+00:00");
+    assert_eq!(at("90m"), "2024-01-10T10:30:00+00:00");
+    assert_eq!(at("2024-01-01"), "2024-01-01T00:00:00+00:00");
+    assert_eq!(at("2024-01-01T06:00:00Z"), "2024-01-01T06:00:00+00:00");
+    assert!(TimeBound::parse_at("yesterday", now).is_err());
+
+    let window = TimeArgs {
+        since: Some(TimeBound::parse_at("2d", now).unwrap()),
+        before: Some(TimeBound::parse_at("1d", now).unwrap()),
+    };
+    assert!(window.contains_timestamp("2024-01-09T00:00:00Z"));
+    assert!(!window.contains_timestamp("2024-01-07T00:00:00Z"));
+    assert!(!window.contains_timestamp("2024-01-10T00:00:00Z"));
+    assert!(window.contains_timestamp("not a time"));
+    assert!(window.is_past("2024-01-09T00:00:00Z", "2024-01-07T00:00:00Z"));
+    assert!(!window.is_past("2024-01-10T00:00:00Z", "2024-01-09T00:00:00Z"));
+    assert!(window.is_past("2024-01-09T00:00:00Z", "2024-01-10T00:00:00Z"));
+    assert!(!window.is_past("2024-01-07T00:00:00Z", "2024-01-09T00:00:00Z"));
+    assert!(!TimeArgs::default().is_past("2024-01-09T00:00:00Z", "2024-01-07T00:00:00Z"));
+    assert!(TimeArgs::default().contains(now));
+}
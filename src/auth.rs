@@ -0,0 +1,155 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! How requests authenticate, chosen with the `auth` setting.
+//!
+//! Evergreen itself takes `Api-User` and `Api-Key` headers. Deployments behind an auth proxy,
+//! e.g. Kanopy, want a bearer token instead:
+//!
+//! ```yaml
+//! auth:
+//!   type: bearer_token
+//!   token_command: kanopy-oidc login
+//! ```
+
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthConfig {
+    /// Api-User and Api-Key headers from the user and api_key settings
+    #[default]
+    ApiKey,
+
+    /// An `Authorization: Bearer` header
+    BearerToken {
+        // The token, or keyring:<name> to read it from the keychain
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+
+        // A shell command that prints a token, run once per invocation
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token_command: Option<String>,
+    },
+
+    /// A fixed set of headers
+    Headers { headers: BTreeMap<String, String> },
+}
+
+fn header(name: &str, value: &str) -> Result<(HeaderName, HeaderValue)> {
+    let name = HeaderName::from_bytes(name.as_bytes())
+        .map_err(|e| anyhow!("Invalid header name '{}': {}", name, e))?;
+    let mut value = HeaderValue::from_str(value)
+        .map_err(|e| anyhow!("Invalid value for header '{}': {}", name, e))?;
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+/// Run `command` with the shell and return its trimmed output
+fn run_token_command(command: &str) -> Result<String> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()?
+    } else {
+        Command::new("sh").args(["-c", command]).output()?
+    };
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Token command '{}' failed with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_owned())
+}
+
+impl AuthConfig {
+    /// The headers sent with every request
+    pub fn headers(&self, user: &str, api_key: &str) -> Result<HeaderMap> {
+        let pairs = match self {
+            AuthConfig::ApiKey => vec![header("Api-User", user)?, header("Api-Key", api_key)?],
+            AuthConfig::BearerToken {
+                token,
+                token_command,
+            } => {
+                let token = match (token, token_command) {
+                    (Some(token), _) => match config::keyring_name(token, user) {
+                        Some(name) => config::read_keyring(name)?,
+                        None => token.clone(),
+                    },
+                    (None, Some(command)) => run_token_command(command)?,
+                    (None, None) => {
+                        return Err(anyhow!("Bearer token auth needs a token or token_command"))
+                    }
+                };
+                vec![header(
+                    AUTHORIZATION.as_str(),
+                    &format!("Bearer {}", token),
+                )?]
+            }
+            AuthConfig::Headers { headers } => headers
+                .iter()
+                .map(|(k, v)| header(k, v))
+                .collect::<Result<_>>()?,
+        };
+        Ok(pairs.into_iter().collect())
+    }
+}
+
+#[test]
+fn test_auth_headers() {
+    let headers = AuthConfig::ApiKey.headers("mark", "abc").unwrap();
+    assert_eq!(headers["Api-User"], "mark");
+    assert_eq!(headers["Api-Key"], "abc");
+
+    let bearer: AuthConfig = serde_yaml::from_str("{type: bearer_token, token: t0k}").unwrap();
+    let headers = bearer.headers("mark", "").unwrap();
+    assert_eq!(headers[AUTHORIZATION], "Bearer t0k");
+    assert!(!headers.contains_key("Api-Key"));
+
+    #[cfg(unix)]
+    {
+        let command = AuthConfig::BearerToken {
+            token: None,
+            token_command: Some("echo fresh".to_owned()),
+        };
+        assert_eq!(
+            command.headers("mark", "").unwrap()[AUTHORIZATION],
+            "Bearer fresh"
+        );
+        let failing = AuthConfig::BearerToken {
+            token: None,
+            token_command: Some("exit 3".to_owned()),
+        };
+        assert!(failing.headers("mark", "").is_err());
+    }
+
+    let custom: AuthConfig =
+        serde_yaml::from_str("{type: headers, headers: {X-Auth: secret}}").unwrap();
+    assert_eq!(custom.headers("mark", "").unwrap()["x-auth"], "secret");
+    assert!(AuthConfig::Headers {
+        headers: vec![("bad header".to_owned(), "v".to_owned())]
+            .into_iter()
+            .collect()
+    }
+    .headers("mark", "")
+    .is_err());
+}
@@ -0,0 +1,114 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Opening Evergreen pages in the default browser.
+//!
+//! `$BROWSER` is used when set, otherwise the platform's opener: `open` on macOS, `start` on
+//! Windows and `xdg-open` elsewhere. Like other tools reading it, `$BROWSER` is a `:` separated
+//! list of commands tried in turn, each may have arguments and a `%s` for the URL.
+
+use std::process::Command;
+
+use anyhow::Result;
+
+/// Open `url` in the browser and return once the opener has handed it off
+pub fn open(url: &str) -> Result<()> {
+    let commands = match std::env::var("BROWSER") {
+        Ok(browser) if !browser.trim().is_empty() => browser_commands(&browser, url),
+        _ => vec![default_command(url)],
+    };
+
+    let mut error = None;
+    for mut command in commands {
+        match command.status() {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => {
+                error = Some(anyhow!(
+                    "The browser failed with {} opening {}",
+                    status,
+                    url
+                ))
+            }
+            Err(e) => error = Some(anyhow!("Could not start a browser for {}: {}", url, e)),
+        }
+    }
+    Err(error.unwrap_or_else(|| anyhow!("No browser to open {}", url)))
+}
+
+/// The commands in `$BROWSER`, the URL replaces `%s` or else is added as the last argument
+fn browser_commands(browser: &str, url: &str) -> Vec<Command> {
+    browser_args(browser, url)
+        .into_iter()
+        .map(|args| {
+            let mut command = Command::new(&args[0]);
+            command.args(&args[1..]);
+            command
+        })
+        .collect()
+}
+
+fn browser_args(browser: &str, url: &str) -> Vec<Vec<String>> {
+    browser
+        .split(':')
+        .filter_map(|entry| {
+            let mut args: Vec<String> = entry.split_whitespace().map(str::to_owned).collect();
+            if args.is_empty() {
+                return None;
+            }
+            if args.iter().any(|a| a.contains("%s")) {
+                for arg in &mut args {
+                    *arg = arg.replace("%s", url);
+                }
+            } else {
+                args.push(url.to_owned());
+            }
+            Some(args)
+        })
+        .collect()
+}
+
+/// The platform's opener, `start` is a cmd builtin whose first quoted argument is the title
+#[cfg(windows)]
+fn default_command(url: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    // Quoted by hand since cmd would split the URL at its & otherwise
+    let mut command = Command::new("cmd");
+    command.raw_arg(format!("/C start \"\" \"{}\"", url));
+    command
+}
+
+#[cfg(not(windows))]
+fn default_command(url: &str) -> Command {
+    let mut command = match cfg!(target_os = "macos") {
+        true => Command::new("open"),
+        false => Command::new("xdg-open"),
+    };
+    command.arg(url);
+    command
+}
+
+#[test]
+fn test_browser_args() {
+    let url = "https://evergreen.example.com/task/t_1";
+    assert_eq!(browser_args("firefox", url), vec![vec!["firefox", url]]);
+    assert_eq!(
+        browser_args("firefox --new-tab:w3m", url),
+        vec![vec!["firefox", "--new-tab", url], vec!["w3m", url]]
+    );
+    assert_eq!(
+        browser_args("open -a Safari %s ::", url),
+        vec![vec!["open", "-a", "Safari", url]]
+    );
+}
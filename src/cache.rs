@@ -0,0 +1,299 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An on-disk cache of GET responses.
+//!
+//! The last response from each URL is kept in the user's cache directory. Those with an `ETag`
+//! or `Last-Modified` header are revalidated with `If-None-Match` and `If-Modified-Since`, so an
+//! unchanged list costs a 304 instead of the whole body. `--offline` answers from the cache
+//! without contacting the server, `--no-cache` skips it and `cache clear` empties it.
+//!
+//! Responses may hold anything the user can read, so on Unix the files are only readable by the
+//! user. Entries older than `MAX_AGE`, then the oldest beyond `MAX_BYTES`, are pruned the first
+//! time each run stores a response, or with `cache clean`.
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::debug;
+use reqwest::blocking::RequestBuilder;
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Names temporary files uniquely when several threads store responses at once
+static NEXT_TEMP: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether this run has pruned the cache
+static PRUNED: AtomicBool = AtomicBool::new(false);
+
+/// Entries not refreshed for this long are pruned
+pub const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The oldest entries are pruned until the cache is at most this size
+pub const MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    // The user and URL the response is for
+    pub key: String,
+
+    pub fetched_at: DateTime<Utc>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+
+    // Response headers, the client reads e.g. Link to find the next page
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl CachedResponse {
+    pub fn new(key: &str, headers: &HeaderMap, body: String) -> CachedResponse {
+        let value = |name| {
+            headers
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+                .map(str::to_owned)
+        };
+        CachedResponse {
+            key: key.to_owned(),
+            fetched_at: Utc::now(),
+            etag: value(header::ETAG),
+            last_modified: value(header::LAST_MODIFIED),
+            headers: headers
+                .iter()
+                .filter(|(name, _)| *name != header::SET_COOKIE)
+                .filter_map(|(name, v)| Some((name.to_string(), v.to_str().ok()?.to_owned())))
+                .collect(),
+            body,
+        }
+    }
+
+    /// Ask the server to answer 304 if the response is still current
+    pub fn validate(&self, mut req: RequestBuilder) -> RequestBuilder {
+        if let Some(etag) = &self.etag {
+            req = req.header(header::IF_NONE_MATCH, etag.as_str());
+        }
+        if let Some(date) = &self.last_modified {
+            req = req.header(header::IF_MODIFIED_SINCE, date.as_str());
+        }
+        req
+    }
+
+    pub fn header_map(&self) -> HeaderMap {
+        self.headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::from_bytes(name.as_bytes()).ok()?,
+                    HeaderValue::from_str(value).ok()?,
+                ))
+            })
+            .collect()
+    }
+}
+
+pub struct HttpCache {
+    dir: PathBuf,
+}
+
+impl HttpCache {
+    pub fn new(dir: PathBuf) -> HttpCache {
+        HttpCache { dir }
+    }
+
+    /// The cache in the user's cache directory
+    pub fn from_home() -> Option<HttpCache> {
+        dirs::cache_dir().map(|d| HttpCache::new(d.join("evergreen-rs").join("http")))
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let digest = Sha256::digest(key.as_bytes());
+        let name: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(name + ".json")
+    }
+
+    /// The cached response for `key`, None if there is none or it cannot be read
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        let text = fs::read_to_string(self.path(key)).ok()?;
+        serde_json::from_str(&text)
+            .ok()
+            .filter(|entry: &CachedResponse| entry.key == key)
+    }
+
+    pub fn put(&self, entry: &CachedResponse) -> Result<()> {
+        let mut dir = fs::DirBuilder::new();
+        dir.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            dir.mode(0o700);
+        }
+        dir.create(&self.dir)?;
+
+        // Write then rename so a concurrent reader never sees half a file
+        let temp = self.dir.join(format!(
+            ".{}-{}.tmp",
+            std::process::id(),
+            NEXT_TEMP.fetch_add(1, Ordering::SeqCst)
+        ));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&temp)?
+            .write_all(&serde_json::to_vec(entry)?)?;
+        fs::rename(&temp, self.path(&entry.key))?;
+
+        if !PRUNED.swap(true, Ordering::SeqCst) {
+            if let Err(e) = self.prune(MAX_AGE, MAX_BYTES) {
+                debug!("Failed to prune the cache: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// The path, size and modification time of each cached response
+    fn entries(&self) -> Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut out = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let meta = entry.metadata()?;
+                out.push((path, meta.len(), meta.modified()?));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Remove responses older than `max_age`, then the oldest until the rest fit in
+    /// `max_bytes`, returns how many were removed
+    pub fn prune(&self, max_age: Duration, max_bytes: u64) -> Result<usize> {
+        let mut entries = self.entries()?;
+        // Newest first, so the ones to remove are at the end
+        entries.sort_by_key(|e| std::cmp::Reverse(e.2));
+
+        let now = SystemTime::now();
+        let mut total = 0;
+        let mut removed = 0;
+        for (path, size, modified) in entries {
+            let age = now.duration_since(modified).unwrap_or_default();
+            if age > max_age || total + size > max_bytes {
+                fs::remove_file(&path)?;
+                removed += 1;
+            } else {
+                total += size;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Remove every cached response, returns how many there were
+    pub fn clear(&self) -> Result<usize> {
+        let entries = self.entries()?;
+        for (path, _, _) in &entries {
+            fs::remove_file(path)?;
+        }
+        Ok(entries.len())
+    }
+}
+
+#[test]
+fn test_http_cache() {
+    let dir = std::env::temp_dir().join(format!("evg-http-cache-{}", std::process::id()));
+    let cache = HttpCache::new(dir.clone());
+    assert_eq!(cache.clear().unwrap(), 0);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::LINK,
+        HeaderValue::from_static("<next>; rel=\"next\""),
+    );
+    assert_eq!(
+        CachedResponse::new("mark url", &headers, "[]".to_owned()).etag,
+        None
+    );
+
+    headers.insert(header::ETAG, HeaderValue::from_static("\"v1\""));
+    headers.insert(header::SET_COOKIE, HeaderValue::from_static("session=1"));
+    let entry = CachedResponse::new("mark url", &headers, "[1]".to_owned());
+    assert_eq!(entry.etag.as_deref(), Some("\"v1\""));
+    assert!(!entry.header_map().contains_key(header::SET_COOKIE));
+    assert_eq!(entry.header_map()[header::LINK], "<next>; rel=\"next\"");
+
+    assert!(cache.get("mark url").is_none());
+    cache.put(&entry).unwrap();
+    assert_eq!(cache.get("mark url"), Some(entry));
+    assert!(cache.get("other url").is_none());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(cache.path("mark url"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    assert_eq!(cache.clear().unwrap(), 1);
+    assert!(cache.get("mark url").is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_http_cache_prune() {
+    let dir = std::env::temp_dir().join(format!("evg-http-prune-{}", std::process::id()));
+    let cache = HttpCache::new(dir.clone());
+    let body = "x".repeat(100);
+    for key in &["a", "b", "c"] {
+        cache
+            .put(&CachedResponse::new(key, &HeaderMap::new(), body.clone()))
+            .unwrap();
+    }
+    // Make a the oldest
+    let old = SystemTime::now() - Duration::from_secs(60);
+    fs::File::options()
+        .write(true)
+        .open(cache.path("a"))
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+
+    assert_eq!(cache.prune(MAX_AGE, MAX_BYTES).unwrap(), 0);
+    let size = fs::metadata(cache.path("b")).unwrap().len();
+    assert_eq!(cache.prune(MAX_AGE, size * 2).unwrap(), 1);
+    assert!(cache.get("a").is_none());
+    assert!(cache.get("b").is_some());
+    assert_eq!(cache.prune(Duration::from_secs(0), MAX_BYTES).unwrap(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
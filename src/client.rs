@@ -0,0 +1,997 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use reqwest::blocking::{ClientBuilder, Request, RequestBuilder, Response};
+use reqwest::header;
+use reqwest::{Certificate, Identity, Method, StatusCode, Url};
+use std::io::ErrorKind;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::args::{PageArgs, TimeArgs};
+use crate::cache::{CachedResponse, HttpCache};
+use crate::config::{ConfigArgs, EvergreenConfig};
+use crate::error::EvergreenError;
+use crate::limits::{LimitArgs, Limits};
+use crate::logging;
+use crate::middleware::{HmacSigner, RateLimiter, RequestMiddleware};
+use crate::models::{
+    Banner, Build, DistroHostStats, Host, HostEvent, HostModify, Manifest, Patch, PermissionMap,
+    PermissionsQuery, PublicKey, ServiceFlags, SpawnRequest, StatsQuery, Tag, Task, TaskAnnotation,
+    TaskQueueItem, TaskStats, TaskUpdate, TestResult, TestStats, UserPermissions, ValidateRequest,
+    ValidationError, Version,
+};
+use crate::proxy::build_proxy;
+use crate::vcr::{self, Cassette};
+use std::collections::BTreeMap;
+
+fn get_hosts_url(config: &EvergreenConfig, user: &str, status: Option<&str>) -> Url {
+    let mut url = Url::parse(&format!(
+        "{}/rest/v2/users/{}/hosts",
+        config.api_server_host, user
+    ))
+    .unwrap();
+    if let Some(status) = status {
+        url.query_pairs_mut().append_pair("status", status);
+    }
+    url
+}
+
+/// The URL of the `rel="next"` entry of a Link header
+fn next_link(headers: &header::HeaderMap) -> Option<Url> {
+    let link = headers.get(header::LINK)?.to_str().ok()?;
+    link.split(',').find_map(|entry| {
+        let (url, params) = entry.split_once(';')?;
+        let is_next = params
+            .split(';')
+            .any(|p| p.trim().trim_start_matches("rel=").trim_matches('"') == "next");
+        if !is_next {
+            return None;
+        }
+        Url::parse(url.trim().trim_start_matches('<').trim_end_matches('>')).ok()
+    })
+}
+
+/// The total number of records when the server reports it
+fn total_count(headers: &header::HeaderMap) -> Option<u64> {
+    headers
+        .get("X-Total-Count")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+/// Tell the user a list stopped at a page boundary, on stderr so piped output is unchanged
+fn warn_truncated(shown: usize, total: Option<u64>) {
+    match total {
+        Some(total) => eprintln!(
+            "Warning: showing {} of {} records, pass --all to fetch every page",
+            shown, total
+        ),
+        None => eprintln!(
+            "Warning: showing the first {} records, more are available, pass --all to fetch every page",
+            shown
+        ),
+    }
+}
+
+/// Parse a page of records, `lenient` skips records that do not fit the model instead of failing
+fn parse_records<T: DeserializeOwned>(body: &str, lenient: bool) -> Result<Vec<T>> {
+    if !lenient {
+        return Ok(serde_json::from_str(body)?);
+    }
+
+    let values: Vec<serde_json::Value> = serde_json::from_str(body)?;
+    Ok(values
+        .into_iter()
+        .filter_map(|value| match serde_json::from_value(value) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                warn!("Skipping a record that could not be read: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Whether sending a request twice has the same effect as sending it once
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+    )
+}
+
+/// Whether a response is worth sending the request again for
+///
+/// A 5xx may come after the server acted on the request, so only idempotent requests are resent,
+/// while a 429 means the request was turned away and is resent whatever the method.
+fn is_retryable(method: &Method, status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || (is_idempotent(method) && status.is_server_error())
+}
+
+/// Whether a request that failed to send may be sent again, a POST such as spawning a host is
+/// only resent when it never reached the server
+fn is_resendable(method: &Method, err: &reqwest::Error) -> bool {
+    err.is_connect() || (is_idempotent(method) && is_transient(err))
+}
+
+/// Whether a failed request may succeed if sent again, e.g. after a timeout or a reset connection
+fn is_transient(err: &reqwest::Error) -> bool {
+    if err.is_connect() || err.is_timeout() {
+        return true;
+    }
+
+    let mut source = std::error::Error::source(err);
+    while let Some(e) = source {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            return matches!(
+                io.kind(),
+                ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::UnexpectedEof
+            );
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// The wait a Retry-After header asks for, capped at a minute
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let value = resp.headers().get(header::RETRY_AFTER)?.to_str().ok()?;
+    let seconds: u64 = value.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds.min(60)))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))
+}
+
+/// Split a PEM bundle into its certificates
+fn split_pem(pem: &str) -> Vec<&str> {
+    const END: &str = "-----END CERTIFICATE-----";
+    pem.split_inclusive(END)
+        .filter(|c| c.contains(END))
+        .map(str::trim)
+        .collect()
+}
+
+/// Trust the configured CA certificates and present the client certificate, if any
+fn configure_tls(mut builder: ClientBuilder, config: &EvergreenConfig) -> Result<ClientBuilder> {
+    if let Some(path) = &config.ca_cert {
+        let pem = String::from_utf8(read_file(path)?)?;
+        let certs = split_pem(&pem);
+        if certs.is_empty() {
+            return Err(anyhow!("No certificates found in {}", path.display()));
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(Certificate::from_pem(cert.as_bytes())?);
+        }
+    }
+
+    match (&config.client_cert, &config.client_key) {
+        (Some(cert), Some(key)) => {
+            let mut pem = read_file(cert)?;
+            pem.push(b'\n');
+            pem.extend(read_file(key)?);
+            // Only the rustls backend reads a PEM certificate and key
+            builder = builder.use_rustls_tls().identity(Identity::from_pem(&pem)?);
+        }
+        (None, None) => {}
+        _ => return Err(anyhow!("client_cert and client_key must be set together")),
+    }
+    Ok(builder)
+}
+
+fn get_rest_url(config: &EvergreenConfig, path: &str) -> Result<Url> {
+    Ok(Url::parse(&format!(
+        "{}/rest/v2/{}",
+        config.api_server_host, path
+    ))?)
+}
+
+/// A route of the older API, outside of /rest/v2
+fn get_api_url(config: &EvergreenConfig, path: &str) -> Result<Url> {
+    Ok(Url::parse(&format!("{}/{}", config.api_server_host, path))?)
+}
+
+pub struct EvergreenClient {
+    pub config: EvergreenConfig,
+
+    pub limits: Limits,
+
+    client: reqwest::blocking::Client,
+
+    middleware: Vec<Box<dyn RequestMiddleware>>,
+
+    cache: Option<HttpCache>,
+
+    // Answer GETs from the cache and refuse every other request
+    offline: bool,
+
+    offline_notice: Once,
+
+    // Record responses to fixtures or replay them
+    cassette: Option<Cassette>,
+
+    // Print requests that change anything instead of sending them
+    dry_run: bool,
+
+    // Skip listed records that fail to parse
+    lenient: bool,
+}
+
+impl EvergreenClient {
+    /// Create a client from ~/.evergreen.yml, `limits` overrides the configured limits
+    pub fn new_from_home(args: &ConfigArgs, limits: &LimitArgs) -> Result<EvergreenClient> {
+        EvergreenClient::new(EvergreenConfig::load(args)?, limits)
+    }
+
+    pub fn new(config: EvergreenConfig, limits: &LimitArgs) -> Result<EvergreenClient> {
+        let headers = config
+            .auth
+            .clone()
+            .unwrap_or_default()
+            .headers(&config.user, &config.api_key)?;
+
+        let limits = limits.resolve(
+            config.limits.as_deref(),
+            config.retries,
+            config.timeout.as_deref(),
+        )?;
+
+        let mut builder = reqwest::blocking::Client::builder()
+            .default_headers(headers)
+            .timeout(limits.timeout);
+        if let Some(proxy) = build_proxy(config.proxy.as_ref(), &config.user)? {
+            builder = builder.proxy(proxy);
+        }
+        let client = configure_tls(builder, &config)?.build()?;
+
+        let mut middleware: Vec<Box<dyn RequestMiddleware>> = Vec::new();
+        if limits.requests_per_second > 0 {
+            middleware.push(Box::new(RateLimiter::new(limits.requests_per_second)));
+        }
+        if let Some(signing) = &config.request_signing {
+            middleware.push(Box::new(HmacSigner::new(signing)?));
+        }
+
+        Ok(EvergreenClient {
+            config,
+            limits,
+            client,
+            middleware,
+            cache: None,
+            offline: false,
+            offline_notice: Once::new(),
+            cassette: None,
+            dry_run: false,
+            lenient: false,
+        })
+    }
+
+    /// Record every response to a fixture, or answer every request from one
+    pub fn with_cassette(mut self, cassette: Cassette) -> EvergreenClient {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    /// Revalidate GET responses kept in `cache` instead of always fetching them in full
+    pub fn with_cache(mut self, cache: HttpCache) -> EvergreenClient {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Answer from the responses kept in `cache` without contacting the server
+    pub fn offline(mut self, cache: HttpCache) -> EvergreenClient {
+        self.cache = Some(cache);
+        self.offline = true;
+        self
+    }
+
+    /// Print every request other than a GET instead of sending it
+    pub fn dry_run(mut self) -> EvergreenClient {
+        self.dry_run = true;
+        self
+    }
+
+    /// Skip records in lists that do not fit the model, with a warning, instead of failing
+    pub fn lenient(mut self) -> EvergreenClient {
+        self.lenient = true;
+        self
+    }
+
+    /// Print a request that --dry-run skips and answer it with an empty 200
+    fn skip_request(req: &Request) -> Result<Response> {
+        println!("{} {}", req.method(), req.url());
+        if let Some(body) = req.body().and_then(|b| b.as_bytes()) {
+            match serde_json::from_slice::<serde_json::Value>(body) {
+                Ok(json) => println!("{}", serde_json::to_string_pretty(&json)?),
+                Err(_) => println!("{}", String::from_utf8_lossy(body)),
+            }
+        }
+        Ok(Response::from(http::Response::new(String::new())))
+    }
+
+    /// Apply the middleware and send a request
+    ///
+    /// Connection failures, 429 and 5xx responses are retried up to the configured limit, waiting
+    /// at least as long as a Retry-After header asks. Requests that are not idempotent are only
+    /// retried when the server cannot have acted on them, see `is_retryable`.
+    fn send(&self, req: RequestBuilder) -> Result<Response> {
+        self.send_request(req, false)
+    }
+
+    /// Like `send` for a request that changes nothing whatever its method, e.g. the POST of
+    /// `validate`, so `--dry-run` still sends it
+    fn send_read_only(&self, req: RequestBuilder) -> Result<Response> {
+        self.send_request(req, true)
+    }
+
+    fn send_request(&self, req: RequestBuilder, read_only: bool) -> Result<Response> {
+        let req = req.build()?;
+        if self.offline {
+            return Err(anyhow!(
+                "Cannot {} {} with --offline, only cached reads are available",
+                req.method(),
+                req.url()
+            ));
+        }
+        if self.dry_run && !read_only && req.method() != Method::GET {
+            return Self::skip_request(&req);
+        }
+        if let Some(Cassette::Replay(dir)) = &self.cassette {
+            return vcr::replay(dir, &req);
+        }
+        let mut attempt = 0;
+        loop {
+            // Streaming bodies cannot be cloned so those requests are only tried once
+            let retry = req.try_clone().filter(|_| attempt < self.limits.retries);
+            let mut current = match retry {
+                Some(r) => r,
+                None => return self.send_once(req),
+            };
+            for m in &self.middleware {
+                m.apply(&mut current)?;
+            }
+
+            let mut delay = self.limits.backoff(attempt);
+            match self.execute_logged(current) {
+                Ok(resp) if !is_retryable(req.method(), resp.status()) => {
+                    return self.record(req.method(), resp)
+                }
+                Ok(resp) => {
+                    debug!("Retrying {} after {}", resp.url(), resp.status());
+                    delay = delay.max(retry_after(&resp).unwrap_or_default());
+                }
+                Err(e) if is_resendable(req.method(), &e) => debug!("Retrying after {}", e),
+                Err(e) => return Err(e.into()),
+            }
+
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    fn send_once(&self, mut req: Request) -> Result<Response> {
+        for m in &self.middleware {
+            m.apply(&mut req)?;
+        }
+
+        let method = req.method().clone();
+        let resp = self.execute_logged(req)?;
+        self.record(&method, resp)
+    }
+
+    /// Save the response as a fixture when recording
+    fn record(&self, method: &Method, resp: Response) -> Result<Response> {
+        match &self.cassette {
+            Some(Cassette::Record(dir)) => vcr::record(dir, method, resp),
+            _ => Ok(resp),
+        }
+    }
+
+    /// Send a request, logging it with -v and its headers and body with -vv
+    fn execute_logged(&self, req: Request) -> reqwest::Result<Response> {
+        let (method, url) = (req.method().clone(), req.url().clone());
+        if logging::show_details() {
+            for (name, value) in req.headers() {
+                debug!("> {}: {}", name, logging::redact(name, value));
+            }
+            if let Some(body) = req.body().and_then(|b| b.as_bytes()) {
+                debug!("> {}", logging::redact_body(body));
+            }
+        }
+
+        let start = Instant::now();
+        let res = self.client.execute(req);
+        let elapsed = start.elapsed().as_millis();
+        match &res {
+            Ok(resp) => {
+                info!("{} {} {} {}ms", method, url, resp.status(), elapsed);
+                for (name, value) in resp.headers() {
+                    debug!("< {}: {}", name, logging::redact(name, value));
+                }
+            }
+            Err(e) => info!("{} {} failed after {}ms: {}", method, url, elapsed, e),
+        }
+        res
+    }
+
+    /// Send a request and return the response body, failing on non-2xx responses
+    fn execute(&self, req: RequestBuilder) -> Result<String> {
+        let resp = self.send(req)?;
+        Self::read_body(resp)
+    }
+
+    fn read_body(resp: Response) -> Result<String> {
+        let status = resp.status();
+        let url = resp.url().clone();
+        let body = resp.text()?;
+        debug!("< {}", logging::truncate(body.as_bytes()));
+
+        if !status.is_success() {
+            return Err(EvergreenError::from_response(status.as_u16(), url.as_str(), &body).into());
+        }
+
+        Ok(body)
+    }
+
+    /// GET a URL and return the response headers and body, failing on non-2xx responses
+    ///
+    /// A cached copy is revalidated and used if the server says it is unchanged.
+    fn get_body(&self, url: Url) -> Result<(header::HeaderMap, String)> {
+        let key = format!("{} {}", self.config.user, url);
+        let cached = self.cache.as_ref().and_then(|c| c.get(&key));
+
+        if self.offline {
+            let entry = cached.ok_or_else(|| {
+                anyhow!(
+                    "No cached response for {}, run the command once without --offline",
+                    url
+                )
+            })?;
+            self.offline_notice.call_once(|| {
+                eprintln!(
+                    "Offline, showing responses cached as of {}",
+                    entry
+                        .fetched_at
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M")
+                )
+            });
+            return Ok((entry.header_map(), entry.body));
+        }
+
+        let mut req = self.client.get(url.clone());
+        if let Some(entry) = &cached {
+            req = entry.validate(req);
+        }
+        let resp = self.send(req)?;
+        if let (StatusCode::NOT_MODIFIED, Some(entry)) = (resp.status(), cached) {
+            debug!("Using cached {}", url);
+            return Ok((entry.header_map(), entry.body));
+        }
+
+        let headers = resp.headers().clone();
+        let body = Self::read_body(resp)?;
+        if let Some(cache) = &self.cache {
+            let entry = CachedResponse::new(&key, &headers, body.clone());
+            if let Err(e) = cache.put(&entry) {
+                debug!("Could not cache {}: {}", url, e);
+            }
+        }
+        Ok((headers, body))
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, url: Url) -> Result<T> {
+        let (_, body) = self.get_body(url)?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Get an unpaginated list, skipping records that do not fit the model with `--lenient`
+    fn get_list<T: DeserializeOwned>(&self, url: Url) -> Result<Vec<T>> {
+        let (_, body) = self.get_body(url)?;
+        parse_records(&body, self.lenient)
+    }
+
+    /// Get a paginated list, `--limit` is sent as the page size and `--all` follows next links
+    fn get_paged<T: DeserializeOwned>(&self, url: Url, page: &PageArgs) -> Result<Vec<T>> {
+        let mut records = Vec::new();
+        self.for_each_page(url, page, |p| {
+            records.extend(p);
+            Ok(())
+        })?;
+        Ok(records)
+    }
+
+    /// Like `get_paged` but hands each page to `on_page` as soon as it arrives
+    fn for_each_page<T, F>(&self, url: Url, page: &PageArgs, mut on_page: F) -> Result<()>
+    where
+        T: DeserializeOwned,
+        F: FnMut(Vec<T>) -> Result<()>,
+    {
+        self.page_through(url, page, |records| {
+            on_page(records)?;
+            Ok(ControlFlow::Continue(()))
+        })
+    }
+
+    /// Like `for_each_page` but stops early when `on_page` breaks
+    fn page_through<T, F>(&self, mut url: Url, page: &PageArgs, mut on_page: F) -> Result<()>
+    where
+        T: DeserializeOwned,
+        F: FnMut(Vec<T>) -> Result<ControlFlow<()>>,
+    {
+        if let Some(limit) = page.limit {
+            url.query_pairs_mut()
+                .append_pair("limit", &limit.to_string());
+        }
+
+        let mut fetched = 0;
+        let mut next = Some(url);
+        while let Some(url) = next {
+            let (headers, body) = self.get_body(url)?;
+            next = next_link(&headers);
+            let total = total_count(&headers);
+            let records: Vec<T> = parse_records(&body, self.lenient)?;
+            fetched += records.len();
+            if on_page(records)?.is_break() {
+                break;
+            }
+
+            if !page.all {
+                if next.is_some() {
+                    warn_truncated(fetched, total);
+                }
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get JSON from the UI server, only for use by the opt-in UI fallbacks
+    pub fn get_ui_json<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let url = Url::parse(&format!("{}/{}", self.config.ui_server_host, path))?;
+        self.get_json(url)
+    }
+
+    /// Get an arbitrary REST resource without mapping it to a model
+    pub fn get_rest_json(&self, path: &str) -> Result<serde_json::Value> {
+        let url = get_rest_url(&self.config, path)?;
+        self.get_json(url)
+    }
+
+    /// Get a REST resource unless it still matches `etag`
+    ///
+    /// Returns None when the server reports the resource is unchanged, otherwise the resource
+    /// and its new ETag if the server sent one.
+    pub fn get_rest_if_changed<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+    ) -> Result<Option<(T, Option<String>)>> {
+        let url = get_rest_url(&self.config, path)?;
+        let mut req = self.client.get(url);
+        if let Some(etag) = etag {
+            req = req.header(header::IF_NONE_MATCH, etag);
+        }
+
+        let resp = self.send(req)?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+
+        let etag = resp
+            .headers()
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_owned());
+        let body = Self::read_body(resp)?;
+        Ok(Some((serde_json::from_str(&body)?, etag)))
+    }
+
+    /// Get every host of a user
+    pub fn get_hosts(&self, user: Option<&str>) -> Result<Vec<Host>> {
+        self.get_hosts_page(user, None, &PageArgs::all())
+    }
+
+    /// Get a user's hosts, only those in `status` if given
+    pub fn get_hosts_page(
+        &self,
+        user: Option<&str>,
+        status: Option<&str>,
+        page: &PageArgs,
+    ) -> Result<Vec<Host>> {
+        let url = get_hosts_url(&self.config, user.unwrap_or(&self.config.user), status);
+        self.get_paged(url, page)
+    }
+
+    /// Stream a user's hosts one page at a time
+    pub fn for_each_hosts_page<F>(
+        &self,
+        user: Option<&str>,
+        status: Option<&str>,
+        page: &PageArgs,
+        on_page: F,
+    ) -> Result<()>
+    where
+        F: FnMut(Vec<Host>) -> Result<()>,
+    {
+        let url = get_hosts_url(&self.config, user.unwrap_or(&self.config.user), status);
+        self.for_each_page(url, page, on_page)
+    }
+
+    pub fn get_host(&self, host_id: &str) -> Result<Host> {
+        let url = get_rest_url(&self.config, &format!("hosts/{}", host_id))?;
+        self.get_json(url)
+    }
+
+    /// Spawn a host, None with --dry-run since nothing is spawned
+    pub fn spawn_host(&self, request: &SpawnRequest) -> Result<Option<Host>> {
+        let url = get_rest_url(&self.config, "hosts")?;
+        let resp = self.execute(self.client.post(url).json(request))?;
+        if self.dry_run {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&resp)?))
+    }
+
+    pub fn terminate_host(&self, host_id: &str) -> Result<()> {
+        let url = get_rest_url(&self.config, &format!("hosts/{}/terminate", host_id))?;
+        self.execute(self.client.post(url))?;
+        Ok(())
+    }
+
+    /// Stop a host, it keeps its volumes and can be started again
+    pub fn stop_host(&self, host_id: &str) -> Result<()> {
+        let url = get_rest_url(&self.config, &format!("hosts/{}/stop", host_id))?;
+        self.execute(self.client.post(url))?;
+        Ok(())
+    }
+
+    /// Push out a host's expiration
+    pub fn extend_host(&self, host_id: &str, hours: u32) -> Result<()> {
+        let url = get_rest_url(&self.config, &format!("hosts/{}", host_id))?;
+        self.execute(
+            self.client
+                .patch(url)
+                .json(&serde_json::json!({ "add_hours": hours })),
+        )?;
+        Ok(())
+    }
+
+    /// Check a project configuration, its errors and warnings, none with --dry-run
+    ///
+    /// The server answers 400 with the same list when the configuration has errors.
+    pub fn validate_project(&self, yaml: &str, quiet: bool) -> Result<Vec<ValidationError>> {
+        let url = get_api_url(&self.config, "validate")?;
+        let request = ValidateRequest {
+            config_yaml: base64::encode(yaml),
+            quiet,
+        };
+        let resp = self.send_read_only(self.client.post(url).json(&request))?;
+        if resp.status() == StatusCode::BAD_REQUEST {
+            let url = resp.url().clone();
+            let body = resp.text()?;
+            debug!("< {}", logging::truncate(body.as_bytes()));
+            return serde_json::from_str(&body)
+                .map_err(|_| EvergreenError::from_response(400, url.as_str(), &body).into());
+        }
+
+        let body = Self::read_body(resp)?;
+        if body.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Change the instance type or the instance tags of a host
+    pub fn modify_host(&self, host_id: &str, modify: &HostModify) -> Result<()> {
+        let url = get_rest_url(&self.config, &format!("hosts/{}", host_id))?;
+        self.execute(self.client.patch(url).json(modify))?;
+        Ok(())
+    }
+
+    /// Add or replace instance tags on a host
+    pub fn add_host_tags(&self, host_id: &str, tags: &[Tag]) -> Result<()> {
+        let url = get_rest_url(&self.config, &format!("hosts/{}", host_id))?;
+        self.execute(
+            self.client
+                .patch(url)
+                .json(&serde_json::json!({ "add_instance_tags": tags })),
+        )?;
+        Ok(())
+    }
+
+    /// Stream a host's events in `window` one page at a time
+    ///
+    /// A window follows next links without `--all`, up to the first page that ends outside of it.
+    pub fn for_each_host_events_page<F>(
+        &self,
+        host_id: &str,
+        page: &PageArgs,
+        window: &TimeArgs,
+        mut on_page: F,
+    ) -> Result<()>
+    where
+        F: FnMut(Vec<HostEvent>) -> Result<()>,
+    {
+        let url = get_rest_url(&self.config, &format!("hosts/{}/events", host_id))?;
+        let page = PageArgs {
+            all: page.all || *window != TimeArgs::default(),
+            ..*page
+        };
+        self.page_through(url, &page, |mut events: Vec<HostEvent>| {
+            let past = match (events.first(), events.last()) {
+                (Some(first), Some(last)) => window.is_past(&first.timestamp, &last.timestamp),
+                _ => false,
+            };
+            events.retain(|e| window.contains_timestamp(&e.timestamp));
+            on_page(events)?;
+            Ok(match past {
+                true => ControlFlow::Break(()),
+                false => ControlFlow::Continue(()),
+            })
+        })
+    }
+
+    pub fn get_task(&self, task_id: &str) -> Result<Task> {
+        let url = get_rest_url(&self.config, &format!("tasks/{}", task_id))?;
+        self.get_json(url)
+    }
+
+    pub fn restart_task(&self, task_id: &str) -> Result<()> {
+        let url = get_rest_url(&self.config, &format!("tasks/{}/restart", task_id))?;
+        self.execute(self.client.post(url))?;
+        Ok(())
+    }
+
+    pub fn update_task(&self, task_id: &str, update: &TaskUpdate) -> Result<()> {
+        let url = get_rest_url(&self.config, &format!("tasks/{}", task_id))?;
+        self.execute(self.client.patch(url).json(update))?;
+        Ok(())
+    }
+
+    /// Get up to `limit` test results of a task with a status, e.g. fail
+    pub fn get_task_tests(
+        &self,
+        task_id: &str,
+        status: &str,
+        limit: u32,
+    ) -> Result<Vec<TestResult>> {
+        let url = get_rest_url(
+            &self.config,
+            &format!("tasks/{}/tests?status={}&limit={}", task_id, status, limit),
+        )?;
+        self.get_list(url)
+    }
+
+    pub fn get_task_annotations(&self, task_id: &str) -> Result<Vec<TaskAnnotation>> {
+        let url = get_rest_url(&self.config, &format!("task/{}/annotations", task_id))?;
+        self.get_list(url)
+    }
+
+    /// Append the note and issues to the task's annotation
+    pub fn add_task_annotation(&self, annotation: &TaskAnnotation) -> Result<()> {
+        let url = get_rest_url(
+            &self.config,
+            &format!("task/{}/annotation", annotation.task_id),
+        )?;
+        self.execute(self.client.patch(url).json(annotation))?;
+        Ok(())
+    }
+
+    /// Get a user, which also checks the credentials are valid
+    pub fn get_user(&self, user: &str) -> Result<serde_json::Value> {
+        self.get_rest_json(&format!("users/{}", user))
+    }
+
+    pub fn get_user_permissions(&self, user: &str) -> Result<Vec<UserPermissions>> {
+        let url = get_rest_url(&self.config, &format!("users/{}/permissions", user))?;
+        self.get_list(url)
+    }
+
+    /// Get the permissions every user has on a resource, keyed by user
+    pub fn get_resource_permissions(
+        &self,
+        query: &PermissionsQuery,
+    ) -> Result<BTreeMap<String, PermissionMap>> {
+        let url = get_rest_url(&self.config, "users/permissions")?;
+        let resp = self.execute(self.client.get(url).json(query))?;
+        Ok(serde_json::from_str(&resp)?)
+    }
+
+    pub fn get_banner(&self) -> Result<Banner> {
+        let url = get_rest_url(&self.config, "admin/banner")?;
+        self.get_json(url)
+    }
+
+    pub fn get_service_flags(&self) -> Result<ServiceFlags> {
+        let url = get_rest_url(&self.config, "admin/service_flags")?;
+        self.get_json(url)
+    }
+
+    pub fn get_task_stats(&self, project: &str, query: &StatsQuery) -> Result<Vec<TaskStats>> {
+        let mut url = get_rest_url(&self.config, &format!("projects/{}/task_stats", project))?;
+        url.set_query(Some(&serde_urlencoded::to_string(query)?));
+        self.get_list(url)
+    }
+
+    pub fn get_test_stats(&self, project: &str, query: &StatsQuery) -> Result<Vec<TestStats>> {
+        let mut url = get_rest_url(&self.config, &format!("projects/{}/test_stats", project))?;
+        url.set_query(Some(&serde_urlencoded::to_string(query)?));
+        self.get_list(url)
+    }
+
+    pub fn get_version_manifest(&self, version_id: &str) -> Result<Manifest> {
+        let url = get_rest_url(&self.config, &format!("versions/{}/manifest", version_id))?;
+        self.get_json(url)
+    }
+
+    pub fn get_keys(&self) -> Result<Vec<PublicKey>> {
+        let url = get_rest_url(&self.config, "keys")?;
+        self.get_list(url)
+    }
+
+    pub fn add_key(&self, key: &PublicKey) -> Result<()> {
+        let url = get_rest_url(&self.config, "keys")?;
+        self.execute(self.client.post(url).json(key))?;
+        Ok(())
+    }
+
+    /// Get the subscriptions owned by a user, as models or raw JSON
+    pub fn get_user_subscriptions<T: DeserializeOwned>(&self, user: &str) -> Result<Vec<T>> {
+        let url = get_rest_url(
+            &self.config,
+            &format!("subscriptions?owner={}&type=person", user),
+        )?;
+        self.get_list(url)
+    }
+
+    /// Create subscriptions, from models or raw JSON
+    pub fn add_subscriptions<T: Serialize>(&self, subscriptions: &[T]) -> Result<()> {
+        let url = get_rest_url(&self.config, "subscriptions")?;
+        self.execute(self.client.post(url).json(subscriptions))?;
+        Ok(())
+    }
+
+    pub fn get_distro_queue(&self, distro_id: &str) -> Result<Vec<TaskQueueItem>> {
+        let url = get_rest_url(&self.config, &format!("distros/{}/queue", distro_id))?;
+        self.get_list(url)
+    }
+
+    /// Get host counts for every distro
+    pub fn get_distro_host_stats(&self) -> Result<Vec<DistroHostStats>> {
+        let url = get_rest_url(&self.config, "status/hosts/distros")?;
+        let resp: serde_json::Value = self.get_json(url)?;
+        // The stats are wrapped in a "distros" document
+        Ok(serde_json::from_value(
+            resp.get("distros").cloned().unwrap_or(resp),
+        )?)
+    }
+
+    /// Get a user's most recent patches, newest first
+    pub fn get_user_patches(&self, user: &str, limit: u32) -> Result<Vec<Patch>> {
+        let url = get_rest_url(
+            &self.config,
+            &format!("users/{}/patches?limit={}", user, limit),
+        )?;
+        self.get_list(url)
+    }
+
+    /// Get a project's most recent mainline versions, newest first
+    pub fn get_project_versions(&self, project: &str, limit: u32) -> Result<Vec<Version>> {
+        let url = get_rest_url(
+            &self.config,
+            &format!("projects/{}/versions?limit={}", project, limit),
+        )?;
+        self.get_list(url)
+    }
+
+    pub fn get_patch(&self, patch_id: &str) -> Result<Patch> {
+        let url = get_rest_url(&self.config, &format!("patches/{}", patch_id))?;
+        self.get_json(url)
+    }
+
+    pub fn get_version_builds(&self, version_id: &str) -> Result<Vec<Build>> {
+        let url = get_rest_url(&self.config, &format!("versions/{}/builds", version_id))?;
+        self.get_list(url)
+    }
+
+    /// Get every task of a build, following each page
+    pub fn get_build_tasks(&self, build_id: &str) -> Result<Vec<Task>> {
+        let url = get_rest_url(&self.config, &format!("builds/{}/tasks", build_id))?;
+        self.get_paged(url, &PageArgs::all())
+    }
+
+    pub fn delete_subscription(&self, id: &str) -> Result<()> {
+        let mut url = get_rest_url(&self.config, "subscriptions")?;
+        url.query_pairs_mut().append_pair("id", id);
+        self.execute(self.client.delete(url))?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_next_link() {
+    let mut headers = header::HeaderMap::new();
+    assert_eq!(next_link(&headers), None);
+
+    headers.insert(
+        header::LINK,
+        header::HeaderValue::from_static(
+            "<https://evg/rest/v2/hosts?limit=2>; rel=\"prev\", <https://evg/rest/v2/hosts?key=h3&limit=2>; rel=\"next\"",
+        ),
+    );
+    assert_eq!(
+        next_link(&headers).unwrap().as_str(),
+        "https://evg/rest/v2/hosts?key=h3&limit=2"
+    );
+}
+
+#[test]
+fn test_parse_records() {
+    let body = r#"[{ "host_id": "h-1" }, { "status": "running" }]"#;
+    assert!(parse_records::<Host>(body, false).is_err());
+    let hosts: Vec<Host> = parse_records(body, true).unwrap();
+    assert_eq!(hosts.len(), 1);
+    assert_eq!(hosts[0].host_id, "h-1");
+    assert!(parse_records::<Host>("{}", true).is_err());
+}
+
+#[test]
+fn test_split_pem() {
+    let pem = "-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----\n\
+               -----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----\n";
+    let certs = split_pem(pem);
+    assert_eq!(certs.len(), 2);
+    assert!(certs[1].starts_with("-----BEGIN CERTIFICATE-----\nBBB"));
+    assert!(split_pem("not a certificate").is_empty());
+}
+
+#[test]
+fn test_client_cert_needs_key() {
+    let config = EvergreenConfig {
+        client_cert: Some("client.pem".into()),
+        ..Default::default()
+    };
+    let err = configure_tls(reqwest::blocking::Client::builder(), &config).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "client_cert and client_key must be set together"
+    );
+}
+
+#[test]
+fn test_is_retryable() {
+    assert!(is_retryable(&Method::GET, StatusCode::BAD_GATEWAY));
+    assert!(is_retryable(
+        &Method::DELETE,
+        StatusCode::SERVICE_UNAVAILABLE
+    ));
+    assert!(!is_retryable(&Method::POST, StatusCode::BAD_GATEWAY));
+    assert!(!is_retryable(&Method::PATCH, StatusCode::GATEWAY_TIMEOUT));
+    assert!(is_retryable(&Method::POST, StatusCode::TOO_MANY_REQUESTS));
+    assert!(!is_retryable(&Method::GET, StatusCode::NOT_FOUND));
+}
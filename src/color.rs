@@ -0,0 +1,102 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// A `--color` value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(anyhow!(
+                "Could not parse '{}' as a color choice, choose from auto, always, never",
+                s
+            )),
+        }
+    }
+}
+
+impl ColorChoice {
+    /// Whether to color, auto colors only a terminal and honours NO_COLOR
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+/// Whether the field at `path` holds a status, e.g. status or details.status
+pub fn is_status_field(path: &str) -> bool {
+    path.rsplit('.').next().unwrap_or(path).ends_with("status")
+}
+
+/// How a status reads at a glance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusKind {
+    Good,
+    Bad,
+    Pending,
+}
+
+pub fn classify_status(status: &str) -> Option<StatusKind> {
+    match status {
+        "running" | "success" | "succeeded" | "started" => Some(StatusKind::Good),
+        "failed" | "terminated" | "decommissioned" | "system-failed" | "setup-failed"
+        | "provision failed" | "quarantined" => Some(StatusKind::Bad),
+        "provisioning" | "starting" | "building" | "dispatched" | "undispatched" | "stopping"
+        | "stopped" | "created" => Some(StatusKind::Pending),
+        _ => None,
+    }
+}
+
+/// Wrap `text` in the color for `status`, unchanged if the status has no color
+pub fn paint_status(text: &str, status: &str) -> String {
+    let color = match classify_status(status.trim()) {
+        Some(StatusKind::Good) => GREEN,
+        Some(StatusKind::Bad) => RED,
+        Some(StatusKind::Pending) => YELLOW,
+        None => return text.to_owned(),
+    };
+    format!("{}{}{}", color, text, RESET)
+}
+
+#[test]
+fn test_paint_status() {
+    assert!(is_status_field("distro.status"));
+    assert!(!is_status_field("status.reason"));
+    assert_eq!(paint_status("failed", "failed"), "\x1b[31mfailed\x1b[0m");
+    assert_eq!(paint_status("odd", "odd"), "odd");
+    assert_eq!("NEVER".parse::<ColorChoice>().unwrap(), ColorChoice::Never);
+    assert!(!ColorChoice::Never.enabled());
+}
@@ -0,0 +1,588 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Loading `~/.evergreen.yml` and choosing a profile from it.
+//!
+//! The file is read from `--config` if given, otherwise from `~/.evergreen.yml` or, when that
+//! does not exist, `$XDG_CONFIG_HOME/evergreen/.evergreen.yml`.
+//!
+//! Besides the usual top level settings the file may have a `profiles` section of named
+//! deployments. A profile only needs the settings that differ from the top level:
+//!
+//! ```yaml
+//! api_server_host: https://evergreen.mongodb.com/api
+//! ui_server_host: https://evergreen.mongodb.com
+//! user: mark.benvenuto
+//! api_key: abc
+//! profiles:
+//!   staging:
+//!     api_server_host: http://localhost:9090/api
+//!     ui_server_host: http://localhost:9090
+//!     api_key: def
+//! ```
+//!
+//! The server and credentials can also be given with `--api-server`, `--api-user` and
+//! `--api-key` or the `EVG_API_SERVER`, `EVG_API_USER` and `EVG_API_KEY` environment variables,
+//! which take precedence over the file. When all three are set the file is optional, e.g. in
+//! a CI container.
+//!
+//! An `api_key` of `keyring:` keeps the key out of the file, it is read from the OS keychain
+//! entry for the user instead. `keyring:<name>` reads a differently named entry, e.g. when one
+//! user has keys for several deployments.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use structopt::StructOpt;
+
+use crate::auth::AuthConfig;
+use crate::middleware::RequestSigningConfig;
+use crate::proxy::ProxyConfig;
+
+/// Key of the section holding the named profiles
+const PROFILES_KEY: &str = "profiles";
+
+/// Name of the configuration file in the home and configuration directories
+const CONFIG_FILE: &str = ".evergreen.yml";
+
+/// Prefix of an api_key that refers to a keychain entry
+pub const KEYRING_PREFIX: &str = "keyring:";
+
+/// Service the API keys are stored under in the keychain
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "evergreen-rs";
+
+/// Offered by `config init` when no server is given
+const DEFAULT_API_SERVER: &str = "https://evergreen.mongodb.com/api";
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EvergreenConfig {
+    // A full URL
+    // http://evergreen-api.mongodb.com:8080/api
+    pub api_server_host: String,
+
+    // A full URL, defaults to api_server_host without the /api suffix
+    // https://evergreen.mongodb.com
+    #[serde(default)]
+    pub ui_server_host: String,
+    // Only needed for the default api_key auth
+    #[serde(default)]
+    pub api_key: String,
+    pub user: String,
+
+    // How requests authenticate, Api-User and Api-Key headers by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthConfig>,
+
+    // Base URL used to turn issue keys into links
+    // https://jira.mongodb.org/browse
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issue_tracker_url: Option<String>,
+
+    // Number and date formatting for human readable output, e.g. en-GB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+
+    // Retry, parallelism, rate and timeout preset: small, default or aggressive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<String>,
+
+    // Override the retries and the timeout of each request of the preset, e.g. 45s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+
+    // Sign request bodies for API gateways that require it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_signing: Option<RequestSigningConfig>,
+
+    // PEM file of extra CA certificates to trust, e.g. a corporate root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<PathBuf>,
+
+    // PEM client certificate and key for servers that require mutual TLS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<PathBuf>,
+
+    // HTTP proxy, HTTPS_PROXY and friends are used when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<ProxyConfig>,
+
+    // Hourly price of each instance_type for `hosts report`, e.g. m5.xlarge: 0.192
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub instance_prices: BTreeMap<String, f64>,
+
+    // Command lines saved by name for `run`, e.g. mine: hosts list --tag owner=mark
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub queries: BTreeMap<String, String>,
+
+    // Host IDs saved by name for `--group`, e.g. build-farm: [i-0123, i-0456]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+/// Global options that choose which configuration to use
+#[derive(StructOpt, Debug, Default, Clone)]
+pub struct ConfigArgs {
+    /// Read the configuration from this file instead of ~/.evergreen.yml
+    #[structopt(long, global = true, env = "EVG_CONFIG", parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    /// Use a named profile from the profiles section of ~/.evergreen.yml
+    #[structopt(long, global = true, env = "EVG_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Evergreen API URL, overrides api_server_host
+    #[structopt(long, global = true, env = "EVG_API_SERVER")]
+    pub api_server: Option<String>,
+
+    /// User name, overrides user
+    #[structopt(long, global = true, env = "EVG_API_USER")]
+    pub api_user: Option<String>,
+
+    /// API key, overrides api_key
+    #[structopt(long, global = true, env = "EVG_API_KEY", hide_env_values = true)]
+    pub api_key: Option<String>,
+}
+
+impl ConfigArgs {
+    /// Environment variables that pass these options on to a child process
+    pub fn envs(&self) -> Vec<(&'static str, String)> {
+        let config = self
+            .config
+            .as_ref()
+            .map(|p| p.to_string_lossy().into_owned());
+        let vars = [
+            ("EVG_CONFIG", &config),
+            ("EVG_PROFILE", &self.profile),
+            ("EVG_API_SERVER", &self.api_server),
+            ("EVG_API_USER", &self.api_user),
+            ("EVG_API_KEY", &self.api_key),
+        ];
+        vars.iter()
+            .filter_map(|(name, value)| Some((*name, (*value).clone()?)))
+            .collect()
+    }
+
+    /// The configuration file to read, the first of the default locations that exists unless
+    /// `--config` is given
+    pub fn config_file(&self) -> Option<PathBuf> {
+        match &self.config {
+            Some(path) => Some(path.clone()),
+            None => default_paths().into_iter().find(|p| p.exists()),
+        }
+    }
+
+    /// Where `config init` writes, the `--config` file or ~/.evergreen.yml
+    pub fn init_file(&self) -> Option<PathBuf> {
+        self.config
+            .clone()
+            .or_else(|| default_paths().into_iter().next())
+    }
+
+    /// Whether the options give everything needed to connect without a configuration file
+    fn is_complete(&self) -> bool {
+        self.api_server.is_some() && self.api_user.is_some() && self.api_key.is_some()
+    }
+
+    /// Replace the settings given on the command line or in the environment
+    fn apply(&self, map: &mut Mapping) {
+        let overrides = [
+            ("api_server_host", &self.api_server),
+            ("user", &self.api_user),
+            ("api_key", &self.api_key),
+        ];
+        for (key, value) in overrides.iter() {
+            if let Some(value) = value {
+                map.insert(Value::from(*key), Value::from(value.as_str()));
+            }
+        }
+    }
+}
+
+/// The UI server of an API server, the same URL without the /api suffix
+fn ui_server_of(api_server_host: &str) -> String {
+    api_server_host
+        .trim_end_matches('/')
+        .trim_end_matches("/api")
+        .to_owned()
+}
+
+/// The keychain entry an api_key refers to, None for a plain key
+pub fn keyring_name<'a>(api_key: &'a str, user: &'a str) -> Option<&'a str> {
+    let name = api_key.strip_prefix(KEYRING_PREFIX)?;
+    Some(if name.is_empty() { user } else { name })
+}
+
+#[cfg(feature = "keyring")]
+pub fn read_keyring(name: &str) -> Result<String> {
+    keyring::Entry::new(KEYRING_SERVICE, name)
+        .and_then(|entry| entry.get_password())
+        .map_err(|e| {
+            anyhow!(
+                "Could not read the API key '{}' from the keychain: {}",
+                name,
+                e
+            )
+        })
+}
+
+#[cfg(feature = "keyring")]
+pub fn store_keyring(name: &str, api_key: &str) -> Result<()> {
+    keyring::Entry::new(KEYRING_SERVICE, name)
+        .and_then(|entry| entry.set_password(api_key))
+        .map_err(|e| {
+            anyhow!(
+                "Could not store the API key '{}' in the keychain: {}",
+                name,
+                e
+            )
+        })
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn read_keyring(_name: &str) -> Result<String> {
+    Err(anyhow!(
+        "The api_key refers to the keychain but keychain support is not built in, enable the keyring feature"
+    ))
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn store_keyring(_name: &str, _api_key: &str) -> Result<()> {
+    read_keyring("").map(|_| ())
+}
+
+/// Where to look for the configuration file without `--config`, in order
+pub fn default_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(home) = dirs::home_dir() {
+        paths.push(home.join(CONFIG_FILE));
+    }
+    if let Some(config) = dirs::config_dir() {
+        paths.push(config.join("evergreen").join(CONFIG_FILE));
+    }
+    paths
+}
+
+/// Merge the settings of the profile and then the overrides in `args` over the top level
+/// settings
+fn apply_profile(mut root: Value, args: &ConfigArgs) -> Result<Value> {
+    let map = root
+        .as_mapping_mut()
+        .ok_or_else(|| anyhow!("The configuration must be a mapping of settings"))?;
+    let profiles = map.remove(&Value::from(PROFILES_KEY));
+
+    if let Some(name) = args.profile.as_deref() {
+        let settings = profiles
+            .as_ref()
+            .and_then(|p| p.get(name))
+            .and_then(Value::as_mapping)
+            .ok_or_else(|| {
+                let names: Vec<_> = profiles
+                    .as_ref()
+                    .and_then(Value::as_mapping)
+                    .map(|p| p.iter().filter_map(|(k, _)| k.as_str()).collect())
+                    .unwrap_or_default();
+                anyhow!(
+                    "Unknown profile '{}', choose from: {}",
+                    name,
+                    names.join(", ")
+                )
+            })?;
+        for (k, v) in settings {
+            map.insert(k.clone(), v.clone());
+        }
+    }
+    args.apply(map);
+    Ok(root)
+}
+
+impl EvergreenConfig {
+    /// Parse a configuration file, applying the profile and overrides in `args`
+    pub fn from_reader<R: Read>(reader: R, args: &ConfigArgs) -> Result<EvergreenConfig> {
+        EvergreenConfig::from_value(serde_yaml::from_reader(reader)?, args)
+    }
+
+    fn from_value(root: Value, args: &ConfigArgs) -> Result<EvergreenConfig> {
+        let mut config: EvergreenConfig = serde_yaml::from_value(apply_profile(root, args)?)?;
+        if config.ui_server_host.is_empty() {
+            config.ui_server_host = ui_server_of(&config.api_server_host);
+        }
+        if let Some(name) = keyring_name(&config.api_key, &config.user) {
+            config.api_key = read_keyring(name)?;
+        }
+        Ok(config)
+    }
+
+    /// Load the configuration file chosen by `args`
+    pub fn load(args: &ConfigArgs) -> Result<EvergreenConfig> {
+        let path = match args.config_file() {
+            Some(path) => path,
+            None if args.is_complete() => {
+                return EvergreenConfig::from_value(Value::Mapping(Mapping::new()), args)
+            }
+            None => {
+                let paths: Vec<_> = default_paths()
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect();
+                return Err(anyhow!(
+                    "No configuration file found, create one of {} or pass --config",
+                    paths.join(", ")
+                ));
+            }
+        };
+
+        let file =
+            File::open(&path).map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        EvergreenConfig::from_reader(file, args)
+    }
+
+    /// Write the configuration to `path`, on Unix only the user may read it since it holds the
+    /// API key
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // The mode only applies when the file is created
+            if path.exists() {
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+
+        let mut file = options.open(path)?;
+        file.write_all(serde_yaml::to_string(self)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Ask a question on stderr, an empty answer takes `default` if there is one
+fn ask<R: BufRead>(input: &mut R, question: &str, default: Option<&str>) -> Result<String> {
+    loop {
+        match default {
+            Some(default) => eprint!("{} [{}]: ", question, default),
+            None => eprint!("{}: ", question),
+        }
+        io::stderr().flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Err(anyhow!("No answer given for '{}'", question));
+        }
+        match (line.trim(), default) {
+            ("", Some(default)) => return Ok(default.to_owned()),
+            ("", None) => continue,
+            (answer, _) => return Ok(answer.to_owned()),
+        }
+    }
+}
+
+/// Ask for the server and credentials, the command line overrides are offered as defaults
+pub fn prompt_config(args: &ConfigArgs) -> Result<EvergreenConfig> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+
+    let api_server = args.api_server.as_deref().unwrap_or(DEFAULT_API_SERVER);
+    let api_server_host = ask(&mut input, "Evergreen API server", Some(api_server))?;
+    let ui_server_host = ask(
+        &mut input,
+        "Evergreen UI server",
+        Some(&ui_server_of(&api_server_host)),
+    )?;
+    let user = ask(&mut input, "User", args.api_user.as_deref())?;
+    let api_key = match &args.api_key {
+        Some(key) => key.clone(),
+        None => rpassword::prompt_password("API key, from your Evergreen settings page: ")?,
+    };
+    if api_key.trim().is_empty() {
+        return Err(anyhow!("An API key is required"));
+    }
+
+    Ok(EvergreenConfig {
+        api_server_host,
+        ui_server_host,
+        api_key: api_key.trim().to_owned(),
+        user,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_profiles() {
+    let yaml = r#"
+api_server_host: https://evergreen.example.com/api
+ui_server_host: https://evergreen.example.com
+user: mark
+api_key: abc
+locale: en-GB
+profiles:
+  local:
+    api_server_host: http://localhost:9090/api
+    ui_server_host: http://localhost:9090
+    api_key: def
+  kanopy:
+    auth:
+      type: bearer_token
+      token_command: kanopy-oidc login
+"#;
+    let config = EvergreenConfig::from_reader(yaml.as_bytes(), &ConfigArgs::default()).unwrap();
+    assert_eq!(config.api_server_host, "https://evergreen.example.com/api");
+    assert_eq!(config.api_key, "abc");
+
+    let args = ConfigArgs {
+        profile: Some("local".to_owned()),
+        ..Default::default()
+    };
+    let local = EvergreenConfig::from_reader(yaml.as_bytes(), &args).unwrap();
+    assert_eq!(local.api_server_host, "http://localhost:9090/api");
+    assert_eq!(local.api_key, "def");
+    // Settings the profile leaves out come from the top level
+    assert_eq!(local.user, "mark");
+    assert_eq!(local.locale.as_deref(), Some("en-GB"));
+    assert_eq!(local.auth, None);
+
+    let args = ConfigArgs {
+        profile: Some("kanopy".to_owned()),
+        ..Default::default()
+    };
+    let kanopy = EvergreenConfig::from_reader(yaml.as_bytes(), &args).unwrap();
+    assert_eq!(
+        kanopy.auth,
+        Some(AuthConfig::BearerToken {
+            token: None,
+            token_command: Some("kanopy-oidc login".to_owned()),
+        })
+    );
+
+    let args = ConfigArgs {
+        profile: Some("prod".to_owned()),
+        ..Default::default()
+    };
+    let err = EvergreenConfig::from_reader(yaml.as_bytes(), &args).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Unknown profile 'prod', choose from: local, kanopy"
+    );
+}
+
+#[test]
+fn test_overrides() {
+    let yaml = "{api_server_host: https://evg.example.com/api, user: mark, api_key: abc}";
+    let args = ConfigArgs {
+        api_key: Some("ci-key".to_owned()),
+        ..Default::default()
+    };
+    let config = EvergreenConfig::from_reader(yaml.as_bytes(), &args).unwrap();
+    assert_eq!(config.api_key, "ci-key");
+    assert_eq!(config.user, "mark");
+    assert_eq!(config.ui_server_host, "https://evg.example.com");
+    assert_eq!(args.envs(), vec![("EVG_API_KEY", "ci-key".to_owned())]);
+
+    // Without a file every setting comes from the overrides
+    let args = ConfigArgs {
+        api_server: Some("http://localhost:9090/api".to_owned()),
+        api_user: Some("ci".to_owned()),
+        api_key: Some("k".to_owned()),
+        ..Default::default()
+    };
+    assert!(args.is_complete());
+    let config = EvergreenConfig::from_value(Value::Mapping(Mapping::new()), &args).unwrap();
+    assert_eq!(config.api_server_host, "http://localhost:9090/api");
+    assert_eq!(config.ui_server_host, "http://localhost:9090");
+    assert_eq!(config.user, "ci");
+}
+
+#[test]
+fn test_config_file() {
+    let path = std::env::temp_dir().join(format!("evg-config-{}.yml", std::process::id()));
+    std::fs::write(
+        &path,
+        "{api_server_host: https://evg.example.com/api, user: mark, api_key: abc}",
+    )
+    .unwrap();
+    let args = ConfigArgs {
+        config: Some(path.clone()),
+        ..Default::default()
+    };
+    let config = EvergreenConfig::load(&args);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(config.unwrap().user, "mark");
+
+    let err = EvergreenConfig::load(&args).unwrap_err();
+    assert!(err.to_string().starts_with("Could not read"));
+    assert_eq!(
+        args.envs(),
+        vec![("EVG_CONFIG", path.to_string_lossy().into_owned())]
+    );
+}
+
+#[test]
+fn test_ask() {
+    let mut input = "\n  mark  \n".as_bytes();
+    assert_eq!(
+        ask(&mut input, "Server", Some("https://evg")).unwrap(),
+        "https://evg"
+    );
+    assert_eq!(ask(&mut input, "User", None).unwrap(), "mark");
+    // Blank answers to a question without a default are asked again
+    let mut input = "\n\nmark\n".as_bytes();
+    assert_eq!(ask(&mut input, "User", None).unwrap(), "mark");
+    assert!(ask(&mut "".as_bytes(), "User", None).is_err());
+}
+
+#[test]
+fn test_write() {
+    let path = std::env::temp_dir().join(format!("evg-write-{}.yml", std::process::id()));
+    let config = EvergreenConfig {
+        api_server_host: "https://evg.example.com/api".to_owned(),
+        ui_server_host: "https://evg.example.com".to_owned(),
+        api_key: "abc".to_owned(),
+        user: "mark".to_owned(),
+        ..Default::default()
+    };
+    config.write(&path).unwrap();
+
+    let read = EvergreenConfig::from_reader(File::open(&path).unwrap(), &ConfigArgs::default());
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(&path).unwrap().permissions().mode() & 0o777
+    };
+    fs::remove_file(&path).unwrap();
+    assert_eq!(read.unwrap(), config);
+    #[cfg(unix)]
+    assert_eq!(mode, 0o600);
+}
+
+#[test]
+fn test_keyring_name() {
+    assert_eq!(keyring_name("keyring:", "mark"), Some("mark"));
+    assert_eq!(keyring_name("keyring:staging", "mark"), Some("staging"));
+    assert_eq!(keyring_name("0123abcd", "mark"), None);
+}
@@ -0,0 +1,118 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defaults set with `use` and read by later commands.
+//!
+//! The context is kept per shell when `EVG_SESSION` is set, e.g. `export EVG_SESSION=$$`,
+//! otherwise per working directory.
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use evergreen_rs_derive::{EvgFields, EvgValues};
+
+/// Names the session a context belongs to
+pub const SESSION_ENV: &str = "EVG_SESSION";
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct SessionContext {
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+/// The file name for a session, or for a directory when there is no session
+fn context_key(session: Option<&str>, dir: &str) -> String {
+    match session {
+        Some(session) => format!("session-{}.json", session),
+        None => {
+            let digest = Sha256::digest(dir.as_bytes());
+            let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+            format!("dir-{}.json", hex)
+        }
+    }
+}
+
+fn context_path() -> Result<PathBuf> {
+    let cache = dirs::cache_dir().ok_or_else(|| anyhow!("Could not find the cache directory"))?;
+    let session = std::env::var(SESSION_ENV).ok().filter(|s| !s.is_empty());
+    let dir = std::env::current_dir()?;
+    Ok(cache
+        .join("evergreen-rs")
+        .join("context")
+        .join(context_key(session.as_deref(), &dir.to_string_lossy())))
+}
+
+impl SessionContext {
+    /// The current context, empty when nothing has been set
+    pub fn load() -> Result<SessionContext> {
+        let path = context_path()?;
+        if !path.exists() {
+            return Ok(SessionContext::default());
+        }
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = context_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+        Ok(())
+    }
+
+    pub fn clear() -> Result<()> {
+        let path = context_path()?;
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The given value or the one stored by `use <kind>`
+fn or_context(
+    arg: Option<&String>,
+    kind: &str,
+    get: fn(SessionContext) -> Option<String>,
+) -> Result<String> {
+    if let Some(arg) = arg {
+        return Ok(arg.clone());
+    }
+    get(SessionContext::load()?)
+        .ok_or_else(|| anyhow!("No {} given, pass one or run `use {} <id>`", kind, kind))
+}
+
+pub fn project_or_context(arg: Option<&String>) -> Result<String> {
+    or_context(arg, "project", |c| c.project)
+}
+
+pub fn version_or_context(arg: Option<&String>) -> Result<String> {
+    or_context(arg, "version", |c| c.version)
+}
+
+#[test]
+fn test_context_key() {
+    assert_eq!(context_key(Some("123"), "/src"), "session-123.json");
+    let a = context_key(None, "/src/a");
+    assert!(a.starts_with("dir-") && a.ends_with(".json"));
+    assert_ne!(a, context_key(None, "/src/b"));
+    assert_eq!(a, context_key(None, "/src/a"));
+}
@@ -0,0 +1,88 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::Serialize;
+
+use evergreen_rs_derive::{EvgFields, EvgValues};
+
+use crate::models::TaskQueueItem;
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+#[derive(Debug, PartialEq, Serialize, EvgFields, EvgValues)]
+pub struct QueueSummary {
+    pub distro_id: String,
+    pub depth: usize,
+    pub hosts: i64,
+    pub total_expected_secs: i64,
+    // Time until the whole queue, or the chosen task, is dispatched
+    pub expected_wait_secs: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_position: Option<usize>,
+}
+
+/// Estimate the wait assuming the queue drains evenly across `hosts` hosts
+///
+/// With `task_id` the estimate covers only the tasks ahead of it.
+pub fn summarize_queue(
+    distro_id: &str,
+    queue: &[TaskQueueItem],
+    hosts: i64,
+    task_id: Option<&str>,
+) -> QueueSummary {
+    let hosts = hosts.max(1);
+    let task_position = task_id.and_then(|t| queue.iter().position(|i| i.id == t));
+
+    let ahead = match task_position {
+        Some(pos) => &queue[..pos],
+        None => queue,
+    };
+    let ahead_nanos: i64 = ahead.iter().map(|i| i.expected_duration).sum();
+    let total_nanos: i64 = queue.iter().map(|i| i.expected_duration).sum();
+
+    QueueSummary {
+        distro_id: distro_id.to_owned(),
+        depth: queue.len(),
+        hosts,
+        total_expected_secs: total_nanos / NANOS_PER_SEC,
+        expected_wait_secs: ahead_nanos / NANOS_PER_SEC / hosts,
+        task_position,
+    }
+}
+
+#[test]
+fn test_summarize_queue() {
+    let item = |id: &str, secs: i64| TaskQueueItem {
+        id: id.to_owned(),
+        display_name: String::new(),
+        build_variant: String::new(),
+        project: String::new(),
+        version: String::new(),
+        requester: String::new(),
+        priority: 0,
+        expected_duration: secs * NANOS_PER_SEC,
+        extra: Default::default(),
+    };
+    let queue = vec![item("a", 60), item("b", 120), item("c", 180)];
+
+    let summary = summarize_queue("d", &queue, 2, None);
+    assert_eq!(summary.depth, 3);
+    assert_eq!(summary.total_expected_secs, 360);
+    assert_eq!(summary.expected_wait_secs, 180);
+
+    let summary = summarize_queue("d", &queue, 0, Some("c"));
+    assert_eq!(summary.hosts, 1);
+    assert_eq!(summary.task_position, Some(2));
+    assert_eq!(summary.expected_wait_secs, 180);
+}
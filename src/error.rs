@@ -0,0 +1,130 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+use serde::Deserialize;
+
+/// Error body returned by the REST API
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    #[serde(default)]
+    error: String,
+}
+
+/// A failed API request, classified for the common cases users can fix themselves
+#[derive(Debug, PartialEq)]
+pub enum EvergreenError {
+    InvalidCredentials {
+        message: String,
+    },
+    DistroNotFound {
+        message: String,
+    },
+    SpawnLimitReached {
+        message: String,
+    },
+    PatchTooLarge {
+        message: String,
+    },
+    Api {
+        status: u16,
+        url: String,
+        message: String,
+    },
+}
+
+impl EvergreenError {
+    /// Classify an error response by its status and message
+    pub fn from_response(status: u16, url: &str, body: &str) -> EvergreenError {
+        let message = serde_json::from_str::<ErrorResponse>(body)
+            .ok()
+            .map(|e| e.error)
+            .filter(|e| !e.is_empty())
+            .unwrap_or_else(|| body.trim().to_owned());
+        let lower = message.to_lowercase();
+
+        if status == 401 || lower.contains("invalid api key") || lower.contains("key expired") {
+            EvergreenError::InvalidCredentials { message }
+        } else if lower.contains("distro")
+            && (lower.contains("not found") || lower.contains("could not find"))
+        {
+            EvergreenError::DistroNotFound { message }
+        } else if lower.contains("spawn host") && lower.contains("limit")
+            || lower.contains("maximum number of hosts")
+        {
+            EvergreenError::SpawnLimitReached { message }
+        } else if status == 413 || lower.contains("patch is too large") {
+            EvergreenError::PatchTooLarge { message }
+        } else {
+            EvergreenError::Api {
+                status,
+                url: url.to_owned(),
+                message,
+            }
+        }
+    }
+}
+
+impl fmt::Display for EvergreenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvergreenError::InvalidCredentials { message } => {
+                write!(f, "Evergreen rejected the credentials: {}", message)
+            }
+            EvergreenError::DistroNotFound { message } => {
+                write!(f, "Distro not found: {}", message)
+            }
+            EvergreenError::SpawnLimitReached { message } => {
+                write!(f, "Spawn host limit reached: {}", message)
+            }
+            EvergreenError::PatchTooLarge { message } => write!(f, "Patch too large: {}", message),
+            EvergreenError::Api {
+                status,
+                url,
+                message,
+            } => write!(f, "{} returned {}: {}", url, status, message),
+        }
+    }
+}
+
+impl std::error::Error for EvergreenError {}
+
+#[test]
+fn test_from_response() {
+    assert_eq!(
+        EvergreenError::from_response(401, "u", "unauthorized"),
+        EvergreenError::InvalidCredentials {
+            message: "unauthorized".to_owned()
+        }
+    );
+    assert_eq!(
+        EvergreenError::from_response(
+            404,
+            "u",
+            r#"{"status": 404, "error": "distro 'ubuntu' not found"}"#
+        ),
+        EvergreenError::DistroNotFound {
+            message: "distro 'ubuntu' not found".to_owned()
+        }
+    );
+    assert_eq!(
+        EvergreenError::from_response(500, "u", r#"{"error": "boom"}"#),
+        EvergreenError::Api {
+            status: 500,
+            url: "u".to_owned(),
+            message: "boom".to_owned()
+        }
+    );
+}
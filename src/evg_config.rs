@@ -0,0 +1,645 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Project configuration files, the `evergreen.yml` defining a project's tasks and variants.
+//!
+//! Only the parts needed to follow references between tasks, task groups, build variants and
+//! functions are modeled, everything else is kept in `extra`. Like Evergreen, a list of one item
+//! may be written as the item, and a task reference as its name:
+//!
+//! ```yaml
+//! tasks:
+//!   - name: compile
+//!     tags: [build]
+//!     commands:
+//!       - func: fetch source
+//! buildvariants:
+//!   - name: linux
+//!     run_on: ubuntu2004-small
+//!     tasks:
+//!       - .build
+//!       - name: lint
+//! ```
+//!
+//! Variants select tasks by name, by task group, with `*` for every task or with tag selectors
+//! such as `.build !.slow`, which selects the tasks tagged `build` and not `slow`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_yaml::Value;
+
+use crate::models::ValidationError;
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct ProjectConfig {
+    // Function name to its commands
+    #[serde(default)]
+    pub functions: BTreeMap<String, Commands>,
+    #[serde(default)]
+    pub tasks: Vec<TaskDef>,
+    #[serde(default)]
+    pub task_groups: Vec<TaskGroup>,
+    #[serde(default)]
+    pub buildvariants: Vec<BuildVariant>,
+    #[serde(default)]
+    pub pre: Commands,
+    #[serde(default)]
+    pub post: Commands,
+    #[serde(default)]
+    pub timeout: Commands,
+    // Files merged into this one by the server
+    #[serde(default)]
+    pub include: Vec<Include>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct TaskDef {
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, deserialize_with = "named_list")]
+    pub depends_on: Vec<Dependency>,
+    #[serde(default)]
+    pub commands: Commands,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct Dependency {
+    // A task name, `*` or a tag selector
+    pub name: String,
+    // The variant of the task, the depending task's own by default
+    #[serde(default)]
+    pub variant: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl From<String> for Dependency {
+    fn from(name: String) -> Dependency {
+        Dependency {
+            name,
+            ..Default::default()
+        }
+    }
+}
+
+/// A function call or a command, e.g. `{func: fetch source}` or `{command: shell.exec}`
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct CommandDef {
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub func: Option<String>,
+    #[serde(default)]
+    pub params: Option<Value>,
+    #[serde(default)]
+    pub vars: Option<Value>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// A list of commands, written as the command when there is one
+#[derive(Debug, Default, PartialEq)]
+pub struct Commands(pub Vec<CommandDef>);
+
+impl<'de> Deserialize<'de> for Commands {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Commands, D::Error> {
+        Ok(Commands(one_or_many(deserializer)?))
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct TaskGroup {
+    pub name: String,
+    #[serde(default)]
+    pub tasks: Vec<String>,
+    #[serde(default)]
+    pub max_hosts: Option<i64>,
+    #[serde(default)]
+    pub setup_group: Commands,
+    #[serde(default)]
+    pub setup_task: Commands,
+    #[serde(default)]
+    pub teardown_task: Commands,
+    #[serde(default)]
+    pub teardown_group: Commands,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct BuildVariant {
+    // Empty for a matrix, which is named by matrix_name
+    #[serde(default)]
+    pub name: String,
+    // The server expands a matrix into a variant for each combination of its matrix_spec
+    #[serde(default)]
+    pub matrix_name: Option<String>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub run_on: Vec<String>,
+    #[serde(default, deserialize_with = "named_list")]
+    pub tasks: Vec<VariantTask>,
+    // Added to the dependencies of every task of the variant
+    #[serde(default, deserialize_with = "named_list")]
+    pub depends_on: Vec<Dependency>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl BuildVariant {
+    /// The name, or the matrix_name of a matrix
+    pub fn id(&self) -> &str {
+        match &self.matrix_name {
+            Some(matrix) if self.name.is_empty() => matrix,
+            _ => &self.name,
+        }
+    }
+}
+
+/// A task of a build variant
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct VariantTask {
+    // A task or task group name, `*` or a tag selector
+    pub name: String,
+    #[serde(default, deserialize_with = "named_list")]
+    pub depends_on: Vec<Dependency>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl From<String> for VariantTask {
+    fn from(name: String) -> VariantTask {
+        VariantTask {
+            name,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct Include {
+    pub filename: String,
+    #[serde(default)]
+    pub module: Option<String>,
+}
+
+/// Read a list that may be written as its only item
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    match Option::<Value>::deserialize(deserializer)? {
+        None => Ok(Vec::new()),
+        Some(Value::Sequence(items)) => items
+            .into_iter()
+            .map(|item| serde_yaml::from_value(item).map_err(serde::de::Error::custom))
+            .collect(),
+        Some(item) => Ok(vec![
+            serde_yaml::from_value(item).map_err(serde::de::Error::custom)?
+        ]),
+    }
+}
+
+/// Read a list of references that may each be written as a name
+fn named_list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + From<String>,
+{
+    let items: Vec<Value> = one_or_many(deserializer)?;
+    items
+        .into_iter()
+        .map(|item| match item {
+            Value::String(name) => Ok(T::from(name)),
+            item => serde_yaml::from_value(item).map_err(serde::de::Error::custom),
+        })
+        .collect()
+}
+
+/// The names given more than once
+fn duplicates<'a>(names: impl Iterator<Item = &'a str>) -> BTreeSet<&'a str> {
+    let mut seen = BTreeSet::new();
+    names.filter(|n| !seen.insert(*n)).collect()
+}
+
+/// Whether a task is selected by a name, `*` or tag selector such as `.build !.slow`
+pub fn selects(selector: &str, task: &TaskDef) -> bool {
+    if selector == "*" {
+        return true;
+    }
+    selector.split_whitespace().all(|criterion| {
+        let (negated, criterion) = match criterion.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, criterion),
+        };
+        let matched = match criterion.strip_prefix('.') {
+            Some(tag) => task.tags.iter().any(|t| t == tag),
+            None => task.name == criterion,
+        };
+        matched != negated
+    })
+}
+
+impl ProjectConfig {
+    pub fn parse(yaml: &str) -> Result<ProjectConfig> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    pub fn task(&self, name: &str) -> Option<&TaskDef> {
+        self.tasks.iter().find(|t| t.name == name)
+    }
+
+    pub fn task_group(&self, name: &str) -> Option<&TaskGroup> {
+        self.task_groups.iter().find(|g| g.name == name)
+    }
+
+    pub fn variant(&self, name: &str) -> Option<&BuildVariant> {
+        self.buildvariants.iter().find(|v| v.id() == name)
+    }
+
+    /// The tasks a selector picks, a task group name picks the group's tasks
+    pub fn select(&self, selector: &str) -> Vec<&TaskDef> {
+        match self.task_group(selector) {
+            Some(group) => self
+                .tasks
+                .iter()
+                .filter(|t| group.tasks.iter().any(|s| selects(s, t)))
+                .collect(),
+            None => self.tasks.iter().filter(|t| selects(selector, t)).collect(),
+        }
+    }
+
+    /// The tasks a build variant runs, in the order they are defined
+    pub fn variant_tasks(&self, variant: &BuildVariant) -> Vec<&TaskDef> {
+        let selected: BTreeSet<&str> = variant
+            .tasks
+            .iter()
+            .flat_map(|t| self.select(&t.name))
+            .map(|t| t.name.as_str())
+            .collect();
+        self.tasks
+            .iter()
+            .filter(|t| selected.contains(t.name.as_str()))
+            .collect()
+    }
+
+    /// The build variants that run a task
+    pub fn variants_running(&self, task: &str) -> Vec<&BuildVariant> {
+        self.buildvariants
+            .iter()
+            .filter(|v| self.variant_tasks(v).iter().any(|t| t.name == task))
+            .collect()
+    }
+
+    /// Every list of commands with what it belongs to, e.g. `task 'compile'`
+    fn command_lists(&self) -> Vec<(String, &Commands)> {
+        let mut lists = vec![
+            ("pre".to_owned(), &self.pre),
+            ("post".to_owned(), &self.post),
+            ("timeout".to_owned(), &self.timeout),
+        ];
+        for (name, commands) in &self.functions {
+            lists.push((format!("function '{}'", name), commands));
+        }
+        for task in &self.tasks {
+            lists.push((format!("task '{}'", task.name), &task.commands));
+        }
+        for group in &self.task_groups {
+            let owner = format!("task group '{}'", group.name);
+            for commands in &[
+                &group.setup_group,
+                &group.setup_task,
+                &group.teardown_task,
+                &group.teardown_group,
+            ] {
+                lists.push((owner.clone(), commands));
+            }
+        }
+        lists
+    }
+
+    /// Problems found without the server: duplicate names, references to undefined tasks and
+    /// functions, malformed commands, and tasks or functions nothing uses
+    ///
+    /// Included files are not read, so references are not checked in a file with includes.
+    pub fn lint(&self) -> Vec<ValidationError> {
+        let mut problems = Vec::new();
+        let mut problem = |level: &str, message: String| {
+            problems.push(ValidationError {
+                level: level.to_owned(),
+                message,
+                ..Default::default()
+            })
+        };
+
+        for name in duplicates(self.tasks.iter().map(|t| t.name.as_str())) {
+            problem(
+                "error",
+                format!("task '{}' is defined more than once", name),
+            );
+        }
+        for name in duplicates(self.task_groups.iter().map(|g| g.name.as_str())) {
+            problem(
+                "error",
+                format!("task group '{}' is defined more than once", name),
+            );
+        }
+        if self.buildvariants.iter().any(|v| v.id().is_empty()) {
+            problem(
+                "error",
+                "a buildvariant has neither a name nor a matrix_name".to_owned(),
+            );
+        }
+        for name in duplicates(self.buildvariants.iter().map(|v| v.id())) {
+            problem(
+                "error",
+                format!("buildvariant '{}' is defined more than once", name),
+            );
+        }
+
+        for (owner, commands) in self.command_lists() {
+            for command in &commands.0 {
+                match (&command.command, &command.func) {
+                    (Some(_), Some(_)) => problem(
+                        "error",
+                        format!("{} has a command with both command and func", owner),
+                    ),
+                    (None, None) => problem(
+                        "error",
+                        format!("{} has a command with neither command nor func", owner),
+                    ),
+                    _ => {}
+                }
+            }
+        }
+
+        if !self.include.is_empty() {
+            problem(
+                "notice",
+                "includes are not read, references between files are not checked".to_owned(),
+            );
+            return problems;
+        }
+
+        let mut called = BTreeSet::new();
+        for (owner, commands) in self.command_lists() {
+            for func in commands.0.iter().filter_map(|c| c.func.as_deref()) {
+                called.insert(func);
+                if !self.functions.contains_key(func) {
+                    problem(
+                        "error",
+                        format!("{} calls undefined function '{}'", owner, func),
+                    );
+                }
+            }
+        }
+        for name in self.functions.keys() {
+            if !called.contains(name.as_str()) {
+                problem("warning", format!("function '{}' is not used", name));
+            }
+        }
+
+        let mut check_dependencies = |owner: &str, deps: &[Dependency]| {
+            for dep in deps {
+                if self.select(&dep.name).is_empty() {
+                    problem(
+                        "error",
+                        format!("{} depends on '{}' which matches no task", owner, dep.name),
+                    );
+                }
+            }
+        };
+        for task in &self.tasks {
+            check_dependencies(&format!("task '{}'", task.name), &task.depends_on);
+        }
+        for variant in &self.buildvariants {
+            let owner = format!("buildvariant '{}'", variant.id());
+            check_dependencies(&owner, &variant.depends_on);
+            for task in &variant.tasks {
+                check_dependencies(&owner, &task.depends_on);
+            }
+        }
+
+        for group in &self.task_groups {
+            for task in &group.tasks {
+                if self.tasks.iter().all(|t| !selects(task, t)) {
+                    problem(
+                        "error",
+                        format!(
+                            "task group '{}' references undefined task '{}'",
+                            group.name, task
+                        ),
+                    );
+                }
+            }
+        }
+        for variant in &self.buildvariants {
+            for task in &variant.tasks {
+                if self.select(&task.name).is_empty() {
+                    problem(
+                        "error",
+                        format!(
+                            "buildvariant '{}' references '{}' which matches no task or task group",
+                            variant.id(),
+                            task.name
+                        ),
+                    );
+                }
+            }
+        }
+
+        let run: BTreeSet<&str> = self
+            .buildvariants
+            .iter()
+            .flat_map(|v| self.variant_tasks(v))
+            .map(|t| t.name.as_str())
+            .collect();
+        for task in &self.tasks {
+            if !run.contains(task.name.as_str()) {
+                problem(
+                    "warning",
+                    format!("task '{}' is not run by any buildvariant", task.name),
+                );
+            }
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+const EXAMPLE: &str = r#"
+functions:
+  fetch source:
+    command: git.get_project
+    params:
+      directory: src
+  unused: []
+pre:
+  - func: fetch source
+tasks:
+  - name: compile
+    tags: [build]
+    commands:
+      - func: fetch source
+      - command: subprocess.exec
+  - name: lint
+    tags: [build, slow]
+    depends_on: compile
+  - name: unit
+    depends_on:
+      - name: compile
+        variant: linux
+  - name: docs
+task_groups:
+  - name: tests
+    max_hosts: 2
+    tasks: [unit]
+buildvariants:
+  - name: linux
+    run_on: ubuntu2004-small
+    tasks:
+      - .build !.slow
+      - tests
+  - name: windows
+    run_on: [windows-64-vs2019]
+    tasks:
+      - name: "*"
+"#;
+
+#[test]
+fn test_parse() {
+    let config = ProjectConfig::parse(EXAMPLE).unwrap();
+    assert_eq!(config.functions["fetch source"].0.len(), 1);
+    assert_eq!(config.pre.0[0].func.as_deref(), Some("fetch source"));
+    assert_eq!(config.task("lint").unwrap().depends_on[0].name, "compile");
+    assert_eq!(
+        config.task("unit").unwrap().depends_on[0]
+            .variant
+            .as_deref(),
+        Some("linux")
+    );
+    assert_eq!(config.task_group("tests").unwrap().max_hosts, Some(2));
+    assert_eq!(
+        config.variant("linux").unwrap().run_on,
+        vec!["ubuntu2004-small"]
+    );
+    assert_eq!(config.variant("windows").unwrap().tasks[0].name, "*");
+}
+
+#[test]
+fn test_variant_tasks() {
+    let config = ProjectConfig::parse(EXAMPLE).unwrap();
+    let names = |tasks: Vec<&TaskDef>| -> Vec<String> {
+        tasks.into_iter().map(|t| t.name.clone()).collect()
+    };
+    assert_eq!(
+        names(config.variant_tasks(config.variant("linux").unwrap())),
+        vec!["compile", "unit"]
+    );
+    assert_eq!(
+        names(config.variant_tasks(config.variant("windows").unwrap())),
+        vec!["compile", "lint", "unit", "docs"]
+    );
+
+    let variants: Vec<&str> = config
+        .variants_running("lint")
+        .into_iter()
+        .map(|v| v.name.as_str())
+        .collect();
+    assert_eq!(variants, vec!["windows"]);
+}
+
+#[test]
+fn test_lint() {
+    let config = ProjectConfig::parse(EXAMPLE).unwrap();
+    let messages = |config: &ProjectConfig| -> Vec<String> {
+        config
+            .lint()
+            .into_iter()
+            .map(|p| format!("{}: {}", p.level, p.message))
+            .collect()
+    };
+    assert_eq!(
+        messages(&config),
+        vec!["warning: function 'unused' is not used"]
+    );
+
+    let broken = ProjectConfig::parse(
+        r#"
+tasks:
+  - name: compile
+    depends_on: [generate]
+    commands:
+      - func: fetch
+      - command: shell.exec
+        func: fetch
+  - name: compile
+task_groups:
+  - name: group
+    tasks: [missing]
+buildvariants:
+  - name: linux
+    tasks: [.nothing]
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        messages(&broken),
+        vec![
+            "error: task 'compile' is defined more than once",
+            "error: task 'compile' has a command with both command and func",
+            "error: task 'compile' calls undefined function 'fetch'",
+            "error: task 'compile' calls undefined function 'fetch'",
+            "error: task 'compile' depends on 'generate' which matches no task",
+            "error: task group 'group' references undefined task 'missing'",
+            "error: buildvariant 'linux' references '.nothing' which matches no task or task group",
+            "warning: task 'compile' is not run by any buildvariant",
+            "warning: task 'compile' is not run by any buildvariant",
+        ]
+    );
+}
+
+#[test]
+fn test_matrix() {
+    let config = ProjectConfig::parse(
+        r#"
+tasks:
+  - name: compile
+buildvariants:
+  - matrix_name: tests
+    matrix_spec: { os: "*" }
+    tasks: [compile]
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.variant("tests").unwrap().id(), "tests");
+    assert!(config.lint().is_empty());
+
+    let unnamed = ProjectConfig::parse("buildvariants:\n  - display_name: Linux\n").unwrap();
+    assert_eq!(
+        unnamed.lint()[0].message,
+        "a buildvariant has neither a name nor a matrix_name"
+    );
+}
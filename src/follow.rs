@@ -0,0 +1,314 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Follow the state of a task, version or patch until it finishes.
+//!
+//! By default the object is polled with conditional requests, which are cheap for the server
+//! when nothing changed. With a callback URL an `evergreen-webhook` subscription is created for
+//! the object and a small local listener reacts as soon as Evergreen posts the outcome, polling
+//! only as a slow fallback. The subscription is deleted when following stops.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use evergreen_rs_derive::{EvgFields, EvgValues};
+
+use crate::client::EvergreenClient;
+use crate::middleware::hmac_sha256_hex;
+use crate::models::{Patch, Subscriber, Subscription, SubscriptionSelector, Task, Version};
+
+/// Header Evergreen signs webhook bodies with
+const SIGNATURE_HEADER: &str = "x-evergreen-signature";
+
+/// Largest webhook body read, outcome notifications are a few KB
+const MAX_BODY: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FollowTarget {
+    Task(String),
+    Version(String),
+    Patch(String),
+}
+
+impl FollowTarget {
+    pub fn id(&self) -> &str {
+        match self {
+            FollowTarget::Task(id) | FollowTarget::Version(id) | FollowTarget::Patch(id) => id,
+        }
+    }
+
+    fn resource_type(&self) -> &'static str {
+        match self {
+            FollowTarget::Task(_) => "TASK",
+            FollowTarget::Version(_) => "VERSION",
+            FollowTarget::Patch(_) => "PATCH",
+        }
+    }
+
+    fn path(&self) -> String {
+        match self {
+            FollowTarget::Task(id) => format!("tasks/{}", id),
+            FollowTarget::Version(id) => format!("versions/{}", id),
+            FollowTarget::Patch(id) => format!("patches/{}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, EvgFields, EvgValues)]
+pub struct StateChange {
+    pub id: String,
+    pub status: String,
+    pub observed_at: DateTime<Utc>,
+}
+
+/// Tasks, versions and patches finish as success or failed, older patches as succeeded
+pub fn is_terminal(status: &str) -> bool {
+    is_success(status) || status == "failed"
+}
+
+pub fn is_success(status: &str) -> bool {
+    status == "success" || status == "succeeded"
+}
+
+/// Webhook delivery for one followed object
+pub struct WebhookConfig {
+    /// URL Evergreen can reach the listener at
+    pub callback_url: String,
+    /// Local address to listen on
+    pub listen: String,
+}
+
+/// Waits for signed webhook posts, owns the subscription that produces them
+struct WebhookListener<'a> {
+    client: &'a EvergreenClient,
+    listener: TcpListener,
+    secret: String,
+    // The subscription this listener created, the only one it deletes
+    subscription_id: String,
+}
+
+impl<'a> WebhookListener<'a> {
+    fn start(
+        client: &'a EvergreenClient,
+        target: &FollowTarget,
+        config: &WebhookConfig,
+    ) -> Result<WebhookListener<'a>> {
+        let listener = TcpListener::bind(&config.listen)?;
+        listener.set_nonblocking(true)?;
+
+        // Not a password, only needs to be unguessable by other webhook senders
+        let seed = format!("{}-{:?}", std::process::id(), Instant::now());
+        let secret = hmac_sha256_hex(seed.as_bytes(), Utc::now().to_rfc3339().as_bytes())?;
+
+        // Evergreen keeps an ObjectId given by the client, so the subscription can be deleted
+        // without guessing which of the user's subscriptions it is
+        let subscription_id = format!("{:08x}{}", Utc::now().timestamp(), &secret[..16]);
+        let sub = Subscription {
+            id: subscription_id.clone(),
+            resource_type: target.resource_type().to_owned(),
+            trigger: "outcome".to_owned(),
+            selectors: vec![SubscriptionSelector {
+                selector_type: "id".to_owned(),
+                data: target.id().to_owned(),
+                extra: Default::default(),
+            }],
+            regex_selectors: Vec::new(),
+            subscriber: Subscriber {
+                subscriber_type: "evergreen-webhook".to_owned(),
+                target: serde_json::json!({
+                    "url": config.callback_url,
+                    "secret": secret,
+                }),
+                extra: Default::default(),
+            },
+            owner_type: "person".to_owned(),
+            owner: client.config.user.clone(),
+            trigger_data: Default::default(),
+            extra: Default::default(),
+        };
+        client.add_subscriptions(&[sub])?;
+
+        Ok(WebhookListener {
+            client,
+            listener,
+            secret,
+            subscription_id,
+        })
+    }
+
+    /// Wait up to `timeout` for a valid webhook post, true if one arrived
+    fn wait(&self, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            match self.listener.accept() {
+                // One bad connection must not stop following
+                Ok((stream, peer)) => match self.handle(stream) {
+                    Ok(true) => return Ok(true),
+                    Ok(false) => {}
+                    Err(e) => warn!("Ignoring webhook request from {}: {}", peer, e),
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(200));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(false)
+    }
+
+    /// Read one request and acknowledge it, true if its signature is valid
+    ///
+    /// Bodies over `MAX_BODY` are refused with 413 before reading them.
+    fn handle(&self, mut stream: TcpStream) -> Result<bool> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let mut reader = BufReader::new(&stream);
+        let mut content_length = 0;
+        let mut signature = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                let name = name.trim().to_lowercase();
+                if name == "content-length" {
+                    content_length = value.trim().parse().unwrap_or(0);
+                } else if name == SIGNATURE_HEADER {
+                    signature = Some(value.trim().to_owned());
+                }
+            }
+        }
+
+        if content_length > MAX_BODY {
+            write!(
+                stream,
+                "HTTP/1.1 413 Payload Too Large\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )?;
+            return Ok(false);
+        }
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+
+        let expected = hmac_sha256_hex(self.secret.as_bytes(), &body)?;
+        let valid = signature.is_some_and(|s| s.trim_start_matches("sha256=") == expected);
+
+        let status = if valid {
+            "204 No Content"
+        } else {
+            "401 Unauthorized"
+        };
+        write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status)?;
+        Ok(valid)
+    }
+}
+
+impl Drop for WebhookListener<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.client.delete_subscription(&self.subscription_id) {
+            eprintln!(
+                "Could not delete subscription {}: {}",
+                self.subscription_id, e
+            );
+        }
+    }
+}
+
+fn fetch_status<T: DeserializeOwned>(
+    client: &EvergreenClient,
+    target: &FollowTarget,
+    etag: &mut Option<String>,
+    status: fn(T) -> String,
+) -> Result<Option<String>> {
+    match client.get_rest_if_changed::<T>(&target.path(), etag.as_deref())? {
+        Some((obj, new_etag)) => {
+            *etag = new_etag;
+            Ok(Some(status(obj)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Report each status change of `target` to `on_change` until it reaches a terminal state
+///
+/// Returns the final status, or the last status seen if `timeout` passes first.
+pub fn follow<F>(
+    client: &EvergreenClient,
+    target: &FollowTarget,
+    interval: Duration,
+    webhook: Option<&WebhookConfig>,
+    timeout: Option<Duration>,
+    mut on_change: F,
+) -> Result<String>
+where
+    F: FnMut(&StateChange) -> Result<()>,
+{
+    let listener = match webhook {
+        Some(config) => Some(WebhookListener::start(client, target, config)?),
+        None => None,
+    };
+
+    let started = Instant::now();
+    let mut etag = None;
+    let mut last_status = String::new();
+    loop {
+        let status = match target {
+            FollowTarget::Task(_) => fetch_status(client, target, &mut etag, |t: Task| t.status)?,
+            FollowTarget::Version(_) => {
+                fetch_status(client, target, &mut etag, |v: Version| v.status)?
+            }
+            FollowTarget::Patch(_) => fetch_status(client, target, &mut etag, |p: Patch| p.status)?,
+        };
+
+        if let Some(status) = status {
+            if status != last_status {
+                on_change(&StateChange {
+                    id: target.id().to_owned(),
+                    status: status.clone(),
+                    observed_at: Utc::now(),
+                })?;
+                last_status = status;
+            }
+        }
+
+        if is_terminal(&last_status) || timeout.is_some_and(|t| started.elapsed() >= t) {
+            return Ok(last_status);
+        }
+
+        match &listener {
+            Some(listener) => {
+                listener.wait(interval)?;
+            }
+            None => std::thread::sleep(interval),
+        }
+    }
+}
+
+#[test]
+fn test_is_terminal() {
+    assert!(is_terminal("success"));
+    assert!(is_terminal("failed"));
+    assert!(is_terminal("succeeded"));
+    assert!(!is_terminal("started"));
+    assert!(!is_terminal(""));
+}
@@ -0,0 +1,294 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Task dependency graphs, written as Graphviz DOT or Mermaid.
+//!
+//! Edges point from a task to the tasks waiting on it, so reading along the arrows shows what
+//! blocks what.
+
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+
+use anyhow::Result;
+
+use crate::evg_config::ProjectConfig;
+use crate::models::Task;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+impl FromStr for GraphFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            _ => Err(anyhow!(
+                "Unknown format '{}', choose from dot or mermaid",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub id: String,
+    // The task name, --task matches it
+    pub name: String,
+    pub label: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    // Indexes into nodes, from the upstream task to the one depending on it
+    pub edges: BTreeSet<(usize, usize)>,
+}
+
+impl Graph {
+    /// The tasks of a configuration, a dependency on a tag selector or `*` adds an edge from each
+    /// task it selects
+    pub fn from_config(config: &ProjectConfig) -> Graph {
+        let nodes = config
+            .tasks
+            .iter()
+            .map(|t| Node {
+                id: t.name.clone(),
+                name: t.name.clone(),
+                label: t.name.clone(),
+            })
+            .collect();
+        let mut graph = Graph {
+            nodes,
+            edges: BTreeSet::new(),
+        };
+        let mut edges = Vec::new();
+
+        let variant_deps = config
+            .buildvariants
+            .iter()
+            .flat_map(|v| &v.tasks)
+            .flat_map(|vt| config.select(&vt.name).into_iter().map(move |t| (t, vt)))
+            .flat_map(|(t, vt)| vt.depends_on.iter().map(move |d| (t, d)));
+        let task_deps = config
+            .tasks
+            .iter()
+            .flat_map(|t| t.depends_on.iter().map(move |d| (t, d)));
+        for (task, dep) in task_deps.chain(variant_deps) {
+            for upstream in config.select(&dep.name) {
+                edges.push((upstream.name.as_str(), task.name.as_str()));
+            }
+        }
+        graph.add_edges(edges);
+        graph
+    }
+
+    /// The tasks of a version, dependencies on tasks outside of `tasks` are left out
+    pub fn from_tasks(tasks: &[Task]) -> Graph {
+        let variants: BTreeSet<&str> = tasks.iter().map(|t| t.build_variant.as_str()).collect();
+        let nodes = tasks
+            .iter()
+            .map(|t| Node {
+                id: t.task_id.clone(),
+                name: t.display_name.clone(),
+                label: match variants.len() {
+                    1 => t.display_name.clone(),
+                    _ => format!("{} ({})", t.display_name, t.build_variant),
+                },
+            })
+            .collect();
+        let mut graph = Graph {
+            nodes,
+            edges: BTreeSet::new(),
+        };
+        graph.add_edges(tasks.iter().flat_map(|task| {
+            task.depends_on
+                .iter()
+                .map(move |dep| (dep.id.as_str(), task.task_id.as_str()))
+        }));
+        graph
+    }
+
+    /// Add an edge for each pair of node IDs, pairs naming a missing node are left out
+    fn add_edges<'a>(&mut self, edges: impl IntoIterator<Item = (&'a str, &'a str)>) {
+        let index: HashMap<&str, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.id.as_str(), i))
+            .collect();
+        for (from, to) in edges {
+            if let (Some(from), Some(to)) = (index.get(from), index.get(to)) {
+                if from != to {
+                    self.edges.insert((*from, *to));
+                }
+            }
+        }
+    }
+
+    /// Only the tasks named `name`, the tasks they wait on and the tasks waiting on them
+    pub fn around(&self, name: &str) -> Result<Graph> {
+        let start: Vec<usize> = (0..self.nodes.len())
+            .filter(|i| self.nodes[*i].name == name)
+            .collect();
+        if start.is_empty() {
+            return Err(anyhow!("No task named '{}' to graph", name));
+        }
+        let mut keep: BTreeSet<usize> = start.iter().copied().collect();
+        // Upstream then downstream, so siblings of the task are not pulled in
+        for upstream in &[true, false] {
+            let mut queue = start.clone();
+            while let Some(node) = queue.pop() {
+                for (from, to) in &self.edges {
+                    let (this, next) = if *upstream { (to, from) } else { (from, to) };
+                    if *this == node && keep.insert(*next) {
+                        queue.push(*next);
+                    }
+                }
+            }
+        }
+
+        let new_index: HashMap<usize, usize> = keep
+            .iter()
+            .enumerate()
+            .map(|(new, old)| (*old, new))
+            .collect();
+        Ok(Graph {
+            nodes: keep.iter().map(|i| self.nodes[*i].clone()).collect(),
+            edges: self
+                .edges
+                .iter()
+                .filter_map(|(from, to)| Some((*new_index.get(from)?, *new_index.get(to)?)))
+                .collect(),
+        })
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Mermaid => self.to_mermaid(),
+        }
+    }
+
+    fn to_dot(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut text = "digraph tasks {\n    rankdir=LR;\n".to_owned();
+        for node in &self.nodes {
+            text += &format!("    {} [label={}];\n", quote(&node.id), quote(&node.label));
+        }
+        for (from, to) in &self.edges {
+            text += &format!(
+                "    {} -> {};\n",
+                quote(&self.nodes[*from].id),
+                quote(&self.nodes[*to].id)
+            );
+        }
+        text + "}\n"
+    }
+
+    /// Mermaid node IDs cannot hold most characters of task names, so nodes are numbered
+    fn to_mermaid(&self) -> String {
+        let mut text = "graph LR\n".to_owned();
+        for (i, node) in self.nodes.iter().enumerate() {
+            text += &format!("    n{}[\"{}\"]\n", i, node.label.replace('"', "#quot;"));
+        }
+        for (from, to) in &self.edges {
+            text += &format!("    n{} --> n{}\n", from, to);
+        }
+        text
+    }
+}
+
+#[test]
+fn test_from_config() {
+    let config = ProjectConfig::parse(
+        r#"
+tasks:
+  - name: compile
+    tags: [build]
+  - name: lint
+    tags: [build]
+  - name: test
+    depends_on: .build
+  - name: package
+buildvariants:
+  - name: linux
+    tasks:
+      - name: package
+        depends_on: test
+"#,
+    )
+    .unwrap();
+    let graph = Graph::from_config(&config);
+    assert_eq!(
+        graph.render(GraphFormat::Mermaid),
+        r#"graph LR
+    n0["compile"]
+    n1["lint"]
+    n2["test"]
+    n3["package"]
+    n0 --> n2
+    n1 --> n2
+    n2 --> n3
+"#
+    );
+
+    // lint is a sibling of compile, not upstream or downstream of it
+    let around = graph.around("compile").unwrap();
+    assert_eq!(
+        around.render(GraphFormat::Dot),
+        r#"digraph tasks {
+    rankdir=LR;
+    "compile" [label="compile"];
+    "test" [label="test"];
+    "package" [label="package"];
+    "compile" -> "test";
+    "test" -> "package";
+}
+"#
+    );
+}
+
+#[test]
+fn test_from_tasks() {
+    use crate::models::TaskDependency;
+
+    let task = |id: &str, name: &str, variant: &str, deps: &[&str]| Task {
+        task_id: id.to_owned(),
+        display_name: name.to_owned(),
+        build_variant: variant.to_owned(),
+        depends_on: deps
+            .iter()
+            .map(|d| TaskDependency {
+                id: d.to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let tasks = vec![
+        task("l_compile", "compile", "linux", &["elsewhere"]),
+        task("l_test", "test", "linux", &["l_compile"]),
+        task("w_test", "test", "windows", &["l_compile"]),
+    ];
+    let graph = Graph::from_tasks(&tasks);
+    assert_eq!(graph.nodes[0].label, "compile (linux)");
+    assert_eq!(graph.edges, vec![(0, 1), (0, 2)].into_iter().collect());
+    assert_eq!(graph.around("test").unwrap().nodes.len(), 3);
+    assert!(graph.around("nope").is_err());
+}
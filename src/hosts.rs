@@ -0,0 +1,978 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::time::Instant;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use evergreen_rs_derive::{EvgFields, EvgValues};
+use evergreen_rs_types::make_name;
+
+use crate::client::EvergreenClient;
+use crate::models::{Host, HostEvent, HostModify, Tag};
+use crate::output::lookup_path;
+use crate::relative::format_span;
+use crate::selector::Resource;
+use crate::template::Template;
+
+/// Prefix for tags written by `hosts fingerprint`
+pub const FINGERPRINT_TAG_PREFIX: &str = "auto:";
+
+/// Shell script run on the host, prints one `key=value` line per fact on Linux and macOS
+pub const FINGERPRINT_SCRIPT: &str = r#"
+if [ -r /etc/os-release ]; then . /etc/os-release; echo "os=$PRETTY_NAME"; else echo "os=$(sw_vers -productName) $(sw_vers -productVersion)"; fi
+echo "kernel=$(uname -sr)"
+echo "arch=$(uname -m)"
+echo "cpus=$(nproc 2>/dev/null || sysctl -n hw.ncpu)"
+if [ -r /proc/meminfo ]; then echo "memory_mb=$(awk '/MemTotal/ {print int($2 / 1024)}' /proc/meminfo)"; else echo "memory_mb=$(($(sysctl -n hw.memsize) / 1048576))"; fi
+"#;
+
+/// Turn the output of `FINGERPRINT_SCRIPT` into prefixed instance tags, skipping empty facts
+pub fn parse_fingerprint(output: &str) -> Vec<Tag> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(k, v)| (k.trim(), v.trim()))
+        .filter(|(k, v)| !k.is_empty() && !v.is_empty())
+        .map(|(k, v)| Tag {
+            key: format!("{}{}", FINGERPRINT_TAG_PREFIX, k),
+            value: v.to_owned(),
+            can_be_modified: true,
+            extra: Default::default(),
+        })
+        .collect()
+}
+
+/// The host statuses each `--status` shorthand stands for
+const STATUS_SHORTHANDS: &[(&str, &[&str])] = &[
+    (
+        "starting",
+        &["uninitialized", "building", "starting", "provisioning"],
+    ),
+    ("stopped", &["stopping", "stopped"]),
+    ("failed", &["building-failed", "provision failed"]),
+    ("terminated", &["decommissioned", "terminated"]),
+];
+
+/// Expand `--status` shorthands into host statuses, other names are taken as exact statuses
+pub fn expand_statuses(names: &[String]) -> Vec<String> {
+    let mut statuses: Vec<String> = Vec::new();
+    for name in names {
+        let name = name.to_lowercase();
+        let expanded = match STATUS_SHORTHANDS.iter().find(|(short, _)| *short == name) {
+            Some((_, statuses)) => statuses.iter().map(|s| s.to_string()).collect(),
+            None => vec![name],
+        };
+        for status in expanded {
+            if !statuses.contains(&status) {
+                statuses.push(status);
+            }
+        }
+    }
+    statuses
+}
+
+/// Whether a host status is stopped or going away, so the host no longer runs up a cost
+pub fn is_down(status: &str) -> bool {
+    STATUS_SHORTHANDS
+        .iter()
+        .filter(|(short, _)| *short == "stopped" || *short == "terminated")
+        .any(|(_, statuses)| statuses.contains(&status))
+}
+
+/// When a host went down, from its newest event stopping it or changing it to a down status
+pub fn down_since(events: &[HostEvent]) -> Option<DateTime<Utc>> {
+    events
+        .iter()
+        .filter(|e| match e.event_type.as_str() {
+            "HOST_STOPPED" => true,
+            "HOST_STATUS_CHANGED" => e.data["new_status"].as_str().is_some_and(is_down),
+            _ => false,
+        })
+        .filter_map(|e| DateTime::parse_from_rfc3339(&e.timestamp).ok())
+        .map(|t| t.with_timezone(&Utc))
+        .max()
+}
+
+/// Width of the timeline bar in characters
+const TIMELINE_WIDTH: usize = 30;
+
+/// When a host expires, None for hosts that never expire
+pub fn host_expiration(host: &Host) -> Option<DateTime<Utc>> {
+    if host.no_expiration {
+        return None;
+    }
+    host.expiration_time
+}
+
+/// Sort hosts by expiration, soonest first and hosts that never expire last
+pub fn sort_by_expiration(hosts: &mut [Host]) {
+    hosts.sort_by_key(|h| (host_expiration(h).is_none(), host_expiration(h)));
+}
+
+/// Render a bar where the marker shows when in the window the host expires
+fn timeline_bar(remaining: Duration, window: Duration) -> String {
+    if remaining > window {
+        return format!("[{}]>", "-".repeat(TIMELINE_WIDTH));
+    }
+
+    let secs = remaining.num_seconds().max(0) as f64;
+    let pos = ((secs / window.num_seconds().max(1) as f64) * TIMELINE_WIDTH as f64) as usize;
+    let pos = pos.min(TIMELINE_WIDTH - 1);
+    format!(
+        "[{}X{}] ",
+        "=".repeat(pos),
+        "-".repeat(TIMELINE_WIDTH - pos - 1)
+    )
+}
+
+fn format_remaining(remaining: Duration) -> String {
+    if remaining <= Duration::zero() {
+        return "expired".to_owned();
+    }
+    format!("in {}", format_span(remaining))
+}
+
+/// Render one timeline line for a host
+pub fn timeline_line(host: &Host, now: DateTime<Utc>, window: Duration) -> String {
+    let name = if host.display_name.is_empty() {
+        &host.host_id
+    } else {
+        &host.display_name
+    };
+
+    match host_expiration(host) {
+        Some(expires) => {
+            let remaining = expires - now;
+            format!(
+                "{} {:<10} {}  {}",
+                timeline_bar(remaining, window),
+                format_remaining(remaining),
+                expires.format("%Y-%m-%d %H:%M UTC"),
+                name
+            )
+        }
+        None => format!(
+            "[{}]  {:<10} {:<20}  {}",
+            " ".repeat(TIMELINE_WIDTH),
+            "never",
+            "",
+            name
+        ),
+    }
+}
+
+/// Instance tag that keeps a host from being reaped, any value but false counts
+pub const KEEP_TAG: &str = "keep";
+
+/// When a host last did something, its newest event or else its creation
+pub fn last_activity(host: &Host, events: &[HostEvent]) -> Option<DateTime<Utc>> {
+    events
+        .iter()
+        .filter_map(|e| DateTime::parse_from_rfc3339(&e.timestamp).ok())
+        .map(|t| t.with_timezone(&Utc))
+        .max()
+        .or(host.creation_time)
+        .filter(|t| t.timestamp() > 0)
+}
+
+/// Whether `hosts reap` may consider a host, it is up or stopped, not running a task and not
+/// tagged to keep
+pub fn is_reapable(host: &Host, stopping: bool) -> bool {
+    let status_ok = match stopping {
+        true => host.status == "running",
+        false => host.status == "running" || host.status == "stopped",
+    };
+    status_ok && !host.is_busy() && host.tag(KEEP_TAG).is_none_or(|v| v == "false")
+}
+
+/// Arguments to rsync `local` to `remote` on a host over ssh, `remote` defaults to the home
+/// directory
+///
+/// Only changed files are sent, so running it again is cheap. `delete` removes remote files that
+/// no longer exist locally. The host must be running, otherwise rsync would be pointed at
+/// `user@:`, which it takes as a local path.
+pub fn rsync_args(
+    host: &Host,
+    local: &str,
+    remote: Option<&str>,
+    delete: bool,
+) -> Result<Vec<String>> {
+    if host.status != "running" {
+        return Err(anyhow!(
+            "Cannot sync to {}, it is {}, not running",
+            host.host_id,
+            host.status
+        ));
+    }
+    if host.host_url.is_empty() {
+        return Err(anyhow!(
+            "Cannot sync to {}, it has no address yet",
+            host.host_id
+        ));
+    }
+
+    let mut args = vec![
+        "--archive".to_owned(),
+        "--compress".to_owned(),
+        "--partial".to_owned(),
+        "--rsh=ssh".to_owned(),
+    ];
+    if delete {
+        args.push("--delete".to_owned());
+    }
+    // A local path starting with - is not an option
+    args.push("--".to_owned());
+    args.push(local.to_owned());
+    args.push(format!(
+        "{}@{}:{}",
+        host.user,
+        host.host_url,
+        remote.unwrap_or_default()
+    ));
+    Ok(args)
+}
+
+/// Split a forward on the colons outside of brackets, None if a bracket is not closed
+fn split_forward(spec: &str) -> Option<Vec<&str>> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut bracketed = false;
+    for (i, c) in spec.char_indices() {
+        match c {
+            '[' if !bracketed => bracketed = true,
+            ']' if bracketed => bracketed = false,
+            ':' if !bracketed => {
+                parts.push(&spec[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if bracketed {
+        return None;
+    }
+    parts.push(&spec[start..]);
+    Some(parts)
+}
+
+/// Check a forward is `[bind_address:]port:host:hostport`, as ssh -L takes
+///
+/// IPv6 addresses are written in brackets, e.g. `[::1]:8080:[::1]:80`.
+pub fn parse_forward(spec: &str) -> Result<String> {
+    let parts = split_forward(spec).unwrap_or_default();
+    let ports_ok = parts.len() >= 3
+        && parts[parts.len() - 1].parse::<u16>().is_ok()
+        && parts[parts.len() - 3].parse::<u16>().is_ok();
+    if !ports_ok || parts.len() > 4 {
+        return Err(anyhow!(
+            "Could not parse the forward '{}', use [bind_address:]port:host:hostport, e.g. \
+             8080:localhost:8080",
+            spec
+        ));
+    }
+    Ok(spec.to_owned())
+}
+
+/// ssh runs of `hosts tunnel` shorter than this that fail count as failing to connect
+const TUNNEL_QUICK_EXIT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How many failures to connect in a row `hosts tunnel` retries before giving up
+pub const TUNNEL_MAX_QUICK_FAILURES: u32 = 5;
+
+/// Counts the ssh runs of `hosts tunnel` that fail soon after starting, e.g. a refused login or
+/// a local port in use, which reconnecting will not fix
+#[derive(Debug, Default)]
+pub struct QuickFailures {
+    count: u32,
+}
+
+impl QuickFailures {
+    /// Record a run of ssh, returns true after `TUNNEL_MAX_QUICK_FAILURES` in a row
+    pub fn record(&mut self, success: bool, ran_for: std::time::Duration) -> bool {
+        if success || ran_for >= TUNNEL_QUICK_EXIT {
+            self.count = 0;
+        } else {
+            self.count += 1;
+        }
+        self.count >= TUNNEL_MAX_QUICK_FAILURES
+    }
+}
+
+/// Arguments to ssh to hold `forwards` open to a host without running a command
+///
+/// Keepalives make ssh exit soon after the connection drops so it can be reconnected.
+pub fn tunnel_args(host: &Host, forwards: &[String]) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "-N",
+        "-o",
+        "ExitOnForwardFailure=yes",
+        "-o",
+        "ServerAliveInterval=15",
+        "-o",
+        "ServerAliveCountMax=3",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    for forward in forwards {
+        args.push("-L".to_owned());
+        args.push(forward.clone());
+    }
+    args.push(format!("{}@{}", host.user, host.host_url));
+    args
+}
+
+/// Statuses a host does not come back from
+const FAILED_STATUSES: &[&str] = &[
+    "terminated",
+    "decommissioned",
+    "quarantined",
+    "building-failed",
+    "provision failed",
+];
+
+/// Whether something accepts connections on the ssh port of `host_url`
+pub fn ssh_reachable(host_url: &str, timeout: std::time::Duration) -> bool {
+    let addrs = match (host_url, 22).to_socket_addrs() {
+        Ok(addrs) => addrs,
+        Err(_) => return false,
+    };
+    addrs
+        .into_iter()
+        .any(|addr| TcpStream::connect_timeout(&addr, timeout).is_ok())
+}
+
+/// Poll a host until it is provisioned and accepts ssh connections, printing its progress
+pub fn wait_until_ready(
+    client: &EvergreenClient,
+    host_id: &str,
+    interval: std::time::Duration,
+    timeout: std::time::Duration,
+) -> Result<Host> {
+    let started = Instant::now();
+    let mut last_state = String::new();
+    loop {
+        let host = client.get_host(host_id)?;
+        if FAILED_STATUSES.contains(&host.status.as_str()) {
+            return Err(anyhow!("Host {} is {}", host_id, host.status));
+        }
+
+        let ready = host.provisioned && ssh_reachable(&host.host_url, interval);
+        let state = match (ready, host.provisioned) {
+            (true, _) => "ready".to_owned(),
+            (false, true) => format!("{}, waiting for ssh", host.status),
+            (false, false) => host.status.clone(),
+        };
+        if state != last_state {
+            eprintln!("{} is {}", host_id, state);
+            last_state = state;
+        }
+        if ready {
+            return Ok(host);
+        }
+
+        if started.elapsed() >= timeout {
+            return Err(anyhow!(
+                "Gave up waiting for {}, it is {}",
+                host_id,
+                last_state
+            ));
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Parse a `key=value` instance tag
+pub fn parse_tag(s: &str) -> Result<Tag> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok(Tag {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            can_be_modified: true,
+            extra: Default::default(),
+        }),
+        _ => Err(anyhow!("Could not parse '{}' as a tag, use key=value", s)),
+    }
+}
+
+/// Check a change can be made to a host, tags Evergreen set cannot be changed and the instance
+/// type can only change while the host is stopped
+pub fn check_modify(host: &Host, modify: &HostModify) -> Result<()> {
+    if modify.instance_type.is_some() && host.status != "stopped" {
+        return Err(anyhow!(
+            "Stop {} before changing its instance type, it is {}",
+            host.host_id,
+            host.status
+        ));
+    }
+
+    let changed = modify
+        .add_instance_tags
+        .iter()
+        .map(|t| t.key.as_str())
+        .chain(modify.delete_instance_tags.iter().map(String::as_str));
+    for key in changed {
+        let fixed = host
+            .instance_tags
+            .iter()
+            .any(|t| t.key == key && !t.can_be_modified);
+        if fixed {
+            return Err(anyhow!(
+                "The tag '{}' on {} cannot be modified",
+                key,
+                host.host_id
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The hosts `rename --from-template` names, in the order of `ids`
+///
+/// Repeated IDs name the host once. Without IDs every host that is not terminated is named.
+pub fn rename_targets(mut hosts: Vec<Host>, ids: &[String]) -> Result<Vec<Host>> {
+    if ids.is_empty() {
+        hosts.retain(|h| h.status != "terminated");
+        return Ok(hosts);
+    }
+
+    let mut targets: Vec<Host> = Vec::new();
+    for id in ids {
+        if targets.iter().any(|h| &h.host_id == id) {
+            continue;
+        }
+        let at = hosts
+            .iter()
+            .position(|h| &h.host_id == id)
+            .ok_or_else(|| anyhow!("Unknown host ID '{}'", id))?;
+        targets.push(hosts.swap_remove(at));
+    }
+    Ok(targets)
+}
+
+/// Name hosts from a template of their fields, `{index}` counts the hosts from 1
+///
+/// Each field must be a single value, e.g. `{distro}` is refused in favor of
+/// `{distro.distro_id}`.
+pub fn template_names(hosts: &[Host], template: &Template) -> Result<Vec<String>> {
+    hosts
+        .iter()
+        .enumerate()
+        .map(|(i, host)| {
+            let mut record = serde_json::to_value(host)?;
+            record["index"] = (i + 1).into();
+            for path in template.paths() {
+                match lookup_path(&record, &path).as_deref() {
+                    Some(Value::Object(fields)) => {
+                        let example = fields
+                            .keys()
+                            .next()
+                            .map(|k| format!(", e.g. {{{}}}", make_name(&path, k)))
+                            .unwrap_or_default();
+                        return Err(anyhow!(
+                            "The template field {{{}}} has several fields, name one of them{}",
+                            path,
+                            example
+                        ));
+                    }
+                    Some(Value::Array(_)) => {
+                        return Err(anyhow!(
+                            "The template field {{{}}} is a list, name one of its members",
+                            path
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            let name = template.render(&record);
+            if name.trim().is_empty() {
+                return Err(anyhow!("The template gives {} an empty name", host.host_id));
+            }
+            Ok(name)
+        })
+        .collect()
+}
+
+/// What `hosts report` adds up spend by
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReportGroup {
+    // The user who started the host
+    User,
+    InstanceType,
+    Distro,
+    // The value of an instance tag, e.g. tag:team
+    Tag(String),
+}
+
+impl FromStr for ReportGroup {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(ReportGroup::User),
+            "instance_type" => Ok(ReportGroup::InstanceType),
+            "distro" => Ok(ReportGroup::Distro),
+            _ => match s.strip_prefix("tag:") {
+                Some(key) if !key.is_empty() => Ok(ReportGroup::Tag(key.to_owned())),
+                _ => Err(anyhow!(
+                    "Unknown group '{}', choose from user, instance_type, distro or tag:<key>",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl ReportGroup {
+    /// The group of a host, hosts without the tag are grouped under "(none)"
+    pub fn key(&self, host: &Host) -> String {
+        let key = match self {
+            ReportGroup::User => &host.started_by,
+            ReportGroup::InstanceType => &host.instance_type,
+            ReportGroup::Distro => &host.distro.distro_id,
+            ReportGroup::Tag(key) => host.tag(key).unwrap_or_default(),
+        };
+        match key.is_empty() {
+            true => "(none)".to_owned(),
+            false => key.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, EvgFields, EvgValues)]
+pub struct HostCost {
+    pub host_id: String,
+    pub group: String,
+    pub instance_type: String,
+    pub status: String,
+    // From when the host was created until now or until it went down
+    pub uptime_hours: f64,
+    // None when the instance type has no price
+    pub cost: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, Serialize, EvgFields, EvgValues)]
+pub struct SpendSummary {
+    pub group: String,
+    pub hosts: usize,
+    pub uptime_hours: f64,
+    pub cost: f64,
+    // Hosts left out of the cost since their instance type has no price
+    pub unpriced: usize,
+}
+
+fn round_cents(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// A host's uptime until `end` and its cost at the hourly `prices` of each instance type
+///
+/// `end` is now for a host that is up and when it went down for the rest.
+pub fn host_cost(
+    host: &Host,
+    group: &ReportGroup,
+    prices: &BTreeMap<String, f64>,
+    end: DateTime<Utc>,
+) -> HostCost {
+    let uptime = host
+        .creation_time
+        .filter(|c| c.timestamp() > 0)
+        .map_or(Duration::zero(), |created| end - created);
+    let uptime_hours = uptime.num_minutes().max(0) as f64 / 60.0;
+    HostCost {
+        host_id: host.host_id.clone(),
+        group: group.key(host),
+        instance_type: host.instance_type.clone(),
+        status: host.status.clone(),
+        uptime_hours: round_cents(uptime_hours),
+        cost: prices
+            .get(&host.instance_type)
+            .map(|price| round_cents(price * uptime_hours)),
+    }
+}
+
+/// Add up the uptime and cost of the hosts in each group
+pub fn summarize_spend(costs: &[HostCost]) -> Vec<SpendSummary> {
+    let mut groups: BTreeMap<&str, SpendSummary> = BTreeMap::new();
+    for cost in costs {
+        let summary = groups
+            .entry(cost.group.as_str())
+            .or_insert_with(|| SpendSummary {
+                group: cost.group.clone(),
+                hosts: 0,
+                uptime_hours: 0.0,
+                cost: 0.0,
+                unpriced: 0,
+            });
+        summary.hosts += 1;
+        summary.uptime_hours = round_cents(summary.uptime_hours + cost.uptime_hours);
+        match cost.cost {
+            Some(c) => summary.cost = round_cents(summary.cost + c),
+            None => summary.unpriced += 1,
+        }
+    }
+    groups.into_values().collect()
+}
+
+#[test]
+fn test_timeline_bar() {
+    let window = Duration::hours(10);
+    assert_eq!(
+        timeline_bar(Duration::hours(0), window),
+        format!("[X{}] ", "-".repeat(TIMELINE_WIDTH - 1))
+    );
+    assert_eq!(
+        timeline_bar(Duration::hours(5), window),
+        format!("[{}X{}] ", "=".repeat(15), "-".repeat(14))
+    );
+    assert_eq!(
+        timeline_bar(Duration::hours(11), window),
+        format!("[{}]>", "-".repeat(TIMELINE_WIDTH))
+    );
+}
+
+#[test]
+fn test_format_remaining() {
+    assert_eq!(format_remaining(Duration::minutes(-5)), "expired");
+    assert_eq!(format_remaining(Duration::minutes(42)), "in 42m");
+    assert_eq!(format_remaining(Duration::hours(5)), "in 5h");
+    assert_eq!(format_remaining(Duration::hours(72)), "in 3d");
+}
+
+#[test]
+fn test_expand_statuses() {
+    let expand = |names: &[&str]| {
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        expand_statuses(&names)
+    };
+    assert_eq!(expand(&["Running"]), ["running"]);
+    assert_eq!(expand(&["terminated"]), ["decommissioned", "terminated"]);
+    assert_eq!(
+        expand(&["stopped", "stopping", "quarantined"]),
+        ["stopping", "stopped", "quarantined"]
+    );
+    assert!(expand(&[]).is_empty());
+}
+
+#[test]
+fn test_parse_fingerprint() {
+    let tags = parse_fingerprint("os=Ubuntu 22.04.3 LTS\nkernel=Linux 5.15.0\ncpus=\nnoise\n");
+    assert_eq!(
+        tags,
+        vec![
+            Tag {
+                key: "auto:os".to_owned(),
+                value: "Ubuntu 22.04.3 LTS".to_owned(),
+                can_be_modified: true,
+                extra: Default::default(),
+            },
+            Tag {
+                key: "auto:kernel".to_owned(),
+                value: "Linux 5.15.0".to_owned(),
+                can_be_modified: true,
+                extra: Default::default(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_host_cost() {
+    use crate::models::Distro;
+
+    let now = DateTime::parse_from_rfc3339("2020-06-03T12:00:00Z")
+        .unwrap()
+        .with_timezone(&Utc);
+    let host = |id: &str, user: &str, instance_type: &str, hours: i64| Host {
+        host_id: id.to_owned(),
+        started_by: user.to_owned(),
+        instance_type: instance_type.to_owned(),
+        distro: Distro {
+            distro_id: "ubuntu".to_owned(),
+            ..Default::default()
+        },
+        creation_time: Some(now - Duration::minutes(hours * 60 + 30)),
+        instance_tags: vec![Tag {
+            key: "team".to_owned(),
+            value: "server".to_owned(),
+            can_be_modified: true,
+            extra: Default::default(),
+        }],
+        ..Default::default()
+    };
+    let prices: BTreeMap<String, f64> = vec![("m5.xlarge".to_owned(), 0.2)].into_iter().collect();
+
+    let costs: Vec<HostCost> = [
+        host("h-1", "mark", "m5.xlarge", 10),
+        host("h-2", "mark", "m5.4xlarge", 1),
+        host("h-3", "", "m5.xlarge", 2),
+    ]
+    .iter()
+    .map(|h| host_cost(h, &ReportGroup::User, &prices, now))
+    .collect();
+    assert_eq!(costs[0].uptime_hours, 10.5);
+    assert_eq!(costs[0].cost, Some(2.1));
+    assert_eq!(costs[1].cost, None);
+
+    let stopped = host("h-4", "mark", "m5.xlarge", 10);
+    let end = now - Duration::hours(8);
+    assert_eq!(
+        host_cost(&stopped, &ReportGroup::User, &prices, end).uptime_hours,
+        2.5
+    );
+
+    let spend = summarize_spend(&costs);
+    assert_eq!(spend[0].group, "(none)");
+    assert_eq!(spend[0].cost, 0.5);
+    assert_eq!(
+        spend[1],
+        SpendSummary {
+            group: "mark".to_owned(),
+            hosts: 2,
+            uptime_hours: 12.0,
+            cost: 2.1,
+            unpriced: 1,
+        }
+    );
+
+    let by_team: ReportGroup = "tag:team".parse().unwrap();
+    assert_eq!(by_team.key(&host("h-1", "", "", 0)), "server");
+    assert!("tag:".parse::<ReportGroup>().is_err());
+    assert!("team".parse::<ReportGroup>().is_err());
+}
+
+#[test]
+fn test_reap() {
+    let host = |status: &str, tags: &[(&str, &str)]| Host {
+        host_id: "h-1".to_owned(),
+        status: status.to_owned(),
+        creation_time: Some(Utc::now() - Duration::days(2)),
+        instance_tags: tags
+            .iter()
+            .map(|(k, v)| Tag {
+                key: k.to_string(),
+                value: v.to_string(),
+                can_be_modified: true,
+                extra: Default::default(),
+            })
+            .collect(),
+        ..Default::default()
+    };
+
+    assert!(is_reapable(&host("running", &[]), true));
+    assert!(is_reapable(&host("stopped", &[]), false));
+    assert!(!is_reapable(&host("stopped", &[]), true));
+    assert!(!is_reapable(&host("starting", &[]), false));
+    assert!(!is_reapable(&host("running", &[("keep", "yes")]), false));
+    assert!(is_reapable(&host("running", &[("keep", "false")]), false));
+
+    let event = |timestamp: &str| HostEvent {
+        id: String::new(),
+        timestamp: timestamp.to_owned(),
+        event_type: "HOST_TASK_FINISHED".to_owned(),
+        data: Default::default(),
+        extra: Default::default(),
+    };
+    let h = host("running", &[]);
+    assert_eq!(last_activity(&h, &[]), h.creation_time);
+
+    let status_event = |timestamp: &str, event_type: &str, status: &str| HostEvent {
+        event_type: event_type.to_owned(),
+        data: serde_json::json!({ "new_status": status }),
+        ..event(timestamp)
+    };
+    assert!(is_down("stopping") && is_down("decommissioned") && !is_down("running"));
+    assert_eq!(
+        down_since(&[
+            status_event("2020-06-01T09:00:00Z", "HOST_STATUS_CHANGED", "stopped"),
+            status_event("2020-06-01T10:00:00Z", "HOST_STATUS_CHANGED", "running"),
+            status_event("2020-06-01T11:00:00Z", "HOST_STOPPED", ""),
+            event("2020-06-01T12:00:00Z"),
+        ])
+        .unwrap()
+        .to_rfc3339(),
+        "2020-06-01T11:00:00+00:00"
+    );
+    assert_eq!(down_since(&[event("2020-06-01T12:00:00Z")]), None);
+    assert_eq!(
+        last_activity(
+            &h,
+            &[event("2020-06-01T09:05:00Z"), event("2020-06-01T09:00:00Z")]
+        )
+        .unwrap()
+        .to_rfc3339(),
+        "2020-06-01T09:05:00+00:00"
+    );
+}
+
+#[test]
+fn test_rsync_args() {
+    let mut host = Host {
+        host_id: "h-1".to_owned(),
+        user: "ubuntu".to_owned(),
+        host_url: "h-1.example.com".to_owned(),
+        status: "running".to_owned(),
+        ..Default::default()
+    };
+    assert_eq!(
+        rsync_args(&host, "src/", Some("mongo/src"), true).unwrap()[4..],
+        ["--delete", "--", "src/", "ubuntu@h-1.example.com:mongo/src"]
+    );
+    assert_eq!(
+        rsync_args(&host, "mongo", None, false)
+            .unwrap()
+            .last()
+            .unwrap(),
+        "ubuntu@h-1.example.com:"
+    );
+
+    host.host_url.clear();
+    assert!(rsync_args(&host, "mongo", None, false).is_err());
+    host.host_url = "h-1.example.com".to_owned();
+    host.status = "stopped".to_owned();
+    let err = rsync_args(&host, "mongo", None, false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Cannot sync to h-1, it is stopped, not running"
+    );
+}
+
+#[test]
+fn test_tunnel_args() {
+    assert!(parse_forward("8080:localhost:8080").is_ok());
+    assert!(parse_forward("127.0.0.1:9229:localhost:9229").is_ok());
+    assert!(parse_forward("8080:localhost").is_err());
+    assert!(parse_forward("http:localhost:8080").is_err());
+    assert!(parse_forward("[::1]:8080:[fe80::1%eth0]:80").is_ok());
+    assert!(parse_forward("8080:[::1]:80").is_ok());
+    assert!(parse_forward("8080:[::1:80").is_err());
+    assert!(parse_forward("8080:::1:80").is_err());
+
+    let mut failures = QuickFailures::default();
+    let quick = std::time::Duration::from_secs(1);
+    for _ in 1..TUNNEL_MAX_QUICK_FAILURES {
+        assert!(!failures.record(false, quick));
+    }
+    assert!(!failures.record(false, TUNNEL_QUICK_EXIT));
+    for _ in 1..TUNNEL_MAX_QUICK_FAILURES {
+        assert!(!failures.record(false, quick));
+    }
+    assert!(failures.record(false, quick));
+
+    let host = Host {
+        user: "ubuntu".to_owned(),
+        host_url: "h-1.example.com".to_owned(),
+        ..Default::default()
+    };
+    let args = tunnel_args(&host, &["8080:localhost:8080".to_owned()]);
+    assert_eq!(
+        args[7..],
+        ["-L", "8080:localhost:8080", "ubuntu@h-1.example.com"]
+    );
+}
+
+#[test]
+fn test_check_modify() {
+    let host = Host {
+        host_id: "h-1".to_owned(),
+        status: "running".to_owned(),
+        instance_tags: vec![Tag {
+            key: "owner".to_owned(),
+            value: "mark".to_owned(),
+            can_be_modified: false,
+            extra: Default::default(),
+        }],
+        ..Default::default()
+    };
+    let modify = |add: &[&str], delete: &[&str], instance_type: Option<&str>| HostModify {
+        add_instance_tags: add.iter().map(|t| parse_tag(t).unwrap()).collect(),
+        delete_instance_tags: delete.iter().map(|k| k.to_string()).collect(),
+        instance_type: instance_type.map(str::to_owned),
+        ..Default::default()
+    };
+
+    assert!(check_modify(&host, &modify(&["team=server"], &["temp"], None)).is_ok());
+    assert_eq!(
+        check_modify(&host, &modify(&[], &["owner"], None))
+            .unwrap_err()
+            .to_string(),
+        "The tag 'owner' on h-1 cannot be modified"
+    );
+    assert!(check_modify(&host, &modify(&["owner=me"], &[], None)).is_err());
+    assert!(check_modify(&host, &modify(&[], &[], Some("m5.4xlarge"))).is_err());
+
+    assert_eq!(parse_tag("a=b=c").unwrap().value, "b=c");
+    assert!(parse_tag("=b").is_err());
+    assert!(parse_tag("team").is_err());
+}
+
+#[test]
+fn test_template_names() {
+    use crate::models::Distro;
+
+    let host = |id: &str, distro: &str| Host {
+        host_id: id.to_owned(),
+        distro: Distro {
+            distro_id: distro.to_owned(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let hosts = [host("h-1", "ubuntu"), host("h-2", "rhel")];
+    let template: Template = "{distro.distro_id}-{index}".parse().unwrap();
+    assert_eq!(
+        template_names(&hosts, &template).unwrap(),
+        ["ubuntu-1", "rhel-2"]
+    );
+
+    let empty: Template = "{nope}".parse().unwrap();
+    assert!(template_names(&hosts, &empty).is_err());
+
+    let object: Template = "{distro}-{index}".parse().unwrap();
+    let err = template_names(&hosts, &object).unwrap_err().to_string();
+    assert!(err.contains("e.g. {distro.distro_id}"), "{}", err);
+    let list: Template = "{instance_tags}".parse().unwrap();
+    assert!(template_names(&hosts, &list).is_err());
+}
+
+#[test]
+fn test_rename_targets() {
+    let host = |id: &str, status: &str| Host {
+        host_id: id.to_owned(),
+        status: status.to_owned(),
+        ..Default::default()
+    };
+    let hosts = || {
+        vec![
+            host("h-1", "running"),
+            host("h-2", "terminated"),
+            host("h-3", "stopped"),
+        ]
+    };
+    let ids = |hosts: Vec<Host>| -> Vec<String> { hosts.into_iter().map(|h| h.host_id).collect() };
+    let names = |ids: &[&str]| -> Vec<String> { ids.iter().map(|s| s.to_string()).collect() };
+
+    assert_eq!(ids(rename_targets(hosts(), &[]).unwrap()), ["h-1", "h-3"]);
+    assert_eq!(
+        ids(rename_targets(hosts(), &names(&["h-3", "h-1", "h-3"])).unwrap()),
+        ["h-3", "h-1"]
+    );
+    assert!(rename_targets(hosts(), &names(&["h-4"])).is_err());
+}
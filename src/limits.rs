@@ -0,0 +1,204 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use structopt::StructOpt;
+
+/// Retry, parallelism, rate and timeout settings applied together
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    pub name: &'static str,
+
+    // Extra attempts for requests that fail to connect or get a 429 or 5xx
+    pub retries: u32,
+
+    // Concurrent requests for bulk operations
+    pub parallelism: usize,
+
+    // 0 means unlimited
+    pub requests_per_second: u32,
+
+    pub timeout: Duration,
+}
+
+/// Named presets, small suits a self-hosted deployment and aggressive a large production one
+const LIMIT_PRESETS: &[Limits] = &[
+    Limits {
+        name: "small",
+        retries: 2,
+        parallelism: 1,
+        requests_per_second: 2,
+        timeout: Duration::from_secs(60),
+    },
+    Limits {
+        name: "default",
+        retries: 3,
+        parallelism: 4,
+        requests_per_second: 10,
+        timeout: Duration::from_secs(30),
+    },
+    Limits {
+        name: "aggressive",
+        retries: 5,
+        parallelism: 16,
+        requests_per_second: 0,
+        timeout: Duration::from_secs(15),
+    },
+];
+
+impl Default for Limits {
+    fn default() -> Self {
+        LIMIT_PRESETS[1].clone()
+    }
+}
+
+impl FromStr for Limits {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LIMIT_PRESETS
+            .iter()
+            .find(|l| l.name.eq_ignore_ascii_case(s))
+            .cloned()
+            .ok_or_else(|| {
+                let names: Vec<_> = LIMIT_PRESETS.iter().map(|l| l.name).collect();
+                anyhow!(
+                    "Unknown limits preset '{}', choose from {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Global options that override the limits preset
+#[derive(StructOpt, Debug, Default, Clone)]
+pub struct LimitArgs {
+    /// Retry, parallelism, rate and timeout preset: small, default or aggressive
+    #[structopt(long, global = true)]
+    pub profile_limits: Option<Limits>,
+
+    /// Requests sent at once by commands that act on many objects
+    #[structopt(long, global = true)]
+    pub concurrency: Option<usize>,
+
+    /// Extra attempts for requests that fail with a 429, a 5xx or a dropped connection
+    #[structopt(long, global = true)]
+    pub retries: Option<u32>,
+
+    /// Timeout for each request, e.g. 30s or 2m
+    ///
+    /// Not --timeout, which wait and hosts spawn --wait already take for the whole wait.
+    #[structopt(
+        long,
+        global = true,
+        parse(try_from_str = humantime::parse_duration)
+    )]
+    pub request_timeout: Option<Duration>,
+}
+
+impl LimitArgs {
+    /// The preset named by `--profile-limits` or else `preset`, with the overrides applied
+    ///
+    /// `retries` and `timeout` are the configured overrides, the options take precedence.
+    pub fn resolve(
+        &self,
+        preset: Option<&str>,
+        retries: Option<u32>,
+        timeout: Option<&str>,
+    ) -> Result<Limits> {
+        let mut limits = match (&self.profile_limits, preset) {
+            (Some(limits), _) => limits.clone(),
+            (None, Some(name)) => name.parse()?,
+            (None, None) => Limits::default(),
+        };
+        if let Some(concurrency) = self.concurrency {
+            if concurrency == 0 {
+                return Err(anyhow!("--concurrency must be at least 1"));
+            }
+            limits.parallelism = concurrency;
+        }
+        if let Some(retries) = self.retries.or(retries) {
+            limits.retries = retries;
+        }
+        if let Some(timeout) = self.request_timeout {
+            limits.timeout = timeout;
+        } else if let Some(timeout) = timeout {
+            limits.timeout = humantime::parse_duration(timeout)
+                .map_err(|e| anyhow!("Invalid timeout '{}': {}", timeout, e))?;
+        }
+        Ok(limits)
+    }
+}
+
+/// Scale `delay` by a random factor between one half and one, so that clients that failed
+/// together do not retry together
+fn jitter(delay: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    let fraction = 0.5 + (random % 1000) as f64 / 2000.0;
+    delay.mul_f64(fraction)
+}
+
+impl Limits {
+    /// Delay before retry `attempt`, doubling from half a second with jitter
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        jitter(Duration::from_millis(500 * 2u64.pow(attempt.min(6))))
+    }
+}
+
+#[test]
+fn test_limits_presets() {
+    let small: Limits = "Small".parse().unwrap();
+    assert_eq!(small.parallelism, 1);
+    assert_eq!(Limits::default().name, "default");
+    assert!("huge".parse::<Limits>().is_err());
+    let backoff = small.backoff(2);
+    assert!(backoff >= Duration::from_secs(1) && backoff <= Duration::from_secs(2));
+}
+
+#[test]
+fn test_limit_args() {
+    let none = LimitArgs::default();
+    assert_eq!(none.resolve(None, None, None).unwrap(), Limits::default());
+    assert_eq!(
+        none.resolve(Some("small"), None, None).unwrap().name,
+        "small"
+    );
+    assert!(none.resolve(None, None, Some("soon")).is_err());
+    let zero = LimitArgs {
+        concurrency: Some(0),
+        ..Default::default()
+    };
+    assert!(zero.resolve(None, None, None).is_err());
+
+    let configured = none.resolve(None, Some(7), Some("2m")).unwrap();
+    assert_eq!(configured.retries, 7);
+    assert_eq!(configured.timeout, Duration::from_secs(120));
+
+    let args = LimitArgs {
+        profile_limits: Some("aggressive".parse().unwrap()),
+        concurrency: Some(2),
+        retries: Some(0),
+        request_timeout: Some(Duration::from_secs(5)),
+    };
+    let limits = args.resolve(Some("small"), Some(7), Some("2m")).unwrap();
+    assert_eq!(limits.name, "aggressive");
+    assert_eq!(limits.parallelism, 2);
+    assert_eq!(limits.retries, 0);
+    assert_eq!(limits.timeout, Duration::from_secs(5));
+}
@@ -0,0 +1,185 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+/// Number and date conventions for human readable output
+#[derive(Debug, Clone, PartialEq)]
+pub struct Locale {
+    pub name: &'static str,
+    pub thousands_separator: &'static str,
+    pub decimal_separator: &'static str,
+    pub date_format: &'static str,
+}
+
+const LOCALES: &[Locale] = &[
+    // Values exactly as the API returns them
+    Locale {
+        name: "C",
+        thousands_separator: "",
+        decimal_separator: ".",
+        date_format: "",
+    },
+    Locale {
+        name: "en-US",
+        thousands_separator: ",",
+        decimal_separator: ".",
+        date_format: "%m/%d/%Y %H:%M",
+    },
+    Locale {
+        name: "en-GB",
+        thousands_separator: ",",
+        decimal_separator: ".",
+        date_format: "%d/%m/%Y %H:%M",
+    },
+    Locale {
+        name: "de-DE",
+        thousands_separator: ".",
+        decimal_separator: ",",
+        date_format: "%d.%m.%Y %H:%M",
+    },
+    Locale {
+        name: "fr-FR",
+        thousands_separator: "\u{202f}",
+        decimal_separator: ",",
+        date_format: "%d/%m/%Y %H:%M",
+    },
+    Locale {
+        name: "ISO",
+        thousands_separator: "",
+        decimal_separator: ".",
+        date_format: "%Y-%m-%d %H:%M",
+    },
+];
+
+static DEFAULT_LOCALE: OnceLock<Locale> = OnceLock::new();
+
+impl FromStr for Locale {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Accept en_US style names from LANG too
+        let s = s.split('.').next().unwrap_or(s).replace('_', "-");
+        LOCALES
+            .iter()
+            .find(|l| l.name.eq_ignore_ascii_case(&s))
+            .cloned()
+            .ok_or_else(|| {
+                let names: Vec<_> = LOCALES.iter().map(|l| l.name).collect();
+                anyhow!("Unknown locale '{}', choose from {}", s, names.join(", "))
+            })
+    }
+}
+
+impl Locale {
+    /// The configured locale when `--locale` is not given
+    pub fn default_locale() -> Locale {
+        DEFAULT_LOCALE
+            .get()
+            .cloned()
+            .unwrap_or_else(|| LOCALES[0].clone())
+    }
+
+    /// Set the default from the config file, called once at startup
+    pub fn set_default(locale: Locale) {
+        let _ = DEFAULT_LOCALE.set(locale);
+    }
+
+    fn group_digits(&self, digits: &str) -> String {
+        if self.thousands_separator.is_empty() {
+            return digits.to_owned();
+        }
+
+        let (sign, digits) = match digits.strip_prefix('-') {
+            Some(d) => ("-", d),
+            None => ("", digits),
+        };
+        let mut out = String::new();
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                out += self.thousands_separator;
+            }
+            out.push(c);
+        }
+        format!("{}{}", sign, out)
+    }
+
+    pub fn format_number(&self, n: &serde_json::Number) -> String {
+        let s = n.to_string();
+        match s.split_once('.') {
+            Some((int, frac)) => format!(
+                "{}{}{}",
+                self.group_digits(int),
+                self.decimal_separator,
+                frac
+            ),
+            None => self.group_digits(&s),
+        }
+    }
+
+    pub fn format_date(&self, date: &DateTime<Utc>) -> String {
+        if self.date_format.is_empty() {
+            return date.to_rfc3339();
+        }
+        date.format(self.date_format).to_string()
+    }
+
+    /// Render a scalar for display, numbers and RFC 3339 timestamps follow the locale
+    pub fn format_value(&self, v: &Value) -> String {
+        match v {
+            Value::Null => String::new(),
+            Value::Number(n) => self.format_number(n),
+            Value::String(s) => {
+                if self.date_format.is_empty() {
+                    return s.clone();
+                }
+                match DateTime::parse_from_rfc3339(s) {
+                    Ok(d) => self.format_date(&d.with_timezone(&Utc)),
+                    Err(_) => s.clone(),
+                }
+            }
+            Value::Bool(b) => b.to_string(),
+            Value::Array(_) | Value::Object(_) => v.to_string(),
+        }
+    }
+}
+
+#[test]
+fn test_format_number() {
+    let de: Locale = "de_DE.UTF-8".parse().unwrap();
+    assert_eq!(de.format_value(&serde_json::json!(1234567)), "1.234.567");
+    assert_eq!(de.format_value(&serde_json::json!(-1234.5)), "-1.234,5");
+    assert_eq!(de.format_value(&serde_json::json!(123)), "123");
+
+    let c: Locale = "C".parse().unwrap();
+    assert_eq!(c.format_value(&serde_json::json!(1234567)), "1234567");
+}
+
+#[test]
+fn test_format_date() {
+    let gb: Locale = "en-GB".parse().unwrap();
+    assert_eq!(
+        gb.format_value(&serde_json::json!("2020-05-20T14:03:00.000Z")),
+        "20/05/2020 14:03"
+    );
+    assert_eq!(
+        gb.format_value(&serde_json::json!("not a date")),
+        "not a date"
+    );
+    assert!("xx-YY".parse::<Locale>().is_err());
+}
@@ -0,0 +1,166 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Diagnostics on stderr, chosen with `-v`.
+//!
+//! `-v` logs each request with its status and duration, `-vv` adds the headers and the start of
+//! each body, and `-vvv` also shows the logs of the HTTP libraries. Credentials and scripts are
+//! left out of the headers and request bodies.
+
+use std::borrow::Cow;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use reqwest::header::{HeaderName, HeaderValue};
+
+/// Bytes of a body shown with -vv
+const BODY_LIMIT: usize = 2048;
+
+/// Headers never written to the log
+const SECRET_HEADERS: &[&str] = &[
+    "api-key",
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+
+/// Fields of JSON request bodies never written to the log, spawn scripts may hold secrets
+const SECRET_FIELDS: &[&str] = &["userdata", "setup_script", "password", "token", "api_key"];
+
+struct StderrLogger {
+    // Show the logs of other crates too
+    all_targets: bool,
+}
+
+static OWN_TARGETS: StderrLogger = StderrLogger { all_targets: false };
+static ALL_TARGETS: StderrLogger = StderrLogger { all_targets: true };
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+            && (self.all_targets || metadata.target().starts_with(env!("CARGO_CRATE_NAME")))
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{:<5} {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the logger, `verbose` is the number of times -v was given
+pub fn init(verbose: u8) {
+    let level = match verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let logger = match level {
+        LevelFilter::Trace => &ALL_TARGETS,
+        _ => &OWN_TARGETS,
+    };
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Whether -vv was given
+pub fn show_details() -> bool {
+    log::log_enabled!(Level::Debug)
+}
+
+/// A header value for the log, with credentials replaced
+pub fn redact<'a>(name: &HeaderName, value: &'a HeaderValue) -> Cow<'a, str> {
+    if value.is_sensitive() || SECRET_HEADERS.contains(&name.as_str()) {
+        return Cow::Borrowed("<redacted>");
+    }
+    String::from_utf8_lossy(value.as_bytes())
+}
+
+/// The start of a body for the log
+pub fn truncate(body: &[u8]) -> Cow<'_, str> {
+    if body.len() <= BODY_LIMIT {
+        return String::from_utf8_lossy(body);
+    }
+    let start = String::from_utf8_lossy(&body[..BODY_LIMIT]);
+    Cow::Owned(format!("{}... ({} bytes)", start, body.len()))
+}
+
+/// The start of a request body for the log, with the values of secret fields replaced
+pub fn redact_body(body: &[u8]) -> Cow<'_, str> {
+    fn redact_fields(value: &mut serde_json::Value) -> bool {
+        let mut redacted = false;
+        match value {
+            serde_json::Value::Object(map) => {
+                for (k, v) in map.iter_mut() {
+                    if SECRET_FIELDS.contains(&k.as_str()) && !v.is_null() {
+                        *v = serde_json::Value::from("<redacted>");
+                        redacted = true;
+                    } else {
+                        redacted |= redact_fields(v);
+                    }
+                }
+            }
+            serde_json::Value::Array(values) => {
+                for v in values {
+                    redacted |= redact_fields(v);
+                }
+            }
+            _ => {}
+        }
+        redacted
+    }
+
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(mut value) => {
+            if !redact_fields(&mut value) {
+                return truncate(body);
+            }
+            Cow::Owned(truncate(value.to_string().as_bytes()).into_owned())
+        }
+        Err(_) => truncate(body),
+    }
+}
+
+#[test]
+fn test_redact() {
+    let name = HeaderName::from_static("api-key");
+    assert_eq!(
+        redact(&name, &HeaderValue::from_static("abc")),
+        "<redacted>"
+    );
+
+    let name = HeaderName::from_static("x-signature");
+    let mut value = HeaderValue::from_static("abc");
+    assert_eq!(redact(&name, &value), "abc");
+    value.set_sensitive(true);
+    assert_eq!(redact(&name, &value), "<redacted>");
+
+    assert_eq!(truncate(b"[]"), "[]");
+    let long = vec![b'a'; BODY_LIMIT + 10];
+    assert!(truncate(&long).ends_with(&format!("... ({} bytes)", BODY_LIMIT + 10)));
+
+    assert_eq!(
+        redact_body(br#"{"distro":"ubuntu","userdata":"export TOKEN=abc","setup_script":null}"#),
+        r#"{"distro":"ubuntu","setup_script":null,"userdata":"<redacted>"}"#
+    );
+    assert_eq!(
+        redact_body(b"[{\"token\":\"abc\"}]"),
+        r#"[{"token":"<redacted>"}]"#
+    );
+    assert_eq!(redact_body(b"not json"), "not json");
+}
@@ -12,287 +12,2293 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use reqwest::header;
-use reqwest::Url;
-use std::fmt::Write;
-use std::fs::File;
-use std::str::FromStr;
+use std::collections::BTreeSet;
 use std::string::String;
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
-use json::JsonValue;
-use regex::Regex;
-
 use log::info;
 
-use evergreen_rs_derive::EvgFields;
-
 #[macro_use]
 extern crate anyhow;
 
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-struct EvergreenConfig {
-    // A full URL
-    // http://evergreen-api.mongodb.com:8080/api
-    api_server_host: String,
-
-    // A full URL
-    // https://evergreen.mongodb.com
-    ui_server_host: String,
-    api_key: String,
-    user: String,
-}
-
-fn get_hosts_url(config: &EvergreenConfig, user: &str) -> Url {
-    Url::parse(&format!(
-        "{}/rest/v2/users/{}/hosts",
-        config.api_server_host, user
-    ))
-    .unwrap()
-}
-
-#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields)]
-struct Distro {
-    distro_id: String,
-    provider: String,
-    image_id: String,
-}
-
-#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields)]
-struct Tag {
-    key: String,
-    value: String,
-    can_be_modified: bool,
-}
-
-#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields)]
-struct Host {
-    host_id: String,
-    host_url: String,
-    distro: Distro,
-    provisioned: bool,
-    started_by: String,
-    host_type: String,
-    user: String,
-    status: String,
-    // running_task: {
-    //   task_id: null,
-    //   name: null,
-    //   dispatch_time: null,
-    //   version_id: null,
-    //   build_id: null
-    // },
-    user_host: bool,
-    no_expiration: bool,
-    instance_tags: Vec<Tag>,
-    instance_type: String,
-    zone: String,
-    display_name: String,
-    home_volume_id: String,
-}
-
-struct EvergreenClient {
-    config: EvergreenConfig,
-
-    client: reqwest::blocking::Client,
-}
-
-impl EvergreenClient {
-    fn new_from_home() -> Result<EvergreenClient> {
-        let home_dir_opt = dirs::home_dir();
-        if home_dir_opt.is_none() {
-            eprintln!("Must set an home directory");
-            return Err(anyhow!("Could not find the user home directory"));
-        }
-        let evg_file = home_dir_opt.unwrap().to_str().unwrap().to_owned();
-        let filename = evg_file + "/.evergreen.yml";
-        let file = File::open(filename)?;
-
-        let config: EvergreenConfig = serde_yaml::from_reader(file)?;
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            "Api-User",
-            header::HeaderValue::from_str(&config.user).expect("Bad Api-User"),
-        );
-        headers.insert(
-            "Api-Key",
-            header::HeaderValue::from_str(&config.api_key).expect("Bad Api-Key"),
-        );
-
-        let client = reqwest::blocking::Client::builder()
-            .default_headers(headers)
-            .build()?;
-
-        Ok(EvergreenClient {
-            config: config,
-            client: client,
-        })
-    }
 
-    fn get_hosts(&self, user: Option<&str>) -> Result<Vec<Host>> {
-        let url = get_hosts_url(&self.config, user.unwrap_or(&self.config.user));
-        let resp = self.client.get(url).send()?.text()?;
+mod args;
+mod auth;
+mod browser;
+mod cache;
+mod client;
+mod color;
+mod config;
+mod context;
+mod distros;
+mod error;
+mod evg_config;
+mod follow;
+mod graph;
+mod hosts;
+mod limits;
+mod locale;
+mod logging;
+mod middleware;
+mod models;
+mod notify;
+mod output;
+mod patches;
+mod pool;
+mod prompt;
+mod proxy;
+mod queries;
+mod relative;
+mod resource;
+mod selector;
+mod snapshot;
+mod tasks;
+mod template;
+mod tui;
+mod ui;
+mod ui_fallback;
+mod validate;
+mod vcr;
 
-        let v: Vec<Host> = serde_json::from_str(&resp)?;
-        Ok(v)
-    }
+use args::{IdArgs, PageArgs, TimeArgs, TimeBound};
+use client::EvergreenClient;
+use error::EvergreenError;
+use models::{
+    AnnotationNote, Banner, HostModify, IssueLink, PermissionsQuery, ServiceFlags, SpawnRequest,
+    StatsQuery, Subscriber, Subscription, SubscriptionSelector, TaskAnnotation, TaskUpdate,
+};
+use output::{print_record, print_records, print_value, OutputArgs, OutputSink};
+use selector::Selector;
+use serde::Serialize;
+
+use evergreen_rs_derive::{EvgFields, EvgValues};
+
+#[derive(StructOpt, Debug)]
+/// Command line client for Evergreen
+struct Cli {
+    /// Log requests, give twice to also log headers and bodies
+    #[structopt(short, long, global = true, parse(from_occurrences))]
+    verbose: u8,
+
+    /// Read values the REST API does not expose from unsupported UI endpoints
+    #[structopt(long, global = true)]
+    allow_ui_fallback: bool,
+
+    #[structopt(flatten)]
+    limits: limits::LimitArgs,
+
+    /// Always fetch full responses instead of revalidating cached ones
+    #[structopt(long, global = true)]
+    no_cache: bool,
+
+    /// Show the responses cached by earlier commands without contacting the server
+    #[structopt(long, global = true, conflicts_with = "no-cache")]
+    offline: bool,
+
+    /// Save every response as a fixture file in this directory
+    #[structopt(long, global = true, parse(from_os_str))]
+    record: Option<std::path::PathBuf>,
+
+    /// Answer requests from the fixture files in this directory instead of the server
+    #[structopt(
+        long,
+        global = true,
+        parse(from_os_str),
+        conflicts_with_all = &["record", "offline"]
+    )]
+    replay: Option<std::path::PathBuf>,
+
+    /// Print the method, URL and body of each request that would change anything instead of
+    /// sending it
+    #[structopt(long, global = true)]
+    dry_run: bool,
+
+    /// Skip records that cannot be read, with a warning, instead of failing the whole list
+    #[structopt(long, global = true)]
+    lenient: bool,
+
+    /// Go ahead with destructive operations without asking
+    #[structopt(short, long, global = true)]
+    yes: bool,
+
+    #[structopt(flatten)]
+    config: config::ConfigArgs,
+
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Spawn host commands
+    Hosts(HostsCommand),
+
+    /// Task commands
+    Tasks(TasksCommand),
+
+    /// Task annotation commands
+    Annotations(AnnotationsCommand),
+
+    /// User commands
+    Users(UsersCommand),
+
+    /// Notification subscription commands
+    Notifications(NotificationsCommand),
+
+    /// Distro commands
+    Distros(DistrosCommand),
+
+    /// Version commands
+    Versions(VersionsCommand),
+
+    /// Patch commands
+    Patches(PatchesCommand),
+
+    /// Project configuration file commands, read locally
+    Project(ProjectCommand),
+
+    /// Write the task dependency graph of a project's latest version, a version or a project
+    /// configuration file
+    Graph {
+        /// Project ID, its most recent mainline version is used
+        #[structopt(long, required_unless_one = &["version-id", "file"], conflicts_with_all = &["version-id", "file"])]
+        project: Option<String>,
+
+        #[structopt(long = "version", conflicts_with = "file")]
+        version_id: Option<String>,
+
+        /// Project configuration file, read locally
+        #[structopt(long)]
+        file: Option<std::path::PathBuf>,
+
+        /// Only this task, the tasks it waits on and the tasks waiting on it
+        #[structopt(long)]
+        task: Option<String>,
+
+        /// dot or mermaid
+        #[structopt(long, default_value = "dot")]
+        format: graph::GraphFormat,
+    },
+
+    /// Project task and test statistics
+    Stats(StatsCommand),
+
+    /// Dump hosts, volumes, keys, recent patches and subscriptions to timestamped JSON files
+    Snapshot {
+        /// Directory to write the snapshot to
+        #[structopt(long, parse(from_os_str))]
+        out: Option<std::path::PathBuf>,
+
+        /// Number of recent patches to include
+        #[structopt(long, default_value = "50")]
+        patches: u32,
+
+        #[structopt(subcommand)]
+        cmd: Option<SnapshotCommand>,
+    },
+
+    /// Print each status change of a task or version until it finishes
+    Follow {
+        #[structopt(subcommand)]
+        target: FollowCommand,
+
+        /// Time between polls, or between fallback polls with --callback-url
+        #[structopt(long, default_value = "10s", parse(try_from_str = humantime::parse_duration))]
+        interval: std::time::Duration,
+
+        /// Have Evergreen post the outcome to this URL instead of relying on polling
+        #[structopt(long)]
+        callback_url: Option<String>,
+
+        /// Address to listen on for --callback-url posts, e.g. 0.0.0.0:8088 to accept posts from
+        /// other hosts rather than through a local proxy or tunnel
+        #[structopt(long, default_value = "127.0.0.1:8088")]
+        listen: String,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Wait for a patch or version to finish, print its failed tasks and exit non-zero if any
+    Wait {
+        #[structopt(subcommand)]
+        target: WaitCommand,
+
+        /// Time between polls
+        #[structopt(long, default_value = "30s", parse(try_from_str = humantime::parse_duration))]
+        interval: std::time::Duration,
+
+        /// Give up after this long
+        #[structopt(long, parse(try_from_str = humantime::parse_duration))]
+        timeout: Option<std::time::Duration>,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Print a one line status for shell prompts, served from a cache
+    Prompt {
+        /// Refresh the cache in the background once it is older than this
+        #[structopt(long, default_value = "60s", parse(try_from_str = humantime::parse_duration))]
+        ttl: std::time::Duration,
+
+        /// Hosts expiring within this window are counted as expiring
+        #[structopt(long, default_value = "24h", parse(try_from_str = humantime::parse_duration))]
+        expiring_within: std::time::Duration,
+
+        /// Fetch the status and update the cache, used by the background refresh
+        #[structopt(long)]
+        refresh: bool,
+    },
+
+    /// Report whether Evergreen is degraded, exits with 2 during an outage
+    Status {
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Set defaults for later commands in this shell or directory, or show them
+    Use {
+        #[structopt(subcommand)]
+        target: Option<UseCommand>,
+
+        /// Forget every default
+        #[structopt(long, conflicts_with = "target")]
+        clear: bool,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Interactive dashboard of spawn hosts, recent patches and failed tasks
+    Tui,
+
+    /// Show a host, task, patch, version or build given its ID or a UI or REST URL
+    Get {
+        id: String,
+
+        /// Resource type when the ID is ambiguous, host|task|patch|version|build
+        #[structopt(long = "type")]
+        kind: Option<resource::ResourceKind>,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Configuration file commands
+    Config(ConfigCommand),
+
+    /// Response cache commands
+    Cache(CacheCommand),
+
+    /// List the fields of a host, task, patch, version or build for --fields, --sort, etc.
+    Describe {
+        kind: resource::ResourceKind,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Open a host, task, patch, version or build in the browser
+    Open {
+        kind: resource::ResourceKind,
+
+        id: String,
+
+        /// Print the URL instead of opening it
+        #[structopt(long)]
+        print_url: bool,
+    },
+
+    /// Run a query saved in the queries section of the configuration, arguments are appended
+    #[structopt(settings = &[AppSettings::TrailingVarArg, AppSettings::AllowLeadingHyphen])]
+    Run {
+        name: String,
+
+        /// Arguments added to the saved command line
+        #[structopt(allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Check a project configuration, errors and warnings are printed with their line
+    Validate {
+        path: std::path::PathBuf,
+
+        /// Only check the file locally, without sending it to the server
+        #[structopt(long)]
+        local: bool,
+
+        /// Leave warnings out
+        #[structopt(long)]
+        quiet: bool,
+    },
 }
 
-#[derive(Debug)]
-enum OutputType {
-    Flat,
-    Json,
+#[derive(StructOpt, Debug)]
+enum ConfigCommand {
+    /// Ask for the server and credentials, check them and write the configuration file
+    Init {
+        /// Replace an existing configuration file
+        #[structopt(long)]
+        force: bool,
+
+        /// Store the API key in the OS keychain instead of the file
+        #[structopt(long)]
+        keyring: bool,
+    },
 }
 
-impl FromStr for OutputType {
-    type Err = anyhow::Error;
-    fn from_str(day: &str) -> Result<Self, Self::Err> {
-        match day {
-            "json" => Ok(OutputType::Json),
-            "flat" => Ok(OutputType::Flat),
-            _ => Err(anyhow!("Could not parse a on output type")),
-        }
-    }
+#[derive(StructOpt, Debug)]
+enum CacheCommand {
+    /// Remove every cached response
+    Clear,
+
+    /// Remove old cached responses, then the oldest until the cache fits in --max-size-mb
+    Clean {
+        /// Remove responses fetched longer ago than this
+        #[structopt(long, default_value = "7d", parse(try_from_str = humantime::parse_duration))]
+        older_than: std::time::Duration,
+
+        /// Largest size of the cache in MiB
+        #[structopt(long, default_value = "100")]
+        max_size_mb: u64,
+    },
 }
 
 #[derive(StructOpt, Debug)]
-/// Dumps spawn hosts from evergreen
-struct Cli {
-    #[structopt(
-        short = "o",
-        long = "output",
-        default_value = "flat",
-        case_insensitive = true
-    )]
-    output: OutputType,
+enum HostsCommand {
+    /// Dumps spawn hosts from evergreen
+    List(HostsList),
+
+    /// Spawn a host
+    Spawn {
+        /// Distro to spawn, e.g. ubuntu1804-workstation
+        #[structopt(long)]
+        distro: String,
+
+        /// Name of the public key to install, defaults to your only key
+        #[structopt(long)]
+        key: Option<String>,
+
+        #[structopt(long)]
+        region: Option<String>,
+
+        #[structopt(long)]
+        instance_type: Option<String>,
+
+        /// Script Evergreen runs on the host once it is provisioned
+        #[structopt(long, parse(from_os_str))]
+        setup_script: Option<std::path::PathBuf>,
+
+        /// User data, e.g. a cloud-init config, the host runs as it boots
+        #[structopt(long, parse(from_os_str))]
+        userdata: Option<std::path::PathBuf>,
+
+        /// Wait until the host is provisioned and accepts ssh connections
+        #[structopt(long)]
+        wait: bool,
+
+        /// Time between polls with --wait
+        #[structopt(long, default_value = "10s", parse(try_from_str = humantime::parse_duration))]
+        interval: std::time::Duration,
+
+        /// Give up waiting after this long
+        #[structopt(long, default_value = "30m", parse(try_from_str = humantime::parse_duration))]
+        timeout: std::time::Duration,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Terminate spawn hosts
+    Terminate(Selector),
+
+    /// Terminate, or stop, spawn hosts that have been idle for a while, hosts with a keep
+    /// instance tag are left alone
+    Reap {
+        /// Hours since the host's last event, or its creation, for it to count as idle
+        #[structopt(long)]
+        idle_hours: u32,
+
+        /// Stop the idle hosts instead of terminating them
+        #[structopt(long)]
+        stop: bool,
+
+        #[structopt(flatten)]
+        selector: Selector,
+    },
+
+    /// Change the instance type or the instance tags of a host
+    Modify {
+        host_id: String,
+
+        /// New instance type, the host must be stopped
+        #[structopt(long)]
+        instance_type: Option<String>,
+
+        /// Add or replace an instance tag, key=value, may be repeated
+        #[structopt(long = "add-tag", number_of_values = 1, parse(try_from_str = hosts::parse_tag))]
+        add_tags: Vec<models::Tag>,
+
+        /// Remove the instance tag with this key, may be repeated
+        #[structopt(long = "remove-tag", number_of_values = 1)]
+        remove_tags: Vec<String>,
+    },
+
+    /// Set the display name of a host, or name several hosts from a template
+    Rename {
+        /// Host ID and its new name, or with --from-template the hosts to name, all of yours if
+        /// none are given
+        args: Vec<String>,
+
+        /// Name each host from its fields, e.g. '{distro.distro_id}-{index}', index counts from 1
+        #[structopt(long)]
+        from_template: Option<template::Template>,
+    },
+
+    /// Extend the expiration of spawn hosts
+    Extend {
+        /// Hours to add
+        #[structopt(long)]
+        hours: u32,
+
+        #[structopt(flatten)]
+        selector: Selector,
+    },
+
+    /// Run a command over ssh on spawn hosts
+    Exec {
+        #[structopt(flatten)]
+        selector: Selector,
+
+        /// Command to run, after `--`
+        #[structopt(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// List hosts by expiration, exits with 2 if any expire within the window
+    Expiring {
+        /// Window to check, e.g. 48h or 3d
+        #[structopt(long, default_value = "48h", parse(try_from_str = humantime::parse_duration))]
+        within: std::time::Duration,
+    },
+
+    /// Estimate the spend on hosts from their uptime and the instance_prices in the configuration
+    Report {
+        /// User whose hosts to include, may be repeated, defaults to yourself
+        #[structopt(long = "user", number_of_values = 1)]
+        users: Vec<String>,
+
+        /// Add up spend by user, instance_type, distro or tag:<key>, e.g. tag:team
+        #[structopt(long, default_value = "user")]
+        by: hosts::ReportGroup,
+
+        /// Show the uptime and cost of each host instead of the totals
+        #[structopt(long)]
+        per_host: bool,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Show the provisioning, status change and termination history of a host
+    Events {
+        host_id: String,
+
+        #[structopt(flatten)]
+        page: PageArgs,
+
+        #[structopt(flatten)]
+        window: TimeArgs,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Copy a local file or directory to a host with rsync over ssh
+    Sync {
+        host_id: String,
+
+        local_path: String,
+
+        /// Defaults to the home directory
+        remote_path: Option<String>,
+
+        /// Remove remote files that no longer exist locally
+        #[structopt(long)]
+        delete: bool,
+
+        /// Keep syncing every interval, 2s if no interval is given
+        #[structopt(long)]
+        watch: Option<Option<humantime::Duration>>,
+    },
+
+    /// Forward local ports to a host over ssh, reconnecting when the connection drops
+    Tunnel {
+        host_id: String,
+
+        /// Forward [bind_address:]port:host:hostport like ssh -L, may be repeated
+        #[structopt(short = "L", number_of_values = 1, required = true, parse(try_from_str = hosts::parse_forward))]
+        forwards: Vec<String>,
+
+        /// Time to wait before reconnecting
+        #[structopt(long, default_value = "5s", parse(try_from_str = humantime::parse_duration))]
+        reconnect_delay: std::time::Duration,
+    },
+
+    /// Collect OS, kernel, CPU and memory facts over ssh and save them as auto: instance tags
+    Fingerprint {
+        host_id: String,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+struct HostsList {
+    #[structopt(flatten)]
+    output: OutputArgs,
 
     // Display only the URL
     #[structopt(long)]
     url: bool,
 
-    // List of entries for hosts to display matching a regex
-    #[structopt(short, long)]
-    filter: Option<String>,
+    /// Display only the link to each host's page in the UI
+    #[structopt(long, conflicts_with = "url")]
+    print_url: bool,
+
+    /// Only show hosts that are executing a task
+    #[structopt(long)]
+    busy: bool,
+
+    /// Only show hosts that are starting, running, stopped, failed or terminated, or in an exact
+    /// host status, may be repeated
+    #[structopt(long, number_of_values = 1)]
+    status: Vec<String>,
+
+    #[structopt(flatten)]
+    selector: Selector,
+
+    #[structopt(flatten)]
+    page: PageArgs,
+}
+
+// Subcommands are parsed once per run, so the size of variants with output arguments does not
+// matter
+#[derive(StructOpt, Debug)]
+#[allow(clippy::large_enum_variant)]
+enum TasksCommand {
+    /// Restart tasks, upstream dependencies are restarted before the tasks that depend on them
+    Restart(IdArgs),
+
+    /// Restart the failed tasks of a version and report what was restarted
+    RestartFailed {
+        /// Version (or patch version) to restart the failed tasks of
+        #[structopt(long = "version")]
+        version_id: String,
+
+        /// Skip tasks without a failing test, e.g. system and setup failures
+        #[structopt(long)]
+        only_test_failures: bool,
+    },
+
+    /// Schedule (activate) tasks
+    Schedule(IdArgs),
+
+    /// Set the priority of tasks
+    Priority {
+        /// New priority, -1 disables the tasks
+        #[structopt(allow_hyphen_values = true)]
+        priority: i64,
+
+        #[structopt(flatten)]
+        ids: IdArgs,
+    },
+
+    /// Show the position of a task in its distro's queue, requires --allow-ui-fallback
+    QueuePosition {
+        task_id: String,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+#[allow(clippy::large_enum_variant)]
+enum AnnotationsCommand {
+    /// Show the annotations of a task
+    Get {
+        task_id: String,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Add a note and issues to the annotations of tasks
+    Add {
+        /// Note to attach to the task
+        #[structopt(long)]
+        note: Option<String>,
+
+        /// Issue key (e.g. KEY-123) or URL, may be repeated
+        #[structopt(long = "issue")]
+        issues: Vec<String>,
+
+        #[structopt(flatten)]
+        ids: IdArgs,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum UsersCommand {
+    /// Show the permissions of a user, or of every user on a project
+    Permissions {
+        /// User to query, defaults to the configured user
+        user: Option<String>,
+
+        /// List the permissions every user has on this project instead
+        #[structopt(long, conflicts_with = "user")]
+        project: Option<String>,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum SnapshotCommand {
+    /// Recreate keys and subscriptions from the newest files in a snapshot directory
+    Apply {
+        #[structopt(parse(from_os_str))]
+        dir: std::path::PathBuf,
+
+        /// Only apply these resources, comma separated
+        #[structopt(long, use_delimiter = true)]
+        only: Vec<String>,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum FollowCommand {
+    Task {
+        task_id: String,
+    },
+    Version {
+        /// Defaults to the version set with `use version`
+        version_id: Option<String>,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum WaitCommand {
+    Patch {
+        patch_id: String,
+    },
+    Version {
+        /// Defaults to the version set with `use version`
+        version_id: Option<String>,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum UseCommand {
+    /// Default project for commands that take --project
+    Project { project: String },
+
+    /// Default version for commands that take a version ID
+    Version { version_id: String },
+}
+
+#[derive(StructOpt, Debug)]
+enum DistrosCommand {
+    /// Show the depth of a distro's task queue and the expected wait
+    Queue {
+        distro_id: String,
+
+        /// Report the position of and the wait for this task
+        #[structopt(long)]
+        task: Option<String>,
+
+        /// Hosts draining the queue, looked up from the distro's host stats by default
+        #[structopt(long)]
+        hosts: Option<i64>,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum VersionsCommand {
+    /// Show the module revisions a version was built with
+    Manifest {
+        /// Defaults to the version set with `use version`
+        version_id: Option<String>,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum PatchesCommand {
+    /// Summarize a patch's tasks by build variant, with links to the failed tasks
+    Status {
+        patch_id: String,
+
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum ProjectCommand {
+    /// List the tasks a configuration defines, or the ones a build variant runs
+    Tasks {
+        path: std::path::PathBuf,
+
+        #[structopt(long)]
+        variant: Option<String>,
+    },
+
+    /// List the build variants of a configuration, or the ones that run a task
+    Variants {
+        path: std::path::PathBuf,
+
+        #[structopt(long)]
+        task: Option<String>,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+#[allow(clippy::large_enum_variant)]
+enum NotificationsCommand {
+    /// List your subscriptions
+    List {
+        #[structopt(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Subscribe to a version, patch, build or task
+    Add {
+        /// Resource type, version|patch|build|task
+        #[structopt(long = "resource-type", default_value = "version")]
+        resource_type: String,
+
+        /// ID of the version, patch, build or task
+        id: String,
+
+        /// When to notify, e.g. outcome, success or failure
+        #[structopt(long, default_value = "outcome")]
+        trigger: String,
+
+        /// How to notify, email|slack|jira-comment|evergreen-webhook
+        #[structopt(long = "subscriber-type", default_value = "email")]
+        subscriber_type: String,
+
+        /// Email address, slack channel or user, etc.
+        #[structopt(long)]
+        target: String,
+    },
+
+    /// Delete subscriptions
+    Delete(IdArgs),
+}
+
+#[derive(StructOpt, Debug)]
+enum StatsCommand {
+    /// Pass/fail counts and durations of tasks
+    Tasks(StatsArgs),
+
+    /// Pass/fail counts and durations of tests
+    Tests(StatsArgs),
+}
+
+#[derive(StructOpt, Debug)]
+struct StatsArgs {
+    /// Project identifier, defaults to the project set with `use project`
+    #[structopt(long)]
+    project: Option<String>,
+
+    /// Start of the window, YYYY-MM-DD, or use --since
+    #[structopt(long = "after-date", conflicts_with = "since")]
+    after_date: Option<String>,
+
+    /// End of the window, YYYY-MM-DD, defaults to the end of today
+    #[structopt(long = "before-date", conflicts_with = "before")]
+    before_date: Option<String>,
+
+    #[structopt(flatten)]
+    window: TimeArgs,
+
+    /// Grouping, task|task_variant|task_variant_distro for tasks or
+    /// test|test_task|test_task_variant|test_task_variant_distro for tests
+    #[structopt(long = "group-by")]
+    group_by: Option<String>,
+
+    /// Number of days to aggregate into each result
+    #[structopt(long = "group-num-days")]
+    group_num_days: Option<u32>,
+
+    /// Only include these tasks, comma separated
+    #[structopt(long)]
+    tasks: Option<String>,
+
+    /// Only include these tests, comma separated
+    #[structopt(long)]
+    tests: Option<String>,
+
+    /// Only include these build variants, comma separated
+    #[structopt(long)]
+    variants: Option<String>,
+
+    #[structopt(long)]
+    limit: Option<u32>,
+
+    #[structopt(flatten)]
+    output: OutputArgs,
 }
 
-fn to_flat_json_int(v: &JsonValue, prefix: &str, writer: &mut dyn Write) -> Result<()> {
-    match v {
-        JsonValue::Null => {
-            write!(writer, "{}:null\n", prefix)?;
+impl StatsArgs {
+    /// The query, --since and --before are sent as the dates they fall on
+    fn to_query(&self) -> Result<StatsQuery> {
+        let date = |bound: &TimeBound| bound.0.format("%Y-%m-%d").to_string();
+        let after_date = match (&self.after_date, &self.window.since) {
+            (Some(after), _) => after.clone(),
+            (None, Some(since)) => date(since),
+            (None, None) => return Err(anyhow!("Pass --after-date or --since")),
+        };
+        let before_date = match (&self.before_date, &self.window.before) {
+            (Some(before), _) => before.clone(),
+            (None, Some(before)) => date(before),
+            (None, None) => date(&TimeBound(chrono::Utc::now() + chrono::Duration::days(1))),
+        };
+
+        Ok(StatsQuery {
+            after_date,
+            before_date,
+            group_by: self.group_by.clone(),
+            group_num_days: self.group_num_days,
+            tasks: self.tasks.clone(),
+            tests: self.tests.clone(),
+            variants: self.variants.clone(),
+            limit: self.limit,
+        })
+    }
+}
+
+/// Run an operation for every ID, reporting failures and continuing with the rest
+fn for_each_id<F>(ids: &[String], parallelism: usize, verb: &str, f: F) -> Result<()>
+where
+    F: Fn(&str) -> Result<()> + Sync,
+{
+    let failed = pool::map_bounded(ids, parallelism, |id| match f(id) {
+        Ok(()) => {
+            println!("{} {}", verb, id);
+            false
+        }
+        Err(e) => {
+            eprintln!("Failed on {}: {}", id, e);
+            true
+        }
+    });
+
+    let failures = failed.into_iter().filter(|f| *f).count();
+    if failures > 0 {
+        return Err(anyhow!("{} of {} operations failed", failures, ids.len()));
+    }
+    Ok(())
+}
+
+/// Turn an issue key or URL into a link, keys are resolved against the configured issue tracker
+fn make_issue_link(issue: &str, tracker_url: Option<&str>) -> Result<IssueLink> {
+    if issue.starts_with("http://") || issue.starts_with("https://") {
+        let key = issue
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(issue);
+        return Ok(IssueLink {
+            url: issue.to_owned(),
+            issue_key: key.to_owned(),
+            source: None,
+            extra: Default::default(),
+        });
+    }
+
+    match tracker_url {
+        Some(base) => Ok(IssueLink {
+            url: format!("{}/{}", base.trim_end_matches('/'), issue),
+            issue_key: issue.to_owned(),
+            source: None,
+            extra: Default::default(),
+        }),
+        None => Err(anyhow!(
+            "Issue '{}' is not a URL, set issue_tracker_url in the config to use issue keys",
+            issue
+        )),
+    }
+}
+
+/// Write a configuration file from the user's answers once a request with them succeeds
+fn init_config(args: &config::ConfigArgs, force: bool, keyring: bool) -> Result<()> {
+    let path = args
+        .init_file()
+        .ok_or_else(|| anyhow!("Could not find the home directory, pass --config"))?;
+    if path.exists() && !force {
+        return Err(anyhow!(
+            "{} already exists, pass --force to replace it",
+            path.display()
+        ));
+    }
+
+    let client = EvergreenClient::new(config::prompt_config(args)?, &limits::LimitArgs::default())?;
+    client.get_user(&client.config.user)?;
+
+    let mut config = client.config;
+    if keyring {
+        config::store_keyring(&config.user, &config.api_key)?;
+        config.api_key = config::KEYRING_PREFIX.to_owned();
+    }
+    config.write(&path)?;
+    eprintln!("Wrote {}", path.display());
+    Ok(())
+}
+
+#[derive(Debug, Serialize, EvgFields, EvgValues)]
+struct FieldDescription {
+    path: String,
+    rust_type: &'static str,
+    collection: bool,
+    optional: bool,
+}
+
+fn describe(kind: resource::ResourceKind) -> Vec<FieldDescription> {
+    let mut fields = Vec::new();
+    evergreen_rs_types::describe_fields(kind.fields(), "", &mut fields);
+    fields
+        .into_iter()
+        .map(|(path, info)| FieldDescription {
+            path,
+            rust_type: info.rust_type,
+            collection: info.is_collection,
+            optional: info.is_optional,
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize, EvgFields, EvgValues)]
+struct ServiceStatus {
+    banner: Banner,
+    // None when the service flags are not visible to this user
+    disabled_services: Option<Vec<String>>,
+    degraded: bool,
+    outage: bool,
+}
+
+/// Exit code for `status` when Evergreen reports an outage
+const OUTAGE_EXIT_CODE: i32 = 2;
+
+/// Exit code for `wait` when tasks failed
+const WAIT_FAILED_EXIT_CODE: i32 = 1;
+
+/// Exit code for `wait` when --timeout passes first, matching timeout(1)
+const WAIT_TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Exit code for `hosts expiring` when a host expires within the window
+const EXPIRING_EXIT_CODE: i32 = 2;
+
+impl ServiceStatus {
+    fn new(banner: Banner, flags: Option<ServiceFlags>) -> ServiceStatus {
+        let disabled_services = flags.map(|flags| {
+            flags
+                .into_iter()
+                .filter(|(_, disabled)| *disabled)
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        });
+
+        let outage = banner.theme == "important";
+        let degraded = outage
+            || banner.theme == "warning"
+            || disabled_services.as_ref().is_some_and(|d| !d.is_empty());
+
+        ServiceStatus {
+            banner,
+            disabled_services,
+            degraded,
+            outage,
         }
-        JsonValue::Short(s) => {
-            write!(writer, "{}:{}\n", prefix, s)?;
+    }
+}
+
+fn get_status(client: &EvergreenClient, allow_ui_fallback: bool) -> Result<ServiceStatus> {
+    let banner = match client.get_banner() {
+        Ok(banner) => banner,
+        Err(e) if allow_ui_fallback => {
+            info!("Could not get banner from REST: {}", e);
+            ui_fallback::get_banner(client)?
         }
-        JsonValue::String(s) => {
-            write!(writer, "{}:{}\n", prefix, s)?;
+        Err(e) => return Err(e),
+    };
+
+    // Service flags are often restricted to admins, treat them as best effort
+    let flags = match client.get_service_flags() {
+        Ok(flags) => Some(flags),
+        Err(e) => {
+            info!("Could not get service flags: {}", e);
+            None
         }
-        JsonValue::Number(n) => {
-            write!(writer, "{}:{}\n", prefix, n)?;
+    };
+
+    Ok(ServiceStatus::new(banner, flags))
+}
+
+fn list_hosts(client: &EvergreenClient, args: &HostsList) -> Result<()> {
+    let statuses = hosts::expand_statuses(&args.status);
+    // The server filters by a single status, more are filtered here
+    let server_status = match statuses.as_slice() {
+        [status] => Some(status.as_str()),
+        _ => None,
+    };
+    let keep = |h: &models::Host| {
+        (!args.busy || h.is_busy()) && (statuses.is_empty() || statuses.contains(&h.status))
+    };
+
+    // Without a selector every host is shown, so stream each page as it arrives
+    if args.selector.is_empty() && !args.url && !args.print_url {
+        let mut sink = args.output.sink()?;
+        client.for_each_hosts_page(Option::None, server_status, &args.page, |mut hosts| {
+            hosts.retain(keep);
+            sink.records(&hosts)
+        })?;
+        return sink.end();
+    }
+
+    let mut hosts =
+        args.selector
+            .resolve(client.get_hosts_page(Option::None, server_status, &args.page)?)?;
+    hosts.retain(keep);
+
+    if args.url {
+        for host in &hosts {
+            println!("{}@{}", host.user, host.host_url);
         }
-        JsonValue::Boolean(b) => {
-            write!(writer, "{}:{}\n", prefix, b)?;
+    } else if args.print_url {
+        for host in &hosts {
+            let ui_server = &client.config.ui_server_host;
+            println!(
+                "{}",
+                resource::ResourceKind::Host.ui_url(ui_server, &host.host_id)
+            );
         }
-        JsonValue::Object(o) => {
-            for field in o.iter() {
-                if prefix == "" {
-                    to_flat_json_int(&field.1, field.0, writer)?;
-                } else {
-                    to_flat_json_int(&field.1, &format!("{}.{}", prefix, field.0), writer)?;
-                }
+    } else {
+        print_records(&hosts, &args.output)?;
+    }
+
+    Ok(())
+}
+
+/// Run a command on a host over ssh, failing if it exits non-zero
+fn exec_on_host(host: &models::Host, command: &[String]) -> Result<()> {
+    let status = std::process::Command::new("ssh")
+        .arg(format!("{}@{}", host.user, host.host_url))
+        .arg("--")
+        .args(command)
+        .status()?;
+    if !status.success() {
+        return Err(anyhow!("ssh exited with {}", status));
+    }
+    Ok(())
+}
+
+/// One of your hosts by ID
+fn find_host(client: &EvergreenClient, host_id: &str) -> Result<models::Host> {
+    client
+        .get_hosts(Option::None)?
+        .into_iter()
+        .find(|h| h.host_id == host_id)
+        .ok_or_else(|| anyhow!("Unknown host ID '{}'", host_id))
+}
+
+/// Run a shell script on a host over ssh and return its stdout
+fn capture_on_host(host: &models::Host, script: &str) -> Result<String> {
+    let output = std::process::Command::new("ssh")
+        .arg(format!("{}@{}", host.user, host.host_url))
+        .arg("--")
+        .arg(script)
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ssh exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Fetch the first of the candidate resource types that exists
+fn get_resource(
+    client: &EvergreenClient,
+    id: &str,
+    kinds: &[resource::ResourceKind],
+) -> Result<serde_json::Value> {
+    for kind in kinds {
+        match client.get_rest_json(&kind.rest_path(id)) {
+            Err(e)
+                if matches!(
+                    e.downcast_ref::<EvergreenError>(),
+                    Some(EvergreenError::Api { status: 404, .. })
+                ) =>
+            {
+                info!("{} is not a {}", id, kind);
             }
+            res => return res,
         }
-        JsonValue::Array(arr) => {
-            for (i, member) in arr.iter().enumerate() {
-                if prefix == "" {
-                    to_flat_json_int(member, &format!("{}", i), writer)?;
-                } else {
-                    to_flat_json_int(member, &format!("{}.{}", prefix, i), writer)?;
+    }
+
+    let names: Vec<_> = kinds.iter().map(|k| k.to_string()).collect();
+    Err(anyhow!("No {} found with ID '{}'", names.join(" or "), id))
+}
+
+fn run() -> Result<()> {
+    let mut args = Cli::from_args();
+    logging::init(args.verbose);
+
+    if let Command::Run { name, args: extra } = &args.cmd {
+        let config = config::EvergreenConfig::load(&args.config)?;
+        let query = config.queries.get(name).ok_or_else(|| {
+            anyhow!(
+                "No saved query '{}', add it to the queries section of the configuration",
+                name
+            )
+        })?;
+        let argv: Vec<String> = std::env::args().collect();
+        args = Cli::from_iter(queries::expand(&argv, name, query, extra)?);
+    }
+
+    // The prompt is served from its cache and only needs a client to refresh it
+    if let Command::Prompt {
+        ttl,
+        expiring_within,
+        refresh,
+    } = &args.cmd
+    {
+        let expiring_within = chrono::Duration::from_std(*expiring_within)?;
+        if *refresh {
+            prompt::refresh(&args.config, expiring_within)?;
+        } else {
+            let ttl = chrono::Duration::from_std(*ttl)?;
+            println!(
+                "{}",
+                prompt::cached_line(&args.config, ttl, expiring_within)?
+            );
+        }
+        return Ok(());
+    }
+
+    if let Command::Open {
+        kind,
+        id,
+        print_url,
+    } = &args.cmd
+    {
+        let config = config::EvergreenConfig::load(&args.config)?;
+        let url = kind.ui_url(&config.ui_server_host, id);
+        return match print_url {
+            true => {
+                println!("{}", url);
+                Ok(())
+            }
+            false => browser::open(&url),
+        };
+    }
+
+    if let Command::Describe { kind, output } = &args.cmd {
+        return print_records(&describe(*kind), output);
+    }
+
+    if let Command::Validate {
+        path,
+        local: true,
+        quiet,
+    } = &args.cmd
+    {
+        return validate_project(None, path, *quiet);
+    }
+
+    if let Command::Project(cmd) = &args.cmd {
+        return project_command(cmd);
+    }
+
+    if let Command::Graph {
+        file: Some(path),
+        task,
+        format,
+        ..
+    } = &args.cmd
+    {
+        let config = evg_config::ProjectConfig::parse(&read_project(path)?)?;
+        return print_graph(graph::Graph::from_config(&config), task.as_deref(), *format);
+    }
+
+    if let Command::Config(ConfigCommand::Init { force, keyring }) = &args.cmd {
+        return init_config(&args.config, *force, *keyring);
+    }
+
+    if let Command::Cache(CacheCommand::Clear) = &args.cmd {
+        let removed = match cache::HttpCache::from_home() {
+            Some(cache) => cache.clear()?,
+            None => 0,
+        };
+        println!("Removed {} cached responses", removed);
+        return Ok(());
+    }
+
+    if let Command::Cache(CacheCommand::Clean {
+        older_than,
+        max_size_mb,
+    }) = &args.cmd
+    {
+        let removed = match cache::HttpCache::from_home() {
+            Some(cache) => cache.prune(*older_than, max_size_mb * 1024 * 1024)?,
+            None => 0,
+        };
+        println!("Removed {} cached responses", removed);
+        return Ok(());
+    }
+
+    // The session context is local state and does not need a client
+    if let Command::Use {
+        target,
+        clear,
+        output,
+    } = &args.cmd
+    {
+        if *clear {
+            return context::SessionContext::clear();
+        }
+
+        let mut ctx = context::SessionContext::load()?;
+        match target {
+            Some(UseCommand::Project { project }) => ctx.project = Some(project.clone()),
+            Some(UseCommand::Version { version_id }) => ctx.version = Some(version_id.clone()),
+            None => return print_record(&ctx, output),
+        }
+        return ctx.save();
+    }
+
+    let mut client = EvergreenClient::new_from_home(&args.config, &args.limits)?;
+    if args.offline {
+        let cache = cache::HttpCache::from_home()
+            .ok_or_else(|| anyhow!("--offline needs a cache directory"))?;
+        client = client.offline(cache);
+    } else if !args.no_cache && args.replay.is_none() {
+        if let Some(cache) = cache::HttpCache::from_home() {
+            client = client.with_cache(cache);
+        }
+    }
+    if let Some(dir) = &args.record {
+        client = client.with_cassette(vcr::Cassette::Record(dir.clone()));
+    } else if let Some(dir) = &args.replay {
+        client = client.with_cassette(vcr::Cassette::Replay(dir.clone()));
+    }
+    if args.dry_run {
+        client = client.dry_run();
+    }
+    if args.lenient {
+        client = client.lenient();
+    }
+    if let Some(name) = &client.config.locale {
+        locale::Locale::set_default(name.parse()?);
+    }
+    if let Some(selector) = command_selector(&mut args.cmd) {
+        selector.expand_groups(&client.config.groups)?;
+    }
+
+    let output = command_output(&args.cmd);
+    models::show_extra_fields(output.is_some_and(|o| o.show_extra));
+    match output.and_then(|o| o.watch_interval()) {
+        Some(interval) => {
+            if !is_watchable(&args.cmd) {
+                return Err(anyhow!(
+                    "--watch is only supported by commands that list or show"
+                ));
+            }
+            loop {
+                // Clear the screen and move to the top left, like watch(1)
+                print!("\x1b[2J\x1b[H");
+                println!(
+                    "Every {}: {}\n",
+                    humantime::format_duration(interval),
+                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+                );
+                if let Err(e) = dispatch(&client, &args) {
+                    eprintln!("Error: {}", e);
                 }
+                std::thread::sleep(interval);
             }
         }
+        None => dispatch(&client, &args),
     }
+}
 
-    Ok(())
+/// The output arguments of a command, if it has any
+fn command_output(cmd: &Command) -> Option<&OutputArgs> {
+    match cmd {
+        Command::Hosts(HostsCommand::List(list)) => Some(&list.output),
+        Command::Hosts(HostsCommand::Events { output, .. })
+        | Command::Hosts(HostsCommand::Fingerprint { output, .. })
+        | Command::Hosts(HostsCommand::Spawn { output, .. })
+        | Command::Tasks(TasksCommand::QueuePosition { output, .. })
+        | Command::Annotations(AnnotationsCommand::Get { output, .. })
+        | Command::Users(UsersCommand::Permissions { output, .. })
+        | Command::Notifications(NotificationsCommand::List { output })
+        | Command::Distros(DistrosCommand::Queue { output, .. })
+        | Command::Versions(VersionsCommand::Manifest { output, .. })
+        | Command::Patches(PatchesCommand::Status { output, .. })
+        | Command::Hosts(HostsCommand::Report { output, .. })
+        | Command::Follow { output, .. }
+        | Command::Wait { output, .. }
+        | Command::Status { output }
+        | Command::Use { output, .. }
+        | Command::Get { output, .. }
+        | Command::Describe { output, .. } => Some(output),
+        Command::Stats(StatsCommand::Tasks(stats)) | Command::Stats(StatsCommand::Tests(stats)) => {
+            Some(&stats.output)
+        }
+        _ => None,
+    }
 }
 
-fn to_flat_json(s: &str) -> Result<String> {
-    let v = json::parse(s)?;
+/// The selector of a command, if it has one
+fn command_selector(cmd: &mut Command) -> Option<&mut Selector> {
+    match cmd {
+        Command::Hosts(HostsCommand::List(list)) => Some(&mut list.selector),
+        Command::Hosts(HostsCommand::Terminate(selector))
+        | Command::Hosts(HostsCommand::Reap { selector, .. })
+        | Command::Hosts(HostsCommand::Extend { selector, .. })
+        | Command::Hosts(HostsCommand::Exec { selector, .. }) => Some(selector),
+        _ => None,
+    }
+}
 
-    let mut r = String::new();
-    to_flat_json_int(&v, "", &mut r)?;
-    Ok(r)
+/// Whether re-running a command only reads, so `--watch` is safe
+fn is_watchable(cmd: &Command) -> bool {
+    !matches!(
+        cmd,
+        Command::Hosts(HostsCommand::Fingerprint { .. })
+            | Command::Follow { .. }
+            | Command::Wait { .. }
+            | Command::Use { .. }
+    )
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Cli::from_args();
+fn read_project(path: &std::path::Path) -> Result<String> {
+    std::fs::read_to_string(path).map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))
+}
 
-    info!("args : {:?}", args);
+/// Check a project configuration with the server, or locally without a client
+fn validate_project(
+    client: Option<&EvergreenClient>,
+    path: &std::path::Path,
+    quiet: bool,
+) -> Result<()> {
+    let yaml = read_project(path)?;
+    let mut findings = match (validate::syntax_error(&yaml), client) {
+        (Some(finding), _) => vec![finding],
+        (None, Some(client)) => validate::locate(&yaml, client.validate_project(&yaml, quiet)?),
+        (None, None) => match serde_yaml::from_str::<evg_config::ProjectConfig>(&yaml) {
+            Ok(config) => validate::locate(&yaml, config.lint()),
+            Err(e) => vec![validate::yaml_error(&e)],
+        },
+    };
+    if quiet {
+        findings.retain(|f| f.is_error());
+    }
 
-    let client = EvergreenClient::new_from_home()?;
+    let path = path.display().to_string();
+    for finding in &findings {
+        println!("{}", finding.format(&path));
+    }
+    let errors = findings.iter().filter(|f| f.is_error()).count();
+    if errors > 0 {
+        return Err(anyhow!("{} has {} error(s)", path, errors));
+    }
+    eprintln!("{} is valid", path);
+    Ok(())
+}
 
-    let hosts = client.get_hosts(Option::None)?;
+fn print_graph(graph: graph::Graph, task: Option<&str>, format: graph::GraphFormat) -> Result<()> {
+    let graph = match task {
+        Some(task) => graph.around(task)?,
+        None => graph,
+    };
+    if graph.nodes.is_empty() {
+        eprintln!("No tasks to graph");
+    }
+    print!("{}", graph.render(format));
+    Ok(())
+}
 
-    let mut filter: Option<Regex> = Option::None;
-    if let Some(filt) = args.filter {
-        filter = Some(Regex::new(&filt)?);
+fn project_command(cmd: &ProjectCommand) -> Result<()> {
+    let names: Vec<String> = match cmd {
+        ProjectCommand::Tasks { path, variant } => {
+            let config = evg_config::ProjectConfig::parse(&read_project(path)?)?;
+            let tasks = match variant {
+                Some(name) => {
+                    let variant = config.variant(name).ok_or_else(|| {
+                        anyhow!("No build variant {} in {}", name, path.display())
+                    })?;
+                    config.variant_tasks(variant)
+                }
+                None => config.tasks.iter().collect(),
+            };
+            tasks.iter().map(|t| t.name.clone()).collect()
+        }
+        ProjectCommand::Variants { path, task } => {
+            let config = evg_config::ProjectConfig::parse(&read_project(path)?)?;
+            let variants = match task {
+                Some(name) => {
+                    if config.task(name).is_none() {
+                        return Err(anyhow!("No task {} in {}", name, path.display()));
+                    }
+                    config.variants_running(name)
+                }
+                None => config.buildvariants.iter().collect(),
+            };
+            variants.iter().map(|v| v.id().to_owned()).collect()
+        }
+    };
+    for name in names {
+        println!("{}", name);
     }
+    Ok(())
+}
 
-    for host in hosts {
-        let flat = to_flat_json(&serde_json::to_string_pretty(&host)?)?;
+/// Run a command that needs a client
+fn dispatch(client: &EvergreenClient, args: &Cli) -> Result<()> {
+    match &args.cmd {
+        Command::Hosts(HostsCommand::List(list)) => list_hosts(client, list)?,
+        Command::Hosts(HostsCommand::Spawn {
+            distro,
+            key,
+            region,
+            instance_type,
+            setup_script,
+            userdata,
+            wait,
+            interval,
+            timeout,
+            output,
+        }) => {
+            let read = |path: &Option<std::path::PathBuf>| {
+                path.as_ref()
+                    .map(|p| {
+                        std::fs::read_to_string(p)
+                            .map_err(|e| anyhow!("Could not read {}: {}", p.display(), e))
+                    })
+                    .transpose()
+            };
+            let keyname = match key {
+                Some(key) => key.clone(),
+                None => match client.get_keys()?.as_slice() {
+                    [only] => only.name.clone(),
+                    [] => {
+                        return Err(anyhow!(
+                            "You have no public keys, add one in your Evergreen settings"
+                        ))
+                    }
+                    _ => return Err(anyhow!("You have several public keys, pick one with --key")),
+                },
+            };
+            let request = SpawnRequest {
+                distro: distro.clone(),
+                keyname,
+                region: region.clone(),
+                instance_type: instance_type.clone(),
+                userdata: read(userdata)?,
+                setup_script: read(setup_script)?,
+            };
+            if let Some(mut host) = client.spawn_host(&request)? {
+                if *wait {
+                    host = hosts::wait_until_ready(client, &host.host_id, *interval, *timeout)?;
+                }
+                print_record(&host, output)?;
+            }
+        }
+        Command::Hosts(HostsCommand::Terminate(selector)) => {
+            let hosts = selector.resolve_required(client.get_hosts(Option::None)?)?;
+            let summary: Vec<_> = hosts
+                .iter()
+                .map(|h| format!("{}  {}  {}", h.host_id, h.distro.distro_id, h.status))
+                .collect();
+            ui::confirm(
+                &format!("Terminate {} hosts", hosts.len()),
+                &summary,
+                args.yes || args.dry_run,
+            )?;
+            let ids: Vec<_> = hosts.into_iter().map(|h| h.host_id).collect();
+            for_each_id(&ids, client.limits.parallelism, "Terminated", |id| {
+                client.terminate_host(id)
+            })?
+        }
+        Command::Hosts(HostsCommand::Reap {
+            idle_hours,
+            stop,
+            selector,
+        }) => {
+            let hosts: Vec<_> = selector
+                .resolve(client.get_hosts(Option::None)?)?
+                .into_iter()
+                .filter(|h| hosts::is_reapable(h, *stop))
+                .collect();
+            let last_activity = pool::try_map_bounded(&hosts, client.limits.parallelism, |h| {
+                let mut events = Vec::new();
+                let window = TimeArgs::default();
+                client.for_each_host_events_page(
+                    &h.host_id,
+                    &PageArgs::all(),
+                    &window,
+                    |page| {
+                        events.extend(page);
+                        Ok(())
+                    },
+                )?;
+                Ok(hosts::last_activity(h, &events))
+            })?;
 
-        if let Some(filt) = filter.as_ref() {
-            if !filt.is_match(&flat) {
-                continue;
+            let now = chrono::Utc::now();
+            let idle_for = chrono::Duration::hours(i64::from(*idle_hours));
+            let idle: Vec<_> = hosts
+                .iter()
+                .zip(last_activity)
+                .filter_map(|(h, last)| Some((h, now - last?)))
+                .filter(|(_, idle)| *idle > idle_for)
+                .collect();
+            if idle.is_empty() {
+                eprintln!("No hosts have been idle for {} hours", idle_hours);
+                return Ok(());
+            }
+
+            let summary: Vec<_> = idle
+                .iter()
+                .map(|(h, idle)| {
+                    let span = relative::format_span(*idle);
+                    format!("{}  {}  idle {}", h.host_id, h.distro.distro_id, span)
+                })
+                .collect();
+            let (action, verb) = match stop {
+                true => ("Stop", "Stopped"),
+                false => ("Terminate", "Terminated"),
+            };
+            ui::confirm(
+                &format!("{} {} idle hosts", action, idle.len()),
+                &summary,
+                args.yes || args.dry_run,
+            )?;
+            let ids: Vec<_> = idle.iter().map(|(h, _)| h.host_id.clone()).collect();
+            for_each_id(&ids, client.limits.parallelism, verb, |id| match stop {
+                true => client.stop_host(id),
+                false => client.terminate_host(id),
+            })?
+        }
+        Command::Hosts(HostsCommand::Modify {
+            host_id,
+            instance_type,
+            add_tags,
+            remove_tags,
+        }) => {
+            let modify = HostModify {
+                add_instance_tags: add_tags.clone(),
+                delete_instance_tags: remove_tags.clone(),
+                instance_type: instance_type.clone(),
+                ..Default::default()
+            };
+            if modify == HostModify::default() {
+                return Err(anyhow!(
+                    "Nothing to change, pass --instance-type, --add-tag or --remove-tag"
+                ));
             }
+            hosts::check_modify(&find_host(client, host_id)?, &modify)?;
+            client.modify_host(host_id, &modify)?;
+            println!("Modified {}", host_id);
         }
+        Command::Hosts(HostsCommand::Rename {
+            args: rename_args,
+            from_template,
+        }) => {
+            let names: Vec<(String, String)> = match (from_template, rename_args.as_slice()) {
+                (None, [host_id, name]) => vec![(host_id.clone(), name.clone())],
+                (None, _) => {
+                    return Err(anyhow!(
+                        "Pass a host ID and its new name, or --from-template to name several hosts"
+                    ))
+                }
+                (Some(template), ids) => {
+                    let hosts = hosts::rename_targets(client.get_hosts(Option::None)?, ids)?;
+                    let names = hosts::template_names(&hosts, template)?;
+                    let names: Vec<_> = hosts.into_iter().map(|h| h.host_id).zip(names).collect();
+                    // Naming every host is easy to do by accident
+                    if ids.is_empty() {
+                        let summary: Vec<_> = names
+                            .iter()
+                            .map(|(id, name)| format!("{}  ->  {}", id, name))
+                            .collect();
+                        ui::confirm(
+                            &format!("Rename {} hosts", names.len()),
+                            &summary,
+                            args.yes || args.dry_run,
+                        )?;
+                    }
+                    names
+                }
+            };
 
-        match args.url {
-            true => {
-                println!("{}@{}", host.user, host.host_url);
+            let ids: Vec<_> = names.iter().map(|(id, _)| id.clone()).collect();
+            for_each_id(&ids, client.limits.parallelism, "Renamed", |id| {
+                let (_, name) = names.iter().find(|(i, _)| i == id).unwrap();
+                let modify = HostModify {
+                    new_name: Some(name.clone()),
+                    ..Default::default()
+                };
+                client.modify_host(id, &modify)
+            })?
+        }
+        Command::Hosts(HostsCommand::Extend { hours, selector }) => {
+            let hosts = selector.resolve_required(client.get_hosts(Option::None)?)?;
+            let ids: Vec<_> = hosts.into_iter().map(|h| h.host_id).collect();
+            for_each_id(&ids, client.limits.parallelism, "Extended", |id| {
+                client.extend_host(id, *hours)
+            })?
+        }
+        Command::Hosts(HostsCommand::Exec { selector, command }) => {
+            let hosts = selector.resolve_required(client.get_hosts(Option::None)?)?;
+            let ids: Vec<_> = hosts.iter().map(|h| h.host_id.clone()).collect();
+            // One host at a time so command output is not interleaved
+            for_each_id(&ids, 1, "Ran on", |id| {
+                let host = hosts.iter().find(|h| h.host_id == id).unwrap();
+                exec_on_host(host, command)
+            })?
+        }
+        Command::Hosts(HostsCommand::Report {
+            users,
+            by,
+            per_host,
+            output,
+        }) => {
+            let users = match users.is_empty() {
+                true => vec![client.config.user.clone()],
+                false => users.clone(),
+            };
+            let hosts = pool::try_map_bounded(&users, client.limits.parallelism, |user| {
+                client.get_hosts(Some(user))
+            })?;
+
+            let now = chrono::Utc::now();
+            let prices = &client.config.instance_prices;
+            let hosts: Vec<&models::Host> = hosts
+                .iter()
+                .flatten()
+                .filter(|h| h.status != "terminated")
+                .collect();
+            // Hosts that went down stopped costing when they did
+            let ends = pool::try_map_bounded(&hosts, client.limits.parallelism, |h| {
+                if !hosts::is_down(&h.status) {
+                    return Ok(now);
+                }
+                let mut events = Vec::new();
+                let window = TimeArgs::default();
+                client.for_each_host_events_page(
+                    &h.host_id,
+                    &PageArgs::all(),
+                    &window,
+                    |page| {
+                        events.extend(page);
+                        Ok(())
+                    },
+                )?;
+                Ok(hosts::down_since(&events).unwrap_or(now))
+            })?;
+            let costs: Vec<_> = hosts
+                .iter()
+                .zip(ends)
+                .map(|(h, end)| hosts::host_cost(h, by, prices, end))
+                .collect();
+
+            let unpriced: BTreeSet<&str> = costs
+                .iter()
+                .filter(|c| c.cost.is_none())
+                .map(|c| c.instance_type.as_str())
+                .collect();
+            if !unpriced.is_empty() {
+                eprintln!(
+                    "No price for {}, add them to instance_prices in the configuration",
+                    unpriced.into_iter().collect::<Vec<_>>().join(", ")
+                );
+            }
+
+            match per_host {
+                true => print_records(&costs, output)?,
+                false => print_records(&hosts::summarize_spend(&costs), output)?,
+            }
+        }
+        Command::Hosts(HostsCommand::Expiring { within }) => {
+            let window = chrono::Duration::from_std(*within)?;
+            let now = chrono::Utc::now();
+
+            let mut hosts: Vec<_> = client
+                .get_hosts(Option::None)?
+                .into_iter()
+                .filter(|h| h.status != "terminated")
+                .collect();
+            hosts::sort_by_expiration(&mut hosts);
+
+            let mut expiring = 0;
+            for host in &hosts {
+                if hosts::host_expiration(host).is_some_and(|e| e - now <= window) {
+                    expiring += 1;
+                }
+                println!("{}", hosts::timeline_line(host, now, window));
+            }
+
+            if expiring > 0 {
+                eprintln!(
+                    "{} host(s) expire within {}",
+                    expiring,
+                    humantime::format_duration(*within)
+                );
+                std::process::exit(EXPIRING_EXIT_CODE);
             }
-            false => match args.output {
-                OutputType::Flat => {
-                    println!("{}", flat);
+        }
+        Command::Hosts(HostsCommand::Events {
+            host_id,
+            page,
+            window,
+            output,
+        }) => {
+            let mut sink = output.sink()?;
+            client
+                .for_each_host_events_page(host_id, page, window, |events| sink.records(&events))?;
+            sink.end()?;
+        }
+        Command::Hosts(HostsCommand::Sync {
+            host_id,
+            local_path,
+            remote_path,
+            delete,
+            watch,
+        }) => {
+            let host = find_host(client, host_id)?;
+            let rsync_args = hosts::rsync_args(&host, local_path, remote_path.as_deref(), *delete)?;
+            let rsync = || -> Result<()> {
+                let status = std::process::Command::new("rsync")
+                    .args(&rsync_args)
+                    .status()
+                    .map_err(|e| anyhow!("Could not run rsync: {}", e))?;
+                if !status.success() {
+                    return Err(anyhow!("rsync exited with {}", status));
+                }
+                Ok(())
+            };
+
+            match watch {
+                None => rsync()?,
+                Some(interval) => {
+                    let interval = interval.map_or(std::time::Duration::from_secs(2), |d| *d);
+                    loop {
+                        if let Err(e) = rsync() {
+                            eprintln!("Error: {}", e);
+                        }
+                        std::thread::sleep(interval);
+                    }
                 }
-                OutputType::Json => {
-                    println!("{}", serde_json::to_string_pretty(&host)?);
+            }
+        }
+        Command::Hosts(HostsCommand::Tunnel {
+            host_id,
+            forwards,
+            reconnect_delay,
+        }) => {
+            let host = find_host(client, host_id)?;
+            let ssh_args = hosts::tunnel_args(&host, forwards);
+            let mut failures = hosts::QuickFailures::default();
+            loop {
+                eprintln!("Forwarding {} to {}", forwards.join(", "), host_id);
+                let started = std::time::Instant::now();
+                let status = std::process::Command::new("ssh")
+                    .args(&ssh_args)
+                    .status()
+                    .map_err(|e| anyhow!("Could not run ssh: {}", e))?;
+                eprintln!("ssh exited with {}", status);
+                if failures.record(status.success(), started.elapsed()) {
+                    return Err(anyhow!(
+                        "ssh failed soon after starting {} times in a row, last with {}, giving up",
+                        hosts::TUNNEL_MAX_QUICK_FAILURES,
+                        status
+                    ));
                 }
-            },
+                eprintln!(
+                    "Reconnecting in {}",
+                    humantime::format_duration(*reconnect_delay)
+                );
+                std::thread::sleep(*reconnect_delay);
+            }
+        }
+        Command::Hosts(HostsCommand::Fingerprint { host_id, output }) => {
+            let host = find_host(client, host_id)?;
+            let facts = capture_on_host(&host, hosts::FINGERPRINT_SCRIPT)?;
+            let tags = hosts::parse_fingerprint(&facts);
+            client.add_host_tags(host_id, &tags)?;
+            print_records(&tags, output)?;
+        }
+        Command::Tasks(TasksCommand::Restart(ids)) => {
+            let tasks = pool::try_map_bounded(&ids.resolve()?, client.limits.parallelism, |id| {
+                client.get_task(id)
+            })?;
+            for layer in tasks::dependency_layers(&tasks)? {
+                for_each_id(&layer, client.limits.parallelism, "Restarted", |id| {
+                    client.restart_task(id)
+                })?
+            }
+        }
+        Command::Tasks(TasksCommand::RestartFailed {
+            version_id,
+            only_test_failures,
+        }) => {
+            let plan = tasks::plan_restart(client, version_id, *only_test_failures)?;
+            if !plan.skipped.is_empty() {
+                eprintln!(
+                    "Skipped {} failed tasks without a failing test: {}",
+                    plan.skipped.len(),
+                    tasks::by_variant(&plan.skipped)
+                );
+            }
+            for layer in tasks::dependency_layers(&plan.restart)? {
+                for_each_id(&layer, client.limits.parallelism, "Restarted", |id| {
+                    client.restart_task(id)
+                })?
+            }
+            eprintln!(
+                "Restarted {} failed tasks of {}: {}",
+                plan.restart.len(),
+                version_id,
+                tasks::by_variant(&plan.restart)
+            );
+        }
+        Command::Tasks(TasksCommand::Schedule(ids)) => {
+            let update = TaskUpdate {
+                activated: Some(true),
+                ..Default::default()
+            };
+            for_each_id(
+                &ids.resolve()?,
+                client.limits.parallelism,
+                "Scheduled",
+                |id| client.update_task(id, &update),
+            )?
+        }
+        Command::Tasks(TasksCommand::Priority { priority, ids }) => {
+            let update = TaskUpdate {
+                priority: Some(*priority),
+                ..Default::default()
+            };
+            for_each_id(
+                &ids.resolve()?,
+                client.limits.parallelism,
+                "Set priority on",
+                |id| client.update_task(id, &update),
+            )?
+        }
+        Command::Tasks(TasksCommand::QueuePosition { task_id, output }) => {
+            if !args.allow_ui_fallback {
+                return Err(anyhow!(
+                    "Queue position is not exposed by the REST API, pass --allow-ui-fallback"
+                ));
+            }
+
+            let task = client.get_task(task_id)?;
+            let position = ui_fallback::get_queue_position(client, &task.distro_id, task_id)?;
+            print_record(&position, output)?;
+        }
+        Command::Annotations(AnnotationsCommand::Get { task_id, output }) => {
+            print_records(&client.get_task_annotations(task_id)?, output)?;
+        }
+        Command::Annotations(AnnotationsCommand::Add { note, issues, ids }) => {
+            if note.is_none() && issues.is_empty() {
+                return Err(anyhow!("Nothing to add, pass --note or --issue"));
+            }
+
+            let tracker_url = client.config.issue_tracker_url.as_deref();
+            let issues = issues
+                .iter()
+                .map(|i| make_issue_link(i, tracker_url))
+                .collect::<Result<Vec<_>>>()?;
+
+            for_each_id(
+                &ids.resolve()?,
+                client.limits.parallelism,
+                "Annotated",
+                |id| {
+                    let annotation = TaskAnnotation {
+                        task_id: id.to_owned(),
+                        note: note.as_ref().map(|m| AnnotationNote {
+                            message: m.clone(),
+                            source: None,
+                            extra: Default::default(),
+                        }),
+                        issues: issues.clone(),
+                        ..Default::default()
+                    };
+                    client.add_task_annotation(&annotation)
+                },
+            )?
+        }
+        Command::Users(UsersCommand::Permissions {
+            user,
+            project,
+            output,
+        }) => match project {
+            Some(project) => {
+                let query = PermissionsQuery {
+                    resource_type: "project".to_owned(),
+                    resource_id: project.clone(),
+                };
+                print_value(&client.get_resource_permissions(&query)?, output)?;
+            }
+            None => {
+                let user = user.as_deref().unwrap_or(&client.config.user);
+                print_records(&client.get_user_permissions(user)?, output)?;
+            }
+        },
+        Command::Distros(DistrosCommand::Queue {
+            distro_id,
+            task,
+            hosts,
+            output,
+        }) => {
+            let queue = client.get_distro_queue(distro_id)?;
+            let hosts = match hosts {
+                Some(h) => *h,
+                None => client
+                    .get_distro_host_stats()?
+                    .iter()
+                    .find(|s| &s.distro == distro_id)
+                    .map_or(1, |s| s.num_hosts),
+            };
+
+            let summary = distros::summarize_queue(distro_id, &queue, hosts, task.as_deref());
+            if let (Some(task), None) = (task, summary.task_position) {
+                eprintln!("Task {} is not in the queue for {}", task, distro_id);
+            }
+            print_record(&summary, output)?;
+        }
+        Command::Versions(VersionsCommand::Manifest { version_id, output }) => {
+            let version_id = context::version_or_context(version_id.as_ref())?;
+            print_record(&client.get_version_manifest(&version_id)?, output)?;
+        }
+        Command::Patches(PatchesCommand::Status { patch_id, output }) => {
+            let (patch, tasks) = patches::patch_tasks(client, patch_id)?;
+            let variants = patches::summarize_variants(&tasks, &client.config.ui_server_host);
+            eprintln!("{}", patches::describe(&patch, &variants));
+            print_records(&variants, output)?;
+        }
+        Command::Notifications(NotificationsCommand::List { output }) => {
+            print_records(
+                &client.get_user_subscriptions::<Subscription>(&client.config.user)?,
+                output,
+            )?;
+        }
+        Command::Notifications(NotificationsCommand::Add {
+            resource_type,
+            id,
+            trigger,
+            subscriber_type,
+            target,
+        }) => {
+            let sub = Subscription {
+                id: String::new(),
+                resource_type: resource_type.to_uppercase(),
+                trigger: trigger.clone(),
+                selectors: vec![SubscriptionSelector {
+                    selector_type: "id".to_owned(),
+                    data: id.clone(),
+                    extra: Default::default(),
+                }],
+                regex_selectors: Vec::new(),
+                subscriber: Subscriber {
+                    subscriber_type: subscriber_type.clone(),
+                    target: serde_json::Value::String(target.clone()),
+                    extra: Default::default(),
+                },
+                owner_type: "person".to_owned(),
+                owner: client.config.user.clone(),
+                trigger_data: Default::default(),
+                extra: Default::default(),
+            };
+            client.add_subscriptions(&[sub])?;
+            println!("Subscribed to {} {}", resource_type, id);
+        }
+        Command::Notifications(NotificationsCommand::Delete(ids)) => {
+            let ids = ids.resolve()?;
+            ui::confirm(
+                &format!("Delete {} subscriptions", ids.len()),
+                &ids,
+                args.yes || args.dry_run,
+            )?;
+            for_each_id(&ids, client.limits.parallelism, "Deleted", |id| {
+                client.delete_subscription(id)
+            })?
+        }
+        Command::Stats(StatsCommand::Tasks(stats)) => {
+            print_records(
+                &client.get_task_stats(
+                    &context::project_or_context(stats.project.as_ref())?,
+                    &stats.to_query()?,
+                )?,
+                &stats.output,
+            )?;
+        }
+        Command::Stats(StatsCommand::Tests(stats)) => {
+            print_records(
+                &client.get_test_stats(
+                    &context::project_or_context(stats.project.as_ref())?,
+                    &stats.to_query()?,
+                )?,
+                &stats.output,
+            )?;
+        }
+        Command::Snapshot {
+            cmd: Some(SnapshotCommand::Apply { dir, only }),
+            ..
+        } => {
+            snapshot::apply_snapshot(client, dir, only)?;
+        }
+        Command::Snapshot {
+            out: Some(out),
+            patches,
+            cmd: None,
+        } => {
+            snapshot::create_snapshot(client, out, *patches)?;
+        }
+        Command::Snapshot { .. } => {
+            return Err(anyhow!(
+                "Pass --out to create a snapshot or use 'snapshot apply'"
+            ));
+        }
+        Command::Follow {
+            target,
+            interval,
+            callback_url,
+            listen,
+            output,
+        } => {
+            let target = match target {
+                FollowCommand::Task { task_id } => follow::FollowTarget::Task(task_id.clone()),
+                FollowCommand::Version { version_id } => {
+                    follow::FollowTarget::Version(context::version_or_context(version_id.as_ref())?)
+                }
+            };
+            let webhook = callback_url.as_ref().map(|url| follow::WebhookConfig {
+                callback_url: url.clone(),
+                listen: listen.clone(),
+            });
+
+            let status = follow::follow(
+                client,
+                &target,
+                *interval,
+                webhook.as_ref(),
+                None,
+                |change| print_record(change, output),
+            )?;
+            if output.notify {
+                let outcome = if follow::is_success(&status) {
+                    "succeeded"
+                } else {
+                    "failed"
+                };
+                notify::send(&format!("{} {}", target.id(), outcome), &status);
+            }
+        }
+        Command::Wait {
+            target,
+            interval,
+            timeout,
+            output,
+        } => {
+            let target = match target {
+                WaitCommand::Patch { patch_id } => follow::FollowTarget::Patch(patch_id.clone()),
+                WaitCommand::Version { version_id } => {
+                    follow::FollowTarget::Version(context::version_or_context(version_id.as_ref())?)
+                }
+            };
+
+            let status = follow::follow(client, &target, *interval, None, *timeout, |change| {
+                eprintln!("{} is {}", change.id, change.status);
+                Ok(())
+            })?;
+            if !follow::is_terminal(&status) {
+                eprintln!("Timed out waiting for {}", target.id());
+                std::process::exit(WAIT_TIMEOUT_EXIT_CODE);
+            }
+            if follow::is_success(&status) {
+                if output.notify {
+                    notify::send(&format!("{} succeeded", target.id()), "");
+                }
+                return Ok(());
+            }
+
+            let version_id = match &target {
+                follow::FollowTarget::Patch(id) => client.get_patch(id)?.version,
+                _ => target.id().to_owned(),
+            };
+            let failures = tasks::failed_tasks(client, &version_id)?;
+            print_records(&failures, output)?;
+            let summary = tasks::failure_summary(&failures);
+            eprintln!("{}", summary);
+            if output.notify {
+                notify::send(&format!("{} failed", target.id()), &summary);
+            }
+            std::process::exit(WAIT_FAILED_EXIT_CODE);
+        }
+        Command::Prompt { .. }
+        | Command::Use { .. }
+        | Command::Describe { .. }
+        | Command::Config(_)
+        | Command::Cache(_)
+        | Command::Run { .. }
+        | Command::Open { .. }
+        | Command::Project(_) => unreachable!(),
+        Command::Tui => tui::run(client)?,
+        Command::Graph {
+            project,
+            version_id,
+            task,
+            format,
+            ..
+        } => {
+            let version_id = match (version_id, project) {
+                (Some(version_id), _) => version_id.clone(),
+                (None, Some(project)) => {
+                    client
+                        .get_project_versions(project, 1)?
+                        .into_iter()
+                        .next()
+                        .ok_or_else(|| anyhow!("Project {} has no versions", project))?
+                        .version_id
+                }
+                (None, None) => unreachable!("--file is handled without a client"),
+            };
+            let tasks = tasks::version_tasks(client, &version_id)?;
+            print_graph(graph::Graph::from_tasks(&tasks), task.as_deref(), *format)?;
+        }
+        Command::Validate { path, quiet, .. } => validate_project(Some(client), path, *quiet)?,
+        Command::Get { id, kind, output } => {
+            let (id, kinds) = resource::infer_resource(id)?;
+            let kinds = kind.map_or(kinds, |k| vec![k]);
+            print_value(&get_resource(client, &id, &kinds)?, output)?;
+        }
+        Command::Status { output } => {
+            let status = get_status(client, args.allow_ui_fallback)?;
+            print_record(&status, output)?;
+            if status.outage {
+                std::process::exit(OUTAGE_EXIT_CODE);
+            }
         }
     }
 
     Ok(())
 }
 
-#[test]
-fn test_flat_json_array() {
-    assert_eq! { to_flat_json(r#"["a","b"]"#).unwrap(),
-r#"0:a
-1:b
-"#};
+/// Suggest how to fix the errors users commonly hit
+fn remediation_hint(err: &EvergreenError) -> Option<&'static str> {
+    match err {
+        EvergreenError::InvalidCredentials { .. } => Some(
+            "Check api_key and user in ~/.evergreen.yml or EVG_API_KEY and EVG_API_USER, a new key can be generated from your Evergreen settings page",
+        ),
+        EvergreenError::DistroNotFound { .. } => {
+            Some("Check the distro name, distro IDs are exact and case sensitive")
+        }
+        EvergreenError::SpawnLimitReached { .. } => {
+            Some("Terminate hosts you no longer need, see 'hosts list' and 'hosts terminate'")
+        }
+        EvergreenError::PatchTooLarge { .. } => {
+            Some("Split the patch or leave generated and binary files out of it")
+        }
+        EvergreenError::Api { .. } => None,
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {:?}", e);
+        if let Some(hint) = e
+            .downcast_ref::<EvergreenError>()
+            .and_then(remediation_hint)
+        {
+            eprintln!("Hint: {}", hint);
+        }
+        std::process::exit(1);
+    }
 }
 
 #[test]
-fn test_flat_json_obj() {
-    assert_eq! { to_flat_json(r#"{"a":"b", "n":42}"#).unwrap(),
-r#"a:b
-n:42
-"#};
+fn test_make_issue_link() {
+    let link = make_issue_link("KEY-123", Some("https://jira.example.com/browse/")).unwrap();
+    assert_eq!(link.url, "https://jira.example.com/browse/KEY-123");
+    assert_eq!(link.issue_key, "KEY-123");
+
+    let link = make_issue_link("https://jira.example.com/browse/KEY-9", None).unwrap();
+    assert_eq!(link.issue_key, "KEY-9");
+
+    assert!(make_issue_link("KEY-123", None).is_err());
 }
 
 #[test]
-fn test_flat_json_obj_nested() {
-    assert_eq! { to_flat_json(r#"{"a": { "n":42 } }"#).unwrap(),
-r#"a.n:42
-"#};
+fn test_service_status() {
+    let banner = |theme: &str| Banner {
+        banner: "msg".to_owned(),
+        theme: theme.to_owned(),
+        extra: Default::default(),
+    };
+
+    let status = ServiceStatus::new(banner("information"), None);
+    assert!(!status.degraded && !status.outage);
+
+    let mut flags = ServiceFlags::new();
+    flags.insert("task_dispatch_disabled".to_owned(), true);
+    flags.insert("hosts_disabled".to_owned(), false);
+    let status = ServiceStatus::new(banner(""), Some(flags));
+    assert!(status.degraded && !status.outage);
+    assert_eq!(
+        status.disabled_services,
+        Some(vec!["task_dispatch_disabled".to_owned()])
+    );
+
+    let status = ServiceStatus::new(banner("important"), None);
+    assert!(status.degraded && status.outage);
 }
 
 #[test]
-fn test_flat_json_array_obj_nested() {
-    assert_eq! { to_flat_json(r#"[{"a": { "n":42 } }]"#).unwrap(),
-r#"0.a.n:42
-"#};
+fn test_describe() {
+    let fields = describe(resource::ResourceKind::Host);
+    let field = |path: &str| fields.iter().find(|f| f.path == path).unwrap();
+
+    assert_eq!(field("distro.distro_id").rust_type, "String");
+    assert!(field("instance_tags[]").collection);
+    assert!(field("expiration_time").optional);
+
+    // The table lists the same fields as the derive
+    let mut type_fields = Vec::new();
+    <models::Host as evergreen_rs_types::EvgFields>::evg_type_fields("", &mut type_fields);
+    assert_eq!(
+        fields.iter().map(|f| f.path.clone()).collect::<Vec<_>>(),
+        type_fields
+    );
 }
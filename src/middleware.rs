@@ -0,0 +1,131 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use hmac::{Hmac, Mac};
+use reqwest::blocking::Request;
+use reqwest::header::{HeaderName, HeaderValue};
+use sha2::Sha256;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// A transformation applied to every request just before it is sent
+pub trait RequestMiddleware: Send + Sync {
+    fn apply(&self, req: &mut Request) -> Result<()>;
+}
+
+/// Spaces requests out so no more than `requests_per_second` are sent
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: u32) -> RateLimiter {
+        RateLimiter {
+            interval: Duration::from_secs(1) / requests_per_second,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl RequestMiddleware for RateLimiter {
+    fn apply(&self, _req: &mut Request) -> Result<()> {
+        let wait = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let slot = (*next).max(now);
+            *next = slot + self.interval;
+            slot - now
+        };
+        std::thread::sleep(wait);
+        Ok(())
+    }
+}
+
+/// Config for signing request bodies, required by some API gateways
+///
+/// ```yaml
+/// request_signing:
+///   header: X-Body-Signature
+///   secret: shared-secret
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestSigningConfig {
+    // Header that receives the hex encoded signature
+    pub header: String,
+    pub secret: String,
+}
+
+/// Hex encoded HMAC-SHA256 of `body`
+pub fn hmac_sha256_hex(secret: &[u8], body: &[u8]) -> Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret)
+        .map_err(|e| anyhow!("Invalid signing secret: {}", e))?;
+    mac.update(body);
+
+    let mut out = String::new();
+    for b in mac.finalize().into_bytes() {
+        write!(out, "{:02x}", b)?;
+    }
+    Ok(out)
+}
+
+/// Adds an HMAC-SHA256 signature of the request body as a header
+pub struct HmacSigner {
+    header: HeaderName,
+    secret: Vec<u8>,
+}
+
+impl HmacSigner {
+    pub fn new(config: &RequestSigningConfig) -> Result<HmacSigner> {
+        Ok(HmacSigner {
+            header: HeaderName::from_bytes(config.header.as_bytes())?,
+            secret: config.secret.as_bytes().to_vec(),
+        })
+    }
+
+    fn sign(&self, body: &[u8]) -> Result<String> {
+        hmac_sha256_hex(&self.secret, body)
+    }
+}
+
+impl RequestMiddleware for HmacSigner {
+    fn apply(&self, req: &mut Request) -> Result<()> {
+        let body = req.body().and_then(|b| b.as_bytes()).unwrap_or(&[]);
+        let signature = self.sign(body)?;
+        req.headers_mut()
+            .insert(self.header.clone(), HeaderValue::from_str(&signature)?);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_hmac_signature() {
+    let signer = HmacSigner::new(&RequestSigningConfig {
+        header: "X-Body-Signature".to_owned(),
+        secret: "key".to_owned(),
+    })
+    .unwrap();
+
+    // Well known HMAC-SHA256 example value
+    assert_eq!(
+        signer
+            .sign(b"The quick brown fox jumps over the lazy dog")
+            .unwrap(),
+        "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+    );
+}
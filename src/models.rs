@@ -0,0 +1,980 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::{DateTime, Utc};
+use evergreen_rs_derive::{EvgFields, EvgValues};
+
+use crate::selector::Resource;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether models are written with the fields they do not know, see `show_extra_fields`
+static SHOW_EXTRA: AtomicBool = AtomicBool::new(false);
+
+/// Include the fields the server sent that the models do not know when writing them, set from
+/// --show-extra
+///
+/// Each model keeps those fields in `extra` so fields added to the API show up without a new
+/// release, they are hidden by default to keep the output stable.
+pub fn show_extra_fields(show: bool) {
+    SHOW_EXTRA.store(show, Ordering::Relaxed);
+}
+
+fn hide_extra(extra: &Map<String, Value>) -> bool {
+    extra.is_empty() || !SHOW_EXTRA.load(Ordering::Relaxed)
+}
+
+/// Read a null as the type's default, for fields the API sends as null or leaves out
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct Distro {
+    pub distro_id: String,
+    #[serde(default)]
+    pub provider: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub image_id: String,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct Tag {
+    pub key: String,
+    #[serde(default)]
+    pub value: String,
+    #[serde(default)]
+    pub can_be_modified: bool,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+// The task a host is executing, every field is null while the host is idle
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct RunningTask {
+    #[serde(default)]
+    pub task_id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub dispatch_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub version_id: Option<String>,
+    #[serde(default)]
+    pub build_id: Option<String>,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct Host {
+    pub host_id: String,
+    // Null until the host is up
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub host_url: String,
+    #[evg(nested)]
+    #[serde(default)]
+    pub distro: Distro,
+    #[serde(default)]
+    pub provisioned: bool,
+    #[serde(default)]
+    pub started_by: String,
+    #[serde(default)]
+    pub host_type: String,
+    #[serde(default)]
+    pub user: String,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub creation_time: Option<DateTime<Utc>>,
+    #[evg(nested)]
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub running_task: RunningTask,
+    #[serde(default)]
+    pub user_host: bool,
+    #[serde(default)]
+    pub no_expiration: bool,
+    // null for hosts without tags
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub instance_tags: Vec<Tag>,
+    #[serde(default)]
+    pub instance_type: String,
+    #[serde(default)]
+    pub zone: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub home_volume_id: String,
+    #[serde(default)]
+    pub expiration_time: Option<DateTime<Utc>>,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct TaskQueueItem {
+    pub id: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub build_variant: String,
+    #[serde(default)]
+    pub project: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub requester: String,
+    #[serde(default)]
+    pub priority: i64,
+    // Nanoseconds
+    #[serde(default)]
+    pub expected_duration: i64,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct DistroHostStats {
+    pub distro: String,
+    #[serde(default)]
+    pub num_hosts: i64,
+    #[serde(default)]
+    pub running_tasks: i64,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+impl Host {
+    /// Whether the host is executing a task
+    pub fn is_busy(&self) -> bool {
+        self.running_task.task_id.is_some()
+    }
+}
+
+impl Resource for Host {
+    fn id(&self) -> &str {
+        &self.host_id
+    }
+
+    fn tag(&self, key: &str) -> Option<&str> {
+        self.instance_tags
+            .iter()
+            .find(|t| t.key == key)
+            .map(|t| t.value.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct HostEvent {
+    #[serde(default)]
+    pub id: String,
+    pub timestamp: String,
+    #[serde(alias = "eventtype")]
+    pub event_type: String,
+    // Event specific details such as the new status or the termination reason
+    #[serde(default)]
+    pub data: serde_json::Value,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct TaskDependency {
+    pub id: String,
+    // The upstream status the dependency waits for, e.g. success
+    #[serde(default)]
+    pub status: String,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct Task {
+    pub task_id: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub project_id: String,
+    #[serde(default)]
+    pub version_id: String,
+    #[serde(default)]
+    pub build_id: String,
+    #[serde(default)]
+    pub build_variant: String,
+    #[serde(default)]
+    pub distro_id: String,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub activated: bool,
+    #[serde(default)]
+    pub priority: i64,
+    #[serde(default)]
+    pub execution: i64,
+    #[serde(default)]
+    pub create_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub start_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub finish_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub depends_on: Vec<TaskDependency>,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct TestResult {
+    #[serde(default)]
+    pub task_id: String,
+    #[serde(default)]
+    pub test_file: String,
+    // pass, fail, skip or silentfail
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub exit_code: i64,
+    #[serde(default)]
+    pub start_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub end_time: Option<DateTime<Utc>>,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct Version {
+    pub version_id: String,
+    #[serde(default)]
+    pub project: String,
+    #[serde(default)]
+    pub branch: String,
+    #[serde(default)]
+    pub revision: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub requester: String,
+    #[serde(default)]
+    pub status: String,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct Patch {
+    pub patch_id: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub project_id: String,
+    #[serde(default)]
+    pub branch: String,
+    #[serde(default)]
+    pub git_hash: String,
+    #[serde(default)]
+    pub patch_number: i64,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub activated: bool,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct StatusCounts {
+    #[serde(default)]
+    pub succeeded: i64,
+    #[serde(default)]
+    pub failed: i64,
+    #[serde(default)]
+    pub started: i64,
+    #[serde(default)]
+    pub undispatched: i64,
+    #[serde(default)]
+    pub inactive: i64,
+    #[serde(default)]
+    pub timed_out: i64,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+impl StatusCounts {
+    pub fn total(&self) -> i64 {
+        self.succeeded
+            + self.failed
+            + self.started
+            + self.undispatched
+            + self.inactive
+            + self.timed_out
+    }
+
+    pub fn add(&mut self, other: &StatusCounts) {
+        self.succeeded += other.succeeded;
+        self.failed += other.failed;
+        self.started += other.started;
+        self.undispatched += other.undispatched;
+        self.inactive += other.inactive;
+        self.timed_out += other.timed_out;
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct Build {
+    #[serde(rename = "_id")]
+    #[evg(rename = "_id")]
+    pub id: String,
+    #[serde(default)]
+    pub project_id: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub build_variant: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub activated: bool,
+    #[serde(default)]
+    pub tasks: Vec<String>,
+    #[serde(default)]
+    #[evg(nested)]
+    pub status_counts: StatusCounts,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct AnnotationNote {
+    #[serde(default)]
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<AnnotationSource>,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct AnnotationSource {
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub time: Option<String>,
+    #[serde(default)]
+    pub requester: String,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct IssueLink {
+    pub url: String,
+    pub issue_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<AnnotationSource>,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct TaskAnnotation {
+    pub task_id: String,
+    #[serde(default)]
+    pub task_execution: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<AnnotationNote>,
+    #[serde(default)]
+    pub issues: Vec<IssueLink>,
+    #[serde(default)]
+    pub suspected_issues: Vec<IssueLink>,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct Banner {
+    #[serde(default)]
+    pub banner: String,
+    // One of announcement, information, warning or important
+    #[serde(default)]
+    pub theme: String,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+/// Disabled service flags, e.g. task_dispatch_disabled: true
+pub type ServiceFlags = BTreeMap<String, bool>;
+
+/// Permission levels keyed by resource id and then permission name
+pub type PermissionMap = BTreeMap<String, BTreeMap<String, i64>>;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct UserPermissions {
+    #[serde(rename = "type")]
+    #[evg(rename = "type")]
+    pub resource_type: String,
+    pub permissions: PermissionMap,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+/// Body for GET /users/permissions
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PermissionsQuery {
+    pub resource_type: String,
+    pub resource_id: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct TaskStats {
+    pub task_name: String,
+    #[serde(default)]
+    pub variant: String,
+    #[serde(default)]
+    pub distro: String,
+    pub date: String,
+    pub num_success: i64,
+    pub num_failed: i64,
+    pub num_total: i64,
+    #[serde(default)]
+    pub num_timeout: i64,
+    #[serde(default)]
+    pub num_test_failed: i64,
+    #[serde(default)]
+    pub num_system_failed: i64,
+    #[serde(default)]
+    pub num_setup_failed: i64,
+    #[serde(default)]
+    pub avg_duration_success: f64,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct TestStats {
+    pub test_file: String,
+    #[serde(default)]
+    pub task_name: String,
+    #[serde(default)]
+    pub variant: String,
+    #[serde(default)]
+    pub distro: String,
+    pub date: String,
+    pub num_pass: i64,
+    pub num_fail: i64,
+    #[serde(default)]
+    pub avg_duration_pass: f64,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+/// Query parameters for the task_stats and test_stats endpoints
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct StatsQuery {
+    pub after_date: String,
+    pub before_date: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_num_days: Option<u32>,
+    // Comma separated lists
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tasks: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tests: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variants: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct ManifestModule {
+    #[serde(default)]
+    pub repo: String,
+    #[serde(default)]
+    pub owner: String,
+    #[serde(default)]
+    pub branch: String,
+    pub revision: String,
+    #[serde(default)]
+    pub url: String,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct Manifest {
+    pub id: String,
+    pub revision: String,
+    pub project: String,
+    pub branch: String,
+    #[serde(default)]
+    pub is_base: bool,
+    // Keyed by module name
+    #[serde(default)]
+    pub modules: BTreeMap<String, ManifestModule>,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct PublicKey {
+    pub name: String,
+    pub key: String,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct SubscriptionSelector {
+    #[serde(rename = "type")]
+    #[evg(rename = "type")]
+    pub selector_type: String,
+    pub data: String,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct Subscriber {
+    // email, slack, jira-comment or evergreen-webhook
+    #[serde(rename = "type")]
+    #[evg(rename = "type")]
+    pub subscriber_type: String,
+    pub target: serde_json::Value,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct Subscription {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    // VERSION, PATCH, BUILD, TASK or HOST
+    pub resource_type: String,
+    // outcome, success, failure, ...
+    pub trigger: String,
+    #[serde(default)]
+    pub selectors: Vec<SubscriptionSelector>,
+    #[serde(default)]
+    pub regex_selectors: Vec<SubscriptionSelector>,
+    #[evg(nested)]
+    pub subscriber: Subscriber,
+    #[serde(default)]
+    pub owner_type: String,
+    #[serde(default)]
+    pub owner: String,
+    #[serde(default)]
+    pub trigger_data: BTreeMap<String, String>,
+    #[evg(skip)]
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+/// Body for PATCH /hosts/{host_id}
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HostModify {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub add_instance_tags: Vec<Tag>,
+    // Keys of the tags to remove
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub delete_instance_tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance_type: Option<String>,
+    // New display name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_name: Option<String>,
+}
+
+/// Body for POST /hosts
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpawnRequest {
+    pub distro: String,
+    // Name of one of the user's public keys
+    pub keyname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance_type: Option<String>,
+    // Passed to the cloud provider, run by cloud-init as the host boots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub userdata: Option<String>,
+    // Run by Evergreen once the host is provisioned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub setup_script: Option<String>,
+}
+
+/// Body for PATCH /tasks/{task_id}
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaskUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i64>,
+}
+
+/// Body for POST /api/validate
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ValidateRequest {
+    // Base64 of the project YAML, the server reads it as bytes
+    pub config_yaml: String,
+    // Leave warnings out
+    pub quiet: bool,
+}
+
+/// A problem the server found in a project configuration
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ValidationError {
+    // error, warning or notice
+    #[serde(deserialize_with = "validation_level")]
+    pub level: String,
+    pub message: String,
+    #[serde(flatten, skip_serializing_if = "hide_extra")]
+    pub extra: Map<String, Value>,
+}
+
+/// Read a validation level, older servers send it as a number
+fn validation_level<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(n) => match n.as_i64() {
+            Some(0) => "error".to_owned(),
+            Some(1) => "warning".to_owned(),
+            _ => "notice".to_owned(),
+        },
+        Value::String(s) => s.to_lowercase(),
+        _ => "error".to_owned(),
+    })
+}
+
+#[test]
+fn test_generic_evg_fields() {
+    use evergreen_rs_types::EvgFields;
+
+    #[derive(Serialize, EvgFields)]
+    struct Paginated<'a, T: Clone> {
+        items: Vec<T>,
+        next: Option<&'a str>,
+    }
+
+    let page = Paginated::<u32> {
+        items: vec![1],
+        next: None,
+    };
+    assert_eq!(page.evg_fields(), vec!["items[]", "next"]);
+    assert_eq!(
+        serde_json::to_value(&page).unwrap(),
+        serde_json::json!({"items": [1], "next": null})
+    );
+}
+
+#[test]
+fn test_enum_evg_fields() {
+    use evergreen_rs_types::EvgFields;
+
+    #[derive(Serialize, EvgFields)]
+    struct Reason {
+        code: i64,
+    }
+
+    #[derive(Serialize, EvgFields)]
+    #[serde(untagged)]
+    #[allow(dead_code)]
+    enum Response {
+        Named { status: String, reason: String },
+        Wrapped(Reason),
+        Pair(String, i64),
+        Empty,
+    }
+
+    let named = Response::Named {
+        status: "running".to_owned(),
+        reason: String::new(),
+    };
+    assert_eq!(named.evg_fields(), vec!["status", "reason"]);
+    assert_eq!(
+        Response::Wrapped(Reason { code: 1 }).evg_fields(),
+        vec!["code"]
+    );
+    assert_eq!(
+        Response::Pair("a".to_owned(), 1).evg_fields(),
+        vec!["0", "1"]
+    );
+    assert!(Response::Empty.evg_fields().is_empty());
+}
+
+#[test]
+fn test_evg_field_attributes() {
+    use evergreen_rs_types::EvgFields;
+
+    #[derive(Serialize, EvgFields)]
+    #[allow(dead_code)]
+    struct Credentials {
+        user: String,
+        #[evg(skip)]
+        api_key: String,
+        #[serde(rename = "type")]
+        #[evg(rename = "type")]
+        kind: String,
+    }
+
+    let creds = Credentials {
+        user: String::new(),
+        api_key: String::new(),
+        kind: String::new(),
+    };
+    assert_eq!(creds.evg_fields(), vec!["user", "type"]);
+    assert_eq!(Build::default().evg_fields()[0], "_id");
+}
+
+#[test]
+fn test_nested_evg_fields() {
+    use evergreen_rs_types::EvgFields;
+
+    let fields = Build::default().evg_fields();
+    assert!(fields.contains(&"status_counts.failed".to_owned()));
+    assert!(!fields.contains(&"status_counts".to_owned()));
+
+    #[derive(Serialize, EvgFields)]
+    #[allow(dead_code)]
+    enum Owner {
+        Build {
+            #[evg(nested)]
+            counts: StatusCounts,
+            name: String,
+        },
+    }
+
+    let owner = Owner::Build {
+        counts: StatusCounts::default(),
+        name: String::new(),
+    };
+    assert_eq!(owner.evg_fields()[0], "counts.succeeded");
+    assert_eq!(owner.evg_fields().last().unwrap(), "name");
+}
+
+#[test]
+fn test_collection_evg_fields() {
+    use evergreen_rs_types::EvgFields;
+
+    #[derive(Serialize, EvgFields)]
+    struct Tagged {
+        #[evg(nested)]
+        tags: Vec<Tag>,
+        #[evg(nested)]
+        distro: Option<Distro>,
+        names: Vec<String>,
+    }
+
+    let tagged = Tagged {
+        tags: Vec::new(),
+        distro: None,
+        names: Vec::new(),
+    };
+    assert_eq!(
+        tagged.evg_fields(),
+        vec![
+            "tags[].key",
+            "tags[].value",
+            "tags[].can_be_modified",
+            "distro.distro_id",
+            "distro.provider",
+            "distro.image_id",
+            "names[]",
+        ]
+    );
+}
+
+#[test]
+fn test_evg_values() {
+    use evergreen_rs_types::EvgValues;
+
+    // Fields serde skips are left out
+    let note = AnnotationNote {
+        message: "flaky".to_owned(),
+        source: None,
+        extra: Default::default(),
+    };
+    assert_eq!(
+        note.evg_values(),
+        vec![("message".to_owned(), serde_json::json!("flaky"))]
+    );
+
+    let build = Build::default().evg_values();
+    assert_eq!(build[0], ("_id".to_owned(), serde_json::json!("")));
+    assert!(build.contains(&("status_counts.timed_out".to_owned(), serde_json::json!(0))));
+}
+
+#[test]
+fn test_tuple_struct_evg_fields() {
+    use evergreen_rs_types::{EvgFields, EvgValues};
+
+    #[derive(Serialize, EvgFields, EvgValues)]
+    struct Pair(String, #[evg(nested)] StatusCounts);
+
+    #[derive(Serialize, EvgFields)]
+    struct Wrapper {
+        #[evg(nested)]
+        pair: Pair,
+    }
+
+    let pair = Pair("a".to_owned(), StatusCounts::default());
+    assert_eq!(pair.evg_fields()[..2], ["0", "1.succeeded"]);
+    assert_eq!(
+        pair.evg_values()[0],
+        ("0".to_owned(), serde_json::json!("a"))
+    );
+
+    let wrapper = Wrapper { pair };
+    assert_eq!(wrapper.evg_fields()[0], "pair.0");
+}
+
+#[test]
+fn test_evg_field_macro() {
+    use evergreen_rs_derive::evg_field;
+
+    const COLUMNS: &[&str] = &[
+        evg_field!(Host, host_id),
+        evg_field!(Host, distro.distro_id),
+        evg_field!(Host, instance_tags[].key),
+        evg_field!(Build, id),
+        evg_field!(TaskAnnotation, note.source.author),
+        evg_field!(TaskAnnotation, issues[].source.author),
+    ];
+    assert_eq!(
+        COLUMNS,
+        [
+            "host_id",
+            "distro.distro_id",
+            "instance_tags[].key",
+            "_id",
+            "note.source.author",
+            "issues[].source.author"
+        ]
+    );
+}
+
+#[test]
+fn test_field_enum() {
+    let field: HostField = "distro.distro_id".parse().unwrap();
+    assert_eq!(field, HostField::Distro(DistroField::DistroId));
+    assert_eq!(field.to_string(), "distro.distro_id");
+
+    let tags: HostField = "instance_tags".parse().unwrap();
+    assert_eq!(tags.to_string(), "instance_tags[]");
+    assert_eq!("_id".parse::<BuildField>().unwrap(), BuildField::Id);
+
+    let err = "distro.arch".parse::<HostField>().unwrap_err();
+    assert!(err.starts_with(
+        "Unknown field 'distro.arch', choose from host_id, host_url, distro.distro_id"
+    ));
+    assert!("distro".parse::<HostField>().is_err());
+}
+
+#[test]
+fn test_partial_host() {
+    let host: Host = serde_json::from_str(
+        r#"{
+            "host_id": "h-1",
+            "host_url": null,
+            "distro": { "distro_id": "ubuntu1804-workstation", "image_id": null },
+            "status": "starting",
+            "instance_tags": null,
+            "home_volume_id": null,
+            "unknown_field": 1
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(host.distro.image_id, "");
+    assert_eq!(host.host_url, "");
+    assert!(host.instance_tags.is_empty());
+    assert_eq!(host.home_volume_id, "");
+    assert_eq!(host.expiration_time, None);
+    assert!(!host.is_busy());
+
+    assert!(serde_json::from_str::<Host>(r#"{ "status": "running" }"#).is_err());
+}
+
+#[test]
+fn test_extra_fields() {
+    use evergreen_rs_types::EvgFields;
+
+    let tag: Tag = serde_json::from_str(
+        r#"{ "key": "owner", "value": "mark", "can_be_modified": true, "source": "user" }"#,
+    )
+    .unwrap();
+    assert_eq!(tag.extra["source"], "user");
+    assert!(tag.evg_fields().iter().all(|f| f != "extra"));
+
+    // Hidden unless --show-extra
+    let json = serde_json::to_value(&tag).unwrap();
+    assert!(json.get("source").is_none());
+    assert!(json.get("extra").is_none());
+}
+
+#[test]
+fn test_validation_level() {
+    let errors: Vec<ValidationError> = serde_json::from_str(
+        r#"[{"level": 0, "message": "a"}, {"level": 1, "message": "b"}, {"level": "ERROR", "message": "c"}]"#,
+    )
+    .unwrap();
+    let levels: Vec<&str> = errors.iter().map(|e| e.level.as_str()).collect();
+    assert_eq!(levels, vec!["error", "warning", "error"]);
+}
@@ -0,0 +1,91 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Desktop notifications for `--notify`.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use log::warn;
+use serde_json::Value;
+
+use crate::follow::is_terminal;
+
+/// Fields that identify a record, checked in order
+const ID_FIELDS: &[&str] = &[
+    "id",
+    "host_id",
+    "task_id",
+    "patch_id",
+    "version_id",
+    "build_id",
+];
+
+/// Last status of each record seen while watching
+static SEEN: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Whether a status is worth a notification: a task, version or patch finished, or a host is
+/// ready or gone
+pub fn is_settled(status: &str) -> bool {
+    is_terminal(status) || matches!(status, "running" | "stopped" | "terminated")
+}
+
+/// Show a desktop notification, a missing notification daemon only warns
+pub fn send(summary: &str, body: &str) {
+    let result = notify_rust::Notification::new()
+        .appname("evergreen-rs")
+        .summary(summary)
+        .body(body)
+        .show();
+    if let Err(e) = result {
+        warn!("Failed to show notification: {}", e);
+    }
+}
+
+/// Record the status of `id`, returns true when it changed to a settled status
+///
+/// The first status seen for an ID never counts as a change so starting `--watch` does not
+/// notify about everything already finished.
+fn observe(seen: &mut BTreeMap<String, String>, id: &str, status: &str) -> bool {
+    match seen.insert(id.to_owned(), status.to_owned()) {
+        Some(previous) => previous != status && is_settled(status),
+        None => false,
+    }
+}
+
+/// Notify when a record shown by `--watch` changes to a settled status
+pub fn observe_record(record: &Value) {
+    let id = ID_FIELDS
+        .iter()
+        .find_map(|f| record.get(*f).and_then(Value::as_str));
+    let status = record.get("status").and_then(Value::as_str);
+
+    if let (Some(id), Some(status)) = (id, status) {
+        if observe(&mut SEEN.lock().unwrap(), id, status) {
+            send(&format!("{} is {}", id, status), "");
+        }
+    }
+}
+
+#[test]
+fn test_observe() {
+    let mut seen = BTreeMap::new();
+    assert!(!observe(&mut seen, "h1", "running"));
+    assert!(!observe(&mut seen, "h2", "starting"));
+    assert!(!observe(&mut seen, "h2", "starting"));
+    assert!(observe(&mut seen, "h2", "running"));
+    assert!(!observe(&mut seen, "h1", "running"));
+    assert!(!observe(&mut seen, "h1", "stopping"));
+    assert!(observe(&mut seen, "h1", "stopped"));
+}
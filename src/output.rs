@@ -0,0 +1,1737 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+
+use evergreen_rs_types::{
+    escape_segment, make_name, split_path, EvgFieldEnum, EvgFields, EvgValues, FieldPath,
+};
+
+use crate::color::{self, ColorChoice};
+use crate::locale::Locale;
+use crate::relative;
+use crate::template::Template;
+
+/// Renders a stream of records
+///
+/// Formatters that need every record before writing, e.g. to align columns, buffer them in
+/// `record` and write them in `end`.
+pub trait Formatter {
+    /// Called once with the record's field names, empty when they are not known up front
+    fn begin(&mut self, _fields: &[String], _out: &mut dyn Write) -> Result<()> {
+        Ok(())
+    }
+
+    fn record(&mut self, record: &Value, out: &mut dyn Write) -> Result<()>;
+
+    /// Write a record from the paths and values of its fields, returns false if the formatter
+    /// needs the whole record passed to `record` instead
+    fn record_values(&mut self, _values: &[(String, Value)], _out: &mut dyn Write) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn end(&mut self, _out: &mut dyn Write) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates a fresh formatter for one invocation
+type MakeFormatter = fn(&OutputArgs) -> Box<dyn Formatter>;
+
+/// Every `--output` value and the formatter it creates
+const FORMATTERS: &[(&str, MakeFormatter)] = &[
+    ("flat", |args| {
+        Box::new(FlatFormatter {
+            color: args.use_color(),
+            style: FlatStyle {
+                separator: args.flat_separator.clone(),
+                kv_delim: args.flat_kv_delim.clone(),
+                bracket_indices: args.flat_bracket_indices,
+            },
+        })
+    }),
+    ("json", |_| Box::new(JsonFormatter)),
+    ("ndjson", |_| Box::new(NdjsonFormatter)),
+    ("yaml", |_| Box::new(YamlFormatter)),
+    ("porcelain", |_| Box::new(PorcelainFormatter)),
+    ("table", |args| {
+        Box::new(TableFormatter::new(
+            args.columns.clone(),
+            args.columns.is_empty() && !args.fields.is_empty(),
+            args.locale(),
+            args.use_color(),
+            Some(Utc::now()).filter(|_| !args.absolute_times),
+        ))
+    }),
+    ("csv", |_| Box::new(DelimitedFormatter::new(','))),
+    ("tsv", |_| Box::new(DelimitedFormatter::new('\t'))),
+];
+
+/// A validated `--output` value, a format name or a file whose extension names the format
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputFormat {
+    name: String,
+    path: Option<PathBuf>,
+}
+
+impl OutputFormat {
+    pub fn formatter(&self, args: &OutputArgs) -> Box<dyn Formatter> {
+        let (_, make) = FORMATTERS
+            .iter()
+            .find(|(name, _)| *name == self.name)
+            .expect("OutputFormat is validated on parse");
+        make(args)
+    }
+
+    /// Where records are written, stdout or the file given with e.g. `-o hosts.json`
+    ///
+    /// A file is written under a temporary name that `RecordSink::end` renames into place.
+    fn writer(&self) -> Result<(Box<dyn Write>, Option<PendingFile>)> {
+        Ok(match &self.path {
+            Some(path) => {
+                let pending = PendingFile::new(path);
+                let file = File::create(&pending.temp)?;
+                (Box::new(BufWriter::new(file)), Some(pending))
+            }
+            None => (Box::new(io::stdout()), None),
+        })
+    }
+}
+
+/// A `-o` file being written next to its final path, removed unless the output completes so a
+/// failed command leaves an earlier file alone
+struct PendingFile {
+    temp: PathBuf,
+    path: PathBuf,
+}
+
+impl PendingFile {
+    fn new(path: &Path) -> PendingFile {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        PendingFile {
+            temp: path.with_file_name(format!(".{}.{}.tmp", name, std::process::id())),
+            path: path.to_owned(),
+        }
+    }
+}
+
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.temp);
+    }
+}
+
+fn is_format(name: &str) -> bool {
+    FORMATTERS.iter().any(|(n, _)| *n == name)
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        if is_format(&lower) {
+            return Ok(OutputFormat {
+                name: lower,
+                path: None,
+            });
+        }
+
+        let path = Path::new(s);
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let ext = match ext.as_deref() {
+            Some("yml") => Some("yaml".to_owned()),
+            Some("jsonl") => Some("ndjson".to_owned()),
+            _ => ext,
+        };
+        if let Some(ext) = ext.filter(|e| is_format(e)) {
+            return Ok(OutputFormat {
+                name: ext,
+                path: Some(path.to_owned()),
+            });
+        }
+
+        let names: Vec<_> = FORMATTERS.iter().map(|(name, _)| *name).collect();
+        Err(anyhow!(
+            "Could not parse '{}' as an output type or a file with a known extension, choose from {}",
+            s,
+            names.join(", ")
+        ))
+    }
+}
+
+/// A `--query` JMESPath expression, checked when the arguments are parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query(String);
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        jmespath::compile(s).map_err(|e| anyhow!("Invalid query: {}", e))?;
+        Ok(Query(s.to_owned()))
+    }
+}
+
+impl Query {
+    /// Evaluate against the array of every record, an array result becomes the new records
+    pub fn apply(&self, values: &[Value]) -> Result<Vec<Value>> {
+        let expr = jmespath::compile(&self.0)?;
+        let result = expr.search(Value::Array(values.to_vec()))?;
+        Ok(match serde_json::to_value(&*result)? {
+            Value::Array(values) => values,
+            Value::Null => Vec::new(),
+            value => vec![value],
+        })
+    }
+}
+
+/// A `--sort` value, `field[:asc|:desc]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub path: String,
+    pub descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, descending) = match s.rsplit_once(':') {
+            Some((path, "desc")) => (path, true),
+            Some((path, "asc")) => (path, false),
+            Some((_, order)) => {
+                return Err(anyhow!("Unknown sort order '{}', use asc or desc", order))
+            }
+            None => (s, false),
+        };
+        Ok(SortKey {
+            path: path.to_owned(),
+            descending,
+        })
+    }
+}
+
+/// Compare numbers, and strings that hold numbers, numerically, everything else as text
+///
+/// Missing values sort after present ones.
+fn compare_values(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    fn as_number(v: &Value) -> Option<f64> {
+        match v {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    let a = a.filter(|v| !v.is_null());
+    let b = b.filter(|v| !v.is_null());
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => match (as_number(a), as_number(b)) {
+            (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+            _ => match (a, b) {
+                (Value::String(x), Value::String(y)) => x.cmp(y),
+                _ => a.to_string().cmp(&b.to_string()),
+            },
+        },
+    }
+}
+
+fn sort_values(values: &mut [Value], key: &SortKey) {
+    values.sort_by(|a, b| {
+        let (a, b) = (lookup_path(a, &key.path), lookup_path(b, &key.path));
+        let (a, b) = (a.as_deref(), b.as_deref());
+        if key.descending {
+            // Keep missing values last in both directions
+            match (a.is_none_or(Value::is_null), b.is_none_or(Value::is_null)) {
+                (false, false) => compare_values(b, a),
+                _ => compare_values(a, b),
+            }
+        } else {
+            compare_values(a, b)
+        }
+    });
+}
+
+#[derive(StructOpt, Debug)]
+pub struct OutputArgs {
+    /// Output format, flat|json|ndjson|yaml|porcelain|table|csv|tsv, or a file to write such as
+    /// hosts.json with the format taken from the extension
+    #[structopt(short = "o", long = "output", default_value = "flat")]
+    pub output: OutputFormat,
+
+    /// Columns for table output as flattened field paths, e.g. host_id,distro.distro_id or
+    /// distro.*
+    #[structopt(long, use_delimiter = true)]
+    pub columns: Vec<String>,
+
+    /// Only print these fields, as flattened paths, e.g. host_id,distro.distro_id or distro.*
+    #[structopt(long, use_delimiter = true)]
+    pub fields: Vec<String>,
+
+    /// Filter or reshape the records with a JMESPath expression, e.g. "[?status=='running'].host_id"
+    #[structopt(long)]
+    pub query: Option<Query>,
+
+    /// Sort records by a field path, append :desc for descending order, e.g. uptime:desc, a glob
+    /// must match one field
+    #[structopt(long)]
+    pub sort: Option<SortKey>,
+
+    /// Print each record with a template instead of --output, e.g. '{host_id}\t{distro.distro_id}'
+    #[structopt(long)]
+    pub format: Option<Template>,
+
+    /// Separator between path segments in flat output
+    #[structopt(long, default_value = ".")]
+    pub flat_separator: String,
+
+    /// Delimiter between a path and its value in flat output
+    #[structopt(long, default_value = ":")]
+    pub flat_kv_delim: String,
+
+    /// Write array indices in flat output as a[0] instead of a.0
+    #[structopt(long)]
+    pub flat_bracket_indices: bool,
+
+    /// Highlight statuses in flat and table output, auto|always|never
+    #[structopt(long, default_value = "auto")]
+    pub color: ColorChoice,
+
+    /// Clear the screen and show the output again every interval, 2s if no interval is given
+    #[structopt(long)]
+    pub watch: Option<Option<humantime::Duration>>,
+
+    /// Exit with an error when there are no records, for use as a check in scripts
+    #[structopt(long)]
+    pub fail_if_empty: bool,
+
+    /// Exit with an error when any record has a status matching this regex, e.g. failed
+    #[structopt(long)]
+    pub fail_on_status: Option<Regex>,
+
+    /// Number and date formatting for human readable output, e.g. en-US, de-DE or C
+    #[structopt(long)]
+    pub locale: Option<Locale>,
+
+    /// Include fields the server sent that this version does not know about
+    #[structopt(long)]
+    pub show_extra: bool,
+
+    /// Show timestamps in table output as dates instead of relative to now, e.g. "expires in 3h"
+    #[structopt(long)]
+    pub absolute_times: bool,
+
+    /// Show a desktop notification when `wait` or `follow` finish, or with --watch when a
+    /// record changes to a finished or ready status
+    #[structopt(long)]
+    pub notify: bool,
+}
+
+impl OutputArgs {
+    pub fn locale(&self) -> Locale {
+        self.locale.clone().unwrap_or_else(Locale::default_locale)
+    }
+
+    pub fn watch_interval(&self) -> Option<std::time::Duration> {
+        self.watch
+            .map(|w| w.map_or(std::time::Duration::from_secs(2), |d| *d))
+    }
+
+    /// Never color output written to a file
+    fn use_color(&self) -> bool {
+        self.output.path.is_none() && self.color.enabled()
+    }
+
+    /// Open a sink that writes records as they arrive
+    pub fn sink(&self) -> Result<RecordSink<'_>> {
+        let (out, pending) = self.output.writer()?;
+        Ok(RecordSink {
+            pending,
+            ..self.sink_to(out)
+        })
+    }
+
+    /// Open a sink that writes records to `out` instead of stdout or `-o <file>`
+    pub fn sink_to<'a>(&'a self, out: Box<dyn Write + 'a>) -> RecordSink<'a> {
+        let formatter = match &self.format {
+            Some(template) => Box::new(TemplateFormatter(template.clone())),
+            None => self.output.formatter(self),
+        };
+
+        RecordSink {
+            args: self,
+            formatter,
+            out,
+            pending: None,
+            started: false,
+            // Queries and sorting need every record before anything is written
+            buffered: if self.query.is_some() || self.sort.is_some() {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            fields: Vec::new(),
+            selected: Vec::new(),
+            written: 0,
+            failed_statuses: BTreeSet::new(),
+        }
+    }
+}
+
+/// Levenshtein distance, used to suggest field names
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+fn parse_paths(paths: &[String]) -> Result<Vec<FieldPath>> {
+    paths
+        .iter()
+        .map(|p| FieldPath::parse(p).map_err(|e| anyhow!(e)))
+        .collect()
+}
+
+/// Whether `path` names a known field, a parent of nested fields or a child of a leaf such as
+/// a map
+fn is_related(path: &FieldPath, known: &FieldPath) -> bool {
+    known.starts_with(path) || path.starts_with(known)
+}
+
+fn unknown_field(path: &str, known: &[String]) -> anyhow::Error {
+    let top = path.split('.').next().unwrap_or(path);
+    let mut close: Vec<&str> = known
+        .iter()
+        .filter(|k| edit_distance(k, path) <= 2 || k.contains(top))
+        .map(|k| k.as_str())
+        .collect();
+    if close.is_empty() {
+        close = known.iter().map(|k| k.as_str()).collect();
+    }
+    anyhow!(
+        "Unknown field '{}', did you mean one of: {}",
+        path,
+        close.join(", ")
+    )
+}
+
+/// Check that each requested path is related to a known field
+fn validate_fields(requested: &[String], known: &[String]) -> Result<()> {
+    let known_paths = parse_paths(known)?;
+    for path in requested {
+        let parsed = FieldPath::parse(path).map_err(|e| anyhow!(e))?;
+        if !known_paths.iter().any(|k| is_related(&parsed, k)) {
+            return Err(unknown_field(path, known));
+        }
+    }
+    Ok(())
+}
+
+/// Resolve a `--sort` path against the known fields like `--fields`, it must name one field
+fn resolve_sort_key(key: &SortKey, known: &[String]) -> Result<SortKey> {
+    let resolved = resolve_fields(std::slice::from_ref(&key.path), known)?;
+    match resolved.as_slice() {
+        [path] => Ok(SortKey {
+            path: path.clone(),
+            descending: key.descending,
+        }),
+        _ => Err(anyhow!(
+            "--sort '{}' matches several fields: {}",
+            key.path,
+            resolved.join(", ")
+        )),
+    }
+}
+
+/// Resolve `--fields` against the known fields
+///
+/// A glob such as `distro.*` expands to each field it matches, and a path that is only known
+/// with a different case, e.g. `Host_ID`, takes the known spelling.
+fn resolve_fields(requested: &[String], known: &[String]) -> Result<Vec<String>> {
+    if known.is_empty() {
+        return Ok(requested.to_vec());
+    }
+
+    let known_paths = parse_paths(known)?;
+    let mut out: Vec<String> = Vec::new();
+    for path in requested {
+        let parsed = FieldPath::parse(path).map_err(|e| anyhow!(e))?;
+        if !parsed.is_glob() && known_paths.iter().any(|k| is_related(&parsed, k)) {
+            out.push(path.clone());
+            continue;
+        }
+
+        // Known fields are leaves, so a pattern may match the parent of several of them
+        let mut found = false;
+        for k in known_paths.iter().filter(|k| k.len() >= parsed.len()) {
+            let candidate = k.prefix(parsed.len());
+            if parsed.matches(&candidate) || parsed.eq_ignore_case(&candidate) {
+                found = true;
+                let candidate = candidate.to_string();
+                if !out.contains(&candidate) {
+                    out.push(candidate);
+                }
+            }
+        }
+        if !found {
+            return Err(unknown_field(path, known));
+        }
+    }
+    Ok(out)
+}
+
+/// Copy only the values at `paths` into a new record with the same nesting
+fn select_fields(record: &Value, paths: &[String]) -> Value {
+    let mut out = Value::Object(serde_json::Map::new());
+    for path in paths {
+        if let Some(v) = lookup_path(record, path) {
+            let mut cur = &mut out;
+            for segment in split_path(path) {
+                cur = cur
+                    .as_object_mut()
+                    .expect("only objects are created")
+                    .entry(segment.trim_end_matches("[]"))
+                    .or_insert_with(|| Value::Object(serde_json::Map::new()));
+            }
+            *cur = v.into_owned();
+        }
+    }
+    out
+}
+
+/// Check `path` names a field of the model `T`
+///
+/// The field enum only goes as deep as the nested models, so a path below any other field, e.g.
+/// `instance_tags[].key` or the keys of a map, is accepted once its parent is a field. Those are
+/// looked up in each record like `--fields` with `lookup_path`.
+pub fn parse_selector<T: EvgFieldEnum>(path: &str) -> Result<()> {
+    let err = match path.parse::<T::Field>() {
+        Ok(_) => return Ok(()),
+        Err(e) => e,
+    };
+    let parsed = FieldPath::parse(path).map_err(|e| anyhow!(e))?;
+    let below_leaf =
+        (1..parsed.len()).any(|len| parsed.prefix(len).to_string().parse::<T::Field>().is_ok());
+    if below_leaf {
+        Ok(())
+    } else {
+        Err(anyhow!(err))
+    }
+}
+
+/// Receives records as they are fetched, e.g. one page at a time
+pub trait OutputSink {
+    /// Called once before the first record with the fields of the records
+    fn begin(&mut self, fields: &[String]) -> Result<()>;
+
+    fn record(&mut self, record: Value) -> Result<()>;
+
+    /// Write anything buffered, called once after the last record
+    fn end(&mut self) -> Result<()>;
+}
+
+/// Applies the output arguments and streams records to stdout or a file
+pub struct RecordSink<'a> {
+    args: &'a OutputArgs,
+    formatter: Box<dyn Formatter>,
+    out: Box<dyn Write + 'a>,
+    // The -o file `out` writes to, renamed into place by end
+    pending: Option<PendingFile>,
+    started: bool,
+    // Records held back until end, only for --query and --sort
+    buffered: Option<Vec<Value>>,
+    fields: Vec<String>,
+    // --fields resolved against the fields of the records
+    selected: Vec<String>,
+    written: usize,
+    // Statuses that matched --fail-on-status
+    failed_statuses: BTreeSet<String>,
+}
+
+impl RecordSink<'_> {
+    /// Send model records, the fields come from the first record sent
+    pub fn records<T>(&mut self, records: &[T]) -> Result<()>
+    where
+        T: Serialize + EvgFields + EvgValues + EvgFieldEnum,
+    {
+        if !self.started {
+            self.parse_selectors::<T>()?;
+            if let Some(first) = records.first() {
+                self.begin(&first.evg_fields())?;
+            }
+        }
+        for record in records {
+            // Skip building a Value when nothing needs to look at the whole record
+            if self.writes_directly()
+                && self
+                    .formatter
+                    .record_values(&record.evg_values(), &mut self.out)?
+            {
+                self.written += 1;
+                continue;
+            }
+            self.record(serde_json::to_value(record)?)?;
+        }
+        Ok(())
+    }
+
+    /// Check --columns and --sort name fields of the model, unless --query reshapes it
+    ///
+    /// With --show-extra the records have fields the model does not know, so those are checked
+    /// against the records in `end` instead.
+    fn parse_selectors<T: EvgFieldEnum + EvgFields>(&self) -> Result<()> {
+        if self.args.query.is_some() || self.args.show_extra {
+            return Ok(());
+        }
+        let sort = self.args.sort.iter().map(|key| &key.path);
+        for path in self.args.columns.iter().chain(sort) {
+            if let Err(e) = parse_selector::<T>(path) {
+                // Globs and other cases are resolved like --fields once the records arrive
+                let mut known = Vec::new();
+                T::evg_type_fields("", &mut known);
+                resolve_fields(std::slice::from_ref(path), &known).map_err(|_| e)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether records go straight to the formatter, see `write_record`
+    fn writes_directly(&self) -> bool {
+        self.buffered.is_none()
+            && self.args.fields.is_empty()
+            && self.args.fail_on_status.is_none()
+            && !self.args.show_extra
+            && !(self.args.notify && self.args.watch.is_some())
+    }
+
+    /// Validate the requested paths and start the formatter
+    fn start_formatter(&mut self, fields: &[String]) -> Result<()> {
+        if !fields.is_empty() {
+            if let Some(template) = &self.args.format {
+                validate_fields(&template.paths(), fields)?;
+            }
+        }
+        self.selected = resolve_fields(&self.args.fields, fields)?;
+
+        if self.selected.is_empty() {
+            self.formatter.begin(fields, &mut self.out)
+        } else {
+            self.formatter.begin(&self.selected, &mut self.out)
+        }
+    }
+
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        self.written += 1;
+        if let Some(re) = &self.args.fail_on_status {
+            let mut statuses = Vec::new();
+            record_statuses(record, "", &mut statuses);
+            self.failed_statuses
+                .extend(statuses.into_iter().filter(|s| re.is_match(s)));
+        }
+        if self.args.notify && self.args.watch.is_some() {
+            crate::notify::observe_record(record);
+        }
+
+        if self.selected.is_empty() {
+            self.formatter.record(record, &mut self.out)
+        } else {
+            let selected = select_fields(record, &self.selected);
+            self.formatter.record(&selected, &mut self.out)
+        }
+    }
+}
+
+impl OutputSink for RecordSink<'_> {
+    fn begin(&mut self, fields: &[String]) -> Result<()> {
+        self.started = true;
+        self.fields = fields.to_vec();
+        if self.buffered.is_none() {
+            self.start_formatter(fields)?;
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, record: Value) -> Result<()> {
+        if !self.started {
+            let fields = match &record {
+                Value::Object(obj) => obj.keys().cloned().collect(),
+                _ => Vec::new(),
+            };
+            self.begin(&fields)?;
+        }
+
+        match &mut self.buffered {
+            Some(buffer) => buffer.push(record),
+            None => self.write_record(&record)?,
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if let Some(mut values) = self.buffered.take() {
+            let mut fields = std::mem::take(&mut self.fields);
+            if let Some(query) = &self.args.query {
+                values = query.apply(&values)?;
+                // The result may have a different shape, so take the fields from it rather than
+                // the model
+                fields = match values.first() {
+                    Some(Value::Object(obj)) => obj.keys().cloned().collect(),
+                    _ => Vec::new(),
+                };
+            }
+
+            if let Some(key) = &self.args.sort {
+                let key = resolve_sort_key(key, &fields)?;
+                sort_values(&mut values, &key);
+            }
+
+            self.start_formatter(&fields)?;
+            for value in &values {
+                self.write_record(value)?;
+            }
+        } else if !self.started {
+            self.start_formatter(&[])?;
+        }
+
+        self.formatter.end(&mut self.out)?;
+        self.out.flush()?;
+        if let Some(pending) = self.pending.take() {
+            // Closed first since an open file cannot be renamed on Windows
+            self.out = Box::new(io::sink());
+            std::fs::rename(&pending.temp, &pending.path)?;
+        }
+
+        if self.args.fail_if_empty && self.written == 0 {
+            return Err(anyhow!(
+                "No records found, failing because of --fail-if-empty"
+            ));
+        }
+        if !self.failed_statuses.is_empty() {
+            let statuses: Vec<_> = self.failed_statuses.iter().cloned().collect();
+            return Err(anyhow!(
+                "Found records with a status matching --fail-on-status: {}",
+                statuses.join(", ")
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Collect the values of every status field, e.g. status and distro.status
+fn record_statuses(v: &Value, path: &str, out: &mut Vec<String>) {
+    match v {
+        Value::Object(obj) => {
+            for (k, v) in obj {
+                record_statuses(v, &make_name(path, k), out);
+            }
+        }
+        Value::Array(arr) => {
+            for (i, v) in arr.iter().enumerate() {
+                record_statuses(v, &format!("{}.{}", path, i), out);
+            }
+        }
+        Value::String(s) if color::is_status_field(path) => out.push(s.clone()),
+        _ => {}
+    }
+}
+
+fn write_values(fields: &[String], values: &[Value], args: &OutputArgs) -> Result<()> {
+    let mut sink = args.sink()?;
+    sink.begin(fields)?;
+    for value in values {
+        sink.record(value.clone())?;
+    }
+    sink.end()
+}
+
+/// Write records to stdout with the chosen formatter
+pub fn print_records<T: Serialize + EvgFields + EvgValues + EvgFieldEnum>(
+    records: &[T],
+    args: &OutputArgs,
+) -> Result<()> {
+    let mut sink = args.sink()?;
+    sink.records(records)?;
+    sink.end()
+}
+
+pub fn print_record<T: Serialize + EvgFields + EvgValues + EvgFieldEnum>(
+    record: &T,
+    args: &OutputArgs,
+) -> Result<()> {
+    print_records(std::slice::from_ref(record), args)
+}
+
+/// Write a record that is not a model, e.g. a map, its top level keys are used as the fields
+pub fn print_value<T: Serialize>(record: &T, args: &OutputArgs) -> Result<()> {
+    let value = serde_json::to_value(record)?;
+    let fields = match &value {
+        Value::Object(obj) => obj.keys().cloned().collect(),
+        _ => Vec::new(),
+    };
+    write_values(&fields, &[value], args)
+}
+
+/// Find the value at a dotted path
+///
+/// A `name[]` segment picks the rest of the path from each member of the list, e.g.
+/// `instance_tags[].key` is the list of tag keys. The `[]` may be left out when the next segment
+/// is not an index, as in `instance_tags.key`. Dots in a key are escaped with `\`.
+pub fn lookup_path<'a>(record: &'a Value, path: &str) -> Option<Cow<'a, Value>> {
+    lookup_segments(record, &split_path(path))
+}
+
+fn lookup_segments<'a>(record: &'a Value, segments: &[Cow<str>]) -> Option<Cow<'a, Value>> {
+    let mut cur = record;
+    for (i, segment) in segments.iter().enumerate() {
+        if let Some(name) = segment.strip_suffix("[]") {
+            let list = cur.get(name)?;
+            let rest = &segments[i + 1..];
+            if rest.is_empty() {
+                return Some(Cow::Borrowed(list));
+            }
+
+            let members = list
+                .as_array()?
+                .iter()
+                .filter_map(|v| lookup_segments(v, rest).map(Cow::into_owned))
+                .collect();
+            return Some(Cow::Owned(Value::Array(members)));
+        }
+
+        cur = match cur {
+            Value::Object(obj) => obj.get(segment.as_ref())?,
+            Value::Array(arr) => match segment.parse::<usize>() {
+                Ok(index) => arr.get(index)?,
+                Err(_) => {
+                    let members = arr
+                        .iter()
+                        .filter_map(|v| lookup_segments(v, &segments[i..]).map(Cow::into_owned))
+                        .collect();
+                    return Some(Cow::Owned(Value::Array(members)));
+                }
+            },
+            _ => return None,
+        };
+    }
+    Some(Cow::Borrowed(cur))
+}
+
+/// `path:value` lines with a blank line after each record
+struct FlatFormatter {
+    color: bool,
+    style: FlatStyle,
+}
+
+impl FlatFormatter {
+    fn write_flat(&self, flat: &str, out: &mut dyn Write) -> Result<()> {
+        if !self.color {
+            writeln!(out, "{}", flat)?;
+            return Ok(());
+        }
+
+        for line in flat.lines() {
+            match line.split_once(self.style.kv_delim.as_str()) {
+                Some((path, value)) if color::is_status_field(path) => writeln!(
+                    out,
+                    "{}{}{}",
+                    path,
+                    self.style.kv_delim,
+                    color::paint_status(value, value)
+                )?,
+                _ => writeln!(out, "{}", line)?,
+            }
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+impl Formatter for FlatFormatter {
+    fn record(&mut self, record: &Value, out: &mut dyn Write) -> Result<()> {
+        let flat = to_flat_json_with(record, &self.style)?;
+        self.write_flat(&flat, out)
+    }
+
+    fn record_values(&mut self, values: &[(String, Value)], out: &mut dyn Write) -> Result<bool> {
+        let mut flat = String::new();
+        for (path, value) in values {
+            let path = if self.style.separator == "." {
+                Cow::Borrowed(path.as_str())
+            } else {
+                Cow::Owned(split_path(path).join(&self.style.separator))
+            };
+            to_flat_json_int(value, &path, &self.style, &mut flat)?;
+        }
+        self.write_flat(&flat, out)?;
+        Ok(true)
+    }
+}
+
+/// One line per record from a `--format` template
+struct TemplateFormatter(Template);
+
+impl Formatter for TemplateFormatter {
+    fn record(&mut self, record: &Value, out: &mut dyn Write) -> Result<()> {
+        writeln!(out, "{}", self.0.render(record))?;
+        Ok(())
+    }
+}
+
+/// Pretty printed JSON for each record
+struct JsonFormatter;
+
+impl Formatter for JsonFormatter {
+    fn record(&mut self, record: &Value, out: &mut dyn Write) -> Result<()> {
+        writeln!(out, "{}", serde_json::to_string_pretty(record)?)?;
+        Ok(())
+    }
+}
+
+/// One compact JSON object per line, for `jq -c` and log shippers
+struct NdjsonFormatter;
+
+impl Formatter for NdjsonFormatter {
+    fn record(&mut self, record: &Value, out: &mut dyn Write) -> Result<()> {
+        writeln!(out, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+}
+
+/// A YAML document for each record
+struct YamlFormatter;
+
+impl Formatter for YamlFormatter {
+    fn record(&mut self, record: &Value, out: &mut dyn Write) -> Result<()> {
+        write!(out, "{}", serde_yaml::to_string(record)?)?;
+        Ok(())
+    }
+}
+
+/// One line per record of tab separated `path=value` pairs, stable for scripts
+///
+/// Tabs, newlines and backslashes in values are escaped so every record stays on one line.
+struct PorcelainFormatter;
+
+fn escape_porcelain(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+impl Formatter for PorcelainFormatter {
+    fn record(&mut self, record: &Value, out: &mut dyn Write) -> Result<()> {
+        let flat = to_flat_json(record)?;
+        let fields: Vec<String> = flat
+            .lines()
+            .map(|l| match l.split_once(':') {
+                Some((k, v)) => format!("{}={}", k, escape_porcelain(v)),
+                None => escape_porcelain(l),
+            })
+            .collect();
+        writeln!(out, "{}", fields.join("\t"))?;
+        Ok(())
+    }
+}
+
+/// Column aligned table, buffered so every column fits its widest value
+struct TableFormatter {
+    columns: Vec<String>,
+    // Take the columns from the --fields passed to begin
+    columns_from_fields: bool,
+    locale: Locale,
+    color: bool,
+    // Show timestamps relative to this time instead of as dates
+    now: Option<DateTime<Utc>>,
+    rows: Vec<Value>,
+    // The top level scalar fields of the first record, used when no columns are given
+    default_columns: Vec<String>,
+}
+
+impl TableFormatter {
+    fn new(
+        columns: Vec<String>,
+        columns_from_fields: bool,
+        locale: Locale,
+        color: bool,
+        now: Option<DateTime<Utc>>,
+    ) -> TableFormatter {
+        TableFormatter {
+            columns,
+            columns_from_fields,
+            locale,
+            color,
+            now,
+            rows: Vec::new(),
+            default_columns: Vec::new(),
+        }
+    }
+}
+
+impl TableFormatter {
+    fn cell(&self, row: &Value, column: &str) -> String {
+        let value = match lookup_path(row, column) {
+            Some(v) => v,
+            None => return String::new(),
+        };
+        let lookup = |path: &str| lookup_path(row, path).map(Cow::into_owned);
+        self.now
+            .and_then(|now| relative::describe(column, &value, lookup, now))
+            .unwrap_or_else(|| self.locale.format_value(&value))
+    }
+}
+
+impl Formatter for TableFormatter {
+    fn begin(&mut self, fields: &[String], _out: &mut dyn Write) -> Result<()> {
+        if self.columns_from_fields {
+            self.columns = fields.to_vec();
+        } else {
+            self.columns = resolve_fields(&self.columns, fields)?;
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, record: &Value, _out: &mut dyn Write) -> Result<()> {
+        if self.rows.is_empty() {
+            if let Value::Object(obj) = record {
+                self.default_columns = obj
+                    .iter()
+                    .filter(|(_, v)| !v.is_object() && !v.is_array())
+                    .map(|(k, _)| k.clone())
+                    .collect();
+            }
+        }
+        self.rows.push(record.clone());
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> Result<()> {
+        let columns = if self.columns.is_empty() {
+            &self.default_columns
+        } else {
+            &self.columns
+        };
+
+        let mut table: Vec<Vec<String>> = vec![columns.iter().map(|c| c.to_uppercase()).collect()];
+        for row in &self.rows {
+            table.push(columns.iter().map(|c| self.cell(row, c)).collect());
+        }
+
+        let widths: Vec<usize> = (0..columns.len())
+            .map(|i| {
+                table
+                    .iter()
+                    .map(|r| r[i].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        for (i, row) in table.iter().enumerate() {
+            let mut line = String::new();
+            for ((cell, width), column) in row.iter().zip(&widths).zip(columns) {
+                // Pad outside the color codes so they do not count towards the width
+                let padding = " ".repeat(width - cell.chars().count() + 2);
+                if self.color && i > 0 && color::is_status_field(column) {
+                    line += &color::paint_status(cell, cell);
+                } else {
+                    line += cell;
+                }
+                line += &padding;
+            }
+            writeln!(out, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// CSV or TSV with a header row of the record's fields
+///
+/// Nested objects and arrays are written as compact JSON in a single cell.
+struct DelimitedFormatter {
+    delimiter: char,
+    fields: Vec<String>,
+}
+
+impl DelimitedFormatter {
+    fn new(delimiter: char) -> DelimitedFormatter {
+        DelimitedFormatter {
+            delimiter,
+            fields: Vec::new(),
+        }
+    }
+
+    fn escape(&self, s: &str) -> String {
+        if self.delimiter == '\t' {
+            // TSV has no quoting, escape the characters that would break the row instead
+            return escape_porcelain(s);
+        }
+
+        if s.contains(self.delimiter) || s.contains('"') || s.contains('\n') || s.contains('\r') {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_owned()
+        }
+    }
+
+    fn write_row(&self, cells: &[String], out: &mut dyn Write) -> Result<()> {
+        let row: Vec<String> = cells.iter().map(|c| self.escape(c)).collect();
+        writeln!(out, "{}", row.join(&self.delimiter.to_string()))?;
+        Ok(())
+    }
+}
+
+impl Formatter for DelimitedFormatter {
+    fn begin(&mut self, fields: &[String], out: &mut dyn Write) -> Result<()> {
+        self.fields = fields.to_vec();
+        self.write_row(&self.fields, out)
+    }
+
+    fn record(&mut self, record: &Value, out: &mut dyn Write) -> Result<()> {
+        let cells: Vec<String> = self
+            .fields
+            .iter()
+            .map(|f| match lookup_path(record, f).as_deref() {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => s.clone(),
+                Some(v) => v.to_string(),
+            })
+            .collect();
+        self.write_row(&cells, out)
+    }
+}
+
+/// How flat output joins paths and values
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatStyle {
+    // Between the segments of a path
+    pub separator: String,
+    // Between a path and its value
+    pub kv_delim: String,
+    // Write array indices as a[0] instead of a.0
+    pub bracket_indices: bool,
+}
+
+impl FlatStyle {
+    /// The path of `key` under `prefix`, keys are only escaped when dots separate the path
+    fn join(&self, prefix: &str, key: &str) -> String {
+        let key = if self.separator == "." {
+            escape_segment(key)
+        } else {
+            Cow::Borrowed(key)
+        };
+        if prefix.is_empty() {
+            key.into_owned()
+        } else {
+            format!("{}{}{}", prefix, self.separator, key)
+        }
+    }
+}
+
+impl Default for FlatStyle {
+    fn default() -> Self {
+        FlatStyle {
+            separator: ".".to_owned(),
+            kv_delim: ":".to_owned(),
+            bracket_indices: false,
+        }
+    }
+}
+
+fn to_flat_json_int(
+    v: &Value,
+    prefix: &str,
+    style: &FlatStyle,
+    writer: &mut dyn FmtWrite,
+) -> Result<()> {
+    match v {
+        Value::Null => {
+            writeln!(writer, "{}{}null", prefix, style.kv_delim)?;
+        }
+        Value::String(s) => {
+            writeln!(writer, "{}{}{}", prefix, style.kv_delim, s)?;
+        }
+        Value::Number(n) => {
+            writeln!(writer, "{}{}{}", prefix, style.kv_delim, n)?;
+        }
+        Value::Bool(b) => {
+            writeln!(writer, "{}{}{}", prefix, style.kv_delim, b)?;
+        }
+        Value::Object(o) => {
+            for (key, value) in o.iter() {
+                to_flat_json_int(value, &style.join(prefix, key), style, writer)?;
+            }
+        }
+        Value::Array(arr) => {
+            for (i, member) in arr.iter().enumerate() {
+                let path = if style.bracket_indices {
+                    format!("{}[{}]", prefix, i)
+                } else if prefix.is_empty() {
+                    format!("{}", i)
+                } else {
+                    format!("{}{}{}", prefix, style.separator, i)
+                };
+                to_flat_json_int(member, &path, style, writer)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Flatten a value into `path:value` lines
+pub fn to_flat_json(v: &Value) -> Result<String> {
+    to_flat_json_with(v, &FlatStyle::default())
+}
+
+pub fn to_flat_json_with(v: &Value, style: &FlatStyle) -> Result<String> {
+    let mut r = String::new();
+    to_flat_json_int(v, "", style, &mut r)?;
+    Ok(r)
+}
+
+#[test]
+fn test_output_format_parse() {
+    assert_eq!(
+        "JSON".parse::<OutputFormat>().unwrap(),
+        OutputFormat {
+            name: "json".to_owned(),
+            path: None
+        }
+    );
+    assert_eq!(
+        "out/Hosts.YML".parse::<OutputFormat>().unwrap(),
+        OutputFormat {
+            name: "yaml".to_owned(),
+            path: Some(PathBuf::from("out/Hosts.YML"))
+        }
+    );
+    assert!("xml".parse::<OutputFormat>().is_err());
+}
+
+#[test]
+fn test_sink_to_file() {
+    let path = std::env::temp_dir().join(format!("evg-sink-{}.ndjson", std::process::id()));
+    let args = OutputArgs::from_iter(&["test", "-o", path.to_str().unwrap(), "--fields", "a"]);
+
+    let mut sink = args.sink().unwrap();
+    sink.record(serde_json::json!({"a": 1, "b": 2})).unwrap();
+    sink.record(serde_json::json!({"a": 3, "b": 4})).unwrap();
+    // Nothing is in place until the output is complete
+    assert!(!path.exists());
+    sink.end().unwrap();
+    drop(sink);
+
+    // An output that never ends leaves the file as it was
+    let mut sink = args.sink().unwrap();
+    sink.record(serde_json::json!({"a": 5})).unwrap();
+    drop(sink);
+
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(written, "{\"a\":1}\n{\"a\":3}\n");
+    let leftovers = std::fs::read_dir(std::env::temp_dir())
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            name.starts_with(".evg-sink-") && name.ends_with(".tmp")
+        })
+        .count();
+    assert_eq!(leftovers, 0);
+}
+
+#[test]
+fn test_watch_interval() {
+    use std::time::Duration;
+
+    let interval = |argv: &[&str]| OutputArgs::from_iter(argv).watch_interval();
+    assert_eq!(interval(&["test"]), None);
+    assert_eq!(interval(&["test", "--watch"]), Some(Duration::from_secs(2)));
+    assert_eq!(
+        interval(&["test", "--watch", "1m"]),
+        Some(Duration::from_secs(60))
+    );
+}
+
+#[test]
+fn test_fail_checks() {
+    let mut out = Vec::new();
+    let args = OutputArgs::from_iter(&["test", "-o", "ndjson", "--fail-if-empty"]);
+    let mut sink = args.sink_to(Box::new(&mut out));
+    assert!(sink.end().is_err());
+    drop(sink);
+    assert!(out.is_empty());
+
+    let args = OutputArgs::from_iter(&["test", "-o", "ndjson", "--fail-on-status", "fail"]);
+    let mut sink = args.sink_to(Box::new(&mut out));
+    sink.record(serde_json::json!({"status": "success"}))
+        .unwrap();
+    sink.record(serde_json::json!({"status": "success", "tasks": [{"status": "failed"}]}))
+        .unwrap();
+    assert_eq!(
+        sink.end().unwrap_err().to_string(),
+        "Found records with a status matching --fail-on-status: failed"
+    );
+    drop(sink);
+    // The records are still written before failing
+    assert_eq!(String::from_utf8(out).unwrap().lines().count(), 2);
+}
+
+#[test]
+fn test_porcelain() {
+    let mut out = Vec::new();
+    PorcelainFormatter
+        .record(&serde_json::json!({"a": "x\ty", "b": {"c": 1}}), &mut out)
+        .unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "a=x\\ty\tb.c=1\n");
+}
+
+#[test]
+fn test_ndjson() {
+    let mut out = Vec::new();
+    let mut f = NdjsonFormatter;
+    f.record(&serde_json::json!({"a": {"b": [1, 2]}}), &mut out)
+        .unwrap();
+    f.record(&serde_json::json!({"a": "x"}), &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\"a\":{\"b\":[1,2]}}\n{\"a\":\"x\"}\n"
+    );
+}
+
+#[test]
+fn test_table() {
+    let mut out = Vec::new();
+    let mut table = TableFormatter::new(
+        vec!["id".to_owned(), "d.name".to_owned(), "n".to_owned()],
+        false,
+        "en-US".parse().unwrap(),
+        false,
+        None,
+    );
+    table
+        .record(
+            &serde_json::json!({"id": "a", "d": {"name": "long"}, "n": 1234}),
+            &mut out,
+        )
+        .unwrap();
+    table
+        .record(&serde_json::json!({"id": "bbbbbb", "d": {}}), &mut out)
+        .unwrap();
+    table.end(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "ID      D.NAME  N\na       long    1,234\nbbbbbb\n"
+    );
+}
+
+#[test]
+fn test_table_relative_times() {
+    let now = DateTime::parse_from_rfc3339("2020-06-03T12:00:00Z")
+        .unwrap()
+        .with_timezone(&Utc);
+    let record = serde_json::json!({
+        "id": "t1",
+        "start_time": "2020-06-03T10:00:00Z",
+        "finish_time": "2020-06-03T10:42:00Z"
+    });
+    let columns = vec!["id".to_owned(), "finish_time".to_owned()];
+
+    let mut out = Vec::new();
+    let mut table = TableFormatter::new(
+        columns.clone(),
+        false,
+        "C".parse().unwrap(),
+        false,
+        Some(now),
+    );
+    table.record(&record, &mut out).unwrap();
+    table.end(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "ID  FINISH_TIME\nt1  ran 42m\n"
+    );
+
+    let mut out = Vec::new();
+    let mut table = TableFormatter::new(columns, false, "ISO".parse().unwrap(), false, None);
+    table.record(&record, &mut out).unwrap();
+    table.end(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "ID  FINISH_TIME\nt1  2020-06-03 10:42\n"
+    );
+}
+
+#[test]
+fn test_csv() {
+    let mut out = Vec::new();
+    let mut csv = DelimitedFormatter::new(',');
+    csv.begin(&["a".to_owned(), "b".to_owned()], &mut out)
+        .unwrap();
+    csv.record(&serde_json::json!({"a": "x,y", "b": {"c": 1}}), &mut out)
+        .unwrap();
+    csv.record(&serde_json::json!({"a": "say \"hi\""}), &mut out)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "a,b\n\"x,y\",\"{\"\"c\"\":1}\"\n\"say \"\"hi\"\"\",\n"
+    );
+}
+
+#[test]
+fn test_fields() {
+    let known = vec!["host_id".to_owned(), "distro".to_owned()];
+    assert!(validate_fields(&["distro.distro_id".to_owned()], &known).is_ok());
+    let nested = vec!["host_id".to_owned(), "distro.distro_id".to_owned()];
+    assert!(validate_fields(&["distro".to_owned()], &nested).is_ok());
+    assert!(validate_fields(&["distro.distro_id".to_owned()], &nested).is_ok());
+    assert!(validate_fields(&["distro.arch".to_owned()], &nested).is_err());
+    assert!(validate_fields(&["dist".to_owned()], &nested).is_err());
+    let err = validate_fields(&["hots_id".to_owned()], &known).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Unknown field 'hots_id', did you mean one of: host_id"
+    );
+
+    let record = serde_json::json!({"host_id": "h", "distro": {"distro_id": "d", "arch": "x"}});
+    assert_eq!(
+        select_fields(
+            &record,
+            &["distro.distro_id".to_owned(), "missing".to_owned()]
+        ),
+        serde_json::json!({"distro": {"distro_id": "d"}})
+    );
+}
+
+#[test]
+fn test_resolve_fields() {
+    let known: Vec<String> = ["host_id", "distro.distro_id", "distro.arch", "tags[]"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let resolve = |requested: &[&str]| {
+        let requested: Vec<String> = requested.iter().map(|s| s.to_string()).collect();
+        resolve_fields(&requested, &known)
+    };
+
+    assert_eq!(
+        resolve(&["distro.*"]).unwrap(),
+        vec!["distro.distro_id", "distro.arch"]
+    );
+    assert_eq!(
+        resolve(&["*"]).unwrap(),
+        vec!["host_id", "distro", "tags[]"]
+    );
+    assert_eq!(
+        resolve(&["*_id", "distro.*_id"]).unwrap(),
+        vec!["host_id", "distro.distro_id"]
+    );
+    assert_eq!(
+        resolve(&["Host_ID", "DISTRO"]).unwrap(),
+        vec!["host_id", "distro"]
+    );
+    assert_eq!(resolve(&["tags.key"]).unwrap(), vec!["tags.key"]);
+    assert!(resolve(&["build.*"]).is_err());
+    assert!(resolve(&["distro..arch"]).is_err());
+
+    // Without known fields the paths are used as given
+    assert_eq!(
+        resolve_fields(&["a.*".to_owned()], &[]).unwrap(),
+        vec!["a.*"]
+    );
+}
+
+#[test]
+fn test_query() {
+    let values = vec![
+        serde_json::json!({"id": "a", "status": "running"}),
+        serde_json::json!({"id": "b", "status": "terminated"}),
+    ];
+    let query: Query = "[?status=='running'].{id: id}".parse().unwrap();
+    assert_eq!(
+        query.apply(&values).unwrap(),
+        vec![serde_json::json!({"id": "a"})]
+    );
+
+    let query: Query = "length(@)".parse().unwrap();
+    assert_eq!(query.apply(&values).unwrap(), vec![serde_json::json!(2)]);
+    assert!("[?".parse::<Query>().is_err());
+}
+
+#[test]
+fn test_sort() {
+    let mut values = vec![
+        serde_json::json!({"id": "a", "n": "10"}),
+        serde_json::json!({"id": "b"}),
+        serde_json::json!({"id": "c", "n": 9}),
+        serde_json::json!({"id": "d", "n": 100.5}),
+    ];
+    let ids = |values: &[Value]| -> Vec<String> {
+        values
+            .iter()
+            .map(|v| v["id"].as_str().unwrap().to_owned())
+            .collect()
+    };
+
+    sort_values(&mut values, &"n".parse().unwrap());
+    assert_eq!(ids(&values), vec!["c", "a", "d", "b"]);
+    sort_values(&mut values, &"n:desc".parse().unwrap());
+    assert_eq!(ids(&values), vec!["d", "a", "c", "b"]);
+    assert!("n:sideways".parse::<SortKey>().is_err());
+}
+
+#[test]
+fn test_columns_and_sort_resolve() {
+    use crate::models::{Distro, Host};
+
+    let host = |id: &str, distro: &str| Host {
+        host_id: id.to_owned(),
+        distro: Distro {
+            distro_id: distro.to_owned(),
+            provider: "ec2".to_owned(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let hosts = vec![host("h-1", "ubuntu"), host("h-2", "rhel")];
+    let table = |argv: &[&str]| -> Result<String> {
+        let mut argv = [&["test", "-o", "table"], argv].concat();
+        argv.push("--color=never");
+        let args = OutputArgs::from_iter(&argv);
+        let mut out = Vec::new();
+        let mut sink = args.sink_to(Box::new(&mut out));
+        sink.records(&hosts)?;
+        sink.end()?;
+        drop(sink);
+        Ok(String::from_utf8(out).unwrap())
+    };
+
+    let written = table(&[
+        "--columns",
+        "HOST_ID,distro.*",
+        "--sort",
+        "Distro.Distro_ID",
+    ])
+    .unwrap();
+    let lines: Vec<&str> = written.lines().map(str::trim_end).collect();
+    assert_eq!(
+        lines[0].split_whitespace().collect::<Vec<_>>(),
+        [
+            "HOST_ID",
+            "DISTRO.DISTRO_ID",
+            "DISTRO.PROVIDER",
+            "DISTRO.IMAGE_ID"
+        ]
+    );
+    assert!(lines[1].starts_with("h-2"), "{}", written);
+
+    let err = table(&["--sort", "distro.*"]).unwrap_err().to_string();
+    assert!(
+        err.starts_with("--sort 'distro.*' matches several fields"),
+        "{}",
+        err
+    );
+    assert!(table(&["--columns", "distro.nope*"]).is_err());
+}
+
+#[test]
+fn test_parse_selector() {
+    use crate::models::{Host, HostEvent};
+
+    assert!(parse_selector::<Host>("distro.distro_id").is_ok());
+    assert!(parse_selector::<Host>("instance_tags[].key").is_ok());
+    assert!(parse_selector::<Host>("instance_tags.key").is_ok());
+    assert!(parse_selector::<HostEvent>("data.hostname").is_ok());
+    assert!(parse_selector::<Host>("distro.nope").is_err());
+    assert!(parse_selector::<Host>("nope.key").is_err());
+}
+
+#[test]
+fn test_lookup_path() {
+    let v = serde_json::json!({"a": {"b": [1, {"c": 2}]}});
+    assert_eq!(
+        lookup_path(&v, "a.b.1.c").as_deref(),
+        Some(&serde_json::json!(2))
+    );
+    assert_eq!(lookup_path(&v, "a.x"), None);
+
+    let host = serde_json::json!({"tags": [{"key": "a"}, {"key": "b"}, {"value": "c"}]});
+    assert_eq!(
+        lookup_path(&host, "tags[].key").as_deref(),
+        Some(&serde_json::json!(["a", "b"]))
+    );
+    assert_eq!(lookup_path(&host, "tags[]").as_deref(), host.get("tags"));
+    assert_eq!(
+        lookup_path(&host, "tags.key").as_deref(),
+        Some(&serde_json::json!(["a", "b"]))
+    );
+
+    let tags = serde_json::json!({"tags": {"build.id": "b1"}});
+    assert_eq!(
+        lookup_path(&tags, "tags.build\\.id").as_deref(),
+        Some(&serde_json::json!("b1"))
+    );
+    assert_eq!(lookup_path(&tags, "tags.build.id"), None);
+    assert_eq!(select_fields(&tags, &["tags.build\\.id".to_owned()]), tags);
+}
+
+#[test]
+fn test_flat_json_array() {
+    assert_eq! { to_flat_json(&serde_json::json!(["a","b"])).unwrap(),
+r#"0:a
+1:b
+"#};
+}
+
+#[test]
+fn test_flat_json_obj() {
+    assert_eq! { to_flat_json(&serde_json::json!({"a":"b", "n":42})).unwrap(),
+r#"a:b
+n:42
+"#};
+}
+
+#[test]
+fn test_flat_json_obj_nested() {
+    assert_eq! { to_flat_json(&serde_json::json!({"a": { "n":42 } })).unwrap(),
+r#"a.n:42
+"#};
+}
+
+#[test]
+fn test_flat_json_array_obj_nested() {
+    assert_eq! { to_flat_json(&serde_json::json!([{"a": { "n":42 } }])).unwrap(),
+r#"0.a.n:42
+"#};
+}
+
+#[test]
+fn test_flat_json_style() {
+    let style = FlatStyle {
+        separator: "/".to_owned(),
+        kv_delim: "=".to_owned(),
+        bracket_indices: true,
+    };
+    assert_eq! { to_flat_json_with(&serde_json::json!({"a": [{ "n":42 }], "b": {"c": null}}), &style).unwrap(),
+r#"a[0]/n=42
+b/c=null
+"#};
+}
+
+#[test]
+fn test_flat_json_dotted_keys() {
+    let v = serde_json::json!({"tags": {"build.id": "b1"}});
+    assert_eq!(to_flat_json(&v).unwrap(), "tags.build\\.id:b1\n");
+
+    let style = FlatStyle {
+        separator: "/".to_owned(),
+        ..FlatStyle::default()
+    };
+    assert_eq!(to_flat_json_with(&v, &style).unwrap(), "tags/build.id:b1\n");
+}
+
+// Run with `cargo test --release bench_flatten -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn bench_flatten_host_list() {
+    let host = serde_json::json!({
+        "host_id": "i-0123456789abcdef0",
+        "host_url": "ec2-1-2-3-4.compute-1.amazonaws.com",
+        "distro": {"distro_id": "ubuntu1804-small", "provider": "ec2-ondemand", "image_id": "ami-0123"},
+        "provisioned": true,
+        "started_by": "mark.benvenuto",
+        "host_type": "m5.xlarge",
+        "user": "ubuntu",
+        "status": "running",
+        "instance_tags": [
+            {"key": "name", "value": "x", "can_be_modified": false},
+            {"key": "owner", "value": "y", "can_be_modified": true}
+        ],
+        "user_host": true,
+        "no_expiration": false,
+        "expiration_time": "2020-05-20T14:03:00Z"
+    });
+    let hosts: Vec<Value> = (0..20000).map(|_| host.clone()).collect();
+
+    let start = std::time::Instant::now();
+    let bytes: usize = hosts.iter().map(|h| to_flat_json(h).unwrap().len()).sum();
+    println!(
+        "Flattened {} hosts, {} bytes, in {:?}",
+        hosts.len(),
+        bytes,
+        start.elapsed()
+    );
+}
+
+#[test]
+fn test_flat_from_values() {
+    let build = crate::models::Build {
+        id: "b1".to_owned(),
+        tasks: vec!["t1".to_owned(), "t2".to_owned()],
+        ..Default::default()
+    };
+    let mut formatter = FlatFormatter {
+        color: false,
+        style: FlatStyle::default(),
+    };
+
+    let (mut direct, mut via_value) = (Vec::new(), Vec::new());
+    assert!(formatter
+        .record_values(&build.evg_values(), &mut direct)
+        .unwrap());
+    formatter
+        .record(&serde_json::to_value(&build).unwrap(), &mut via_value)
+        .unwrap();
+
+    // Values keep the model's field order while a Value sorts its keys
+    let lines = |out: Vec<u8>| {
+        let mut lines: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| l.to_owned())
+            .collect();
+        lines.sort();
+        lines
+    };
+    let direct = lines(direct);
+    assert!(direct.contains(&"status_counts.failed:0".to_owned()));
+    assert!(direct.contains(&"tasks.1:t2".to_owned()));
+    assert_eq!(direct, lines(via_value));
+}
@@ -0,0 +1,141 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A patch at a glance, its tasks counted by build variant.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use evergreen_rs_derive::{EvgFields, EvgValues};
+
+use crate::client::EvergreenClient;
+use crate::models::{Patch, Task};
+use crate::tasks;
+
+#[derive(Debug, Default, PartialEq, Serialize, EvgFields, EvgValues)]
+pub struct VariantStatus {
+    pub build_variant: String,
+    pub passed: i64,
+    pub failed: i64,
+    // Dispatched or started
+    pub running: i64,
+    // Waiting to be dispatched, or not scheduled
+    pub pending: i64,
+    // Links to the failed tasks
+    pub failing: Vec<String>,
+}
+
+/// Fetch the patch and every task of its version
+pub fn patch_tasks(client: &EvergreenClient, patch_id: &str) -> Result<(Patch, Vec<Task>)> {
+    let patch = client.get_patch(patch_id)?;
+    if patch.version.is_empty() {
+        return Err(anyhow!(
+            "Patch {} has not been scheduled, it has no tasks yet",
+            patch_id
+        ));
+    }
+
+    let tasks = tasks::version_tasks(client, &patch.version)?;
+    Ok((patch, tasks))
+}
+
+/// Count the tasks of each build variant, failed tasks link to `ui_server`
+pub fn summarize_variants(tasks: &[Task], ui_server: &str) -> Vec<VariantStatus> {
+    let mut variants: BTreeMap<&str, VariantStatus> = BTreeMap::new();
+    for task in tasks {
+        let variant = variants
+            .entry(task.build_variant.as_str())
+            .or_insert_with(|| VariantStatus {
+                build_variant: task.build_variant.clone(),
+                ..Default::default()
+            });
+        match task.status.as_str() {
+            "success" => variant.passed += 1,
+            "failed" => {
+                variant.failed += 1;
+                variant
+                    .failing
+                    .push(format!("{}/task/{}", ui_server, task.task_id));
+            }
+            "started" | "dispatched" => variant.running += 1,
+            _ => variant.pending += 1,
+        }
+    }
+    variants.into_values().collect()
+}
+
+/// E.g. `Patch 5f1a (failed): 12 passed, 2 failed, 1 running, 0 pending`, followed by a line for
+/// each failed task since tables leave the links out
+pub fn describe(patch: &Patch, variants: &[VariantStatus]) -> String {
+    let sum = |f: fn(&VariantStatus) -> i64| variants.iter().map(f).sum::<i64>();
+    let mut text = format!(
+        "Patch {} ({}): {} passed, {} failed, {} running, {} pending",
+        patch.patch_id,
+        patch.status,
+        sum(|v| v.passed),
+        sum(|v| v.failed),
+        sum(|v| v.running),
+        sum(|v| v.pending)
+    );
+    for variant in variants {
+        for link in &variant.failing {
+            text += &format!("\n  {}  {}", variant.build_variant, link);
+        }
+    }
+    text
+}
+
+#[test]
+fn test_summarize_variants() {
+    let task = |id: &str, variant: &str, status: &str| Task {
+        task_id: id.to_owned(),
+        build_variant: variant.to_owned(),
+        status: status.to_owned(),
+        ..Default::default()
+    };
+    let tasks = vec![
+        task("w1", "windows", "success"),
+        task("l1", "linux", "failed"),
+        task("l2", "linux", "success"),
+        task("l3", "linux", "started"),
+        task("l4", "linux", "undispatched"),
+    ];
+
+    let variants = summarize_variants(&tasks, "https://evg");
+    assert_eq!(
+        variants[0],
+        VariantStatus {
+            build_variant: "linux".to_owned(),
+            passed: 1,
+            failed: 1,
+            running: 1,
+            pending: 1,
+            failing: vec!["https://evg/task/l1".to_owned()],
+        }
+    );
+    assert_eq!(variants[1].build_variant, "windows");
+    assert_eq!(variants[1].passed, 1);
+
+    let patch = Patch {
+        patch_id: "p".to_owned(),
+        status: "started".to_owned(),
+        ..Default::default()
+    };
+    assert_eq!(
+        describe(&patch, &variants),
+        "Patch p (started): 2 passed, 1 failed, 1 running, 1 pending\n  linux  https://evg/task/l1"
+    );
+}
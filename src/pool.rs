@@ -0,0 +1,79 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A bounded pool of threads for commands that send a request per object.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use anyhow::Result;
+
+/// Apply `f` to every item on up to `workers` threads, the results are in the order of `items`
+pub fn map_bounded<I, T, F>(items: &[I], workers: usize, f: F) -> Vec<T>
+where
+    I: Sync,
+    T: Send,
+    F: Fn(&I) -> T + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let worker = || {
+        let mut done = Vec::new();
+        loop {
+            let i = next.fetch_add(1, Ordering::SeqCst);
+            match items.get(i) {
+                Some(item) => done.push((i, f(item))),
+                None => return done,
+            }
+        }
+    };
+
+    let mut results: Vec<(usize, T)> = thread::scope(|s| {
+        let handles: Vec<_> = (1..workers.min(items.len()))
+            .map(|_| s.spawn(worker))
+            .collect();
+        let mut results = worker();
+        for h in handles {
+            results.extend(h.join().expect("worker thread panicked"));
+        }
+        results
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Like `map_bounded` but fails with the first error, in the order of `items`
+pub fn try_map_bounded<I, T, F>(items: &[I], workers: usize, f: F) -> Result<Vec<T>>
+where
+    I: Sync,
+    T: Send,
+    F: Fn(&I) -> Result<T> + Sync,
+{
+    map_bounded(items, workers, f).into_iter().collect()
+}
+
+#[test]
+fn test_map_bounded() {
+    let items: Vec<u64> = (0..50).collect();
+    for workers in &[0, 1, 4, 100] {
+        let squares = map_bounded(&items, *workers, |i| i * i);
+        assert_eq!(squares, items.iter().map(|i| i * i).collect::<Vec<_>>());
+    }
+    assert!(map_bounded(&[] as &[u64], 4, |i| *i).is_empty());
+
+    let res = try_map_bounded(&items, 4, |i| match i {
+        7 | 30 => Err(anyhow!("bad {}", i)),
+        i => Ok(*i),
+    });
+    assert_eq!(res.unwrap_err().to_string(), "bad 7");
+}
@@ -0,0 +1,196 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A terse status line for shell prompts.
+//!
+//! Prompts are redrawn constantly so the line is always served from a cache file. When the
+//! cache is stale a detached `prompt --refresh` process updates it in the background and the
+//! stale line is printed, so only the very first call waits on the network.
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::client::EvergreenClient;
+use crate::config::ConfigArgs;
+use crate::hosts;
+use crate::limits::LimitArgs;
+use crate::models::StatusCounts;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PatchProgress {
+    pub patch_id: String,
+    pub counts: StatusCounts,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PromptState {
+    pub fetched_at: DateTime<Utc>,
+    pub hosts: usize,
+    pub expiring: usize,
+    pub patch: Option<PatchProgress>,
+}
+
+/// Each profile has its own cache so switching profiles never shows another deployment
+fn cache_path(config: &ConfigArgs) -> Option<PathBuf> {
+    let name = match &config.profile {
+        Some(profile) => format!("prompt-{}.json", profile),
+        None => "prompt.json".to_owned(),
+    };
+    dirs::cache_dir().map(|d| d.join("evergreen-rs").join(name))
+}
+
+/// Fetch the current state, hosts expiring within `expiring_within` are called out
+pub fn fetch_state(client: &EvergreenClient, expiring_within: Duration) -> Result<PromptState> {
+    let now = Utc::now();
+    let hosts: Vec<_> = client
+        .get_hosts(None)?
+        .into_iter()
+        .filter(|h| h.status != "terminated")
+        .collect();
+    let expiring = hosts
+        .iter()
+        .filter(|h| hosts::host_expiration(h).is_some_and(|e| e - now <= expiring_within))
+        .count();
+
+    let patch = match client.get_user_patches(&client.config.user, 1)?.pop() {
+        Some(patch) => {
+            let mut counts = StatusCounts::default();
+            for build in client.get_version_builds(&patch.version)? {
+                counts.add(&build.status_counts);
+            }
+            Some(PatchProgress {
+                patch_id: patch.patch_id,
+                counts,
+            })
+        }
+        None => None,
+    };
+
+    Ok(PromptState {
+        fetched_at: now,
+        hosts: hosts.len(),
+        expiring,
+        patch,
+    })
+}
+
+pub fn render(state: &PromptState) -> String {
+    let mut line = format!("evg: {} hosts", state.hosts);
+    if state.expiring > 0 {
+        line += &format!(" ({} expiring)", state.expiring);
+    }
+
+    if let Some(patch) = &state.patch {
+        let short: String = patch.patch_id.chars().take(7).collect();
+        let counts = &patch.counts;
+        line += &format!(
+            ", patch {}: {}/{} \u{2713}",
+            short,
+            counts.succeeded,
+            counts.total()
+        );
+        let failed = counts.failed + counts.timed_out;
+        if failed > 0 {
+            line += &format!(" {} \u{2717}", failed);
+        }
+    }
+
+    line
+}
+
+fn read_cache(config: &ConfigArgs) -> Option<PromptState> {
+    let file = File::open(cache_path(config)?).ok()?;
+    serde_json::from_reader(file).ok()
+}
+
+fn write_cache(config: &ConfigArgs, state: &PromptState) -> Result<()> {
+    let path = cache_path(config).ok_or_else(|| anyhow!("Could not find a cache directory"))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Write then rename so a concurrent prompt never reads a partial file
+    let tmp = path.with_extension("json.tmp");
+    serde_json::to_writer(File::create(&tmp)?, state)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Start a detached process to refresh the cache
+fn spawn_refresh(config: &ConfigArgs) -> Result<()> {
+    Command::new(std::env::current_exe()?)
+        .args(["prompt", "--refresh"])
+        .envs(config.envs())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+/// Fetch the state and update the cache
+pub fn refresh(config: &ConfigArgs, expiring_within: Duration) -> Result<PromptState> {
+    let client = EvergreenClient::new_from_home(config, &LimitArgs::default())?;
+    let state = fetch_state(&client, expiring_within)?;
+    write_cache(config, &state)?;
+    Ok(state)
+}
+
+/// Get the prompt line from the cache, refreshing in the background once it is older than `ttl`
+pub fn cached_line(
+    config: &ConfigArgs,
+    ttl: Duration,
+    expiring_within: Duration,
+) -> Result<String> {
+    match read_cache(config) {
+        Some(state) => {
+            if Utc::now() - state.fetched_at > ttl {
+                spawn_refresh(config)?;
+            }
+            Ok(render(&state))
+        }
+        None => Ok(render(&refresh(config, expiring_within)?)),
+    }
+}
+
+#[test]
+fn test_render() {
+    let mut state = PromptState {
+        fetched_at: Utc::now(),
+        hosts: 3,
+        expiring: 0,
+        patch: None,
+    };
+    assert_eq!(render(&state), "evg: 3 hosts");
+
+    state.expiring = 1;
+    state.patch = Some(PatchProgress {
+        patch_id: "abcdef0123456789".to_owned(),
+        counts: StatusCounts {
+            succeeded: 12,
+            failed: 2,
+            undispatched: 26,
+            ..Default::default()
+        },
+    });
+    assert_eq!(
+        render(&state),
+        "evg: 3 hosts (1 expiring), patch abcdef0: 12/40 \u{2713} 2 \u{2717}"
+    );
+}
@@ -0,0 +1,248 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Which HTTP proxy requests go through.
+//!
+//! The `proxy` setting takes precedence, otherwise `HTTPS_PROXY`, `HTTP_PROXY` and `ALL_PROXY`
+//! are used the way curl uses them. Hosts in `NO_PROXY` and the setting's `no_proxy` list are
+//! connected to directly, an entry matches the host and its subdomains.
+//!
+//! ```yaml
+//! proxy:
+//!   url: http://proxy.corp.example.com:3128
+//!   username: mark
+//!   password: keyring:proxy
+//!   no_proxy: [localhost, .corp.example.com]
+//! ```
+
+use anyhow::Result;
+use reqwest::{Proxy, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    // http://host:port, used for both http and https requests
+    pub url: String,
+
+    // Basic auth for the proxy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    // The password, or keyring:<name> to read it from the keychain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+
+    // Hosts to connect to directly, in addition to NO_PROXY
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_proxy: Vec<String>,
+}
+
+/// Hosts that bypass the proxy
+#[derive(Debug, Clone, Default, PartialEq)]
+struct NoProxy(Vec<String>);
+
+impl NoProxy {
+    /// Parse entries like `localhost`, `.example.com` or `*`, lists may be comma separated
+    fn parse<'a>(entries: impl IntoIterator<Item = &'a str>) -> NoProxy {
+        NoProxy(
+            entries
+                .into_iter()
+                .flat_map(|e| e.split(','))
+                .map(|e| {
+                    let e = e.trim();
+                    e.strip_prefix("*.")
+                        .unwrap_or_else(|| e.trim_start_matches('.'))
+                        .to_ascii_lowercase()
+                })
+                .filter(|e| !e.is_empty())
+                .collect(),
+        )
+    }
+
+    fn contains(&self, host: &str) -> bool {
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_ascii_lowercase();
+        self.0.iter().any(|entry| {
+            entry == "*"
+                || host == *entry
+                || (host.ends_with(entry.as_str())
+                    && host[..host.len() - entry.len()].ends_with('.'))
+        })
+    }
+}
+
+/// The proxies for each scheme of request
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ProxyRules {
+    http: Option<Url>,
+    https: Option<Url>,
+    no_proxy: NoProxy,
+}
+
+fn parse_url(url: &str) -> Result<Url> {
+    // curl assumes http:// for a bare host:port
+    let full = if url.contains("://") {
+        url.to_owned()
+    } else {
+        format!("http://{}", url)
+    };
+    Url::parse(&full).map_err(|e| anyhow!("Invalid proxy URL '{}': {}", url, e))
+}
+
+impl ProxyRules {
+    /// Rules from the setting, or else from the environment variables `var` returns
+    fn new(config: Option<&ProxyConfig>, var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        // Like curl, the lowercase name wins when both are set
+        let env = |name: &str| {
+            var(&name.to_ascii_lowercase())
+                .or_else(|| var(name))
+                .filter(|v| !v.is_empty())
+        };
+        let env_no_proxy = env("NO_PROXY").unwrap_or_default();
+
+        match config {
+            Some(config) => {
+                let url = parse_url(&config.url)?;
+                let entries = config.no_proxy.iter().map(String::as_str);
+                Ok(ProxyRules {
+                    http: Some(url.clone()),
+                    https: Some(url),
+                    no_proxy: NoProxy::parse(entries.chain(Some(env_no_proxy.as_str()))),
+                })
+            }
+            None => {
+                let all = env("ALL_PROXY");
+                let url = |name| {
+                    env(name)
+                        .or_else(|| all.clone())
+                        .map(|u| parse_url(&u))
+                        .transpose()
+                };
+                Ok(ProxyRules {
+                    http: url("HTTP_PROXY")?,
+                    https: url("HTTPS_PROXY")?,
+                    no_proxy: NoProxy::parse(Some(env_no_proxy.as_str())),
+                })
+            }
+        }
+    }
+
+    /// The proxy for a request to `url`, None to connect directly
+    fn proxy_for(&self, url: &Url) -> Option<Url> {
+        if self.no_proxy.contains(url.host_str().unwrap_or_default()) {
+            return None;
+        }
+        match url.scheme() {
+            "https" => self.https.clone(),
+            "http" => self.http.clone(),
+            _ => None,
+        }
+    }
+}
+
+/// The proxy for the client, None when neither the setting nor the environment has one
+pub fn build_proxy(config: Option<&ProxyConfig>, user: &str) -> Result<Option<Proxy>> {
+    let rules = ProxyRules::new(config, |name| std::env::var(name).ok())?;
+    if rules.http.is_none() && rules.https.is_none() {
+        return Ok(None);
+    }
+
+    let mut proxy = Proxy::custom(move |url| rules.proxy_for(url));
+    if let Some(ProxyConfig {
+        username: Some(username),
+        password,
+        ..
+    }) = config
+    {
+        let password = match password {
+            Some(password) => match config::keyring_name(password, user) {
+                Some(name) => config::read_keyring(name)?,
+                None => password.clone(),
+            },
+            None => String::new(),
+        };
+        proxy = proxy.basic_auth(username, &password);
+    }
+    Ok(Some(proxy))
+}
+
+#[test]
+fn test_proxy_rules() {
+    let url = |u: &str| Url::parse(u).unwrap();
+    let env = |vars: &'static [(&'static str, &'static str)]| {
+        move |name: &str| {
+            vars.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        }
+    };
+
+    let rules = ProxyRules::new(
+        None,
+        env(&[
+            ("HTTPS_PROXY", "proxy:3128"),
+            ("NO_PROXY", "localhost, .internal.example.com,10.0.0.1"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(
+        rules.proxy_for(&url("https://evergreen.mongodb.com/api")),
+        Some(url("http://proxy:3128"))
+    );
+    assert_eq!(rules.proxy_for(&url("http://evergreen.mongodb.com")), None);
+    assert_eq!(rules.proxy_for(&url("https://localhost:9090")), None);
+    assert_eq!(
+        rules.proxy_for(&url("https://evg.internal.example.com")),
+        None
+    );
+    assert_eq!(rules.proxy_for(&url("https://10.0.0.1")), None);
+    assert!(rules
+        .proxy_for(&url("https://notinternal.example.com"))
+        .is_some());
+
+    let rules = ProxyRules::new(
+        None,
+        env(&[
+            ("https_proxy", "http://lower:1"),
+            ("HTTPS_PROXY", "http://upper:1"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(rules.https, Some(url("http://lower:1")));
+    assert_eq!(
+        ProxyRules::new(None, env(&[])).unwrap(),
+        ProxyRules::default()
+    );
+
+    let config: ProxyConfig =
+        serde_yaml::from_str("{url: 'http://corp:8080', no_proxy: [evg.local]}").unwrap();
+    let rules = ProxyRules::new(Some(&config), env(&[("NO_PROXY", "*")])).unwrap();
+    assert_eq!(rules.proxy_for(&url("https://evg.local")), None);
+    assert!(rules.no_proxy.contains("anything"));
+    let rules = ProxyRules::new(Some(&config), env(&[])).unwrap();
+    assert_eq!(
+        rules.proxy_for(&url("http://evergreen.mongodb.com")),
+        Some(url("http://corp:8080"))
+    );
+
+    let bad = ProxyConfig {
+        url: "http://[bad".to_owned(),
+        ..Default::default()
+    };
+    assert!(ProxyRules::new(Some(&bad), env(&[])).is_err());
+}
@@ -0,0 +1,131 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Saved queries, named command lines kept in the `queries` section of the configuration.
+//!
+//! ```yaml
+//! queries:
+//!   mine-linux: hosts list --where distro.distro_id~ubuntu --columns host_id,status
+//! ```
+//!
+//! `run mine-linux` runs the saved command line in place of `run mine-linux`, so options before
+//! `run` still apply and arguments after the name are appended, e.g. `run mine-linux -o json`.
+
+use anyhow::Result;
+
+/// Split a command line into words like a POSIX shell, without expansions
+///
+/// Single quotes keep everything literally, double quotes keep everything but backslash escapes
+/// of `"` and `\`, and a backslash elsewhere escapes the next character.
+pub fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => w.push(c),
+                        None => return Err(anyhow!("Unterminated ' in '{}'", line)),
+                    }
+                }
+            }
+            '"' => {
+                let w = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => w.push(c),
+                            Some(c) => {
+                                w.push('\\');
+                                w.push(c);
+                            }
+                            None => return Err(anyhow!("Unterminated \" in '{}'", line)),
+                        },
+                        Some(c) => w.push(c),
+                        None => return Err(anyhow!("Unterminated \" in '{}'", line)),
+                    }
+                }
+            }
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| anyhow!("Trailing \\ in '{}'", line))?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// The command line `argv` with `run <name>` and what follows replaced by the saved `query` and
+/// the `extra` arguments
+pub fn expand(argv: &[String], name: &str, query: &str, extra: &[String]) -> Result<Vec<String>> {
+    let words = split_words(query)?;
+    if words.first().map(String::as_str) == Some("run") {
+        return Err(anyhow!(
+            "Saved query '{}' cannot run another saved query",
+            name
+        ));
+    }
+
+    let at = argv
+        .windows(2)
+        .position(|w| w[0] == "run" && w[1] == name)
+        .ok_or_else(|| anyhow!("Could not find 'run {}' in the arguments", name))?;
+    let mut expanded = argv[..at].to_vec();
+    expanded.extend(words);
+    expanded.extend_from_slice(extra);
+    Ok(expanded)
+}
+
+#[test]
+fn test_split_words() {
+    assert_eq!(
+        split_words("hosts list  --where 'distro.distro_id~ubuntu|rhel' --format \"{host_id}\\t\"")
+            .unwrap(),
+        [
+            "hosts",
+            "list",
+            "--where",
+            "distro.distro_id~ubuntu|rhel",
+            "--format",
+            "{host_id}\\t"
+        ]
+    );
+    assert_eq!(split_words("a\\ b '' c").unwrap(), ["a b", "", "c"]);
+    assert!(split_words("'open").is_err());
+    assert!(split_words("\"open").is_err());
+}
+
+#[test]
+fn test_expand() {
+    let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    let argv = strings(&["evg", "-v", "run", "mine", "-o", "json"]);
+    let extra = strings(&["-o", "json"]);
+    assert_eq!(
+        expand(&argv, "mine", "hosts list --status running", &extra).unwrap(),
+        ["evg", "-v", "hosts", "list", "--status", "running", "-o", "json"]
+    );
+    assert!(expand(&argv, "mine", "run other", &extra).is_err());
+    assert!(expand(&argv, "other", "hosts list", &extra).is_err());
+}
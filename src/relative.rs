@@ -0,0 +1,98 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timestamps relative to now, e.g. "expires in 3h" or "ran 42m", for table output.
+//!
+//! Machine readable formats keep the RFC 3339 timestamps the API returns.
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+
+/// A timestamp field, None for other values and the zero time Evergreen uses for "never"
+pub fn parse_timestamp(v: &Value) -> Option<DateTime<Utc>> {
+    let date = DateTime::parse_from_rfc3339(v.as_str()?).ok()?;
+    Some(date.with_timezone(&Utc)).filter(|d| d.timestamp() > 0)
+}
+
+/// A span at a glance, e.g. 42m, 5h or 3d
+pub fn format_span(span: Duration) -> String {
+    let hours = span.num_hours();
+    if hours >= 48 {
+        format!("{}d", hours / 24)
+    } else if hours > 0 {
+        format!("{}h", hours)
+    } else {
+        format!("{}m", span.num_minutes())
+    }
+}
+
+/// E.g. "in 3h" or "3h ago"
+pub fn format_relative(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    if at > now {
+        format!("in {}", format_span(at - now))
+    } else {
+        format!("{} ago", format_span(now - at))
+    }
+}
+
+/// Describe the timestamp `field` of a record, `lookup` reads its sibling fields
+///
+/// Returns None when the field does not hold a timestamp.
+pub fn describe<F>(field: &str, value: &Value, lookup: F, now: DateTime<Utc>) -> Option<String>
+where
+    F: Fn(&str) -> Option<Value>,
+{
+    let at = parse_timestamp(value)?;
+    let name = field.rsplit('.').next().unwrap_or(field);
+    let sibling = |n: &str| {
+        let path = format!("{}{}", &field[..field.len() - name.len()], n);
+        lookup(&path).as_ref().and_then(parse_timestamp)
+    };
+
+    let text = match name {
+        "expiration_time" if at > now => format!("expires {}", format_relative(at, now)),
+        "expiration_time" => format!("expired {}", format_relative(at, now)),
+        "finish_time" => match sibling("start_time") {
+            Some(start) => format!("ran {}", format_span(at - start)),
+            None => format!("finished {}", format_relative(at, now)),
+        },
+        "start_time" => format!("started {}", format_relative(at, now)),
+        "creation_time" | "create_time" => format!("created {}", format_relative(at, now)),
+        _ => format_relative(at, now),
+    };
+    Some(text)
+}
+
+#[test]
+fn test_describe() {
+    let now = DateTime::parse_from_rfc3339("2020-06-03T12:00:00Z")
+        .unwrap()
+        .with_timezone(&Utc);
+    let task = serde_json::json!({
+        "start_time": "2020-06-03T10:00:00Z",
+        "finish_time": "2020-06-03T10:42:00Z",
+        "expiration_time": "2020-06-03T15:00:00Z",
+        "scheduled_time": "2020-05-30T12:00:00Z",
+        "dispatch_time": "0001-01-01T00:00:00Z",
+        "status": "success"
+    });
+    let describe = |field: &str| describe(field, &task[field], |path| task.get(path).cloned(), now);
+
+    assert_eq!(describe("finish_time").unwrap(), "ran 42m");
+    assert_eq!(describe("start_time").unwrap(), "started 2h ago");
+    assert_eq!(describe("expiration_time").unwrap(), "expires in 3h");
+    assert_eq!(describe("scheduled_time").unwrap(), "4d ago");
+    assert_eq!(describe("dispatch_time"), None);
+    assert_eq!(describe("status"), None);
+}
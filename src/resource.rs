@@ -0,0 +1,169 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Result;
+use evergreen_rs_types::{EvgFieldInfo, EvgFields};
+use reqwest::Url;
+
+use crate::models::{Build, Host, Patch, Task, Version};
+
+/// A resource type `get` can show
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceKind {
+    Host,
+    Task,
+    Patch,
+    Version,
+    Build,
+}
+
+const KINDS: &[(&str, ResourceKind)] = &[
+    ("host", ResourceKind::Host),
+    ("task", ResourceKind::Task),
+    ("patch", ResourceKind::Patch),
+    ("version", ResourceKind::Version),
+    ("build", ResourceKind::Build),
+];
+
+impl FromStr for ResourceKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Accept the plural REST collection names too
+        let s = s.to_lowercase();
+        let singular = s.strip_suffix("es").filter(|s| *s == "patch");
+        let singular = singular.or_else(|| s.strip_suffix('s')).unwrap_or(&s);
+        KINDS
+            .iter()
+            .find(|(name, _)| *name == singular)
+            .map(|(_, kind)| *kind)
+            .ok_or_else(|| {
+                let names: Vec<_> = KINDS.iter().map(|(name, _)| *name).collect();
+                anyhow!(
+                    "Unknown resource type '{}', choose from {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for ResourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, _) = KINDS.iter().find(|(_, kind)| kind == self).unwrap();
+        write!(f, "{}", name)
+    }
+}
+
+impl ResourceKind {
+    /// REST path of a resource of this type
+    pub fn rest_path(self, id: &str) -> String {
+        match self {
+            ResourceKind::Host => format!("hosts/{}", id),
+            ResourceKind::Task => format!("tasks/{}", id),
+            ResourceKind::Patch => format!("patches/{}", id),
+            ResourceKind::Version => format!("versions/{}", id),
+            ResourceKind::Build => format!("builds/{}", id),
+        }
+    }
+
+    /// Page of a resource of this type on the UI server
+    pub fn ui_url(self, ui_server: &str, id: &str) -> String {
+        format!("{}/{}/{}", ui_server.trim_end_matches('/'), self, id)
+    }
+
+    /// Fields of the model for resources of this type
+    pub fn fields(self) -> &'static [EvgFieldInfo] {
+        match self {
+            ResourceKind::Host => Host::FIELDS,
+            ResourceKind::Task => Task::FIELDS,
+            ResourceKind::Patch => Patch::FIELDS,
+            ResourceKind::Version => Version::FIELDS,
+            ResourceKind::Build => Build::FIELDS,
+        }
+    }
+}
+
+fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Work out which resource types an ID or a UI or REST URL may refer to, most likely first
+pub fn infer_resource(input: &str) -> Result<(String, Vec<ResourceKind>)> {
+    if let Ok(url) = Url::parse(input) {
+        let segments: Vec<&str> = url.path_segments().map(|s| s.collect()).unwrap_or_default();
+        for pair in segments.windows(2) {
+            if let Ok(kind) = pair[0].parse::<ResourceKind>() {
+                return Ok((pair[1].to_owned(), vec![kind]));
+            }
+        }
+        return Err(anyhow!("Could not find a resource in the URL '{}'", input));
+    }
+
+    let id = input.trim().to_owned();
+    let kinds = if id.starts_with("i-") || id.starts_with("sir-") || id.starts_with("evg-") {
+        vec![ResourceKind::Host]
+    } else if is_hex(&id, 24) {
+        // Patches and the versions created for them share an ID
+        vec![ResourceKind::Patch, ResourceKind::Version]
+    } else if id.rsplit('_').next().is_some_and(|sha| is_hex(sha, 40)) {
+        // Mainline versions are <project>_<revision>
+        vec![ResourceKind::Version]
+    } else {
+        // Tasks and builds both look like <project>_<variant>_..._<date>
+        vec![ResourceKind::Task, ResourceKind::Build, ResourceKind::Host]
+    };
+    Ok((id, kinds))
+}
+
+#[test]
+fn test_infer_resource() {
+    use ResourceKind::*;
+
+    let infer = |s| infer_resource(s).unwrap();
+    assert_eq!(infer("i-0123456789abcdef0").1, vec![Host]);
+    assert_eq!(infer("5ec5a3b0e3c3316b8c3e8f5a").1, vec![Patch, Version]);
+    assert_eq!(
+        infer("mongodb_mongo_master_0123456789abcdef0123456789abcdef01234567").1,
+        vec![Version]
+    );
+    assert_eq!(
+        infer("mongodb_mongo_master_ubuntu1804_compile_0123456_20_05_20_14_03_00").1,
+        vec![Task, Build, Host]
+    );
+    assert_eq!(
+        infer("https://evergreen.mongodb.com/task/my_task_id/0"),
+        ("my_task_id".to_owned(), vec![Task])
+    );
+    assert_eq!(
+        infer("https://evergreen.mongodb.com/rest/v2/patches/abc"),
+        ("abc".to_owned(), vec![Patch])
+    );
+    assert!(infer_resource("https://evergreen.mongodb.com/waterfall").is_err());
+    assert_eq!("Patches".parse::<ResourceKind>().unwrap(), Patch);
+}
+
+#[test]
+fn test_ui_url() {
+    assert_eq!(
+        ResourceKind::Task.ui_url("https://evg/", "t_1"),
+        "https://evg/task/t_1"
+    );
+    assert_eq!(
+        ResourceKind::Patch.ui_url("https://evg", "5ec5"),
+        "https://evg/patch/5ec5"
+    );
+}
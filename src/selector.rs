@@ -0,0 +1,551 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+use anyhow::Result;
+use evergreen_rs_types::EvgFieldEnum;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::args::read_ids_file;
+use crate::output::{lookup_path, parse_selector, to_flat_json};
+
+/// A model with an ID that a Selector can pick by
+pub trait Resource: Serialize {
+    fn id(&self) -> &str;
+
+    /// The value of the resource's tag `key`, resources without tags have none
+    fn tag(&self, _key: &str) -> Option<&str> {
+        None
+    }
+}
+
+// Chooses resources by ID, ID file, group or a regex over the flattened record, shared by every
+// verb so list, terminate, exec, etc. all select the same way
+#[derive(StructOpt, Debug, Default)]
+pub struct Selector {
+    /// IDs to select
+    ids: Vec<String>,
+
+    /// Read additional IDs from a file, one per line, `-` for stdin
+    #[structopt(long = "ids-file", parse(from_os_str))]
+    ids_file: Option<PathBuf>,
+
+    /// Select the IDs saved under this name in the groups section of the configuration, may be
+    /// repeated
+    #[structopt(long = "group", number_of_values = 1)]
+    groups: Vec<String>,
+
+    /// Only select resources whose flattened fields match this regex
+    #[structopt(short, long)]
+    filter: Option<String>,
+
+    /// Only select resources where a field matches, field=value, field!=value or field~regex,
+    /// may be repeated
+    #[structopt(long = "where", number_of_values = 1)]
+    predicates: Vec<Predicate>,
+
+    /// Only select resources matching at least one of these comma separated conditions, e.g.
+    /// status=failed,status=timed_out, may be repeated
+    #[structopt(long, number_of_values = 1)]
+    any_of: Vec<AnyOf>,
+
+    /// Leave out resources matching this condition, e.g. distro.distro_id~windows, may be
+    /// repeated
+    #[structopt(long, number_of_values = 1)]
+    not: Vec<Predicate>,
+
+    /// Only select resources tagged key=value, or with the tag key at all, may be repeated
+    #[structopt(long = "tag", number_of_values = 1)]
+    tags: Vec<TagFilter>,
+}
+
+/// A `--tag` filter, key=value or just key
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagFilter {
+    key: String,
+    value: Option<String>,
+}
+
+impl FromStr for TagFilter {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = match s.split_once('=') {
+            Some((key, value)) => (key, Some(value.to_owned())),
+            None => (s, None),
+        };
+        if key.is_empty() {
+            return Err(anyhow!(
+                "Could not parse '{}' as a tag, use key=value or key",
+                s
+            ));
+        }
+        Ok(TagFilter {
+            key: key.to_owned(),
+            value,
+        })
+    }
+}
+
+impl TagFilter {
+    fn is_match<T: Resource>(&self, resource: &T) -> bool {
+        match (resource.tag(&self.key), &self.value) {
+            (Some(actual), Some(expected)) => actual == expected,
+            (found, None) => found.is_some(),
+            (None, _) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Comparison {
+    Equals(String),
+    NotEquals(String),
+    Matches(Regex),
+}
+
+/// A `--where` condition on one field
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    path: String,
+    comparison: Comparison,
+}
+
+impl FromStr for Predicate {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            anyhow!(
+                "Could not parse '{}' as a condition, use field=value, field!=value or field~regex",
+                s
+            )
+        };
+        let i = s.find(['=', '~']).ok_or_else(invalid)?;
+
+        let (path, comparison) = match &s[i..i + 1] {
+            "~" => (&s[..i], Comparison::Matches(Regex::new(&s[i + 1..])?)),
+            _ => match s[..i].strip_suffix('!') {
+                Some(path) => (path, Comparison::NotEquals(s[i + 1..].to_owned())),
+                None => (&s[..i], Comparison::Equals(s[i + 1..].to_owned())),
+            },
+        };
+        if path.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Predicate {
+            path: path.to_owned(),
+            comparison,
+        })
+    }
+}
+
+/// An `--any-of` group of conditions, at least one must match
+#[derive(Debug, Clone)]
+pub struct AnyOf(Vec<Predicate>);
+
+impl FromStr for AnyOf {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // A piece without an operator continues the previous value, e.g. the regex a{1,3}
+        let mut conditions: Vec<String> = Vec::new();
+        for piece in s.split(',') {
+            match conditions.last_mut() {
+                Some(last) if !piece.contains(['=', '~']) => {
+                    last.push(',');
+                    last.push_str(piece);
+                }
+                _ => conditions.push(piece.to_owned()),
+            }
+        }
+        Ok(AnyOf(
+            conditions
+                .iter()
+                .map(|c| c.parse())
+                .collect::<Result<_>>()?,
+        ))
+    }
+}
+
+/// A field value as text, nulls and missing fields are empty
+fn value_text(v: &Value) -> String {
+    match v {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        _ => v.to_string(),
+    }
+}
+
+impl Predicate {
+    /// Check the path names a field of `T`, the same paths `--columns` accepts
+    fn validate<T: EvgFieldEnum>(&self) -> Result<()> {
+        parse_selector::<T>(&self.path)
+    }
+
+    /// Whether the record matches, a list matches if any member does
+    fn is_match(&self, record: &Value) -> bool {
+        let value = lookup_path(record, &self.path);
+        let texts: Vec<String> = match value.as_deref() {
+            Some(Value::Array(members)) => members.iter().map(value_text).collect(),
+            Some(v) => vec![value_text(v)],
+            None => vec![String::new()],
+        };
+
+        match &self.comparison {
+            Comparison::Equals(expected) => texts.iter().any(|t| t == expected),
+            Comparison::NotEquals(expected) => texts.iter().all(|t| t != expected),
+            Comparison::Matches(re) => texts.iter().any(|t| re.is_match(t)),
+        }
+    }
+}
+
+impl Selector {
+    /// Replace each `--group` with the IDs saved under its name
+    pub fn expand_groups(&mut self, saved: &BTreeMap<String, Vec<String>>) -> Result<()> {
+        for name in std::mem::take(&mut self.groups) {
+            let ids = saved.get(&name).ok_or_else(|| {
+                anyhow!(
+                    "No group '{}', add it to the groups section of the configuration",
+                    name
+                )
+            })?;
+            if ids.is_empty() {
+                return Err(anyhow!("Group '{}' has no IDs", name));
+            }
+            self.ids.extend(ids.iter().cloned());
+        }
+        Ok(())
+    }
+
+    /// True when nothing narrows the selection, i.e. it selects everything
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+            && self.ids_file.is_none()
+            && self.groups.is_empty()
+            && self.filter.is_none()
+            && !self.has_conditions()
+            && self.tags.is_empty()
+    }
+
+    fn has_conditions(&self) -> bool {
+        !(self.predicates.is_empty() && self.any_of.is_empty() && self.not.is_empty())
+    }
+
+    fn conditions(&self) -> impl Iterator<Item = &Predicate> {
+        let groups = self.any_of.iter().flat_map(|g| g.0.iter());
+        self.predicates.iter().chain(groups).chain(&self.not)
+    }
+
+    /// Whether a record meets every --where, --any-of and --not condition
+    fn is_match(&self, record: &Value) -> bool {
+        self.predicates.iter().all(|p| p.is_match(record))
+            && self
+                .any_of
+                .iter()
+                .all(|g| g.0.iter().any(|p| p.is_match(record)))
+            && !self.not.iter().any(|p| p.is_match(record))
+    }
+
+    /// Pick the matching resources from `candidates`
+    ///
+    /// Every explicitly requested ID must exist. Verbs that change things should refuse an
+    /// empty selector rather than acting on everything, see `resolve_required`.
+    pub fn resolve<T: Resource + EvgFieldEnum>(&self, candidates: Vec<T>) -> Result<Vec<T>> {
+        if let Some(name) = self.groups.first() {
+            return Err(anyhow!("Group '{}' was not expanded", name));
+        }
+
+        let mut ids = self.ids.clone();
+        if let Some(path) = &self.ids_file {
+            ids.extend(read_ids_file(path)?);
+        }
+
+        let filter = match &self.filter {
+            Some(f) => Some(Regex::new(f)?),
+            None => None,
+        };
+
+        for predicate in self.conditions() {
+            predicate.validate::<T>()?;
+        }
+
+        for id in &ids {
+            if !candidates.iter().any(|c| c.id() == id) {
+                return Err(anyhow!("Could not find '{}'", id));
+            }
+        }
+
+        let mut selected = Vec::new();
+        for c in candidates {
+            if !ids.is_empty() && !ids.iter().any(|id| id == c.id()) {
+                continue;
+            }
+
+            if !self.tags.iter().all(|t| t.is_match(&c)) {
+                continue;
+            }
+
+            if filter.is_some() || self.has_conditions() {
+                let record = serde_json::to_value(&c)?;
+                if !self.is_match(&record) {
+                    continue;
+                }
+                if let Some(filter) = &filter {
+                    if !filter.is_match(&to_flat_json(&record)?) {
+                        continue;
+                    }
+                }
+            }
+
+            selected.push(c);
+        }
+
+        Ok(selected)
+    }
+
+    /// Like `resolve` but refuses to select everything by accident
+    pub fn resolve_required<T: Resource + EvgFieldEnum>(
+        &self,
+        candidates: Vec<T>,
+    ) -> Result<Vec<T>> {
+        if self.is_empty() {
+            return Err(anyhow!(
+                "Nothing selected, pass IDs, --ids-file, --group, --tag, --filter, --where, --any-of or --not"
+            ));
+        }
+        self.resolve(candidates)
+    }
+}
+
+#[cfg(test)]
+#[derive(Serialize, evergreen_rs_derive::EvgFields)]
+struct TestResource {
+    id: String,
+    status: String,
+}
+
+#[cfg(test)]
+impl Resource for TestResource {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn tag(&self, key: &str) -> Option<&str> {
+        // Everything is tagged with its status
+        Some(&self.status)
+            .filter(|_| key == "status")
+            .map(String::as_str)
+    }
+}
+
+#[test]
+fn test_selector_resolve() {
+    let candidates = || {
+        vec![
+            TestResource {
+                id: "a".to_owned(),
+                status: "running".to_owned(),
+            },
+            TestResource {
+                id: "b".to_owned(),
+                status: "stopped".to_owned(),
+            },
+        ]
+    };
+
+    let all = Selector::default();
+    assert_eq!(all.resolve(candidates()).unwrap().len(), 2);
+    assert!(all.resolve_required(candidates()).is_err());
+
+    let by_filter = Selector {
+        filter: Some("status:stopped".to_owned()),
+        ..Default::default()
+    };
+    let selected = by_filter.resolve(candidates()).unwrap();
+    assert_eq!(selected.len(), 1);
+    assert_eq!(selected[0].id, "b");
+
+    let missing = Selector {
+        ids: vec!["c".to_owned()],
+        ..Default::default()
+    };
+    assert!(missing.resolve(candidates()).is_err());
+
+    let by_tag = |tags: &[&str]| {
+        let selector = Selector {
+            tags: tags.iter().map(|t| t.parse().unwrap()).collect(),
+            ..Default::default()
+        };
+        selector.resolve(candidates()).unwrap().len()
+    };
+    assert_eq!(by_tag(&["status=running"]), 1);
+    assert_eq!(by_tag(&["status"]), 2);
+    assert_eq!(by_tag(&["status", "team"]), 0);
+    assert_eq!(by_tag(&["status=runn"]), 0);
+    assert!("=x".parse::<TagFilter>().is_err());
+}
+
+#[test]
+fn test_selector_groups() {
+    let saved: BTreeMap<String, Vec<String>> =
+        vec![("farm".to_owned(), vec!["a".to_owned(), "b".to_owned()])]
+            .into_iter()
+            .collect();
+    let candidates = || {
+        vec![
+            TestResource {
+                id: "a".to_owned(),
+                status: "running".to_owned(),
+            },
+            TestResource {
+                id: "b".to_owned(),
+                status: "stopped".to_owned(),
+            },
+        ]
+    };
+
+    let mut by_group = Selector {
+        groups: vec!["farm".to_owned()],
+        filter: Some("status:stopped".to_owned()),
+        ..Default::default()
+    };
+    assert!(!by_group.is_empty());
+    assert!(by_group.resolve(candidates()).is_err());
+    by_group.expand_groups(&saved).unwrap();
+    let selected = by_group.resolve_required(candidates()).unwrap();
+    assert_eq!(selected.len(), 1);
+    assert_eq!(selected[0].id, "b");
+
+    let mut unknown = Selector {
+        groups: vec!["nope".to_owned()],
+        ..Default::default()
+    };
+    assert!(unknown.expand_groups(&saved).is_err());
+}
+
+#[test]
+fn test_selector_where() {
+    let candidates = || {
+        vec![
+            TestResource {
+                id: "a".to_owned(),
+                status: "running".to_owned(),
+            },
+            TestResource {
+                id: "b".to_owned(),
+                status: "stopped".to_owned(),
+            },
+            TestResource {
+                id: "running".to_owned(),
+                status: "starting".to_owned(),
+            },
+        ]
+    };
+    let select = |conditions: &[&str]| -> Result<Vec<String>> {
+        let selector = Selector {
+            predicates: conditions
+                .iter()
+                .map(|c| c.parse())
+                .collect::<Result<_>>()?,
+            ..Default::default()
+        };
+        Ok(selector
+            .resolve(candidates())?
+            .into_iter()
+            .map(|c| c.id)
+            .collect())
+    };
+
+    // Unlike --filter only the named field is compared
+    assert_eq!(select(&["status=running"]).unwrap(), ["a"]);
+    assert_eq!(select(&["status!=running"]).unwrap(), ["b", "running"]);
+    assert_eq!(select(&["status~^st"]).unwrap(), ["b", "running"]);
+    assert_eq!(select(&["status~^st", "id!=b"]).unwrap(), ["running"]);
+    assert_eq!(select(&["status=a=b"]).unwrap(), Vec::<String>::new());
+
+    assert!(select(&["nope=1"]).is_err());
+
+    let select_any = |any_of: &str, not: &str| -> Vec<String> {
+        let selector = Selector {
+            any_of: vec![any_of.parse().unwrap()],
+            not: vec![not.parse().unwrap()],
+            ..Default::default()
+        };
+        selector
+            .resolve(candidates())
+            .unwrap()
+            .into_iter()
+            .map(|c| c.id)
+            .collect()
+    };
+    assert_eq!(select_any("status=running,status=stopped", "id=b"), ["a"]);
+    assert_eq!(select_any("id~^[a-z]{1,2}$", "status=running"), ["b"]);
+    assert!("status,id=a".parse::<AnyOf>().is_err());
+    assert!("status".parse::<Predicate>().is_err());
+    assert!("=running".parse::<Predicate>().is_err());
+    assert!("status~(".parse::<Predicate>().is_err());
+}
+
+#[test]
+fn test_selector_where_lists() {
+    use crate::models::{Host, Tag};
+
+    let host = |id: &str, tags: &[(&str, &str)]| Host {
+        host_id: id.to_owned(),
+        instance_tags: tags
+            .iter()
+            .map(|(key, value)| Tag {
+                key: key.to_string(),
+                value: value.to_string(),
+                can_be_modified: false,
+                extra: Default::default(),
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let select = |conditions: &[&str]| -> Result<Vec<String>> {
+        let selector = Selector {
+            predicates: conditions
+                .iter()
+                .map(|c| c.parse())
+                .collect::<Result<_>>()?,
+            ..Default::default()
+        };
+        let candidates = vec![
+            host("h1", &[("owner", "me"), ("team", "build")]),
+            host("h2", &[("team", "server")]),
+            host("h3", &[]),
+        ];
+        Ok(selector
+            .resolve(candidates)?
+            .into_iter()
+            .map(|h| h.host_id)
+            .collect())
+    };
+
+    assert_eq!(select(&["instance_tags[].key=owner"]).unwrap(), ["h1"]);
+    assert_eq!(select(&["instance_tags.key=owner"]).unwrap(), ["h1"]);
+    assert_eq!(select(&["instance_tags.value~^s"]).unwrap(), ["h2"]);
+    assert_eq!(
+        select(&["instance_tags[].key!=owner"]).unwrap(),
+        ["h2", "h3"]
+    );
+    assert_eq!(select(&["distro.distro_id="]).unwrap(), ["h1", "h2", "h3"]);
+    assert!(select(&["instance_tag.key=owner"]).is_err());
+    assert!(select(&["distro.nope=1"]).is_err());
+}
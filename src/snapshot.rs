@@ -0,0 +1,270 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::client::EvergreenClient;
+use crate::models::PublicKey;
+
+/// Resources that `apply` can recreate, the rest are reported as skipped
+pub const RECREATABLE_RESOURCES: &[&str] = &["keys", "subscriptions"];
+
+/// Format of the timestamp in snapshot file names, sorts lexicographically
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Resources captured by a snapshot and the REST path they are read from
+fn snapshot_resources(user: &str, patches: u32) -> Vec<(&'static str, String)> {
+    vec![
+        ("hosts", format!("users/{}/hosts", user)),
+        ("volumes", "volumes".to_owned()),
+        ("keys", "keys".to_owned()),
+        (
+            "patches",
+            format!("users/{}/patches?limit={}", user, patches),
+        ),
+        (
+            "subscriptions",
+            format!("subscriptions?owner={}&type=person", user),
+        ),
+    ]
+}
+
+pub fn snapshot_file_name(resource: &str, timestamp: &str) -> String {
+    format!("{}-{}.json", resource, timestamp)
+}
+
+/// Fetch every resource concurrently and write each to a timestamped JSON file in `out`
+///
+/// Every resource is attempted even if some fail.
+pub fn create_snapshot(client: &EvergreenClient, out: &Path, patches: u32) -> Result<()> {
+    fs::create_dir_all(out)?;
+    let timestamp = chrono::Utc::now().format(TIMESTAMP_FORMAT).to_string();
+    let resources = snapshot_resources(&client.config.user, patches);
+
+    let results: Vec<(&str, Result<serde_json::Value>)> = thread::scope(|s| {
+        let handles: Vec<_> = resources
+            .iter()
+            .map(|(name, path)| (*name, s.spawn(move || client.get_rest_json(path))))
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|(name, h)| {
+                let res = h
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("Fetching {} panicked", name)));
+                (name, res)
+            })
+            .collect()
+    });
+
+    let mut failures = 0;
+    for (name, res) in results {
+        match res {
+            Ok(value) => {
+                let path = out.join(snapshot_file_name(name, &timestamp));
+                serde_json::to_writer_pretty(File::create(&path)?, &value)?;
+                println!("Wrote {}", path.display());
+            }
+            Err(e) => {
+                eprintln!("Failed to snapshot {}: {}", name, e);
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        return Err(anyhow!(
+            "{} of {} resources failed",
+            failures,
+            resources.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Find the newest snapshot file for a resource in `dir`
+fn latest_snapshot_file(dir: &Path, resource: &str) -> Result<Option<PathBuf>> {
+    let prefix = format!("{}-", resource);
+    let mut latest: Option<PathBuf> = None;
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(n) => n,
+            None => continue,
+        };
+        if !name.starts_with(&prefix) || !name.ends_with(".json") {
+            continue;
+        }
+
+        // Timestamps sort lexicographically so the largest name is the newest
+        if latest
+            .as_ref()
+            .is_none_or(|l| l.file_name().and_then(|n| n.to_str()) < Some(name))
+        {
+            latest = Some(path);
+        }
+    }
+
+    Ok(latest)
+}
+
+fn read_snapshot_file(path: &Path) -> Result<Vec<Value>> {
+    Ok(serde_json::from_reader(File::open(path)?)?)
+}
+
+/// Strip the fields the server assigns so subscriptions can be compared and recreated
+fn normalize_subscription(sub: &Value, owner: &str) -> Value {
+    let mut sub = sub.clone();
+    if let Some(obj) = sub.as_object_mut() {
+        obj.remove("id");
+        obj.insert("owner".to_owned(), Value::String(owner.to_owned()));
+        obj.insert("owner_type".to_owned(), Value::String("person".to_owned()));
+    }
+    sub
+}
+
+fn apply_keys(client: &EvergreenClient, path: &Path) -> Result<String> {
+    let keys: Vec<PublicKey> = serde_json::from_reader(File::open(path)?)?;
+    let existing = client.get_keys()?;
+
+    let mut created = 0;
+    for key in &keys {
+        if existing.iter().any(|e| e.name == key.name) {
+            continue;
+        }
+        client.add_key(key)?;
+        created += 1;
+    }
+
+    Ok(format!(
+        "created {}, already present {}",
+        created,
+        keys.len() - created
+    ))
+}
+
+fn apply_subscriptions(client: &EvergreenClient, path: &Path) -> Result<String> {
+    let user = &client.config.user;
+    let subscriptions = read_snapshot_file(path)?;
+    let existing: Vec<Value> = client
+        .get_user_subscriptions::<Value>(user)?
+        .iter()
+        .map(|s| normalize_subscription(s, user))
+        .collect();
+
+    let missing: Vec<Value> = subscriptions
+        .iter()
+        .map(|s| normalize_subscription(s, user))
+        .filter(|s| !existing.contains(s))
+        .collect();
+
+    if !missing.is_empty() {
+        client.add_subscriptions(&missing)?;
+    }
+
+    Ok(format!(
+        "created {}, already present {}",
+        missing.len(),
+        subscriptions.len() - missing.len()
+    ))
+}
+
+/// Recreate the declarative resources in the newest snapshot files in `dir`
+///
+/// Prints a line per resource describing what was created or why it was skipped.
+pub fn apply_snapshot(client: &EvergreenClient, dir: &Path, only: &[String]) -> Result<()> {
+    for o in only {
+        if !RECREATABLE_RESOURCES.contains(&o.as_str()) {
+            return Err(anyhow!(
+                "Cannot apply '{}', choose from {}",
+                o,
+                RECREATABLE_RESOURCES.join(",")
+            ));
+        }
+    }
+
+    let mut failures = 0;
+    for (resource, _) in snapshot_resources(&client.config.user, 0) {
+        if !RECREATABLE_RESOURCES.contains(&resource) {
+            println!("{}: skipped, not recreatable", resource);
+            continue;
+        }
+        if !only.is_empty() && !only.iter().any(|o| o == resource) {
+            println!("{}: skipped, not selected", resource);
+            continue;
+        }
+
+        let path = match latest_snapshot_file(dir, resource)? {
+            Some(p) => p,
+            None => {
+                println!("{}: skipped, not in snapshot", resource);
+                continue;
+            }
+        };
+
+        let res = match resource {
+            "keys" => apply_keys(client, &path),
+            "subscriptions" => apply_subscriptions(client, &path),
+            _ => unreachable!(),
+        };
+
+        match res {
+            Ok(summary) => println!("{}: {}", resource, summary),
+            Err(e) => {
+                println!("{}: failed, {}", resource, e);
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        return Err(anyhow!("{} resources failed to apply", failures));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_normalize_subscription() {
+    let sub = serde_json::json!({
+        "id": "abc",
+        "owner": "old.user",
+        "owner_type": "person",
+        "trigger": "outcome",
+    });
+    assert_eq!(
+        normalize_subscription(&sub, "new.user"),
+        serde_json::json!({
+            "owner": "new.user",
+            "owner_type": "person",
+            "trigger": "outcome",
+        })
+    );
+}
+
+#[test]
+fn test_snapshot_file_name() {
+    assert_eq!(
+        snapshot_file_name("hosts", "20200102T030405Z"),
+        "hosts-20200102T030405Z.json"
+    );
+}
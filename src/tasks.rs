@@ -0,0 +1,213 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+
+use crate::client::EvergreenClient;
+use crate::models::Task;
+use crate::pool;
+
+/// Fetch every task of a version
+pub fn version_tasks(client: &EvergreenClient, version_id: &str) -> Result<Vec<Task>> {
+    let builds = client.get_version_builds(version_id)?;
+    let tasks = pool::try_map_bounded(&builds, client.limits.parallelism, |build| {
+        client.get_build_tasks(&build.id)
+    })?;
+    Ok(tasks.into_iter().flatten().collect())
+}
+
+/// Fetch the failed tasks of every build in a version
+pub fn failed_tasks(client: &EvergreenClient, version_id: &str) -> Result<Vec<Task>> {
+    let builds: Vec<_> = client
+        .get_version_builds(version_id)?
+        .into_iter()
+        .filter(|b| b.status_counts.failed + b.status_counts.timed_out > 0)
+        .collect();
+    let tasks = pool::try_map_bounded(&builds, client.limits.parallelism, |build| {
+        client.get_build_tasks(&build.id)
+    })?;
+    Ok(tasks
+        .into_iter()
+        .flatten()
+        .filter(|t| t.status == "failed")
+        .collect())
+}
+
+/// The failed tasks of a version to restart, and those left alone
+#[derive(Debug, Default)]
+pub struct RestartPlan {
+    pub restart: Vec<Task>,
+    // Failed without a failing test, e.g. system failures and setup failures
+    pub skipped: Vec<Task>,
+}
+
+/// Decide which failed tasks of a version to restart
+///
+/// With `only_test_failures` a task is restarted only when one of its tests failed, the others
+/// usually failed on the host or in setup and fail again until someone looks at them.
+pub fn plan_restart(
+    client: &EvergreenClient,
+    version_id: &str,
+    only_test_failures: bool,
+) -> Result<RestartPlan> {
+    let failures = failed_tasks(client, version_id)?;
+    if !only_test_failures {
+        return Ok(RestartPlan {
+            restart: failures,
+            skipped: Vec::new(),
+        });
+    }
+
+    let failed_tests = pool::try_map_bounded(&failures, client.limits.parallelism, |task| {
+        Ok(!client.get_task_tests(&task.task_id, "fail", 1)?.is_empty())
+    })?;
+    let mut plan = RestartPlan::default();
+    for (task, failed_test) in failures.into_iter().zip(failed_tests) {
+        if failed_test {
+            plan.restart.push(task);
+        } else {
+            plan.skipped.push(task);
+        }
+    }
+    Ok(plan)
+}
+
+/// Describe failed tasks grouped by build variant, e.g. `2 failed tasks: linux (compile, lint)`
+pub fn failure_summary(failures: &[Task]) -> String {
+    format!("{} failed tasks: {}", failures.len(), by_variant(failures))
+}
+
+/// Task names grouped by build variant, e.g. `linux (compile, lint), windows (lint)`
+pub fn by_variant(tasks: &[Task]) -> String {
+    let mut variants: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for task in tasks {
+        variants
+            .entry(task.build_variant.as_str())
+            .or_default()
+            .push(task.display_name.as_str());
+    }
+
+    let groups: Vec<String> = variants
+        .iter()
+        .map(|(variant, names)| format!("{} ({})", variant, names.join(", ")))
+        .collect();
+    groups.join(", ")
+}
+
+/// Group tasks into layers so each task comes after every task it depends on
+///
+/// Only dependencies within `tasks` are considered, tasks in the same layer are independent of
+/// each other and may be restarted concurrently.
+pub fn dependency_layers(tasks: &[Task]) -> Result<Vec<Vec<String>>> {
+    let ids: BTreeSet<&str> = tasks.iter().map(|t| t.task_id.as_str()).collect();
+
+    // Upstream tasks still waiting to be placed in a layer, for each task
+    let mut pending: BTreeMap<&str, BTreeSet<&str>> = tasks
+        .iter()
+        .map(|t| {
+            let upstream = t
+                .depends_on
+                .iter()
+                .map(|d| d.id.as_str())
+                .filter(|id| ids.contains(id) && *id != t.task_id)
+                .collect();
+            (t.task_id.as_str(), upstream)
+        })
+        .collect();
+
+    let mut layers = Vec::new();
+    while !pending.is_empty() {
+        let ready: Vec<&str> = pending
+            .iter()
+            .filter(|(_, upstream)| upstream.is_empty())
+            .map(|(id, _)| *id)
+            .collect();
+
+        if ready.is_empty() {
+            let cycle: Vec<&str> = pending.keys().copied().collect();
+            return Err(anyhow!(
+                "Tasks depend on each other in a cycle: {}",
+                cycle.join(", ")
+            ));
+        }
+
+        for id in &ready {
+            pending.remove(id);
+        }
+        for upstream in pending.values_mut() {
+            for id in &ready {
+                upstream.remove(id);
+            }
+        }
+        layers.push(ready.into_iter().map(|id| id.to_owned()).collect());
+    }
+
+    Ok(layers)
+}
+
+#[test]
+fn test_dependency_layers() {
+    use crate::models::TaskDependency;
+
+    let task = |id: &str, deps: &[&str]| Task {
+        task_id: id.to_owned(),
+        depends_on: deps
+            .iter()
+            .map(|d| TaskDependency {
+                id: d.to_string(),
+                status: "success".to_owned(),
+                extra: Default::default(),
+            })
+            .collect(),
+        ..Default::default()
+    };
+
+    let tasks = vec![
+        task("test", &["compile", "lint"]),
+        task("compile", &["outside"]),
+        task("lint", &[]),
+        task("package", &["test"]),
+    ];
+    assert_eq!(
+        dependency_layers(&tasks).unwrap(),
+        vec![vec!["compile", "lint"], vec!["test"], vec!["package"]]
+    );
+
+    let cycle = vec![task("a", &["b"]), task("b", &["a"]), task("c", &[])];
+    assert_eq!(
+        dependency_layers(&cycle).unwrap_err().to_string(),
+        "Tasks depend on each other in a cycle: a, b"
+    );
+}
+
+#[test]
+fn test_failure_summary() {
+    let task = |variant: &str, name: &str| Task {
+        build_variant: variant.to_owned(),
+        display_name: name.to_owned(),
+        ..Default::default()
+    };
+
+    let failures = vec![
+        task("windows", "lint"),
+        task("linux", "compile"),
+        task("linux", "test"),
+    ];
+    assert_eq!(
+        failure_summary(&failures),
+        "3 failed tasks: linux (compile, test), windows (lint)"
+    );
+}
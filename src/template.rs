@@ -0,0 +1,133 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::output::lookup_path;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Field(String),
+}
+
+/// A `--format` template such as `{host_id}\t{distro.distro_id}`
+///
+/// `{path}` is replaced by the value at a flattened field path, `{{` and `}}` are literal braces
+/// and `\t`, `\n` and `\\` are unescaped so templates work inside single quotes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut path = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => path.push(c),
+                            None => return Err(anyhow!("Unclosed '{{' in format '{}'", s)),
+                        }
+                    }
+                    let path = path.trim();
+                    if path.is_empty() {
+                        return Err(anyhow!("Empty field in format '{}'", s));
+                    }
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Field(path.to_owned()));
+                }
+                '}' => return Err(anyhow!("Unmatched '}}' in format '{}', use '}}}}'", s)),
+                '\\' => match chars.next() {
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    Some('\\') => text.push('\\'),
+                    Some(c) => {
+                        text.push('\\');
+                        text.push(c);
+                    }
+                    None => text.push('\\'),
+                },
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Template { segments })
+    }
+}
+
+impl Template {
+    /// The field paths the template reads
+    pub fn paths(&self) -> Vec<String> {
+        self.segments
+            .iter()
+            .filter_map(|s| match s {
+                Segment::Field(path) => Some(path.clone()),
+                Segment::Text(_) => None,
+            })
+            .collect()
+    }
+
+    /// Fill in a record, missing and null fields are empty and strings are unquoted
+    pub fn render(&self, record: &Value) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out += text,
+                Segment::Field(path) => match lookup_path(record, path).as_deref() {
+                    None | Some(Value::Null) => {}
+                    Some(Value::String(s)) => out += s,
+                    Some(v) => out += &v.to_string(),
+                },
+            }
+        }
+        out
+    }
+}
+
+#[test]
+fn test_template() {
+    let t: Template = r"{host_id}\t{ distro.distro_id }\t{{{n}}}".parse().unwrap();
+    assert_eq!(t.paths(), vec!["host_id", "distro.distro_id", "n"]);
+
+    let record = serde_json::json!({"host_id": "h1", "distro": {"distro_id": "rhel"}, "n": 3});
+    assert_eq!(t.render(&record), "h1\trhel\t{3}");
+
+    assert!("{host_id".parse::<Template>().is_err());
+    assert!("host_id}".parse::<Template>().is_err());
+    assert!("{}".parse::<Template>().is_err());
+}
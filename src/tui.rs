@@ -0,0 +1,357 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An interactive dashboard of spawn hosts, recent patches and their failed tasks.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::client::EvergreenClient;
+use crate::color::{classify_status, StatusKind};
+use crate::models::{Host, Patch, Task};
+use crate::tasks;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Patches listed in the patches pane
+const RECENT_PATCHES: u32 = 10;
+
+/// Patches whose failed tasks are listed in the failures pane
+const FAILURE_PATCHES: usize = 3;
+
+const HELP: &str =
+    "tab: switch pane  \u{2191}\u{2193}: select  t: terminate  s: ssh  r: restart  R: refresh  q: quit";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pane {
+    Hosts,
+    Patches,
+    Failures,
+}
+
+const PANES: [Pane; 3] = [Pane::Hosts, Pane::Patches, Pane::Failures];
+
+impl Pane {
+    fn index(self) -> usize {
+        PANES.iter().position(|p| *p == self).unwrap()
+    }
+
+    fn next(self) -> Pane {
+        PANES[(self.index() + 1) % PANES.len()]
+    }
+
+    fn prev(self) -> Pane {
+        PANES[(self.index() + PANES.len() - 1) % PANES.len()]
+    }
+}
+
+/// An action waiting for the user to confirm with y
+enum Pending {
+    Terminate(String),
+}
+
+struct Dashboard {
+    hosts: Vec<Host>,
+    patches: Vec<Patch>,
+    failures: Vec<Task>,
+    focus: Pane,
+    lists: [ListState; 3],
+    message: String,
+    pending: Option<Pending>,
+    refreshed: Instant,
+}
+
+/// Move a selection by `delta` within `len` items, wrapping at either end
+fn step(selected: Option<usize>, len: usize, delta: isize) -> Option<usize> {
+    if len == 0 {
+        return None;
+    }
+    let current = selected.unwrap_or(0) as isize;
+    Some((current + delta).rem_euclid(len as isize) as usize)
+}
+
+fn status_span(status: &str, width: usize) -> Span<'static> {
+    let color = match classify_status(status) {
+        Some(StatusKind::Good) => Color::Green,
+        Some(StatusKind::Bad) => Color::Red,
+        Some(StatusKind::Pending) => Color::Yellow,
+        None => Color::Reset,
+    };
+    Span::styled(
+        format!("{:<width$} ", status, width = width),
+        Style::default().fg(color),
+    )
+}
+
+fn host_item(host: &Host) -> ListItem<'static> {
+    ListItem::new(Line::from(vec![
+        Span::raw(format!("{:<22} ", host.host_id)),
+        status_span(&host.status, 12),
+        Span::raw(format!("{:<24} {}", host.distro.distro_id, host.host_url)),
+    ]))
+}
+
+fn patch_item(patch: &Patch) -> ListItem<'static> {
+    let short: String = patch.patch_id.chars().take(7).collect();
+    ListItem::new(Line::from(vec![
+        Span::raw(format!("{} ", short)),
+        status_span(&patch.status, 10),
+        Span::raw(format!("{:<20} {}", patch.project_id, patch.description)),
+    ]))
+}
+
+fn task_item(task: &Task) -> ListItem<'static> {
+    ListItem::new(Line::from(vec![
+        status_span(&task.status, 8),
+        Span::raw(format!(
+            "{:<30} {:<30} {}",
+            task.display_name, task.build_variant, task.task_id
+        )),
+    ]))
+}
+
+impl Dashboard {
+    fn new() -> Dashboard {
+        Dashboard {
+            hosts: Vec::new(),
+            patches: Vec::new(),
+            failures: Vec::new(),
+            focus: Pane::Hosts,
+            lists: Default::default(),
+            message: String::new(),
+            pending: None,
+            refreshed: Instant::now(),
+        }
+    }
+
+    fn len(&self, pane: Pane) -> usize {
+        match pane {
+            Pane::Hosts => self.hosts.len(),
+            Pane::Patches => self.patches.len(),
+            Pane::Failures => self.failures.len(),
+        }
+    }
+
+    fn selected(&self, pane: Pane) -> Option<usize> {
+        self.lists[pane.index()]
+            .selected()
+            .filter(|i| *i < self.len(pane))
+    }
+
+    fn select(&mut self, delta: isize) {
+        let pane = self.focus;
+        let next = step(self.selected(pane), self.len(pane), delta);
+        self.lists[pane.index()].select(next);
+    }
+
+    fn refresh(&mut self, client: &EvergreenClient) {
+        match fetch(client) {
+            Ok((hosts, patches, failures)) => {
+                self.hosts = hosts;
+                self.patches = patches;
+                self.failures = failures;
+                self.message = format!("Refreshed at {}", chrono::Local::now().format("%H:%M:%S"));
+            }
+            Err(e) => self.message = format!("Refresh failed: {}", e),
+        }
+        for pane in PANES {
+            let selected = self
+                .selected(pane)
+                .or_else(|| step(None, self.len(pane), 0));
+            self.lists[pane.index()].select(selected);
+        }
+        self.refreshed = Instant::now();
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(2)]).areas(frame.area());
+        let panes = Layout::vertical([
+            Constraint::Percentage(40),
+            Constraint::Percentage(30),
+            Constraint::Percentage(30),
+        ])
+        .split(main);
+
+        let items: Vec<(Pane, String, Vec<ListItem>)> = vec![
+            (
+                Pane::Hosts,
+                format!("Spawn hosts ({})", self.hosts.len()),
+                self.hosts.iter().map(host_item).collect(),
+            ),
+            (
+                Pane::Patches,
+                format!("Recent patches ({})", self.patches.len()),
+                self.patches.iter().map(patch_item).collect(),
+            ),
+            (
+                Pane::Failures,
+                format!("Failed tasks ({})", self.failures.len()),
+                self.failures.iter().map(task_item).collect(),
+            ),
+        ];
+
+        for ((pane, title, items), area) in items.into_iter().zip(panes.iter()) {
+            let border = if pane == self.focus {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            let list = List::new(items)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(border)
+                        .title(title),
+                )
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            frame.render_stateful_widget(list, *area, &mut self.lists[pane.index()]);
+        }
+
+        let footer_text = vec![Line::from(self.message.clone()), Line::from(HELP)];
+        frame.render_widget(Paragraph::new(footer_text), footer);
+    }
+
+    /// Handle a key press, returns true to quit
+    fn on_key(
+        &mut self,
+        code: KeyCode,
+        client: &EvergreenClient,
+        terminal: &mut DefaultTerminal,
+    ) -> Result<bool> {
+        if let Some(pending) = self.pending.take() {
+            self.message = match (code, pending) {
+                (KeyCode::Char('y'), Pending::Terminate(id)) => match client.terminate_host(&id) {
+                    Ok(()) => format!("Terminated {}", id),
+                    Err(e) => format!("Failed to terminate {}: {}", id, e),
+                },
+                _ => "Cancelled".to_owned(),
+            };
+            return Ok(false);
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
+            KeyCode::Tab => self.focus = self.focus.next(),
+            KeyCode::BackTab => self.focus = self.focus.prev(),
+            KeyCode::Down | KeyCode::Char('j') => self.select(1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+            KeyCode::Char('R') => self.refresh(client),
+            KeyCode::Char('t') if self.focus == Pane::Hosts => {
+                if let Some(i) = self.selected(Pane::Hosts) {
+                    let id = self.hosts[i].host_id.clone();
+                    self.message = format!("Terminate {}? y/n", id);
+                    self.pending = Some(Pending::Terminate(id));
+                }
+            }
+            KeyCode::Char('s') if self.focus == Pane::Hosts => {
+                if let Some(i) = self.selected(Pane::Hosts) {
+                    let host = &self.hosts[i];
+                    let target = format!("{}@{}", host.user, host.host_url);
+
+                    // Hand the terminal to ssh and take it back when the session ends
+                    ratatui::restore();
+                    let status = std::process::Command::new("ssh").arg(&target).status();
+                    *terminal = ratatui::init();
+                    self.message = match status {
+                        Ok(s) => format!("ssh {} exited with {}", target, s),
+                        Err(e) => format!("Failed to run ssh: {}", e),
+                    };
+                }
+            }
+            KeyCode::Char('r') if self.focus == Pane::Failures => {
+                if let Some(i) = self.selected(Pane::Failures) {
+                    let id = &self.failures[i].task_id;
+                    self.message = match client.restart_task(id) {
+                        Ok(()) => format!("Restarted {}", id),
+                        Err(e) => format!("Failed to restart {}: {}", id, e),
+                    };
+                }
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+}
+
+/// Fetch live hosts, recent patches and the failed tasks of the newest patches
+fn fetch(client: &EvergreenClient) -> Result<(Vec<Host>, Vec<Patch>, Vec<Task>)> {
+    let hosts: Vec<Host> = client
+        .get_hosts(None)?
+        .into_iter()
+        .filter(|h| h.status != "terminated")
+        .collect();
+    let patches = client.get_user_patches(&client.config.user, RECENT_PATCHES)?;
+
+    let mut failures = Vec::new();
+    for patch in patches
+        .iter()
+        .filter(|p| !p.version.is_empty())
+        .take(FAILURE_PATCHES)
+    {
+        failures.extend(tasks::failed_tasks(client, &patch.version)?);
+    }
+
+    Ok((hosts, patches, failures))
+}
+
+/// Run the dashboard until the user quits
+pub fn run(client: &EvergreenClient) -> Result<()> {
+    let mut terminal = ratatui::init();
+    let result = event_loop(client, &mut terminal);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(client: &EvergreenClient, terminal: &mut DefaultTerminal) -> Result<()> {
+    let mut dashboard = Dashboard::new();
+    dashboard.message = "Loading...".to_owned();
+    terminal.draw(|f| dashboard.draw(f))?;
+    dashboard.refresh(client);
+
+    loop {
+        terminal.draw(|f| dashboard.draw(f))?;
+
+        if dashboard.refreshed.elapsed() >= REFRESH_INTERVAL {
+            dashboard.refresh(client);
+            continue;
+        }
+
+        if !event::poll(Duration::from_secs(1))? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && dashboard.on_key(key.code, client, terminal)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_step() {
+    assert_eq!(step(None, 0, 1), None);
+    assert_eq!(step(None, 3, 1), Some(1));
+    assert_eq!(step(Some(2), 3, 1), Some(0));
+    assert_eq!(step(Some(0), 3, -1), Some(2));
+    assert_eq!(Pane::Failures.next(), Pane::Hosts);
+    assert_eq!(Pane::Hosts.prev(), Pane::Failures);
+}
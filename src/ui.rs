@@ -0,0 +1,82 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Interactive prompts for commands that destroy things.
+//!
+//! `confirm` lists what a command is about to act on and asks before going ahead. `--yes`
+//! answers for the user, and without a terminal to ask on the command refuses rather than
+//! guessing.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use anyhow::Result;
+
+/// Ask on stderr whether to `action` the `items`, e.g. "Terminate 2 hosts"
+///
+/// `yes` skips the question. Anything but y or yes cancels the command with an error.
+pub fn confirm(action: &str, items: &[String], yes: bool) -> Result<()> {
+    if yes || items.is_empty() {
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "{} needs confirmation, pass --yes to go ahead without a terminal",
+            action
+        ));
+    }
+
+    let stdin = io::stdin();
+    ask(&mut stdin.lock(), &mut io::stderr(), action, items)
+}
+
+fn ask<R: BufRead, W: Write>(
+    input: &mut R,
+    out: &mut W,
+    action: &str,
+    items: &[String],
+) -> Result<()> {
+    writeln!(out, "{}:", action)?;
+    for item in items {
+        writeln!(out, "  {}", item)?;
+    }
+    write!(out, "Continue? [y/N]: ")?;
+    out.flush()?;
+
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    match line.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(anyhow!("Cancelled, nothing was changed")),
+    }
+}
+
+#[test]
+fn test_ask() {
+    let items = vec!["h-1  ubuntu1804-workstation  running".to_owned()];
+    let mut out = Vec::new();
+    ask(&mut "y\n".as_bytes(), &mut out, "Terminate 1 host", &items).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "Terminate 1 host:\n  h-1  ubuntu1804-workstation  running\nContinue? [y/N]: "
+    );
+
+    let mut out = Vec::new();
+    assert!(ask(&mut "YES\n".as_bytes(), &mut out, "Terminate", &items).is_ok());
+    assert!(ask(&mut "\n".as_bytes(), &mut out, "Terminate", &items).is_err());
+    assert!(ask(&mut "no\n".as_bytes(), &mut out, "Terminate", &items).is_err());
+    assert!(ask(&mut "".as_bytes(), &mut out, "Terminate", &items).is_err());
+
+    assert!(confirm("Terminate", &items, true).is_ok());
+    assert!(confirm("Terminate", &[], false).is_ok());
+}
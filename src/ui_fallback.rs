@@ -0,0 +1,68 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fallbacks that read values from the Evergreen UI server.
+//!
+//! These endpoints back the web UI and are NOT part of the public API. They can change or
+//! disappear with any Evergreen release, so they are only used when the user opts in with
+//! `--allow-ui-fallback` and the REST API cannot provide the value.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use evergreen_rs_derive::{EvgFields, EvgValues};
+
+use crate::client::EvergreenClient;
+use crate::models::Banner;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, EvgFields, EvgValues)]
+pub struct QueuePosition {
+    pub task_id: String,
+    // Zero based position in the distro's task queue
+    pub position: i64,
+    pub length: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct UiQueuePosition {
+    position: i64,
+    length: i64,
+}
+
+pub fn get_banner(client: &EvergreenClient) -> Result<Banner> {
+    warn_fallback("banner");
+    client.get_ui_json("json/banner")
+}
+
+pub fn get_queue_position(
+    client: &EvergreenClient,
+    distro_id: &str,
+    task_id: &str,
+) -> Result<QueuePosition> {
+    warn_fallback("queue position");
+    let pos: UiQueuePosition =
+        client.get_ui_json(&format!("json/task_queue/{}/{}", distro_id, task_id))?;
+    Ok(QueuePosition {
+        task_id: task_id.to_owned(),
+        position: pos.position,
+        length: pos.length,
+    })
+}
+
+fn warn_fallback(what: &str) {
+    eprintln!(
+        "Warning: reading {} from an unsupported UI endpoint, results may be unreliable",
+        what
+    );
+}
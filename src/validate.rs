@@ -0,0 +1,191 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Project configuration checks, YAML syntax locally and the rest by the server.
+
+use regex::Regex;
+
+use crate::models::ValidationError;
+
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    // error, warning or notice
+    pub level: String,
+    // 1-based, None when the problem cannot be placed in the file
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Finding {
+    /// E.g. `etc/evergreen.yml:12: error: task 'lint' is not defined`
+    pub fn format(&self, path: &str) -> String {
+        match self.line {
+            Some(line) => format!("{}:{}: {}: {}", path, line, self.level, self.message),
+            None => format!("{}: {}: {}", path, self.level, self.message),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.level == "error"
+    }
+}
+
+/// The YAML syntax error in `yaml`, if any
+pub fn syntax_error(yaml: &str) -> Option<Finding> {
+    serde_yaml::from_str::<serde_yaml::Value>(yaml)
+        .err()
+        .map(|e| yaml_error(&e))
+}
+
+/// An error reading a file, placed on its line when serde_yaml knows it
+pub fn yaml_error(err: &serde_yaml::Error) -> Finding {
+    let message = err.to_string();
+    // The location is already in the line number
+    let message = match message.find(" at line ") {
+        Some(at) => message[..at].to_owned(),
+        None => message,
+    };
+    Finding {
+        level: "error".to_owned(),
+        line: err.location().map(|l| l.line()),
+        message,
+    }
+}
+
+/// Place each of the server's findings on the line defining the first name it quotes
+///
+/// The server only names the tasks, variants and functions at fault, e.g. `buildvariant 'linux'
+/// references a nonexistent task 'lint'`.
+pub fn locate(yaml: &str, errors: Vec<ValidationError>) -> Vec<Finding> {
+    let quoted = Regex::new(r"'([^']+)'").unwrap();
+    errors
+        .into_iter()
+        .map(|e| Finding {
+            line: quoted
+                .captures_iter(&e.message)
+                .find_map(|c| find_definition(yaml, &c[1])),
+            level: e.level,
+            message: e.message,
+        })
+        .collect()
+}
+
+/// Top level sections whose list members are defined by a `name`
+const NAMED_SECTIONS: &[&str] = &["tasks", "task_groups", "buildvariants"];
+
+/// The first line defining `name`: `- name: lint` in a member of a named section, or a
+/// `"fetch source":` key of `functions`
+///
+/// References, such as the tasks listed by a build variant, are nested deeper and never match.
+fn find_definition(yaml: &str, name: &str) -> Option<usize> {
+    let unquote = |s: &str| s.trim().trim_matches(|c| c == '"' || c == '\'').to_owned();
+    let mut section = "";
+    // Indentation of the members of the current section, from the first one
+    let mut member_indent = None;
+    for (i, line) in yaml.lines().enumerate() {
+        let text = line.trim_start();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - text.len();
+        if indent == 0 && !text.starts_with("- ") {
+            section = text.split(':').next().unwrap_or_default().trim();
+            member_indent = None;
+            continue;
+        }
+        let member_indent = *member_indent.get_or_insert(indent);
+
+        let found = if section == "functions" {
+            indent == member_indent
+                && text.ends_with(':')
+                && unquote(&text[..text.len() - 1]) == name
+        } else if NAMED_SECTIONS.contains(&section) {
+            // name may come first in a member or be one of its other keys
+            let key = match text.strip_prefix("- ") {
+                Some(first) if indent == member_indent => Some(first),
+                None if indent == member_indent + 2 => Some(text),
+                _ => None,
+            };
+            key.and_then(|k| k.strip_prefix("name:"))
+                .is_some_and(|value| unquote(value) == name)
+        } else {
+            false
+        };
+        if found {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+#[test]
+fn test_syntax_error() {
+    assert_eq!(syntax_error("tasks:\n  - name: compile\n"), None);
+    assert_eq!(
+        syntax_error("tasks:\n  - name: compile\n   commands: []\n"),
+        Some(Finding {
+            level: "error".to_owned(),
+            line: Some(3),
+            message: "while parsing a block collection, did not find expected '-' indicator"
+                .to_owned(),
+        })
+    );
+}
+
+#[test]
+fn test_locate() {
+    let yaml = r#"functions:
+  "fetch source":
+    - command: git.get_project
+tasks:
+  - name: compile
+buildvariants:
+  - name: linux
+    tasks:
+      - name: lint
+"#;
+    let error = |level: &str, message: &str| ValidationError {
+        level: level.to_owned(),
+        message: message.to_owned(),
+        ..Default::default()
+    };
+    let findings = locate(
+        yaml,
+        vec![
+            error(
+                "error",
+                "buildvariant 'linux' references a nonexistent task 'lint'",
+            ),
+            error("warning", "function 'fetch source' is not used"),
+            error("error", "project has no owner"),
+        ],
+    );
+    let lines: Vec<Option<usize>> = findings.iter().map(|f| f.line).collect();
+    assert_eq!(lines, vec![Some(7), Some(2), None]);
+
+    // Only definitions count, not the variant's reference to a task
+    let referenced = "buildvariants:\n- name: linux\n  tasks:\n  - name: lint\ntasks:\n- display_name: Lint\n  name: lint\n";
+    assert_eq!(find_definition(referenced, "lint"), Some(7));
+    assert_eq!(find_definition(referenced, "linux"), Some(2));
+    assert_eq!(find_definition(yaml, "lint"), None);
+    assert_eq!(find_definition(yaml, "tasks"), None);
+    assert_eq!(
+        findings[1].format("evergreen.yml"),
+        "evergreen.yml:2: warning: function 'fetch source' is not used"
+    );
+    assert_eq!(
+        findings[2].format("evergreen.yml"),
+        "evergreen.yml: error: project has no owner"
+    );
+}
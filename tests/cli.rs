@@ -0,0 +1,1134 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! End-to-end tests of the binary against the mock server.
+
+mod common;
+
+use common::{MockServer, Received};
+
+/// Run the binary and return its stdout, failing the test if it fails
+fn stdout(server: &MockServer, args: &[&str]) -> String {
+    let output = server.evg().args(args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// The JSON documents in `text`, json output writes one per record
+fn documents(text: &str) -> Vec<serde_json::Value> {
+    serde_json::Deserializer::from_str(text)
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+/// Run the binary expecting it to fail and return its stderr
+fn failure(server: &MockServer, args: &[&str]) -> String {
+    let output = server.evg().args(args).output().unwrap();
+    assert!(!output.status.success(), "{:?} succeeded", args);
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn hosts_list_first_page() {
+    let server = MockServer::start();
+    let output = server.evg().args(["hosts", "list"]).output().unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("host_id:h-1"));
+    assert!(!stdout.contains("host_id:h-2"));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("pass --all to fetch every page"),
+        "{}",
+        stderr
+    );
+
+    let received = server.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].path, "/api/rest/v2/users/mark/hosts");
+}
+
+#[test]
+fn hosts_list_all_pages() {
+    let server = MockServer::start();
+    let stdout = stdout(&server, &["hosts", "list", "--all", "--url"]);
+    assert_eq!(
+        stdout,
+        "ubuntu@h-1.compute-1.amazonaws.com\nubuntu@h-2.compute-1.amazonaws.com\n"
+    );
+}
+
+#[test]
+fn hosts_events_window() {
+    let server = MockServer::start();
+    server.route(
+        "GET",
+        "hosts/h-2/events?start_at=e-2",
+        200,
+        serde_json::json!([]),
+    );
+    let ids = |args: &[&str]| -> Vec<String> {
+        let mut all = vec!["hosts", "events", "h-2", "-o", "json"];
+        all.extend(args);
+        documents(&stdout(&server, &all))
+            .iter()
+            .map(|e| e["id"].as_str().unwrap().to_owned())
+            .collect()
+    };
+
+    assert_eq!(ids(&[]), vec!["e-6", "e-5"]);
+
+    // Pages are followed until one ends before --since, and no further, while --before reads
+    // every page of the newest first events
+    assert_eq!(ids(&["--since", "2020-06-02"]), vec!["e-6", "e-5"]);
+    assert_eq!(ids(&["--before", "2020-06-03"]), vec!["e-5", "e-4", "e-3"]);
+    let pages: Vec<String> = server
+        .received()
+        .into_iter()
+        .map(|r| r.path)
+        .filter(|p| p.contains("start_at"))
+        .collect();
+    assert_eq!(
+        pages,
+        vec![
+            "/api/rest/v2/hosts/h-2/events?start_at=e-4",
+            "/api/rest/v2/hosts/h-2/events?start_at=e-4",
+            "/api/rest/v2/hosts/h-2/events?start_at=e-2",
+        ]
+    );
+}
+
+#[test]
+fn hosts_list_filters() {
+    let server = MockServer::start();
+    let by_regex = stdout(
+        &server,
+        &["hosts", "list", "--all", "--filter", "rhel", "--url"],
+    );
+    assert_eq!(by_regex, "ubuntu@h-2.compute-1.amazonaws.com\n");
+
+    let by_id = stdout(&server, &["hosts", "list", "--all", "h-1", "--url"]);
+    assert_eq!(by_id, "ubuntu@h-1.compute-1.amazonaws.com\n");
+
+    let busy = stdout(&server, &["hosts", "list", "--all", "--busy", "-o", "csv"]);
+    assert!(busy.contains("running_task.task_id"), "{}", busy);
+    assert_eq!(busy.lines().count(), 2);
+    assert!(busy.contains("mongodb_linux_compile_abc"), "{}", busy);
+
+    let query = stdout(
+        &server,
+        &[
+            "hosts",
+            "list",
+            "--all",
+            "-o",
+            "json",
+            "--query",
+            "[?no_expiration].host_id",
+        ],
+    );
+    assert_eq!(documents(&query), [serde_json::json!("h-1")]);
+}
+
+#[test]
+fn hosts_list_formats() {
+    let server = MockServer::start();
+    let list = |format: &str| {
+        stdout(
+            &server,
+            &[
+                "hosts",
+                "list",
+                "--all",
+                "-o",
+                format,
+                "--fields",
+                "host_id,distro.distro_id",
+            ],
+        )
+    };
+
+    let json = documents(&list("json"));
+    assert_eq!(json.len(), 2);
+    assert_eq!(json[1]["distro"]["distro_id"], "rhel80-large");
+    assert!(json[0].get("status").is_none());
+
+    let ndjson = list("ndjson");
+    assert_eq!(ndjson.lines().count(), 2);
+    for line in ndjson.lines() {
+        serde_json::from_str::<serde_json::Value>(line).unwrap();
+    }
+
+    let yaml = list("yaml");
+    assert!(yaml.contains("host_id: h-1"), "{}", yaml);
+
+    assert_eq!(
+        list("csv"),
+        "host_id,distro.distro_id\nh-1,ubuntu1804-workstation\nh-2,rhel80-large\n"
+    );
+    assert_eq!(
+        list("tsv"),
+        "host_id\tdistro.distro_id\nh-1\tubuntu1804-workstation\nh-2\trhel80-large\n"
+    );
+
+    let flat = list("flat");
+    assert!(flat.contains("distro.distro_id:rhel80-large"), "{}", flat);
+
+    let table = list("table");
+    let header = table.lines().next().unwrap();
+    assert!(header.contains("HOST_ID") && header.contains("DISTRO.DISTRO_ID"));
+    assert_eq!(table.lines().count(), 3);
+
+    assert!(!list("porcelain").is_empty());
+}
+
+#[test]
+fn hosts_terminate_posts() {
+    let server = MockServer::start();
+    let stderr = failure(&server, &["hosts", "terminate", "h-1"]);
+    assert!(stderr.contains("pass --yes"), "{}", stderr);
+    assert!(server.received().iter().all(|r| r.method == "GET"));
+
+    let terminated = stdout(&server, &["hosts", "terminate", "--yes", "h-1"]);
+    assert_eq!(terminated, "Terminated h-1\n");
+    assert!(server.received().contains(&Received {
+        method: "POST".to_owned(),
+        path: "/api/rest/v2/hosts/h-1/terminate".to_owned(),
+        body: String::new(),
+    }));
+
+    let config = server.home().join("groups.yml");
+    std::fs::write(
+        &config,
+        "api_server_host: http://unused/api\nuser: mark\ngroups:\n  farm: [h-2]\n",
+    )
+    .unwrap();
+    let config = config.to_str().unwrap();
+    let args = ["--config", config, "hosts", "terminate", "--yes", "--group"];
+    server.route("POST", "hosts/h-2/terminate", 200, serde_json::json!({}));
+    let terminated = stdout(&server, &[&args[..], &["farm"]].concat());
+    assert_eq!(terminated, "Terminated h-2\n");
+    let stderr = failure(&server, &[&args[..], &["nope"]].concat());
+    assert!(stderr.contains("No group 'nope'"), "{}", stderr);
+}
+
+#[test]
+fn invalid_credentials() {
+    let server = MockServer::start();
+    server.route(
+        "GET",
+        "users/mark/hosts",
+        401,
+        serde_json::json!({ "error": "invalid API key" }),
+    );
+    let stderr = failure(&server, &["hosts", "list"]);
+    assert!(stderr.contains("invalid API key"), "{}", stderr);
+    assert!(stderr.contains("Hint: Check api_key"), "{}", stderr);
+}
+
+#[test]
+fn server_errors() {
+    let server = MockServer::start();
+    server.route(
+        "GET",
+        "users/mark/hosts",
+        502,
+        serde_json::json!({ "error": "bad gateway" }),
+    );
+    let stderr = failure(&server, &["--retries", "0", "hosts", "list"]);
+    assert!(stderr.contains("bad gateway"), "{}", stderr);
+    assert_eq!(server.received().len(), 1);
+
+    // Each retry reaches the server
+    failure(&server, &["--retries", "1", "hosts", "list"]);
+    assert_eq!(server.received().len(), 3);
+
+    // A POST may have been acted on before the error, so it is not sent again
+    let server = MockServer::start();
+    server.route(
+        "POST",
+        "hosts/h-1/terminate",
+        503,
+        serde_json::json!({ "error": "unavailable" }),
+    );
+    failure(
+        &server,
+        &["--retries", "2", "--yes", "hosts", "terminate", "h-1"],
+    );
+    let posts = server
+        .received()
+        .into_iter()
+        .filter(|r| r.method == "POST")
+        .count();
+    assert_eq!(posts, 1);
+}
+
+#[test]
+fn usage_errors() {
+    let server = MockServer::start();
+    let stderr = failure(&server, &["hosts", "list", "--fields", "nope"]);
+    assert!(stderr.contains("nope"), "{}", stderr);
+
+    let stderr = failure(&server, &["hosts", "list", "-o", "xml"]);
+    assert!(stderr.contains("xml"), "{}", stderr);
+
+    let stderr = failure(&server, &["tasks", "restart", "unknown_task"]);
+    assert!(stderr.contains("not found"), "{}", stderr);
+}
+
+#[test]
+fn dry_run_prints_changes() {
+    let server = MockServer::start();
+    let stdout = stdout(
+        &server,
+        &["hosts", "extend", "--dry-run", "--hours", "2", "h-1"],
+    );
+    let url = format!("{}/rest/v2/hosts/h-1", server.api_url());
+    assert!(
+        stdout.starts_with(&format!("PATCH {}\n{{\n  \"add_hours\": 2\n}}\n", url)),
+        "{}",
+        stdout
+    );
+
+    // The hosts are still read, nothing is changed
+    let received = server.received();
+    assert!(received.iter().all(|r| r.method == "GET"), "{:?}", received);
+    assert!(!received.is_empty());
+}
+
+#[test]
+fn show_extra_fields() {
+    let server = MockServer::start();
+    server.route(
+        "GET",
+        "users/mark/hosts",
+        200,
+        serde_json::json!([{ "host_id": "h-9", "sleep_schedule": "weekdays" }]),
+    );
+    let list = |extra: &[&str]| {
+        let mut args = vec!["hosts", "list", "-o", "json"];
+        args.extend(extra);
+        documents(&stdout(&server, &args))
+    };
+
+    assert!(list(&[])[0].get("sleep_schedule").is_none());
+    assert_eq!(list(&["--show-extra"])[0]["sleep_schedule"], "weekdays");
+}
+
+#[test]
+fn lenient_lists() {
+    let server = MockServer::start();
+    server.route(
+        "GET",
+        "distros/ubuntu/queue",
+        200,
+        serde_json::json!([{ "id": "t1" }, { "display_name": "no id" }]),
+    );
+    let args = ["distros", "queue", "ubuntu", "--hosts", "1", "-o", "json"];
+    assert!(failure(&server, &args).contains("missing field `id`"));
+
+    let mut lenient = args.to_vec();
+    lenient.push("--lenient");
+    assert_eq!(documents(&stdout(&server, &lenient))[0]["depth"], 1);
+}
+
+#[test]
+fn stats_offline() {
+    let server = MockServer::start();
+    let args = [
+        "stats",
+        "tasks",
+        "--project",
+        "mongodb-mongo-master",
+        "--after-date",
+        "2020-06-01",
+        "--before-date",
+        "2020-06-08",
+        "-o",
+        "json",
+    ];
+    let online = server.evg_cached().args(args).output().unwrap();
+    assert!(online.status.success());
+    assert_eq!(
+        documents(&String::from_utf8(online.stdout.clone()).unwrap())[0]["task_name"],
+        "compile"
+    );
+
+    // The cached response is shown without asking the server again
+    let offline = server
+        .evg_cached()
+        .arg("--offline")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        offline.status.success(),
+        "{}",
+        String::from_utf8_lossy(&offline.stderr)
+    );
+    assert_eq!(offline.stdout, online.stdout);
+    assert!(String::from_utf8_lossy(&offline.stderr).contains("Offline"));
+    assert_eq!(server.received().len(), 1);
+}
+
+#[test]
+fn hosts_list_status() {
+    let server = MockServer::start();
+    let running = stdout(&server, &["hosts", "list", "--status", "running", "--url"]);
+    assert_eq!(running.lines().count(), 2);
+    assert_eq!(
+        server.received()[0].path,
+        "/api/rest/v2/users/mark/hosts?status=running"
+    );
+
+    // A shorthand for several statuses is filtered locally
+    let terminated = stdout(
+        &server,
+        &["hosts", "list", "--all", "--status", "terminated", "--url"],
+    );
+    assert_eq!(terminated, "");
+    assert_eq!(server.received()[1].path, "/api/rest/v2/users/mark/hosts");
+}
+
+#[test]
+fn run_saved_query() {
+    let server = MockServer::start();
+    let config = server.home().join("queries.yml");
+    std::fs::write(
+        &config,
+        "api_server_host: http://unused/api\nuser: mark\nqueries:\n  rhel: hosts list --all --filter 'rhel'\n",
+    )
+    .unwrap();
+    let config = config.to_str().unwrap();
+
+    let urls = stdout(&server, &["--config", config, "run", "rhel", "--url"]);
+    assert_eq!(urls, "ubuntu@h-2.compute-1.amazonaws.com\n");
+
+    let stderr = failure(&server, &["--config", config, "run", "nope"]);
+    assert!(stderr.contains("No saved query 'nope'"), "{}", stderr);
+}
+
+#[test]
+fn tasks_restart_failed() {
+    let server = MockServer::start();
+    let args = [
+        "tasks",
+        "restart-failed",
+        "--version",
+        "mongodb_abc",
+        "--only-test-failures",
+    ];
+    let restart = Received {
+        method: "POST".to_owned(),
+        path: "/api/rest/v2/tasks/mongodb_linux_compile_abc/restart".to_owned(),
+        body: String::new(),
+    };
+
+    let stdout = stdout(&server, &args);
+    assert_eq!(stdout, "Restarted mongodb_linux_compile_abc\n");
+    assert!(server.received().contains(&restart));
+
+    // A task without a failing test is left alone
+    let server = MockServer::start();
+    server.route(
+        "GET",
+        "tasks/mongodb_linux_compile_abc/tests?status=fail&limit=1",
+        200,
+        serde_json::json!([]),
+    );
+    let output = server.evg().args(args).output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Skipped 1 failed tasks without a failing test: linux (compile)"),
+        "{}",
+        stderr
+    );
+    assert!(!server.received().contains(&restart));
+}
+
+#[test]
+fn patches_status() {
+    let server = MockServer::start();
+    let output = server
+        .evg()
+        .args([
+            "patches",
+            "status",
+            "5f1a2b3c4d5e6f7a8b9c0d1e",
+            "-o",
+            "json",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("(failed): 1 passed, 1 failed, 0 running, 0 pending"),
+        "{}",
+        stderr
+    );
+
+    let variants = documents(&String::from_utf8(output.stdout).unwrap());
+    assert_eq!(variants.len(), 1);
+    assert_eq!(variants[0]["build_variant"], "linux");
+    assert_eq!(
+        variants[0]["failing"][0],
+        format!("{}/task/mongodb_linux_compile_abc", server.ui_url())
+    );
+}
+
+#[test]
+fn open_in_browser() {
+    let server = MockServer::start();
+    let url = stdout(&server, &["open", "task", "t_1", "--print-url"]);
+    assert_eq!(url, format!("{}/task/t_1\n", server.ui_url()));
+
+    // The browser is handed the page of the resource
+    let output = server
+        .evg()
+        .env("BROWSER", "echo")
+        .args(["open", "version", "v_1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{}/version/v_1\n", server.ui_url())
+    );
+
+    // Each command of a list is tried until one opens the page
+    let output = server
+        .evg()
+        .env("BROWSER", "false:echo opening %s")
+        .args(["open", "version", "v_1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("opening {}/version/v_1\n", server.ui_url())
+    );
+
+    let hosts = stdout(&server, &["hosts", "list", "--all", "--print-url"]);
+    assert_eq!(
+        hosts,
+        format!("{0}/host/h-1\n{0}/host/h-2\n", server.ui_url())
+    );
+    assert!(server.received().iter().all(|r| r.method == "GET"));
+}
+
+#[test]
+fn hosts_report() {
+    let server = MockServer::start();
+    server.route(
+        "GET",
+        "users/mark/hosts",
+        200,
+        serde_json::json!([
+            { "host_id": "h-1", "started_by": "mark", "instance_type": "m5.xlarge",
+              "status": "running", "creation_time": "2020-06-01T00:00:00Z" },
+            { "host_id": "h-2", "started_by": "mark", "instance_type": "m5.4xlarge",
+              "status": "running", "creation_time": "2020-06-01T00:00:00Z" },
+            { "host_id": "h-3", "started_by": "mark", "instance_type": "m5.xlarge",
+              "status": "terminated", "creation_time": "2020-06-01T00:00:00Z" },
+            { "host_id": "h-4", "started_by": "mark", "instance_type": "m5.xlarge",
+              "status": "stopped", "creation_time": "2020-06-01T00:00:00Z" }
+        ]),
+    );
+    server.route(
+        "GET",
+        "hosts/h-4/events",
+        200,
+        serde_json::json!([
+            { "timestamp": "2020-06-01T10:00:00Z", "eventtype": "HOST_STOPPED" },
+            { "timestamp": "2020-06-01T00:00:00Z", "eventtype": "HOST_CREATED" }
+        ]),
+    );
+    let config = server.home().join("prices.yml");
+    std::fs::write(
+        &config,
+        "api_server_host: http://unused/api\nuser: mark\ninstance_prices:\n  m5.xlarge: 0.5\n",
+    )
+    .unwrap();
+
+    let output = server
+        .evg()
+        .args([
+            "--config",
+            config.to_str().unwrap(),
+            "hosts",
+            "report",
+            "-o",
+            "json",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("No price for m5.4xlarge"), "{}", stderr);
+
+    let spend = documents(&String::from_utf8(output.stdout).unwrap());
+    assert_eq!(spend.len(), 1);
+    assert_eq!(spend[0]["group"], "mark");
+    assert_eq!(spend[0]["hosts"], 3);
+    assert_eq!(spend[0]["unpriced"], 1);
+    let hours = spend[0]["uptime_hours"].as_f64().unwrap();
+    let cost = spend[0]["cost"].as_f64().unwrap();
+    assert!(
+        hours > 0.0 && (cost - (hours - 10.0) / 2.0 * 0.5 - 10.0 * 0.5).abs() < 1.0,
+        "{:?}",
+        spend
+    );
+
+    // A stopped host's uptime ends when it stopped
+    let per_host = stdout(
+        &server,
+        &[
+            "--config",
+            config.to_str().unwrap(),
+            "hosts",
+            "report",
+            "--per-host",
+            "-o",
+            "json",
+        ],
+    );
+    let stopped = documents(&per_host)
+        .into_iter()
+        .find(|h| h["host_id"] == "h-4")
+        .unwrap();
+    assert_eq!(stopped["uptime_hours"], 10.0);
+    assert_eq!(stopped["cost"], 5.0);
+}
+
+#[test]
+fn hosts_reap() {
+    let server = MockServer::start();
+    let stderr = failure(&server, &["hosts", "reap", "--idle-hours", "24"]);
+    assert!(stderr.contains("pass --yes"), "{}", stderr);
+
+    // h-2 is running a task, so only h-1 is idle
+    server.route("POST", "hosts/h-1/stop", 200, serde_json::json!({}));
+    let stdout = stdout(
+        &server,
+        &["hosts", "reap", "--idle-hours", "24", "--stop", "--yes"],
+    );
+    assert_eq!(stdout, "Stopped h-1\n");
+    let posts: Vec<_> = server
+        .received()
+        .into_iter()
+        .filter(|r| r.method == "POST")
+        .map(|r| r.path)
+        .collect();
+    assert_eq!(posts, ["/api/rest/v2/hosts/h-1/stop"]);
+}
+
+#[test]
+fn hosts_spawn_with_scripts() {
+    let server = MockServer::start();
+    server.route(
+        "POST",
+        "hosts",
+        200,
+        serde_json::json!({ "host_id": "h-3", "status": "initializing" }),
+    );
+    let setup = server.home().join("setup.sh");
+    let userdata = server.home().join("userdata.yml");
+    std::fs::write(&setup, "apt-get install -y gdb\n").unwrap();
+    std::fs::write(&userdata, "#cloud-config\n").unwrap();
+
+    let stdout = stdout(
+        &server,
+        &[
+            "hosts",
+            "spawn",
+            "--distro",
+            "ubuntu1804-workstation",
+            "--setup-script",
+            setup.to_str().unwrap(),
+            "--userdata",
+            userdata.to_str().unwrap(),
+            "-o",
+            "json",
+        ],
+    );
+    assert_eq!(documents(&stdout)[0]["host_id"], "h-3");
+
+    let spawn = server
+        .received()
+        .into_iter()
+        .find(|r| r.method == "POST")
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_str(&spawn.body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "distro": "ubuntu1804-workstation",
+            "keyname": "laptop",
+            "setup_script": "apt-get install -y gdb\n",
+            "userdata": "#cloud-config\n"
+        })
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn hosts_sync_runs_rsync() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let server = MockServer::start();
+    let bin = server.home().join("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let rsync = bin.join("rsync");
+    std::fs::write(&rsync, "#!/bin/sh\necho \"$@\"\n").unwrap();
+    std::fs::set_permissions(&rsync, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+
+    let output = server
+        .evg()
+        .env("PATH", path)
+        .args(["hosts", "sync", "h-1", "src/", "mongo/src"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "--archive --compress --partial --rsh=ssh -- src/ ubuntu@h-1.compute-1.amazonaws.com:mongo/src\n"
+    );
+}
+
+#[test]
+fn hosts_spawn_wait() {
+    let server = MockServer::start();
+    server.route(
+        "POST",
+        "hosts",
+        200,
+        serde_json::json!({ "host_id": "h-3", "status": "initializing" }),
+    );
+    server.route(
+        "GET",
+        "hosts/h-3",
+        200,
+        serde_json::json!({ "host_id": "h-3", "status": "provision failed" }),
+    );
+    let args = [
+        "hosts",
+        "spawn",
+        "--distro",
+        "ubuntu1804-workstation",
+        "--wait",
+        "--interval",
+        "10ms",
+    ];
+    let stderr = failure(&server, &args);
+    assert!(
+        stderr.contains("Host h-3 is provision failed"),
+        "{}",
+        stderr
+    );
+    server.route(
+        "GET",
+        "hosts/h-3",
+        200,
+        serde_json::json!({ "host_id": "h-3", "status": "building-failed" }),
+    );
+    let stderr = failure(&server, &args);
+    assert!(stderr.contains("Host h-3 is building-failed"), "{}", stderr);
+
+    // A host that never becomes reachable times out
+    server.route(
+        "GET",
+        "hosts/h-3",
+        200,
+        serde_json::json!({ "host_id": "h-3", "status": "running", "provisioned": true,
+                            "host_url": "host.invalid" }),
+    );
+    let mut args = args.to_vec();
+    args.extend(["--timeout", "50ms"]);
+    let stderr = failure(&server, &args);
+    assert!(
+        stderr.contains("h-3 is running, waiting for ssh"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("Gave up waiting for h-3"), "{}", stderr);
+}
+
+#[test]
+fn hosts_modify() {
+    let server = MockServer::start();
+    server.route("PATCH", "hosts/h-1", 200, serde_json::json!({}));
+    let stdout = stdout(
+        &server,
+        &[
+            "hosts",
+            "modify",
+            "h-1",
+            "--add-tag",
+            "team=server",
+            "--remove-tag",
+            "temp",
+        ],
+    );
+    assert_eq!(stdout, "Modified h-1\n");
+    let patch = server
+        .received()
+        .into_iter()
+        .find(|r| r.method == "PATCH")
+        .unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&patch.body).unwrap(),
+        serde_json::json!({
+            "add_instance_tags": [{ "key": "team", "value": "server", "can_be_modified": true }],
+            "delete_instance_tags": ["temp"]
+        })
+    );
+
+    // The host is running
+    let stderr = failure(
+        &server,
+        &["hosts", "modify", "h-1", "--instance-type", "m5.4xlarge"],
+    );
+    assert!(stderr.contains("Stop h-1 before"), "{}", stderr);
+}
+
+#[test]
+fn hosts_rename() {
+    let server = MockServer::start();
+    server.route("PATCH", "hosts/h-1", 200, serde_json::json!({}));
+    server.route("PATCH", "hosts/h-2", 200, serde_json::json!({}));
+    let patches = || {
+        let mut bodies: Vec<_> = server
+            .received()
+            .into_iter()
+            .filter(|r| r.method == "PATCH")
+            .map(|r| (r.path, r.body))
+            .collect();
+        bodies.sort();
+        bodies
+    };
+
+    stdout(&server, &["hosts", "rename", "h-1", "dev box"]);
+    assert_eq!(
+        patches(),
+        [(
+            "/api/rest/v2/hosts/h-1".to_owned(),
+            r#"{"new_name":"dev box"}"#.to_owned()
+        )]
+    );
+
+    let stdout = stdout(
+        &server,
+        &[
+            "hosts",
+            "rename",
+            "--from-template",
+            "{distro.distro_id}-{index}",
+            "h-2",
+            "h-1",
+        ],
+    );
+    assert_eq!(stdout.lines().count(), 2);
+    let bodies: Vec<_> = patches().into_iter().map(|(_, body)| body).collect();
+    assert!(bodies.contains(&r#"{"new_name":"rhel80-large-1"}"#.to_owned()));
+    assert!(bodies.contains(&r#"{"new_name":"ubuntu1804-workstation-2"}"#.to_owned()));
+
+    let stderr = failure(&server, &["hosts", "rename", "h-1"]);
+    assert!(
+        stderr.contains("Pass a host ID and its new name"),
+        "{}",
+        stderr
+    );
+
+    // Naming all of the user's hosts asks first
+    let stderr = failure(&server, &["hosts", "rename", "--from-template", "{index}"]);
+    assert!(stderr.contains("needs confirmation"), "{}", stderr);
+    let stderr = failure(
+        &server,
+        &["hosts", "rename", "--from-template", "{distro}", "h-1"],
+    );
+    assert!(stderr.contains("{distro.distro_id}"), "{}", stderr);
+}
+
+#[test]
+fn validate_project() {
+    let server = MockServer::start();
+    let path = server.home().join("evergreen.yml");
+    std::fs::write(
+        &path,
+        "tasks:\n  - name: compile\nbuildvariants:\n  - name: linux\n    tasks:\n      - name: lint\n",
+    )
+    .unwrap();
+    let path = path.to_str().unwrap();
+
+    server.route_path(
+        "POST",
+        "/api/validate",
+        200,
+        serde_json::json!([{ "level": 1, "message": "buildvariant 'linux' has no display name" }]),
+    );
+    assert_eq!(
+        stdout(&server, &["validate", path]),
+        format!(
+            "{}:4: warning: buildvariant 'linux' has no display name\n",
+            path
+        )
+    );
+    let post = server.received().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_str(&post.body).unwrap();
+    assert_eq!(body["quiet"], false);
+    assert!(body["config_yaml"].is_string());
+
+    // Errors come back with a 400 and fail the command
+    server.route_path(
+        "POST",
+        "/api/validate",
+        400,
+        serde_json::json!([{ "level": 0, "message": "task 'lint' is not defined" }]),
+    );
+    let output = server.evg().args(["validate", path]).output().unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{}: error: task 'lint' is not defined\n", path)
+    );
+
+    // Validating changes nothing, so --dry-run still asks the server
+    let output = server
+        .evg()
+        .args(["--dry-run", "validate", path])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{}: error: task 'lint' is not defined\n", path)
+    );
+
+    // Syntax errors are found without the server
+    std::fs::write(path, "tasks:\n  - name: compile\n   commands: []\n").unwrap();
+    let requests = server.received().len();
+    let output = server.evg().args(["validate", path]).output().unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "{}:3: error: while parsing a block collection, did not find expected '-' indicator\n",
+            path
+        )
+    );
+    assert_eq!(server.received().len(), requests);
+}
+
+#[test]
+fn project_file() {
+    let server = MockServer::start();
+    let path = server.home().join("evergreen.yml");
+    std::fs::write(
+        &path,
+        r#"tasks:
+  - name: compile
+    tags: [build]
+  - name: lint
+    depends_on: generate
+buildvariants:
+  - name: linux
+    tasks: [.build]
+  - name: windows
+    tasks: ["*"]
+"#,
+    )
+    .unwrap();
+    let path = path.to_str().unwrap();
+
+    assert_eq!(
+        stdout(&server, &["project", "tasks", path]),
+        "compile\nlint\n"
+    );
+    assert_eq!(
+        stdout(&server, &["project", "tasks", path, "--variant", "linux"]),
+        "compile\n"
+    );
+    assert_eq!(
+        stdout(&server, &["project", "variants", path, "--task", "lint"]),
+        "windows\n"
+    );
+
+    let output = server
+        .evg()
+        .args(["validate", path, "--local"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "{}:4: error: task 'lint' depends on 'generate' which matches no task\n",
+            path
+        )
+    );
+    assert!(server.received().is_empty());
+}
+
+#[test]
+fn task_graph() {
+    let server = MockServer::start();
+    server.route(
+        "GET",
+        "projects/mongodb/versions?limit=1",
+        200,
+        serde_json::json!([{ "version_id": "5f1a2b3c4d5e6f7a8b9c0d1e" }]),
+    );
+    let dot = stdout(&server, &["graph", "--project", "mongodb"]);
+    assert!(dot.starts_with("digraph tasks {\n"), "{}", dot);
+    let mermaid = stdout(
+        &server,
+        &["graph", "--project", "mongodb", "--format", "mermaid"],
+    );
+    assert_eq!(
+        mermaid,
+        "graph LR\n    n0[\"compile\"]\n    n1[\"fetch\"]\n    n1 --> n0\n"
+    );
+
+    let path = server.home().join("evergreen.yml");
+    std::fs::write(
+        &path,
+        "tasks:\n  - name: compile\n  - name: test\n    depends_on: compile\n  - name: lint\n",
+    )
+    .unwrap();
+    let mermaid = stdout(
+        &server,
+        &[
+            "graph",
+            "--file",
+            path.to_str().unwrap(),
+            "--task",
+            "test",
+            "--format",
+            "mermaid",
+        ],
+    );
+    assert_eq!(
+        mermaid,
+        "graph LR\n    n0[\"compile\"]\n    n1[\"test\"]\n    n0 --> n1\n"
+    );
+    let stderr = failure(
+        &server,
+        &["graph", "--file", path.to_str().unwrap(), "--task", "tset"],
+    );
+    assert!(stderr.contains("No task named 'tset'"), "{}", stderr);
+}
+
+#[test]
+fn follow_webhook_listener() {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::time::{Duration, Instant};
+
+    let server = MockServer::start();
+    server.route(
+        "GET",
+        "tasks/t1",
+        200,
+        serde_json::json!({ "task_id": "t1", "status": "started" }),
+    );
+    server.route("POST", "subscriptions", 200, serde_json::json!({}));
+    let listen = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_evergreen-rs"))
+        .env("HOME", server.home())
+        .env("XDG_CONFIG_HOME", server.home().join(".config"))
+        .env("EVG_API_SERVER", server.api_url())
+        .env("EVG_API_USER", "mark")
+        .env("EVG_API_KEY", "abc")
+        .args(["--no-cache", "follow", "--interval", "1s"])
+        .args(["--callback-url", "http://example.com/hook"])
+        .args(["--listen", &listen.to_string(), "task", "t1"])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(20);
+    let subscription = loop {
+        let post = server
+            .received()
+            .into_iter()
+            .find(|r| r.method == "POST" && r.path.ends_with("/subscriptions"));
+        if let Some(post) = post {
+            break serde_json::from_str::<serde_json::Value>(&post.body).unwrap();
+        }
+        assert!(Instant::now() < deadline, "no subscription was created");
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    let id = subscription[0]["id"].as_str().unwrap().to_owned();
+    assert_eq!(id.len(), 24);
+
+    // An oversized body is refused without reading it
+    let mut stream = TcpStream::connect(listen).unwrap();
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nContent-Length: 10000000000\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+
+    // A truncated request only fails its own connection
+    let mut stream = TcpStream::connect(listen).unwrap();
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nContent-Length: 50\r\n\r\n{")
+        .unwrap();
+    drop(stream);
+
+    server.route(
+        "GET",
+        "tasks/t1",
+        200,
+        serde_json::json!({ "task_id": "t1", "status": "success" }),
+    );
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("follow did not finish");
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    assert!(status.success());
+
+    let deletes: Vec<_> = server
+        .received()
+        .into_iter()
+        .filter(|r| r.method == "DELETE")
+        .map(|r| r.path)
+        .collect();
+    assert_eq!(
+        deletes,
+        vec![format!("/api/rest/v2/subscriptions?id={}", id)]
+    );
+}
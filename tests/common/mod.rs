@@ -0,0 +1,229 @@
+// Copyright [2020] [Mark Benvenuto]
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A wiremock Evergreen server for driving the binary in tests.
+//!
+//! The server answers from the fixture files in `tests/fixtures`, the same files `--replay`
+//! reads, plus routes a test adds for e.g. error responses. Every request is recorded so a test
+//! can check what the binary sent.
+
+#![allow(dead_code)]
+
+use std::cell::Cell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use assert_cmd::Command;
+use futures::executor::block_on;
+use wiremock::matchers::{any, method};
+use wiremock::{Mock, Request, ResponseTemplate};
+
+/// Server the fixtures were recorded against, replaced with the mock server's address
+const RECORDED_SERVER: &str = "https://evergreen.example.com";
+
+/// Priority of the fixtures, the lowest so any route a test adds comes first
+const FIXTURE_PRIORITY: u8 = u8::MAX;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Received {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+/// Matches the path and query exactly, as the fixtures record them
+struct PathAndQuery(String);
+
+impl wiremock::Match for PathAndQuery {
+    fn matches(&self, request: &Request) -> bool {
+        path_and_query(request) == self.0
+    }
+}
+
+/// Evergreen's answer for a missing resource, for the requests no route matches
+struct NotFound;
+
+impl wiremock::Respond for NotFound {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        ResponseTemplate::new(404).set_body_json(serde_json::json!({
+            "error": format!("{} not found", path_and_query(request))
+        }))
+    }
+}
+
+fn path_and_query(request: &Request) -> String {
+    match request.url.query() {
+        Some(query) => format!("{}?{}", request.url.path(), query),
+        None => request.url.path().to_owned(),
+    }
+}
+
+pub struct MockServer {
+    server: wiremock::MockServer,
+    // Priority of the next route a test adds, so a later route replaces an earlier one
+    priority: Cell<u8>,
+    // Home, config and cache directory of the binary
+    home: PathBuf,
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+impl MockServer {
+    /// Start a server that answers from the fixtures
+    pub fn start() -> MockServer {
+        static STARTED: AtomicUsize = AtomicUsize::new(0);
+        let home = std::env::temp_dir().join(format!(
+            "evg-cli-{}-{}",
+            std::process::id(),
+            STARTED.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&home).unwrap();
+
+        let server = MockServer {
+            server: block_on(wiremock::MockServer::start()),
+            priority: Cell::new(FIXTURE_PRIORITY - 1),
+            home,
+        };
+        server.load_fixtures(&fixtures_dir());
+        // Mounted last, so it only answers what no fixture does
+        block_on(
+            Mock::given(any())
+                .respond_with(NotFound)
+                .with_priority(FIXTURE_PRIORITY)
+                .mount(&server.server),
+        );
+        server
+    }
+
+    fn load_fixtures(&self, dir: &Path) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let text = fs::read_to_string(entry.unwrap().path()).unwrap();
+            let text = text.replace(RECORDED_SERVER, &self.server.uri());
+            let fixture: serde_json::Value = serde_json::from_str(&text).unwrap();
+            let body = match &fixture["body"] {
+                serde_json::Value::String(s) => s.clone(),
+                value => value.to_string(),
+            };
+            let mut response = ResponseTemplate::new(fixture["status"].as_u64().unwrap() as u16)
+                .set_body_raw(body, "application/json");
+            if let Some(headers) = fixture["headers"].as_object() {
+                for (name, value) in headers {
+                    response = response.insert_header(name.as_str(), value.as_str().unwrap());
+                }
+            }
+            self.mount(
+                fixture["method"].as_str().unwrap(),
+                fixture["path"].as_str().unwrap(),
+                response,
+                FIXTURE_PRIORITY,
+            );
+        }
+    }
+
+    fn mount(&self, verb: &str, path: &str, response: ResponseTemplate, priority: u8) {
+        block_on(
+            Mock::given(method(verb))
+                .and(PathAndQuery(path.to_owned()))
+                .respond_with(response)
+                .with_priority(priority)
+                .mount(&self.server),
+        );
+    }
+
+    /// Answer `method path` with `status` and a JSON body, in place of any fixture
+    pub fn route(&self, method: &str, path: &str, status: u16, body: serde_json::Value) {
+        self.route_path(method, &format!("/api/rest/v2/{}", path), status, body);
+    }
+
+    /// Like `route` for a full path, for the routes outside of /api/rest/v2
+    pub fn route_path(&self, method: &str, path: &str, status: u16, body: serde_json::Value) {
+        let priority = self.priority.get();
+        assert!(priority > 1, "too many routes");
+        self.priority.set(priority - 1);
+        self.mount(
+            method,
+            path,
+            ResponseTemplate::new(status).set_body_json(body),
+            priority,
+        );
+    }
+
+    pub fn api_url(&self) -> String {
+        format!("{}/api", self.server.uri())
+    }
+
+    /// The UI server the binary derives from the API URL
+    pub fn ui_url(&self) -> String {
+        self.server.uri()
+    }
+
+    /// Home directory of the binary, removed with the server
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    /// The requests received so far
+    pub fn received(&self) -> Vec<Received> {
+        block_on(self.server.received_requests())
+            .unwrap()
+            .iter()
+            .map(|r| Received {
+                method: r.method.to_string(),
+                path: path_and_query(r),
+                body: String::from_utf8_lossy(&r.body).into_owned(),
+            })
+            .collect()
+    }
+
+    /// The binary, configured to talk to this server as the user mark
+    pub fn evg(&self) -> Command {
+        let mut cmd = self.evg_cached();
+        cmd.arg("--no-cache");
+        cmd
+    }
+
+    /// Like `evg` but caching responses in the home directory, as `--offline` reads them
+    pub fn evg_cached(&self) -> Command {
+        let mut cmd = Command::cargo_bin("evergreen-rs").unwrap();
+        for var in &[
+            "EVG_CONFIG",
+            "EVG_PROFILE",
+            "HTTP_PROXY",
+            "HTTPS_PROXY",
+            "ALL_PROXY",
+            "http_proxy",
+            "https_proxy",
+            "all_proxy",
+        ] {
+            cmd.env_remove(var);
+        }
+        cmd.env("HOME", &self.home)
+            .env("XDG_CONFIG_HOME", self.home.join(".config"))
+            .env("XDG_CACHE_HOME", self.home.join(".cache"))
+            .env("EVG_API_SERVER", self.api_url())
+            .env("EVG_API_USER", "mark")
+            .env("EVG_API_KEY", "abc")
+            .env("NO_COLOR", "1");
+        cmd
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.home);
+    }
+}